@@ -59,6 +59,7 @@ fn benchmark_concurrent_stt(c: &mut Criterion) {
                     let input = AudioInput {
                         path: file.clone(),
                         device: "test_device".to_string(),
+                        start_time: chrono::Utc::now(),
                     };
                     sender.send(input).unwrap();
                 }
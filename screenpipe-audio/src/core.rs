@@ -1,4 +1,5 @@
 use anyhow::{anyhow, Result};
+use chrono::Utc;
 use cpal::traits::{DeviceTrait, HostTrait, StreamTrait};
 use cpal::StreamError;
 use log::{debug, error, info, warn};
@@ -14,6 +15,7 @@ use tokio::io::AsyncWriteExt;
 use tokio::process::Command;
 use tokio::sync::mpsc::{self, UnboundedSender};
 
+use crate::metrics::WHISPER_QUEUE_DEPTH;
 use crate::AudioInput;
 
 #[derive(Clone)]
@@ -213,6 +215,7 @@ pub async fn record_and_transcribe(
     is_running: Arc<AtomicBool>,
 ) -> Result<PathBuf> {
     let (cpal_audio_device, config) = get_device_and_config(&audio_device)?;
+    let start_time = Utc::now();
     let sample_rate = config.sample_rate().0;
     let channels = config.channels() as u16;
     debug!(
@@ -295,8 +298,11 @@ pub async fn record_and_transcribe(
     if let Err(e) = whisper_sender.send(AudioInput {
         path: output_path_clone_2.to_str().unwrap().to_string(),
         device: audio_device.to_string(),
+        start_time,
     }) {
         error!("Failed to send audio to whisper: {}", e);
+    } else {
+        WHISPER_QUEUE_DEPTH.inc();
     }
     debug!("Sent audio to whisper");
 
@@ -1,4 +1,5 @@
 mod core;
+pub mod metrics;
 mod multilingual;
 pub mod pcm_decode;
 pub mod stt;
@@ -7,4 +8,7 @@ pub use core::{
     record_and_transcribe, AudioDevice, DeviceControl,
 };
 pub use pcm_decode::pcm_decode;
-pub use stt::{create_whisper_channel, stt, AudioInput, TranscriptionResult, WhisperModel};
+pub use stt::{
+    create_whisper_channel, stt, stt_segments, AudioInput, TranscriptionResult,
+    TranscriptionSegment, WhisperModel,
+};
@@ -0,0 +1,12 @@
+// Prometheus series of the transcription pipeline, registered in the default registry and
+// exposed by the server at `/metrics`.
+use lazy_static::lazy_static;
+use prometheus::{register_int_gauge, IntGauge};
+
+lazy_static! {
+    pub static ref WHISPER_QUEUE_DEPTH: IntGauge = register_int_gauge!(
+        "screenpipe_whisper_queue_depth",
+        "Audio chunks waiting for Whisper"
+    )
+    .unwrap();
+}
@@ -1,6 +1,7 @@
 use std::time::{SystemTime, UNIX_EPOCH};
 
 use anyhow::{Error as E, Result};
+use chrono::{DateTime, Utc};
 use candle::{Device, IndexOp, Tensor};
 use candle_nn::{ops::softmax, VarBuilder};
 use hf_hub::{api::sync::Api, Repo, RepoType};
@@ -14,6 +15,7 @@ use rubato::{
     Resampler, SincFixedIn, SincInterpolationParameters, SincInterpolationType, WindowFunction,
 };
 
+use crate::metrics::WHISPER_QUEUE_DEPTH;
 use crate::{multilingual, pcm_decode::pcm_decode};
 
 #[derive(Clone)]
@@ -117,6 +119,16 @@ struct Segment {
     start: f64,
     duration: f64,
     dr: DecodingResult,
+    // Text between the timestamp tokens of the segment, empty without timestamps
+    pieces: Vec<TranscriptionSegment>,
+}
+
+/// Part of a transcription with its position in the audio file, in seconds.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TranscriptionSegment {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
 }
 
 struct Decoder<'a> {
@@ -324,10 +336,11 @@ impl<'a> Decoder<'a> {
                 info!("no speech detected, skipping {seek} {dr:?}");
                 continue;
             }
-            let segment = Segment {
+            let mut segment = Segment {
                 start: time_offset,
                 duration: segment_duration,
                 dr,
+                pieces: vec![],
             };
             if self.timestamps {
                 info!(
@@ -349,6 +362,11 @@ impl<'a> Decoder<'a> {
                                 .decode(&tokens_to_decode, true)
                                 .map_err(E::msg)?;
                             info!("  {:.1}s-{:.1}s: {}", prev_timestamp_s, timestamp_s, text);
+                            segment.pieces.push(TranscriptionSegment {
+                                start: segment.start + prev_timestamp_s as f64,
+                                end: segment.start + timestamp_s as f64,
+                                text,
+                            });
                             tokens_to_decode.clear()
                         }
                         prev_timestamp_s = timestamp_s;
@@ -363,6 +381,11 @@ impl<'a> Decoder<'a> {
                         .map_err(E::msg)?;
                     if !text.is_empty() {
                         info!("  {:.1}s-...: {}", prev_timestamp_s, text);
+                        segment.pieces.push(TranscriptionSegment {
+                            start: segment.start + prev_timestamp_s as f64,
+                            end: segment.start + segment.duration,
+                            text,
+                        });
                     }
                     tokens_to_decode.clear()
                 }
@@ -398,6 +421,22 @@ enum Task {
 }
 
 pub fn stt(file_path: &str, whisper_model: &WhisperModel) -> Result<String> {
+    Ok(join_segments(&stt_segments(file_path, whisper_model)?))
+}
+
+fn join_segments(segments: &[TranscriptionSegment]) -> String {
+    segments
+        .iter()
+        .map(|s| s.text.clone())
+        .collect::<Vec<String>>()
+        .join("\n")
+}
+
+/// Like [`stt`], with the position of every part of the transcription in the file.
+pub fn stt_segments(
+    file_path: &str,
+    whisper_model: &WhisperModel,
+) -> Result<Vec<TranscriptionSegment>> {
     debug!("Starting speech to text for file: {}", file_path);
     let model = &whisper_model.model;
     let tokenizer = &whisper_model.tokenizer;
@@ -459,10 +498,19 @@ pub fn stt(file_path: &str, whisper_model: &WhisperModel) -> Result<String> {
     let segments = dc.run(&mel)?;
     debug!("Decoding complete");
     Ok(segments
-        .iter()
-        .map(|s| s.dr.text.clone())
-        .collect::<Vec<String>>()
-        .join("\n"))
+        .into_iter()
+        .flat_map(|s| {
+            if s.pieces.is_empty() {
+                vec![TranscriptionSegment {
+                    start: s.start,
+                    end: s.start + s.duration,
+                    text: s.dr.text,
+                }]
+            } else {
+                s.pieces
+            }
+        })
+        .collect())
 }
 
 fn resample(input: Vec<f32>, from_sample_rate: u32, to_sample_rate: u32) -> Result<Vec<f32>> {
@@ -494,12 +542,15 @@ fn resample(input: Vec<f32>, from_sample_rate: u32, to_sample_rate: u32) -> Resu
 pub struct AudioInput {
     pub path: String,
     pub device: String,
+    /// When the recording of the file started
+    pub start_time: DateTime<Utc>,
 }
 
 #[derive(Debug, Clone)]
 pub struct TranscriptionResult {
     pub input: AudioInput,
     pub transcription: Option<String>,
+    pub segments: Vec<TranscriptionSegment>,
     pub timestamp: u64,
     pub error: Option<String>,
 }
@@ -521,23 +572,26 @@ pub async fn create_whisper_channel() -> Result<(
         loop {
             tokio::select! {
                 Some(input) = input_receiver.recv() => {
+                    WHISPER_QUEUE_DEPTH.dec();
                     let timestamp = SystemTime::now()
                         .duration_since(UNIX_EPOCH)
                         .expect("Time went backwards")
                         .as_secs();
 
-                    let result = stt(&input.path, &whisper_model);
+                    let result = stt_segments(&input.path, &whisper_model);
 
                     let transcription_result = match result {
-                        Ok(transcription) => TranscriptionResult {
+                        Ok(segments) => TranscriptionResult {
                             input: input.clone(),
-                            transcription: Some(transcription),
+                            transcription: Some(join_segments(&segments)),
+                            segments,
                             timestamp,
                             error: None,
                         },
                         Err(e) => TranscriptionResult {
                             input: input.clone(),
                             transcription: None,
+                            segments: vec![],
                             timestamp,
                             error: Some(e.to_string()),
                         },
@@ -1,5 +1,6 @@
 // cargo bench --bench db_benchmarks
 
+use chrono::Utc;
 use criterion::{criterion_group, criterion_main, Criterion};
 use rand::Rng;
 use screenpipe_server::{ContentType, DatabaseManager};
@@ -10,13 +11,16 @@ async fn setup_large_db(size: usize) -> DatabaseManager {
     let mut rng = rand::thread_rng();
 
     for _ in 0..size {
-        let _video_id = db.insert_video_chunk("test_video.mp4").await.unwrap();
-        let frame_id = db.insert_frame().await.unwrap();
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
         let ocr_text = format!("OCR text {}", rng.gen::<u32>());
         let text_json = format!(r#"{{"text": "{}"}}"#, ocr_text);
         let new_text_json_vs_previous_frame = format!(r#"{{"text": "{}"}}"#, ocr_text);
         let raw_data_output_from_ocr = format!(r#"{{"output": "{}"}}"#, ocr_text);
-        db.insert_ocr_text(frame_id, &ocr_text, &text_json, &new_text_json_vs_previous_frame, &raw_data_output_from_ocr).await.unwrap();
+        db.insert_ocr_text(frame_id, &ocr_text, &text_json, &new_text_json_vs_previous_frame, &raw_data_output_from_ocr, &[]).await.unwrap();
 
         let audio_id = db.insert_audio_chunk("test_audio.mp4").await.unwrap();
         let audio_text = format!("Audio transcription {}", rng.gen::<u32>());
@@ -0,0 +1,224 @@
+// cargo bench --bench video_encoding
+//
+// Encodes the same synthetic screen recording with every codec, and with the former PNG piping,
+// then prints the CPU time and disk space an hour of recording at 1 fps would take.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use image::{ImageFormat, Rgba, RgbaImage};
+use rand::{rngs::StdRng, Rng, SeedableRng};
+use screenpipe_core::find_ffmpeg_path;
+use screenpipe_server::{VideoCodec, VideoEncoderConfig};
+use std::io::{Cursor, Write};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const WIDTH: u32 = 1920;
+const HEIGHT: u32 = 1080;
+const FRAMES: usize = 30;
+const FPS: f64 = 1.0;
+
+/// Lines of "text" on a light background, each frame types a few more words, like a screen
+/// where little changes between captures.
+fn synthetic_frames() -> Vec<RgbaImage> {
+    let mut rng = StdRng::seed_from_u64(42);
+    let mut image = RgbaImage::from_pixel(WIDTH, HEIGHT, Rgba([245, 245, 245, 255]));
+    let mut cursor = (40, 40);
+    (0..FRAMES)
+        .map(|_| {
+            for _ in 0..20 {
+                let word_width = rng.gen_range(20..120);
+                if cursor.0 + word_width > WIDTH - 40 {
+                    cursor = (40, cursor.1 + 24);
+                }
+                if cursor.1 + 16 > HEIGHT - 40 {
+                    cursor = (40, 40);
+                }
+                for x in cursor.0..cursor.0 + word_width {
+                    for y in cursor.1..cursor.1 + 16 {
+                        if rng.gen_bool(0.4) {
+                            image.put_pixel(x, y, Rgba([30, 30, 30, 255]));
+                        }
+                    }
+                }
+                cursor.0 += word_width + 10;
+            }
+            image.clone()
+        })
+        .collect()
+}
+
+struct Usage {
+    /// User and system time of ffmpeg, plus the PNG encoding when frames are piped as PNG
+    cpu: Duration,
+    bytes: u64,
+}
+
+/// Runs ffmpeg with `args`, feeding it `input`, and reads its CPU time from `-benchmark`.
+fn run_ffmpeg(args: &[String], input: &[Vec<u8>], output: &Path) -> Option<Usage> {
+    let mut child = Command::new(find_ffmpeg_path().expect("ffmpeg not found"))
+        .arg("-benchmark")
+        .arg("-y")
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+    let mut stdin = child.stdin.take()?;
+    for frame in input {
+        if stdin.write_all(frame).is_err() {
+            break;
+        }
+    }
+    drop(stdin);
+    let result = child.wait_with_output().ok()?;
+    if !result.status.success() {
+        return None;
+    }
+
+    // e.g. "bench: utime=1.234s stime=0.123s rtime=1.500s"
+    let stderr = String::from_utf8_lossy(&result.stderr);
+    let bench = stderr
+        .lines()
+        .find(|line| line.starts_with("bench: utime="))?;
+    let seconds = |key: &str| -> f64 {
+        bench
+            .split_whitespace()
+            .find_map(|field| field.strip_prefix(key))
+            .and_then(|value| value.trim_end_matches('s').parse().ok())
+            .unwrap_or(0.0)
+    };
+    Some(Usage {
+        cpu: Duration::from_secs_f64(seconds("utime=") + seconds("stime=")),
+        bytes: std::fs::metadata(output).ok()?.len(),
+    })
+}
+
+fn encode_raw(config: &VideoEncoderConfig, frames: &[RgbaImage], output: &Path) -> Option<Usage> {
+    let args = config.ffmpeg_args(WIDTH, HEIGHT, FPS, &output.to_string_lossy());
+    let input: Vec<Vec<u8>> = frames.iter().map(|frame| frame.as_raw().clone()).collect();
+    run_ffmpeg(&args, &input, output)
+}
+
+fn encode_png(frames: &[RgbaImage], output: &Path) -> Option<Usage> {
+    let started = Instant::now();
+    let input: Vec<Vec<u8>> = frames
+        .iter()
+        .map(|frame| {
+            let mut buffer = Vec::new();
+            frame
+                .write_to(&mut Cursor::new(&mut buffer), ImageFormat::Png)
+                .unwrap();
+            buffer
+        })
+        .collect();
+    let png_encoding = started.elapsed();
+
+    let args: Vec<String> = [
+        "-f",
+        "image2pipe",
+        "-vcodec",
+        "png",
+        "-r",
+        "1",
+        "-i",
+        "-",
+        "-vcodec",
+        "libx264",
+        "-preset",
+        "ultrafast",
+        "-pix_fmt",
+        "yuv420p",
+        "-crf",
+        "25",
+    ]
+    .iter()
+    .map(|arg| arg.to_string())
+    .chain(std::iter::once(output.to_string_lossy().into_owned()))
+    .collect();
+    let usage = run_ffmpeg(&args, &input, output)?;
+    Some(Usage {
+        cpu: usage.cpu + png_encoding,
+        ..usage
+    })
+}
+
+fn configs() -> Vec<(String, VideoEncoderConfig)> {
+    [
+        VideoCodec::X264,
+        VideoCodec::X265,
+        VideoCodec::Vp9,
+        VideoCodec::Av1,
+    ]
+    .into_iter()
+    .map(|codec| {
+        let extension = if codec == VideoCodec::Vp9 {
+            "webm"
+        } else {
+            "mp4"
+        };
+        (
+            format!("{}.{}", codec, extension),
+            VideoEncoderConfig {
+                codec,
+                ..Default::default()
+            },
+        )
+    })
+    .collect()
+}
+
+fn print_hourly_usage(frames: &[RgbaImage], dir: &Path) {
+    let per_hour = 3600.0 * FPS / FRAMES as f64;
+    let report = |name: &str, usage: Option<Usage>| match usage {
+        Some(usage) => println!(
+            "{:<12} {:>8.1} s CPU/h {:>8.1} MB/h",
+            name,
+            usage.cpu.as_secs_f64() * per_hour,
+            usage.bytes as f64 * per_hour / 1_000_000.0
+        ),
+        None => println!("{:<12} not supported by this ffmpeg build", name),
+    };
+
+    println!(
+        "Recording {}x{} at {} fps, extrapolated from {} frames:",
+        WIDTH, HEIGHT, FPS, FRAMES
+    );
+    report("png+x264", encode_png(frames, &dir.join("png.mp4")));
+    for (file, config) in configs() {
+        report(
+            &config.codec.to_string(),
+            encode_raw(&config, frames, &dir.join(file)),
+        );
+    }
+}
+
+fn bench_video_encoding(c: &mut Criterion) {
+    let frames = synthetic_frames();
+    let dir = tempfile::tempdir().unwrap();
+    print_hourly_usage(&frames, dir.path());
+
+    let mut group = c.benchmark_group("video_encoding");
+    group.sample_size(10);
+    group.measurement_time(Duration::from_secs(30));
+
+    group.bench_function("png+x264", |b| {
+        b.iter(|| encode_png(&frames, &dir.path().join("png.mp4")))
+    });
+    for (file, config) in configs() {
+        let output = dir.path().join(file);
+        // Skip codecs the local ffmpeg lacks
+        if encode_raw(&config, &frames, &output).is_none() {
+            continue;
+        }
+        group.bench_function(config.codec.to_string(), |b| {
+            b.iter(|| encode_raw(&config, &frames, &output))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_video_encoding);
+criterion_main!(benches);
@@ -0,0 +1,225 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{header, HeaderValue, Method, StatusCode},
+    response::{IntoResponse, Json as JsonResponse},
+};
+use log::{debug, error, warn};
+use rand::RngCore;
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+
+use crate::db::Scope;
+use crate::plugin::{ApiPlugin, PluginDecision};
+use crate::DatabaseManager;
+
+const TOKEN_PREFIX: &str = "sp_";
+
+/// Scopes of the token that authenticated the request, inserted into the request extensions.
+#[derive(Debug, Clone)]
+pub struct GrantedScopes(Vec<Scope>);
+
+impl GrantedScopes {
+    pub fn all() -> Self {
+        Self(vec![Scope::Admin])
+    }
+
+    pub fn allows(&self, scope: Scope) -> bool {
+        self.0
+            .iter()
+            .any(|granted| *granted == scope || *granted == Scope::Admin)
+    }
+}
+
+/// Returns a new random token and its hash, only the hash should be stored.
+pub fn generate_token() -> (String, String) {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    let token = format!("{}{}", TOKEN_PREFIX, hex::encode(bytes));
+    let hash = hash_token(&token);
+    (token, hash)
+}
+
+/// Tokens are random 256 bit values, a plain SHA-256 is enough to keep them safe at rest.
+pub fn hash_token(token: &str) -> String {
+    hex::encode(Sha256::digest(token.as_bytes()))
+}
+
+/// Scope needed to call an endpoint, `None` for endpoints open to everyone.
+/// Unknown endpoints need `Admin` so new routes are never exposed by accident.
+pub fn required_scope(method: &Method, path: &str) -> Option<Scope> {
+    match (method, path) {
+        (_, "/health") => None,
+        // Every MCP client needs search, tools needing more check their own scope
+        (_, "/mcp") => Some(Scope::ReadSearch),
+        // Media in the export is checked by the handler
+        (_, "/search" | "/export") => Some(Scope::ReadSearch),
+        (_, "/timeline" | "/stats" | "/metrics") => Some(Scope::ReadSearch),
+        (&Method::GET, "/audio/list") | (_, "/audio/status") | (_, "/vision/status") => {
+            Some(Scope::ReadSearch)
+        }
+        // Subtitles are only transcriptions
+        (_, "/audio/subtitles") => Some(Scope::ReadSearch),
+        (_, path) if path.starts_with("/audio/chunks/") && path.ends_with("/subtitles") => {
+            Some(Scope::ReadSearch)
+        }
+        (_, "/audio/start" | "/audio/stop" | "/vision/start" | "/vision/stop") => {
+            Some(Scope::ControlRecording)
+        }
+        (_, path) if path.starts_with("/frames") || path.starts_with("/video") => {
+            Some(Scope::ReadMedia)
+        }
+        (_, path) if path.starts_with("/audio/chunks") => Some(Scope::ReadMedia),
+        _ => Some(Scope::Admin),
+    }
+}
+
+/// Checks the `Authorization: Bearer <token>` header against the tokens stored in the database.
+///
+/// With `allow_anonymous` (the server only listens on localhost), requests without a token are
+/// let through until the first token is created.
+pub struct AuthPlugin {
+    db: Arc<DatabaseManager>,
+    allow_anonymous: bool,
+}
+
+impl AuthPlugin {
+    pub fn new(db: Arc<DatabaseManager>, allow_anonymous: bool) -> Self {
+        Self {
+            db,
+            allow_anonymous,
+        }
+    }
+
+    async fn authenticate(&self, request: &Request<Body>) -> Result<GrantedScopes, AuthError> {
+        let token = request
+            .headers()
+            .get(header::AUTHORIZATION)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.strip_prefix("Bearer "));
+
+        let Some(token) = token else {
+            if self.allow_anonymous && self.db.count_api_tokens().await? == 0 {
+                return Ok(GrantedScopes::all());
+            }
+            return Err(AuthError::Unauthorized("Missing bearer token"));
+        };
+
+        match self.db.use_api_token(&hash_token(token.trim())).await? {
+            Some(token) => {
+                debug!("Request authenticated with token {}", token.name);
+                Ok(GrantedScopes(token.scopes.0))
+            }
+            None => Err(AuthError::Unauthorized("Invalid token")),
+        }
+    }
+}
+
+enum AuthError {
+    Unauthorized(&'static str),
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for AuthError {
+    fn from(e: sqlx::Error) -> Self {
+        AuthError::Database(e)
+    }
+}
+
+#[async_trait]
+impl ApiPlugin for AuthPlugin {
+    fn name(&self) -> &str {
+        "auth"
+    }
+
+    async fn on_request(&self, request: &mut Request<Body>) -> PluginDecision {
+        let Some(scope) = required_scope(request.method(), request.uri().path()) else {
+            return PluginDecision::Allow;
+        };
+
+        let granted = match self.authenticate(request).await {
+            Ok(granted) => granted,
+            Err(AuthError::Unauthorized(message)) => {
+                warn!(
+                    "Rejected {} {}: {}",
+                    request.method(),
+                    request.uri(),
+                    message
+                );
+                return deny(StatusCode::UNAUTHORIZED, message);
+            }
+            Err(AuthError::Database(e)) => {
+                error!("Failed to check API token: {}", e);
+                return deny(
+                    StatusCode::INTERNAL_SERVER_ERROR,
+                    "Failed to check API token",
+                );
+            }
+        };
+
+        if !granted.allows(scope) {
+            warn!(
+                "Rejected {} {}: token lacks the {} scope",
+                request.method(),
+                request.uri(),
+                scope
+            );
+            return deny(
+                StatusCode::FORBIDDEN,
+                &format!("Token lacks the {} scope", scope),
+            );
+        }
+
+        request.extensions_mut().insert(granted);
+        PluginDecision::Allow
+    }
+}
+
+fn deny(status: StatusCode, message: &str) -> PluginDecision {
+    PluginDecision::Deny((status, JsonResponse(json!({ "error": message }))).into_response())
+}
+
+/// CORS for the API. Without an allowlist only pages served from localhost can call it,
+/// `*` allows any origin.
+pub fn cors_layer(allowed_origins: &[String]) -> CorsLayer {
+    let allow_origin = if allowed_origins.iter().any(|origin| origin == "*") {
+        AllowOrigin::from(Any)
+    } else if allowed_origins.is_empty() {
+        AllowOrigin::predicate(|origin: &HeaderValue, _| is_localhost_origin(origin))
+    } else {
+        AllowOrigin::list(allowed_origins.iter().filter_map(|origin| {
+            match HeaderValue::from_str(origin) {
+                Ok(origin) => Some(origin),
+                Err(_) => {
+                    warn!("Ignoring invalid CORS origin {}", origin);
+                    None
+                }
+            }
+        }))
+    };
+
+    CorsLayer::new()
+        .allow_origin(allow_origin)
+        .allow_methods(Any)
+        .allow_headers(Any)
+}
+
+fn is_localhost_origin(origin: &HeaderValue) -> bool {
+    let Ok(origin) = origin.to_str() else {
+        return false;
+    };
+    let host = origin
+        .strip_prefix("http://")
+        .or_else(|| origin.strip_prefix("https://"))
+        .unwrap_or_default();
+    let host = match host.strip_prefix('[') {
+        // IPv6, e.g. [::1]:3000
+        Some(rest) => rest.split(']').next().unwrap_or_default(),
+        None => host.split(':').next().unwrap_or_default(),
+    };
+    matches!(host, "localhost" | "127.0.0.1" | "::1")
+}
@@ -0,0 +1,306 @@
+// Backups of a data directory: a consistent copy of the database made with SQLite's online
+// backup API, the media it references and a manifest of hashes, and the import of such a
+// backup into another data directory.
+//
+// Layout of a backup:
+//   db.sqlite        database, chunk paths rewritten relative to the backup
+//   media/video/...  video chunks and their frame timestamps sidecars
+//   media/audio/...  audio chunks
+//   manifest.json    size and SHA-256 of every file above
+//
+// Media is copied as is, a backup of an encrypted data directory can only be imported with the
+// same key.
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+
+use chrono::{DateTime, Utc};
+use log::{info, warn};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+
+use crate::db::ImportStats;
+use crate::video::timestamps_path;
+use crate::DatabaseManager;
+
+pub const MANIFEST_FILE: &str = "manifest.json";
+const MANIFEST_VERSION: u32 = 1;
+const DATABASE_FILE: &str = "db.sqlite";
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupManifest {
+    pub version: u32,
+    pub created_at: DateTime<Utc>,
+    pub files: Vec<BackupFile>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct BackupFile {
+    /// Relative to the backup directory
+    pub path: String,
+    pub size: u64,
+    pub sha256: String,
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Media {
+    Video,
+    Audio,
+}
+
+impl Media {
+    const ALL: [Media; 2] = [Media::Video, Media::Audio];
+
+    fn dir(&self) -> &'static str {
+        match self {
+            Media::Video => "media/video",
+            Media::Audio => "media/audio",
+        }
+    }
+
+    async fn chunks(&self, db: &DatabaseManager) -> Result<Vec<(i64, String)>, sqlx::Error> {
+        match self {
+            Media::Video => db.get_video_chunks().await,
+            Media::Audio => db.get_audio_chunks().await,
+        }
+    }
+
+    /// Path of the file kept next to a chunk, if any.
+    fn sidecar(&self, chunk_path: &str) -> Option<String> {
+        match self {
+            Media::Video => Some(timestamps_path(chunk_path)),
+            Media::Audio => None,
+        }
+    }
+
+    async fn set_path(&self, db: &DatabaseManager, id: i64, path: &str) -> Result<(), sqlx::Error> {
+        match self {
+            Media::Video => db.set_video_chunk_path(id, path).await,
+            Media::Audio => db.set_audio_chunk_path(id, path).await,
+        }
+    }
+}
+
+/// Backs up the database and its media into a new `screenpipe-backup-<time>` directory inside
+/// `dest_dir`, and returns its path. Recording can keep going while the backup runs.
+pub async fn create_backup(db: &DatabaseManager, dest_dir: &Path) -> anyhow::Result<PathBuf> {
+    let backup_dir = dest_dir.join(format!(
+        "screenpipe-backup-{}",
+        Utc::now().format("%Y%m%d-%H%M%S")
+    ));
+    if backup_dir.exists() {
+        anyhow::bail!("{} already exists", backup_dir.to_string_lossy());
+    }
+    for media in Media::ALL {
+        fs::create_dir_all(backup_dir.join(media.dir()))?;
+    }
+
+    info!(
+        "Backing up the database to {}",
+        backup_dir.to_string_lossy()
+    );
+    let database_path = backup_dir.join(DATABASE_FILE);
+    db.backup(&database_path).await?;
+
+    let snapshot = db.open_snapshot(&database_path).await?;
+    let result = copy_media(&snapshot, &backup_dir).await;
+    snapshot.close().await;
+    let mut files = result?;
+
+    // Hashed last, the chunk paths were just rewritten
+    files.insert(0, hash_file(&backup_dir, DATABASE_FILE.to_string()).await?);
+
+    let manifest = BackupManifest {
+        version: MANIFEST_VERSION,
+        created_at: Utc::now(),
+        files,
+    };
+    fs::write(
+        backup_dir.join(MANIFEST_FILE),
+        serde_json::to_vec_pretty(&manifest)?,
+    )?;
+
+    info!(
+        "Backup finished, {} files in {}",
+        manifest.files.len(),
+        backup_dir.to_string_lossy()
+    );
+    Ok(backup_dir)
+}
+
+/// Copies the chunks referenced by the snapshot next to it and points the snapshot at the copies.
+async fn copy_media(
+    snapshot: &DatabaseManager,
+    backup_dir: &Path,
+) -> anyhow::Result<Vec<BackupFile>> {
+    let mut files = Vec::new();
+    for media in Media::ALL {
+        for (id, file_path) in media.chunks(snapshot).await? {
+            let source = PathBuf::from(&file_path);
+            let Some(name) = source.file_name() else {
+                warn!("Skipping chunk {} with invalid path {}", id, file_path);
+                continue;
+            };
+            if !source.exists() {
+                warn!("Skipping missing chunk {}", file_path);
+                continue;
+            }
+
+            let target = unique_path(&backup_dir.join(media.dir()), &name.to_string_lossy());
+            let relative = format!(
+                "{}/{}",
+                media.dir(),
+                target.file_name().unwrap_or_default().to_string_lossy()
+            );
+            // Renamed along with the chunk, it is found by the chunk's path
+            let sidecar = media
+                .sidecar(&file_path)
+                .filter(|sidecar| Path::new(sidecar).exists())
+                .zip(media.sidecar(&relative));
+            let (size, sha256) = tokio::task::spawn_blocking(move || {
+                copy_hashed(File::open(&source)?, File::create(&target)?)
+            })
+            .await??;
+
+            media.set_path(snapshot, id, &relative).await?;
+            files.push(BackupFile {
+                path: relative,
+                size,
+                sha256,
+            });
+
+            if let Some((source, relative)) = sidecar {
+                let target = backup_dir.join(&relative);
+                let (size, sha256) = tokio::task::spawn_blocking(move || {
+                    copy_hashed(File::open(source)?, File::create(target)?)
+                })
+                .await??;
+                files.push(BackupFile {
+                    path: relative,
+                    size,
+                    sha256,
+                });
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Checks the backup against its manifest, then merges it into `db`. Media is copied into the
+/// `data` directory of `base_dir` and the imported chunks point at the copies.
+pub async fn import_backup(
+    db: &DatabaseManager,
+    backup_dir: &Path,
+    base_dir: &Path,
+) -> anyhow::Result<ImportStats> {
+    let manifest = verify_backup(backup_dir).await?;
+    let listed: HashSet<&str> = manifest.files.iter().map(|f| f.path.as_str()).collect();
+
+    // Migrations may run on the snapshot, leave the backup itself untouched
+    let snapshot_file = tempfile::NamedTempFile::new_in(base_dir)?;
+    fs::copy(backup_dir.join(DATABASE_FILE), snapshot_file.path())?;
+    let snapshot = db.open_snapshot(snapshot_file.path()).await?;
+
+    let data_dir = base_dir.join("data");
+    let mut copied = Vec::new();
+    let result = async {
+        let mut paths: [HashMap<i64, String>; 2] = Default::default();
+        for (media, chunk_paths) in Media::ALL.iter().zip(paths.iter_mut()) {
+            for (id, file_path) in media.chunks(&snapshot).await? {
+                if !listed.contains(file_path.as_str()) {
+                    warn!("Chunk {} is not in the backup, keeping its path", file_path);
+                    continue;
+                }
+                let source = backup_dir.join(&file_path);
+                let name = source.file_name().unwrap_or_default().to_string_lossy();
+                let target = unique_path(&data_dir, &name);
+                tokio::fs::copy(&source, &target).await?;
+                let target_path = target.to_string_lossy().into_owned();
+                copied.push(target);
+                let sidecar = media
+                    .sidecar(&file_path)
+                    .filter(|sidecar| listed.contains(sidecar.as_str()))
+                    .zip(media.sidecar(&target_path));
+                if let Some((sidecar, target)) = sidecar {
+                    tokio::fs::copy(backup_dir.join(sidecar), &target).await?;
+                    copied.push(PathBuf::from(target));
+                }
+                chunk_paths.insert(id, target_path);
+            }
+        }
+
+        let [video_paths, audio_paths] = &paths;
+        Ok::<_, anyhow::Error>(db.import(&snapshot, video_paths, audio_paths).await?)
+    }
+    .await;
+    snapshot.close().await;
+
+    if result.is_err() {
+        for path in &copied {
+            let _ = fs::remove_file(path);
+        }
+    }
+    result
+}
+
+/// Reads the manifest of a backup and checks the size and hash of every file in it.
+pub async fn verify_backup(backup_dir: &Path) -> anyhow::Result<BackupManifest> {
+    let manifest: BackupManifest =
+        serde_json::from_slice(&fs::read(backup_dir.join(MANIFEST_FILE))?)?;
+    if manifest.version != MANIFEST_VERSION {
+        anyhow::bail!("Unsupported backup version {}", manifest.version);
+    }
+
+    for file in &manifest.files {
+        let actual = hash_file(backup_dir, file.path.clone()).await?;
+        if actual.size != file.size || actual.sha256 != file.sha256 {
+            anyhow::bail!("{} does not match the backup manifest", file.path);
+        }
+    }
+    Ok(manifest)
+}
+
+async fn hash_file(backup_dir: &Path, path: String) -> anyhow::Result<BackupFile> {
+    let full_path = backup_dir.join(&path);
+    let (size, sha256) =
+        tokio::task::spawn_blocking(move || copy_hashed(File::open(full_path)?, io::sink()))
+            .await??;
+    Ok(BackupFile { path, size, sha256 })
+}
+
+fn copy_hashed(mut reader: impl Read, mut writer: impl Write) -> io::Result<(u64, String)> {
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0u8; 64 * 1024];
+    let mut size = 0;
+    loop {
+        let read = reader.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+        writer.write_all(&buffer[..read])?;
+        size += read as u64;
+    }
+    writer.flush()?;
+    Ok((size, hex::encode(hasher.finalize())))
+}
+
+/// `dir/name`, with a numeric suffix if that file already exists.
+fn unique_path(dir: &Path, name: &str) -> PathBuf {
+    let candidate = dir.join(name);
+    if !candidate.exists() {
+        return candidate;
+    }
+
+    let path = Path::new(name);
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (1..)
+        .map(|i| dir.join(format!("{}-{}{}", stem, i, extension)))
+        .find(|candidate| !candidate.exists())
+        .expect("unbounded range")
+}
@@ -1,18 +1,20 @@
 use std::{
     collections::HashMap,
     fs::{self, File},
-    net::SocketAddr,
+    net::{IpAddr, Ipv4Addr, SocketAddr},
     ops::Deref,
-    path::PathBuf,
+    path::{Path, PathBuf},
     sync::{atomic::AtomicBool, Arc},
     time::Duration,
 };
 
-use clap::Parser;
+use chrono::{DateTime, Utc};
+use clap::{Parser, Subcommand};
+use futures::StreamExt;
 #[allow(unused_imports)]
 use colored::Colorize;
 use dirs::home_dir;
-use log::{debug, info, LevelFilter};
+use log::{debug, info, warn, LevelFilter};
 use screenpipe_audio::{
     default_input_device, default_output_device, list_audio_devices, parse_audio_device,
     DeviceControl,
@@ -21,7 +23,17 @@ use std::io::Write;
 
 use screenpipe_core::find_ffmpeg_path;
 use screenpipe_server::logs::MultiWriter;
-use screenpipe_server::{start_continuous_recording, DatabaseManager, ResourceMonitor, Server};
+use screenpipe_server::{
+    collect_stats, create_backup, export_clip, export_stream, export_zip, format_stats,
+    generate_token, import_backup, start_continuous_recording, AuthPlugin, ClipOptions,
+    CompactionTier, Compactor, ContentType, DatabaseManager, ExportFormat, ExportOptions, FnPlugin,
+    Pipe, PipeManager, PluginRegistry, ResourceMonitor, Scope, Server, VideoCodec,
+    VideoEncoderConfig, VideoScale, WebhookEngine,
+};
+#[cfg(feature = "encryption")]
+use screenpipe_server::{
+    enable_encryption, enable_media_encryption, rotate_key, EncryptionConfig, KeySource,
+};
 use tokio::sync::mpsc::channel;
 
 // keep in mind this is the most important feature ever // TODO: add a pipe and a ⭐️ e.g screen | ⭐️ somehow in ascii ♥️🤓
@@ -46,6 +58,50 @@ struct Cli {
     #[arg(short, long, default_value_t = 1.0)]
     fps: f64,
 
+    /// Video chunk duration in seconds, at least 1
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    video_chunk_duration: u64,
+
+    /// Skip frames whose perceptual hash is at most this many bits (of 64) away from the last
+    /// frame picked for OCR, neither OCR'd nor stored. 0 keeps every frame
+    #[arg(long, default_value_t = 0)]
+    dedup_threshold: u32,
+
+    /// Number of frames OCR'd in parallel, 0 for one per CPU. More workers keep up with higher
+    /// fps at the cost of CPU
+    #[arg(long, default_value_t = 1)]
+    ocr_workers: usize,
+
+    /// Video codec: x264, x265, vp9 or av1. VP9 recordings are WebM files, the others MP4
+    #[arg(long, default_value = "x264")]
+    video_codec: VideoCodec,
+
+    /// Encoder preset, the codec's fastest by default
+    #[arg(long)]
+    video_preset: Option<String>,
+
+    /// Video quality, lower is better and bigger, the codec's default otherwise
+    #[arg(long)]
+    video_crf: Option<u32>,
+
+    /// Pixel format of the recordings, yuv420p by default
+    #[arg(long)]
+    video_pix_fmt: Option<String>,
+
+    /// Downscale recordings, by a factor like 0.5 or to a size like 1280x720
+    #[arg(long)]
+    video_scale: Option<VideoScale>,
+
+    /// Re-encode video chunks past an age smaller, e.g. 7d,scale=0.5,keep=2,crf=35 keeps every
+    /// other frame at half the resolution after a week. Repeat for more tiers, each tier's keep
+    /// a multiple of the younger tier's
+    #[arg(long)]
+    compaction_tier: Vec<CompactionTier>,
+
+    /// Minutes between compaction runs
+    #[arg(long, default_value_t = 60, value_parser = clap::value_parser!(u64).range(1..))]
+    compaction_interval: u64,
+
     /// Audio chunk duration in seconds
     #[arg(short, long, default_value_t = 30)]
     audio_chunk_duration: u64,
@@ -92,6 +148,242 @@ struct Cli {
     /// Save text files
     #[arg(long, default_value_t = false)]
     save_text_files: bool,
+
+    /// Address to listen on. Only expose the API beyond localhost together with API tokens
+    #[arg(long, default_value_t = IpAddr::V4(Ipv4Addr::LOCALHOST))]
+    host: IpAddr,
+
+    /// Origins allowed to call the API from a browser (can be specified multiple times, "*" for any).
+    /// Defaults to pages served from localhost
+    #[arg(long)]
+    cors_origin: Vec<String>,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Export search results as JSONL, CSV or Markdown, optionally zipped with media
+    Export {
+        /// Text to look for in OCR text and audio transcriptions
+        #[arg(long)]
+        q: Option<String>,
+        /// all, ocr or audio
+        #[arg(long, default_value = "all")]
+        content_type: ContentType,
+        /// Only export content captured at or after this time (RFC 3339)
+        #[arg(long)]
+        start_time: Option<DateTime<Utc>>,
+        /// Only export content captured at or before this time (RFC 3339)
+        #[arg(long)]
+        end_time: Option<DateTime<Utc>>,
+        /// Maximum number of results to export, everything by default
+        #[arg(long)]
+        limit: Option<u32>,
+        /// Number of results to skip
+        #[arg(long, default_value_t = 0)]
+        offset: u32,
+        /// jsonl, csv or markdown
+        #[arg(long, default_value = "jsonl")]
+        format: ExportFormat,
+        /// Bundle the export in a zip archive
+        #[arg(long)]
+        zip: bool,
+        /// Add a thumbnail of every frame to the zip
+        #[arg(long)]
+        thumbnails: bool,
+        /// Add the audio chunks to the zip
+        #[arg(long)]
+        audio: bool,
+        /// File to write to, stdout by default
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// Cut the screen recording of a time range into one mp4, sped up for a timelapse
+    Clip {
+        /// Start of the clip (RFC 3339)
+        #[arg(long)]
+        start_time: DateTime<Utc>,
+        /// End of the clip (RFC 3339)
+        #[arg(long)]
+        end_time: DateTime<Utc>,
+        /// Monitor to export, only 0 is recorded
+        #[arg(long)]
+        monitor: Option<u32>,
+        /// Playback speed, e.g. 60 to watch an hour in a minute
+        #[arg(long, default_value_t = 1.0)]
+        speed: f64,
+        /// Burn the capture time into the video
+        #[arg(long)]
+        timestamps: bool,
+        /// Mix in the audio recorded over the range, only at normal speed
+        #[arg(long)]
+        audio: bool,
+        /// File to write the mp4 to
+        #[arg(short, long, default_value = "clip.mp4")]
+        output: PathBuf,
+    },
+    /// Back up the database and media to a directory, while recording keeps going
+    Backup {
+        /// Directory to create the backup in
+        #[arg(short, long, default_value = ".")]
+        output: PathBuf,
+    },
+    /// Merge a backup made with `screenpipe backup` into this data directory
+    Import {
+        /// Backup directory, the one containing manifest.json
+        path: PathBuf,
+    },
+    /// Show what was recorded per day, disk usage, latencies and when the disk will be full
+    Stats {
+        /// Print JSON instead of a report
+        #[arg(long)]
+        json: bool,
+    },
+    /// Manage the API tokens
+    Token {
+        #[command(subcommand)]
+        action: TokenCommand,
+    },
+    /// Manage encryption at rest, stop recording first
+    #[cfg(feature = "encryption")]
+    Encryption {
+        #[command(subcommand)]
+        action: EncryptionCommand,
+    },
+}
+
+#[derive(Subcommand)]
+enum TokenCommand {
+    /// Create a token, it is only shown once
+    Create {
+        /// Name to recognize the token by
+        name: String,
+        /// Scopes granted to the token: read-search, read-media, control-recording, admin
+        #[arg(long, value_delimiter = ',', required = true)]
+        scope: Vec<Scope>,
+    },
+    /// List the tokens
+    List,
+    /// Revoke a token
+    Revoke {
+        /// Id of the token, as shown by `token list`
+        id: i64,
+    },
+}
+
+#[cfg(feature = "encryption")]
+#[derive(Subcommand)]
+enum EncryptionCommand {
+    /// Encrypt the database and the media recorded so far, and everything recorded from now on
+    Enable {
+        /// Where the key comes from: passphrase or keyring
+        #[arg(long, default_value = "passphrase")]
+        key_source: KeySource,
+    },
+    /// Re-encrypt everything with a new key
+    Rotate {
+        /// Switch to another key source: passphrase or keyring
+        #[arg(long)]
+        key_source: Option<KeySource>,
+    },
+}
+
+#[cfg(feature = "encryption")]
+async fn handle_encryption_command(
+    base_dir: &Path,
+    action: EncryptionCommand,
+) -> anyhow::Result<()> {
+    match action {
+        EncryptionCommand::Enable { key_source } => {
+            enable_encryption(base_dir, key_source).await?;
+            println!("Encryption enabled for {}", base_dir.to_string_lossy());
+        }
+        EncryptionCommand::Rotate { key_source } => {
+            rotate_key(base_dir, key_source).await?;
+            println!("Encryption key rotated for {}", base_dir.to_string_lossy());
+        }
+    }
+    Ok(())
+}
+
+async fn open_database(base_dir: &Path) -> anyhow::Result<DatabaseManager> {
+    let db_path = format!("{}/db.sqlite", base_dir.to_string_lossy());
+
+    #[cfg(feature = "encryption")]
+    if let Some(config) = EncryptionConfig::load(base_dir)? {
+        let key = config.unlock()?;
+        enable_media_encryption(key.clone());
+        return Ok(DatabaseManager::new_encrypted(&db_path, &key).await?);
+    }
+    #[cfg(not(feature = "encryption"))]
+    if base_dir.join("encryption.json").exists() {
+        anyhow::bail!("This data directory is encrypted, build screenpipe with the encryption feature");
+    }
+
+    Ok(DatabaseManager::new(&db_path).await?)
+}
+
+async fn handle_export_command(
+    db: Arc<DatabaseManager>,
+    options: ExportOptions,
+    output: Option<PathBuf>,
+) -> anyhow::Result<()> {
+    options.validate().map_err(|e| anyhow::anyhow!(e))?;
+
+    if options.zip {
+        let output = output.ok_or_else(|| anyhow::anyhow!("--output is required with --zip"))?;
+        export_zip(db, options, File::create(&output)?).await?;
+        println!("Exported to {}", output.to_string_lossy());
+        return Ok(());
+    }
+
+    let mut writer: Box<dyn Write> = match &output {
+        Some(output) => Box::new(std::io::BufWriter::new(File::create(output)?)),
+        None => Box::new(std::io::stdout().lock()),
+    };
+    let mut stream = Box::pin(export_stream(db, options));
+    while let Some(chunk) = stream.next().await {
+        writer.write_all(chunk?.as_bytes())?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+async fn handle_token_command(db: &DatabaseManager, action: TokenCommand) -> anyhow::Result<()> {
+    match action {
+        TokenCommand::Create { name, scope } => {
+            let (token, hash) = generate_token();
+            let id = db.insert_api_token(&name, &hash, &scope).await?;
+            println!("Created token {} ({}), store it now, it won't be shown again:", id, name);
+            println!("{}", token);
+        }
+        TokenCommand::List => {
+            for token in db.get_api_tokens().await? {
+                let scopes: Vec<String> = token.scopes.0.iter().map(Scope::to_string).collect();
+                let last_used = token
+                    .last_used_at
+                    .map_or_else(|| "never".to_string(), |t| t.to_rfc3339());
+                println!(
+                    "{}\t{}\t{}\tcreated {}\tlast used {}",
+                    token.id,
+                    token.name,
+                    scopes.join(","),
+                    token.created_at.to_rfc3339(),
+                    last_used
+                );
+            }
+        }
+        TokenCommand::Revoke { id } => {
+            if db.delete_api_token(id).await? {
+                println!("Revoked token {}", id);
+            } else {
+                anyhow::bail!("Token {} not found", id);
+            }
+        }
+    }
+    Ok(())
 }
 
 fn get_base_dir(custom_path: Option<String>) -> anyhow::Result<PathBuf> {
@@ -108,13 +400,106 @@ fn get_base_dir(custom_path: Option<String>) -> anyhow::Result<PathBuf> {
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
+    let cli = Cli::parse();
+
+    if let Some(command) = cli.command {
+        let local_data_dir = get_base_dir(cli.data_dir)?;
+        return match command {
+            Command::Export {
+                q,
+                content_type,
+                start_time,
+                end_time,
+                limit,
+                offset,
+                format,
+                zip,
+                thumbnails,
+                audio,
+                output,
+            } => {
+                let db = Arc::new(open_database(&local_data_dir).await?);
+                let options = ExportOptions {
+                    q,
+                    content_type,
+                    start_time,
+                    end_time,
+                    limit,
+                    offset,
+                    format,
+                    zip,
+                    thumbnails,
+                    audio,
+                };
+                handle_export_command(db, options, output).await
+            }
+            Command::Clip {
+                start_time,
+                end_time,
+                monitor,
+                speed,
+                timestamps,
+                audio,
+                output,
+            } => {
+                let db = open_database(&local_data_dir).await?;
+                let options = ClipOptions {
+                    start_time,
+                    end_time,
+                    monitor,
+                    speed,
+                    timestamps,
+                    audio,
+                };
+                export_clip(&db, &options, &output).await?;
+                println!("Exported to {}", output.to_string_lossy());
+                Ok(())
+            }
+            Command::Backup { output } => {
+                let db = open_database(&local_data_dir).await?;
+                let backup_dir = create_backup(&db, &output).await?;
+                println!("Backed up to {}", backup_dir.to_string_lossy());
+                Ok(())
+            }
+            Command::Import { path } => {
+                let db = open_database(&local_data_dir).await?;
+                let stats = import_backup(&db, &path, &local_data_dir).await?;
+                println!(
+                    "Imported {} video chunks, {} frames, {} audio chunks and {} transcriptions",
+                    stats.video_chunks,
+                    stats.frames,
+                    stats.audio_chunks,
+                    stats.audio_transcriptions
+                );
+                Ok(())
+            }
+            Command::Stats { json } => {
+                let db = open_database(&local_data_dir).await?;
+                let stats = collect_stats(&db).await?;
+                if json {
+                    println!("{}", serde_json::to_string_pretty(&stats)?);
+                } else {
+                    print!("{}", format_stats(&stats));
+                }
+                Ok(())
+            }
+            Command::Token { action } => {
+                let db = open_database(&local_data_dir).await?;
+                handle_token_command(&db, action).await
+            }
+            #[cfg(feature = "encryption")]
+            Command::Encryption { action } => {
+                handle_encryption_command(&local_data_dir, action).await
+            }
+        };
+    }
+
     if find_ffmpeg_path().is_none() {
         eprintln!("ffmpeg not found. Please install ffmpeg and ensure it is in your PATH.");
         std::process::exit(1);
     }
 
     // Initialize logging
-    let cli = Cli::parse();
 
     let mut builder = env_logger::Builder::new();
     builder
@@ -152,7 +537,6 @@ async fn main() -> anyhow::Result<()> {
     // Add warning for Linux and Windows users
     #[cfg(any(target_os = "linux", target_os = "windows"))]
     {
-        use log::warn;
         warn!("Screenpipe hasn't been extensively tested on this OS. We'd love your feedback!");
         println!(
             "{}",
@@ -254,14 +638,10 @@ async fn main() -> anyhow::Result<()> {
     );
     resource_monitor.start_monitoring(Duration::from_secs(10)); // Log every 10 seconds
 
-    let db = Arc::new(
-        DatabaseManager::new(&format!("{}/db.sqlite", local_data_dir.to_string_lossy()))
-            .await
-            .map_err(|e| {
-                eprintln!("Failed to initialize database: {:?}", e);
-                e
-            })?,
-    );
+    let db = Arc::new(open_database(&local_data_dir).await.map_err(|e| {
+        eprintln!("Failed to initialize database: {:?}", e);
+        e
+    })?);
     info!(
         "Database initialized, will store files in {}",
         local_data_dir.to_string_lossy()
@@ -269,12 +649,41 @@ async fn main() -> anyhow::Result<()> {
     let db_record = db.clone();
     let db_server = db.clone();
 
+    let webhooks = WebhookEngine::new(db.clone()).await?;
+
+    // In-process consumers of new frames and transcriptions, register custom pipes here
+    #[allow(unused_mut)]
+    let mut pipes: Vec<Arc<dyn Pipe>> = vec![webhooks.clone()];
+    #[cfg(feature = "wasm-pipes")]
+    pipes.extend(screenpipe_server::load_wasm_pipes(
+        &local_data_dir.join("pipes"),
+        db.clone(),
+    )?);
+    let pipes = PipeManager::new(db.clone(), pipes);
+
     // Channel for controlling the recorder ! TODO RENAME SHIT
     let (_control_tx, control_rx) = channel(64);
     let vision_control = Arc::new(AtomicBool::new(true));
 
     let vision_control_server_clone = vision_control.clone();
 
+    let video_encoder = VideoEncoderConfig {
+        codec: cli.video_codec,
+        preset: cli.video_preset.clone(),
+        crf: cli.video_crf,
+        pix_fmt: cli.video_pix_fmt.clone(),
+        scale: cli.video_scale,
+    };
+
+    if !cli.compaction_tier.is_empty() {
+        Compactor::new(
+            db.clone(),
+            cli.compaction_tier.clone(),
+            video_encoder.clone(),
+        )?
+        .start(Duration::from_secs(cli.compaction_interval.saturating_mul(60)));
+    }
+
     // Start continuous recording in a separate task
     let _recording_task = tokio::spawn({
         async move {
@@ -284,36 +693,57 @@ async fn main() -> anyhow::Result<()> {
                 db_record,
                 Arc::new(local_data_dir.join("data").to_string_lossy().into_owned()),
                 cli.fps,
+                Duration::from_secs(cli.video_chunk_duration),
+                video_encoder,
+                cli.dedup_threshold,
+                cli.ocr_workers,
                 audio_chunk_duration,
                 control_rx,
                 vision_control,
                 audio_devices_control_receiver,
                 cli.save_text_files,
+                pipes,
             )
             .await
         }
     });
 
+    let addr = SocketAddr::new(cli.host, cli.port);
+    if !addr.ip().is_loopback() && db.count_api_tokens().await? == 0 {
+        warn!(
+            "Listening on {} without any API token, every request will be rejected. Create one with `screenpipe token create`",
+            addr
+        );
+    }
+
     tokio::spawn(async move {
-        let api_plugin = |req: &axum::http::Request<axum::body::Body>| {
-            // Custom plugin logic here
-            // For example, using PostHog for tracking:
-            if req.uri().path() == "/search" {
-                // Track search requests
-                // posthog.capture("search_request", {...})
-            }
-        };
+        let mut plugins = PluginRegistry::new();
+        // Auth goes first so other plugins only see authorized requests
+        plugins.register(AuthPlugin::new(db_server.clone(), addr.ip().is_loopback()));
+        plugins.register(FnPlugin::new(
+            "analytics",
+            |req: &axum::http::Request<axum::body::Body>| {
+                // Custom plugin logic here
+                // For example, using PostHog for tracking:
+                if req.uri().path() == "/search" {
+                    // Track search requests
+                    // posthog.capture("search_request", {...})
+                }
+            },
+        ));
         let server = Server::new(
             db_server,
-            SocketAddr::from(([0, 0, 0, 0], cli.port)),
+            addr,
             vision_control_server_clone,
             audio_devices_control_sender_server,
+            webhooks,
+            cli.cors_origin,
         );
-        server.start(devices_status, api_plugin).await.unwrap();
+        server.start(devices_status, plugins).await.unwrap();
     });
 
     // Wait for the server to start
-    info!("Server started on http://localhost:{}", cli.port);
+    info!("Server started on http://{}", addr);
 
     // print screenpipe in gradient
     println!("\n\n{}", DISPLAY.truecolor(147, 112, 219).bold());
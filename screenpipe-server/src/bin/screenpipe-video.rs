@@ -1,8 +1,9 @@
 use chrono::Utc;
 use env_logger::Env;
+use futures::FutureExt;
 use image::GenericImageView;
 use log::info;
-use screenpipe_server::VideoCapture;
+use screenpipe_server::{VideoCapture, VideoEncoderConfig};
 use serde_json::{json, Value};
 use tokio::sync::mpsc::{channel, Receiver, Sender};
 use std::fs::{File, OpenOptions};
@@ -49,10 +50,21 @@ async fn main() {
     let new_chunk_callback = {
         move |file_path: &str| {
             info!("New chunk: {}", file_path);
+            futures::future::ready(None).boxed()
         }
     };
 
-    let video_capture = VideoCapture::new(output_path, fps, new_chunk_callback, save_text_files);
+    let video_capture = VideoCapture::new(
+        output_path,
+        fps,
+        Duration::from_secs(60),
+        VideoEncoderConfig::default(),
+        new_chunk_callback,
+        |_, _| futures::future::ready(()).boxed(),
+        save_text_files,
+        0,
+        1,
+    );
     let (_tx, rx): (Sender<()>, Receiver<()>) = channel(32);
     let rx = Arc::new(Mutex::new(rx));
     let rx_thread = rx.clone();
@@ -0,0 +1,423 @@
+// Clips and timelapses of a time range: the chunks recorded over it are cut and concatenated by
+// ffmpeg into one mp4, optionally sped up, with the capture time burnt in and the audio recorded
+// meanwhile mixed in.
+use std::fmt::Write;
+use std::path::Path;
+use std::process::Stdio;
+use std::sync::Arc;
+
+use anyhow::anyhow;
+use axum::{
+    body::Body,
+    extract::{Json as JsonExt, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json as JsonResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use log::{error, info, warn};
+use screenpipe_core::find_ffmpeg_path;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::process::Command;
+use tokio_util::io::ReaderStream;
+
+use crate::db::VideoSegment;
+use crate::hls::segment_duration;
+use crate::server::AppState;
+use crate::subtitles::MAX_CHUNK_SECONDS;
+use crate::video::extract_frame;
+use crate::{DatabaseManager, VideoCodec, VideoEncoderConfig};
+
+const MAX_SPEED: f64 = 1000.0;
+/// Audio chunks mixed into one clip at most, each is an ffmpeg input.
+const MAX_AUDIO_CHUNKS: usize = 500;
+
+fn default_speed() -> f64 {
+    1.0
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ClipOptions {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    /// Monitor to export, screenpipe records a single one so only 0 is accepted
+    #[serde(default)]
+    pub monitor: Option<u32>,
+    /// Playback speed, above 1 for a timelapse
+    #[serde(default = "default_speed")]
+    pub speed: f64,
+    /// Burn the capture time in the bottom left corner
+    #[serde(default)]
+    pub timestamps: bool,
+    /// Mix in the audio of every device recorded over the range
+    #[serde(default)]
+    pub audio: bool,
+}
+
+impl ClipOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.end_time <= self.start_time {
+            return Err("end_time must be after start_time".to_string());
+        }
+        if let Some(monitor) = self.monitor.filter(|monitor| *monitor != 0) {
+            return Err(format!(
+                "monitor {} isn't recorded, only monitor 0 is",
+                monitor
+            ));
+        }
+        if !(self.speed > 0.0 && self.speed <= MAX_SPEED) {
+            return Err(format!("speed must be above 0 and at most {}", MAX_SPEED));
+        }
+        if self.audio && self.speed != 1.0 {
+            return Err("audio can only be added at normal speed".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn file_name(&self) -> String {
+        format!("screenpipe-{}.mp4", self.start_time.format("%Y%m%d-%H%M%S"))
+    }
+}
+
+/// A chunk recorded over a clip and the capture time of each frame of its video as recorded.
+#[derive(Debug, Clone)]
+pub struct ClipChunk {
+    pub segment: VideoSegment,
+    pub frame_times: Vec<DateTime<Utc>>,
+}
+
+/// The part of a chunk that goes in a clip, in seconds of its video. Frames were captured at
+/// whatever pace the screen changed and OCR kept up, they play one after the other at the rate
+/// the chunk was encoded at.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ClipPiece {
+    pub file_path: String,
+    pub inpoint: f64,
+    /// `None` to play the chunk to its end
+    pub outpoint: Option<f64>,
+    /// Capture time at `inpoint`
+    pub start_time: DateTime<Utc>,
+    /// Where the piece starts in the clip, before speeding up
+    pub offset: f64,
+    pub duration: f64,
+}
+
+/// Cuts the chunks down to the frames on screen between `start_time` and `end_time`, from the one
+/// showing at `start_time` to the last one captured before `end_time`. Gaps between chunks are
+/// left out of the clip.
+pub fn plan_clip(
+    chunks: &[ClipChunk],
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Vec<ClipPiece> {
+    let mut pieces = Vec::new();
+    let mut offset = 0.0;
+    for chunk in chunks {
+        let frames = &chunk.frame_times;
+        let seconds_per_frame =
+            segment_duration(&chunk.segment) / chunk.segment.frames.max(1) as f64;
+        let first = frames
+            .partition_point(|time| *time <= start_time)
+            .saturating_sub(1);
+        let last = frames.partition_point(|time| *time < end_time);
+        if last <= first {
+            continue;
+        }
+        let from = first as f64 * seconds_per_frame;
+        let to = last as f64 * seconds_per_frame;
+        pieces.push(ClipPiece {
+            file_path: chunk.segment.file_path.clone(),
+            inpoint: from,
+            outpoint: (last < frames.len()).then_some(to),
+            start_time: frames[first],
+            offset,
+            duration: to - from,
+        });
+        offset += to - from;
+    }
+    pieces
+}
+
+/// Position in the clip of what was captured at `time`, placed linearly in its piece. Times in a
+/// gap map to the start of the next piece.
+pub fn clip_offset(pieces: &[ClipPiece], time: DateTime<Utc>) -> f64 {
+    for piece in pieces {
+        let into = seconds_between(piece.start_time, time);
+        if into < piece.duration {
+            return piece.offset + into.max(0.0);
+        }
+    }
+    pieces
+        .last()
+        .map_or(0.0, |piece| piece.offset + piece.duration)
+}
+
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+/// Concat demuxer script playing `paths[i]` from `pieces[i].inpoint` for `pieces[i].duration`.
+pub fn concat_script(pieces: &[ClipPiece], paths: &[String]) -> String {
+    let mut script = String::from("ffconcat version 1.0\n");
+    for (piece, path) in pieces.iter().zip(paths) {
+        let _ = writeln!(script, "file '{}'", path.replace('\'', "'\\''"));
+        let _ = writeln!(script, "inpoint {:.3}", piece.inpoint);
+        if let Some(outpoint) = piece.outpoint {
+            let _ = writeln!(script, "outpoint {:.3}", outpoint);
+        }
+        // Pins every piece at its planned offset, whatever its actual length
+        let _ = writeln!(script, "duration {:.3}", piece.duration);
+    }
+    script
+}
+
+/// Video filters from the concatenated chunks, input 0, to the `[v]` output: every frame fit in
+/// `width`x`height`, as chunks may differ in size, then the timestamps and the speed up.
+pub fn video_filter(
+    pieces: &[ClipPiece],
+    width: u32,
+    height: u32,
+    timestamps: bool,
+    speed: f64,
+) -> String {
+    let mut filter = format!(
+        "[0:v]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1",
+        w = width,
+        h = height
+    );
+    if timestamps {
+        for piece in pieces {
+            // `t` is the position in the clip, shifted to the capture time of the piece
+            let epoch = piece.start_time.timestamp_millis() as f64 / 1000.0 - piece.offset;
+            let _ = write!(
+                filter,
+                ",drawtext=enable='between(t,{:.3},{:.3})':text='%{{pts\\:gmtime\\:{:.3}}}':x=10:y=h-th-10:fontsize=24:fontcolor=white:box=1:boxcolor=black@0.6:boxborderw=6",
+                piece.offset,
+                piece.offset + piece.duration,
+                epoch
+            );
+        }
+    }
+    if speed != 1.0 {
+        let _ = write!(filter, ",setpts=PTS/{}", speed);
+    }
+    filter.push_str("[v]");
+    filter
+}
+
+/// Audio filters from inputs 1 to `starts.len()` to the `[a]` output, each chunk delayed to its
+/// place in the clip and all of them mixed. Audio isn't cut at gaps between chunks, it drifts
+/// ahead by the length of any gap it spans.
+fn audio_filter(
+    pieces: &[ClipPiece],
+    starts: &[DateTime<Utc>],
+    start_time: DateTime<Utc>,
+) -> String {
+    let mut filter = String::new();
+    for (index, chunk_start) in starts.iter().enumerate() {
+        let skip = seconds_between(*chunk_start, start_time).max(0.0);
+        let delay = clip_offset(pieces, (*chunk_start).max(start_time));
+        let _ = write!(
+            filter,
+            "[{}:a]atrim=start={:.3},asetpts=PTS-STARTPTS,adelay=delays={}:all=1[a{}];",
+            index + 1,
+            skip,
+            (delay * 1000.0).round() as u64,
+            index
+        );
+    }
+    for index in 0..starts.len() {
+        let _ = write!(filter, "[a{}]", index);
+    }
+    let _ = write!(
+        filter,
+        "amix=inputs={}:normalize=0:duration=longest[a]",
+        starts.len()
+    );
+    filter
+}
+
+/// Keeps decrypted copies of encrypted chunks around until ffmpeg is done with them.
+#[derive(Default)]
+struct ReadableMedia {
+    #[cfg(feature = "encryption")]
+    files: Vec<crate::encryption::MediaFile>,
+}
+
+impl ReadableMedia {
+    /// Absolute path ffmpeg can read `file_path` at, the concat script resolves relative paths
+    /// against its own directory.
+    async fn path(&mut self, file_path: &str) -> anyhow::Result<String> {
+        #[cfg(feature = "encryption")]
+        let file_path = {
+            let media = crate::encryption::open_media(file_path).await?;
+            let path = media.path().to_string();
+            self.files.push(media);
+            path
+        };
+        let path = tokio::fs::canonicalize(file_path).await?;
+        Ok(path.to_string_lossy().into_owned())
+    }
+}
+
+/// Writes the clip described by `options` to `output` as an mp4.
+pub async fn export_clip(
+    db: &DatabaseManager,
+    options: &ClipOptions,
+    output: &Path,
+) -> anyhow::Result<()> {
+    options.validate().map_err(|e| anyhow!(e))?;
+    let mut chunks = Vec::new();
+    for segment in db
+        .get_video_segments(options.start_time, options.end_time)
+        .await?
+    {
+        let frame_times = db.get_frame_times(segment.video_chunk_id).await?;
+        chunks.push(ClipChunk {
+            segment,
+            frame_times,
+        });
+    }
+    let pieces = plan_clip(&chunks, options.start_time, options.end_time);
+    let Some(first) = pieces.first() else {
+        return Err(anyhow!(
+            "No video recorded between {} and {}",
+            options.start_time,
+            options.end_time
+        ));
+    };
+    let length: f64 = pieces.iter().map(|piece| piece.duration).sum();
+
+    // The clip takes the size of its first frame, even for the encoder
+    let frame = image::load_from_memory(&extract_frame(&first.file_path, 0).await?)?;
+    let (width, height) = (frame.width() & !1, frame.height() & !1);
+
+    let mut media = ReadableMedia::default();
+    let mut paths = Vec::with_capacity(pieces.len());
+    for piece in &pieces {
+        paths.push(media.path(&piece.file_path).await?);
+    }
+    let script_dir = tempfile::tempdir()?;
+    let script_path = script_dir.path().join("clip.ffconcat");
+    tokio::fs::write(&script_path, concat_script(&pieces, &paths)).await?;
+
+    let mut args: Vec<String> = ["-y", "-f", "concat", "-safe", "0", "-i"]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+    args.push(script_path.to_string_lossy().into_owned());
+    let mut filter = video_filter(&pieces, width, height, options.timestamps, options.speed);
+
+    let mut with_audio = false;
+    if options.audio {
+        let mut chunks = db
+            .get_audio_chunks_between(
+                options.start_time - chrono::Duration::seconds(MAX_CHUNK_SECONDS),
+                options.end_time,
+            )
+            .await?;
+        if chunks.len() > MAX_AUDIO_CHUNKS {
+            warn!(
+                "Mixing the first {} of {} audio chunks in the clip",
+                MAX_AUDIO_CHUNKS,
+                chunks.len()
+            );
+            chunks.truncate(MAX_AUDIO_CHUNKS);
+        }
+        let mut starts = Vec::with_capacity(chunks.len());
+        for chunk in chunks {
+            let Some(start_time) = chunk.start_time else {
+                continue;
+            };
+            args.push("-i".to_string());
+            args.push(media.path(&chunk.file_path).await?);
+            starts.push(start_time);
+        }
+        if starts.is_empty() {
+            warn!("No audio recorded over the clip");
+        } else {
+            filter.push(';');
+            filter.push_str(&audio_filter(&pieces, &starts, options.start_time));
+            with_audio = true;
+        }
+    }
+
+    args.extend(["-filter_complex".to_string(), filter]);
+    args.extend(["-map".to_string(), "[v]".to_string()]);
+    if with_audio {
+        args.extend(["-map", "[a]", "-c:a", "aac"].map(String::from));
+    }
+    args.extend(["-t".to_string(), format!("{:.3}", length / options.speed)]);
+    let encoder = VideoEncoderConfig {
+        codec: VideoCodec::X264,
+        preset: Some("veryfast".to_string()),
+        crf: Some(23),
+        ..Default::default()
+    };
+    args.extend(encoder.output_args(&[], &output.to_string_lossy()));
+
+    let ffmpeg_path = find_ffmpeg_path().ok_or_else(|| anyhow!("ffmpeg not found"))?;
+    let result = Command::new(ffmpeg_path)
+        .args(&args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+    if !result.status.success() {
+        return Err(anyhow!(
+            "ffmpeg failed: {}",
+            String::from_utf8_lossy(&result.stderr)
+        ));
+    }
+    Ok(())
+}
+
+pub(crate) async fn export_video(
+    State(state): State<Arc<AppState>>,
+    JsonExt(options): JsonExt<ClipOptions>,
+) -> Response {
+    if let Err(message) = options.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(json!({ "error": message })),
+        )
+            .into_response();
+    }
+
+    info!("Exporting a clip {:?}", options);
+    let output = match tempfile::Builder::new().suffix(".mp4").tempfile() {
+        Ok(output) => output,
+        Err(e) => return clip_error(e.into()),
+    };
+    if let Err(e) = export_clip(&state.db, &options, output.path()).await {
+        return clip_error(e);
+    }
+    // The path goes away with `output`, the file stays readable through this handle
+    let file = match output.reopen() {
+        Ok(file) => file,
+        Err(e) => return clip_error(e.into()),
+    };
+
+    let body = Body::from_stream(ReaderStream::new(tokio::fs::File::from_std(file)));
+    (
+        [
+            (header::CONTENT_TYPE, "video/mp4".to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", options.file_name()),
+            ),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn clip_error(e: anyhow::Error) -> Response {
+    error!("Failed to export clip: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        JsonResponse(json!({ "error": format!("Failed to export clip: {}", e) })),
+    )
+        .into_response()
+}
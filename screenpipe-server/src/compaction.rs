@@ -0,0 +1,347 @@
+// Tiered storage: video chunks past an age are re-encoded smaller, at a lower resolution, frame
+// rate or quality. Frames keep their OCR text, so search is unaffected, and a frame dropped from
+// the video points at the kept frame before it.
+//
+// A chunk is encoded next to itself first, then the database is updated, and only then does the
+// new video replace the chunk. A replacement interrupted after the database update is finished
+// by the next run, one interrupted before it is thrown away.
+use std::path::Path;
+use std::process::Stdio;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use chrono::Utc;
+use log::{debug, error, info, warn};
+use screenpipe_core::find_ffmpeg_path;
+use serde::Serialize;
+use tokio::process::Command;
+
+use crate::db::VideoChunk;
+use crate::video::{finalize_chunk, read_frame_timestamps, timestamps_path};
+use crate::{DatabaseManager, VideoEncoderConfig, VideoScale};
+
+/// How chunks older than `min_age` are stored, e.g. `7d,scale=0.5,keep=2,crf=35`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompactionTier {
+    pub min_age: Duration,
+    /// Relative to the chunk as recorded when a factor
+    pub scale: Option<VideoScale>,
+    /// Keep one recorded frame out of this many
+    pub keep_every: u32,
+    /// The codec's default when `None`
+    pub crf: Option<u32>,
+}
+
+impl FromStr for CompactionTier {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut parts = s.split(',');
+        let min_age = parse_age(parts.next().unwrap_or_default())?;
+        let mut tier = CompactionTier {
+            min_age,
+            scale: None,
+            keep_every: 1,
+            crf: None,
+        };
+        for part in parts {
+            let (key, value) = part
+                .split_once('=')
+                .ok_or_else(|| format!("invalid tier setting {}, expected key=value", part))?;
+            match key {
+                "scale" => tier.scale = Some(value.parse()?),
+                "keep" => {
+                    tier.keep_every = value
+                        .parse()
+                        .ok()
+                        .filter(|keep| *keep > 0)
+                        .ok_or_else(|| format!("invalid keep {}, expected 1 or more", value))?
+                }
+                "crf" => {
+                    tier.crf = Some(
+                        value
+                            .parse()
+                            .map_err(|_| format!("invalid crf {}", value))?,
+                    )
+                }
+                _ => {
+                    return Err(format!(
+                        "unknown tier setting {}, expected scale, keep or crf",
+                        key
+                    ))
+                }
+            }
+        }
+        Ok(tier)
+    }
+}
+
+/// Parses ages like `30m`, `12h`, `7d` or `2w`.
+fn parse_age(s: &str) -> Result<Duration, String> {
+    let invalid = || format!("invalid age {}, expected e.g. 12h, 7d or 2w", s);
+    let unit = s.chars().last().ok_or_else(invalid)?;
+    let value: u64 = s[..s.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    let seconds = match unit {
+        'm' => 60,
+        'h' => 60 * 60,
+        'd' => 24 * 60 * 60,
+        'w' => 7 * 24 * 60 * 60,
+        _ => return Err(invalid()),
+    };
+    let age = Duration::from_secs(value.checked_mul(seconds).ok_or_else(invalid)?);
+    chrono::Duration::from_std(age).map_err(|_| invalid())?;
+    Ok(age)
+}
+
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct CompactionStats {
+    pub chunks: u64,
+    pub bytes_before: u64,
+    pub bytes_after: u64,
+}
+
+pub struct Compactor {
+    db: Arc<DatabaseManager>,
+    /// Youngest first, tier `n` of a chunk is `tiers[n - 1]`
+    tiers: Vec<CompactionTier>,
+    encoder: VideoEncoderConfig,
+}
+
+impl Compactor {
+    /// `encoder` is the recording's, tiers override its scale and CRF. Each tier's `keep_every`
+    /// must be a multiple of the younger tier's, frames it keeps are then still in the video.
+    pub fn new(
+        db: Arc<DatabaseManager>,
+        mut tiers: Vec<CompactionTier>,
+        encoder: VideoEncoderConfig,
+    ) -> anyhow::Result<Arc<Self>> {
+        tiers.sort_by_key(|tier| tier.min_age);
+        for pair in tiers.windows(2) {
+            if pair[1].keep_every % pair[0].keep_every != 0 {
+                return Err(anyhow!(
+                    "compaction tier keep={} is not a multiple of the younger tier's keep={}",
+                    pair[1].keep_every,
+                    pair[0].keep_every
+                ));
+            }
+        }
+        Ok(Arc::new(Self { db, tiers, encoder }))
+    }
+
+    /// Compacts every `interval`, starting right away.
+    pub fn start(self: &Arc<Self>, interval: Duration) {
+        let compactor = Arc::clone(self);
+        tokio::spawn(async move {
+            loop {
+                match compactor.run_once().await {
+                    Ok(stats) if stats.chunks > 0 => info!(
+                        "Compacted {} video chunks from {} to {} bytes",
+                        stats.chunks, stats.bytes_before, stats.bytes_after
+                    ),
+                    Ok(_) => debug!("No video chunk to compact"),
+                    Err(e) => error!("Video compaction failed: {}", e),
+                }
+                tokio::time::sleep(interval).await;
+            }
+        });
+    }
+
+    pub async fn run_once(&self) -> anyhow::Result<CompactionStats> {
+        // Before anything else, a chunk's next tier is encoded from its current video
+        self.finish_interrupted().await?;
+        let mut stats = CompactionStats::default();
+        // Oldest tier first, a chunk old enough for several is encoded once
+        for (index, tier) in self.tiers.iter().enumerate().rev() {
+            let level = index as i64 + 1;
+            let older_than = Utc::now() - chrono::Duration::from_std(tier.min_age)?;
+            for chunk in self
+                .db
+                .get_video_chunks_to_compact(level, older_than)
+                .await?
+            {
+                match self.compact_chunk(&chunk, level, tier).await {
+                    Ok((before, after)) => {
+                        stats.chunks += 1;
+                        stats.bytes_before += before;
+                        stats.bytes_after += after;
+                    }
+                    Err(e) => error!("Failed to compact {}: {}", chunk.file_path, e),
+                }
+            }
+        }
+        Ok(stats)
+    }
+
+    /// Replaces the chunks whose compaction was recorded in the database with their compacted
+    /// files, and removes the files of compactions that weren't recorded.
+    async fn finish_interrupted(&self) -> anyhow::Result<()> {
+        for (id, file_path) in self.db.get_video_chunks().await? {
+            for level in 1..=self.tiers.len() as i64 {
+                let (video, timestamps) = compacted_paths(&file_path, level);
+                if !tokio::fs::try_exists(&video).await? {
+                    continue;
+                }
+                let recorded = self
+                    .db
+                    .get_video_chunk(id)
+                    .await?
+                    .is_some_and(|chunk| chunk.tier == level);
+                if recorded {
+                    warn!("Finishing interrupted compaction of {}", file_path);
+                    swap_in(&file_path, &video, &timestamps).await?;
+                } else {
+                    remove_compacted(&video, &timestamps).await;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Re-encodes a chunk in place, returns its size before and after.
+    async fn compact_chunk(
+        &self,
+        chunk: &VideoChunk,
+        level: i64,
+        tier: &CompactionTier,
+    ) -> anyhow::Result<(u64, u64)> {
+        // `keep_every` counts recorded frames, the chunk may already have dropped some. Tiers
+        // are multiples of each other, so this divides evenly
+        let step = (tier.keep_every as i64 / chunk.frame_step.max(1)).max(1);
+        let (output, timestamps_output) = compacted_paths(&chunk.file_path, level);
+        debug!(
+            "Compacting {} to tier {}, keeping 1 frame out of {}",
+            chunk.file_path, level, step
+        );
+
+        #[cfg(feature = "encryption")]
+        let media = crate::encryption::open_media(&chunk.file_path).await?;
+        #[cfg(feature = "encryption")]
+        let input = media.path();
+        #[cfg(not(feature = "encryption"))]
+        let input = chunk.file_path.as_str();
+
+        let encoder = VideoEncoderConfig {
+            scale: self.relative_scale(chunk, tier),
+            crf: tier.crf,
+            ..self.encoder.clone()
+        };
+        let filters = if step > 1 {
+            vec![format!("select=not(mod(n\\,{}))", step)]
+        } else {
+            Vec::new()
+        };
+        let ffmpeg_path = find_ffmpeg_path().ok_or_else(|| anyhow!("ffmpeg not found"))?;
+        let result = Command::new(ffmpeg_path)
+            // Kept frames keep their timestamps, playback time still matches the recording
+            .args(["-y", "-i", input, "-vsync", "vfr"])
+            .args(encoder.output_args(&filters, &output))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::piped())
+            .output()
+            .await?;
+        if !result.status.success() {
+            let _ = tokio::fs::remove_file(&output).await;
+            return Err(anyhow!(
+                "ffmpeg failed: {}",
+                String::from_utf8_lossy(&result.stderr)
+            ));
+        }
+
+        let recorded = async {
+            if step > 1 {
+                compact_timestamps(&chunk.file_path, &timestamps_output, step).await?;
+            }
+            let before = tokio::fs::metadata(&chunk.file_path).await?.len();
+            let after = tokio::fs::metadata(&output).await?.len();
+            self.db.set_video_chunk_tier(chunk.id, level, step).await?;
+            Ok::<_, anyhow::Error>((before, after))
+        }
+        .await;
+        let sizes = match recorded {
+            Ok(sizes) => sizes,
+            Err(e) => {
+                remove_compacted(&output, &timestamps_output).await;
+                return Err(e);
+            }
+        };
+        swap_in(&chunk.file_path, &output, &timestamps_output).await?;
+        Ok(sizes)
+    }
+
+    /// Scale from the chunk's current video to `tier`. Like `keep_every`, factors count from the
+    /// chunk as recorded.
+    fn relative_scale(&self, chunk: &VideoChunk, tier: &CompactionTier) -> Option<VideoScale> {
+        let current = usize::try_from(chunk.tier - 1)
+            .ok()
+            .and_then(|index| self.tiers.get(index))
+            .and_then(|current| current.scale);
+        match (current, tier.scale) {
+            (Some(VideoScale::Factor(current)), Some(VideoScale::Factor(target))) => {
+                Some(VideoScale::Factor((target / current).min(1.0)))
+            }
+            (_, scale) => scale,
+        }
+    }
+}
+
+/// Where a chunk compacted to `level` is written before replacing it, video and timestamps.
+fn compacted_paths(file_path: &str, level: i64) -> (String, String) {
+    // Same container as the chunk, its path stays the same
+    let extension = Path::new(file_path)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .unwrap_or("mp4");
+    (
+        format!("{}.tier{}.{}", file_path, level, extension),
+        format!("{}.tier{}", timestamps_path(file_path), level),
+    )
+}
+
+/// Writes the timestamps of the frames left in the video to `output`, chunks recorded before the
+/// sidecars existed have none.
+async fn compact_timestamps(file_path: &str, output: &str, step: i64) -> anyhow::Result<()> {
+    if !tokio::fs::try_exists(timestamps_path(file_path)).await? {
+        return Ok(());
+    }
+    let content: String = read_frame_timestamps(file_path)
+        .await?
+        .iter()
+        .step_by(step as usize)
+        .map(|timestamp| {
+            format!(
+                "{}\n",
+                timestamp.to_rfc3339_opts(chrono::SecondsFormat::Millis, true)
+            )
+        })
+        .collect();
+    tokio::fs::write(output, content).await?;
+    Ok(())
+}
+
+/// Moves the compacted files over the chunk's. The video goes last, while it is there the swap
+/// is unfinished.
+async fn swap_in(file_path: &str, video: &str, timestamps: &str) -> anyhow::Result<()> {
+    if tokio::fs::try_exists(timestamps).await? {
+        let path = timestamps_path(file_path);
+        tokio::fs::rename(timestamps, &path).await?;
+        finalize_chunk(path);
+    }
+    tokio::fs::rename(video, file_path).await?;
+    finalize_chunk(file_path.to_string());
+    Ok(())
+}
+
+async fn remove_compacted(video: &str, timestamps: &str) {
+    for path in [video, timestamps] {
+        if let Err(e) = tokio::fs::remove_file(path).await {
+            if e.kind() != std::io::ErrorKind::NotFound {
+                warn!("Failed to remove {}: {}", path, e);
+            }
+        }
+    }
+}
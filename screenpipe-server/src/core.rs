@@ -1,11 +1,16 @@
-use crate::{DatabaseManager, VideoCapture};
+use crate::metrics::TRANSCRIPTION_LATENCY;
+use crate::pipes::{PipeEvent, PipeFrame, PipeManager, PipeTranscription};
+use crate::words::ocr_words;
+use crate::{ChunkStatus, DatabaseManager, VideoCapture, VideoEncoderConfig};
 use anyhow::Result;
 use chrono::Utc;
+use futures::FutureExt;
 use log::{debug, error, info, warn};
 use screenpipe_audio::{
     create_whisper_channel, record_and_transcribe, AudioDevice, AudioInput, DeviceControl,
     TranscriptionResult,
 };
+use screenpipe_vision::metrics::FRAMES_DROPPED;
 use std::collections::HashMap;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
@@ -25,17 +30,28 @@ pub struct DataOutputWrapper {
 
 impl DataOutputWrapper {
     pub fn to_json(&self) -> String {
-        let data_json: Vec<String> = self.data_output.data.iter().map(|d| {
-            format!(
-                r#"{{"level": {}, "page_num": {}, "block_num": {}, "par_num": {}, "line_num": {}, "word_num": {}, "left": {}, "top": {}, "width": {}, "height": {}, "conf": {}, "text": "{}"}}"#,
-                d.level, d.page_num, d.block_num, d.par_num, d.line_num, d.word_num, d.left, d.top, d.width, d.height, d.conf, d.text
-            )
-        }).collect();
-        format!(
-            r#"{{"output": "{}", "data": [{}]}}"#,
-            self.data_output.output,
-            data_json.join(", ")
-        )
+        let data: Vec<serde_json::Value> = self
+            .data_output
+            .data
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "level": d.level,
+                    "page_num": d.page_num,
+                    "block_num": d.block_num,
+                    "par_num": d.par_num,
+                    "line_num": d.line_num,
+                    "word_num": d.word_num,
+                    "left": d.left,
+                    "top": d.top,
+                    "width": d.width,
+                    "height": d.height,
+                    "conf": d.conf,
+                    "text": d.text,
+                })
+            })
+            .collect();
+        serde_json::json!({ "output": self.data_output.output, "data": data }).to_string()
     }
 }
 
@@ -44,11 +60,16 @@ pub async fn start_continuous_recording(
     db: Arc<DatabaseManager>,
     output_path: Arc<String>,
     fps: f64,
+    video_chunk_duration: Duration,
+    video_encoder: VideoEncoderConfig,
+    dedup_threshold: u32,
+    ocr_workers: usize,
     audio_chunk_duration: Duration,
     mut full_control: Receiver<RecorderControl>,
     vision_control: Arc<AtomicBool>,
     audio_devices_control_receiver: Receiver<(AudioDevice, DeviceControl)>,
     save_text_files: bool,
+    pipes: Arc<PipeManager>,
 ) -> Result<()> {
     info!("Recording now");
 
@@ -62,8 +83,11 @@ pub async fn start_continuous_recording(
     let output_path_video = Arc::clone(&output_path);
     let output_path_audio = Arc::clone(&output_path);
 
+    let pipes_video = Arc::clone(&pipes);
+    let pipes_audio = Arc::clone(&pipes);
+
     let video_handle = tokio::spawn(async move {
-        record_video(db_manager_video, output_path_video, fps, is_running_video, save_text_files).await
+        record_video(db_manager_video, output_path_video, fps, video_chunk_duration, video_encoder, dedup_threshold, ocr_workers, is_running_video, save_text_files, pipes_video).await
     });
 
     let audio_handle = tokio::spawn(async move {
@@ -74,6 +98,7 @@ pub async fn start_continuous_recording(
             whisper_sender,
             whisper_receiver,
             audio_devices_control_receiver,
+            pipes_audio,
         )
         .await
     });
@@ -119,48 +144,103 @@ async fn record_video(
     db: Arc<DatabaseManager>,
     output_path: Arc<String>,
     fps: f64,
+    chunk_duration: Duration,
+    encoder: VideoEncoderConfig,
+    dedup_threshold: u32,
+    ocr_workers: usize,
     is_running: Arc<AtomicBool>,
     save_text_files: bool,
+    pipes: Arc<PipeManager>,
 ) -> Result<()> {
+    match db.mark_interrupted_video_chunks().await {
+        Ok(0) => {}
+        Ok(count) => warn!("Marked {} video chunks of the previous run as partial", count),
+        Err(e) => error!("Failed to mark interrupted video chunks: {}", e),
+    }
+
     let db_chunk_callback = Arc::clone(&db);
-    let rt = tokio::runtime::Handle::current();
     let new_chunk_callback = move |file_path: &str| {
         let db_chunk_callback = Arc::clone(&db_chunk_callback);
         let file_path = file_path.to_string();
-        rt.spawn(async move {
-            if let Err(e) = db_chunk_callback.insert_video_chunk(&file_path).await {
-                error!("Failed to insert new video chunk: {}", e);
+        async move {
+            match db_chunk_callback.insert_video_chunk(&file_path, fps).await {
+                Ok(id) => Some(id),
+                Err(e) => {
+                    error!("Failed to insert new video chunk: {}", e);
+                    None
+                }
             }
-        });
+        }
+        .boxed()
+    };
+    let db_finished_callback = Arc::clone(&db);
+    let chunk_finished_callback = move |video_chunk_id: i64, status: ChunkStatus| {
+        let db_finished_callback = Arc::clone(&db_finished_callback);
+        async move {
+            if let Err(e) = db_finished_callback
+                .set_video_chunk_status(video_chunk_id, status)
+                .await
+            {
+                error!("Failed to update status of video chunk {}: {}", video_chunk_id, e);
+            }
+        }
+        .boxed()
     };
     // debug!("record_video: video_capture");
-    let video_capture = VideoCapture::new(&output_path, fps, new_chunk_callback, save_text_files);
+    let video_capture = VideoCapture::new(
+        &output_path,
+        fps,
+        chunk_duration,
+        encoder,
+        new_chunk_callback,
+        chunk_finished_callback,
+        save_text_files,
+        dedup_threshold,
+        ocr_workers,
+    );
     
     while is_running.load(Ordering::SeqCst) {
-        // let queue_lenglth = video_capture.ocr_frame_queue.lock().await.len();
-        // debug!("record_video: Checking for latest frame. Number of frames in OCR queue: {}", queue_length);
-        if let Some(frame) = video_capture.ocr_frame_queue.lock().await.pop_front() {
-            match db.insert_frame().await {
+        // Drained as fast as the database allows, the queue drops frames if it falls behind
+        if let Some(encoded) = video_capture.ocr_frame_queue.pop_timeout(Duration::from_millis(100)).await {
+            let Some(video_chunk_id) = encoded.video_chunk_id else {
+                FRAMES_DROPPED.with_label_values(&["db_error"]).inc();
+                continue;
+            };
+            let mut frame = encoded.frame;
+            match db.insert_frame_in_chunk(video_chunk_id, encoded.offset_index, encoded.captured_at, Some(frame.ocr_duration), Some(frame.image_hash)).await {
                 Ok(frame_id) => {
                     let text_json = serde_json::to_string(&frame.text_json).unwrap_or_default();
                     let new_text_json_vs_previous_frame = serde_json::to_string(&frame.new_text_json).unwrap_or_default();
-                    let raw_data_output_from_ocr = DataOutputWrapper { data_output: frame.data_output }.to_json();
+                    let words = ocr_words(&frame.data_output);
+                    let wrapper = DataOutputWrapper { data_output: frame.data_output };
+                    let raw_data_output_from_ocr = wrapper.to_json();
+                    // Handed back for the pipes
+                    frame.data_output = wrapper.data_output;
 
                     // debug!("insert_ocr_text called for frame {}", frame_id);
-                    if let Err(e) = db.insert_ocr_text(frame_id, &frame.text, &text_json, &new_text_json_vs_previous_frame, &raw_data_output_from_ocr).await {
+                    if let Err(e) = db.insert_ocr_text(frame_id, &frame.text, &text_json, &new_text_json_vs_previous_frame, &raw_data_output_from_ocr, &words).await {
                         error!("Failed to insert OCR text: {}, skipping frame {}", e, frame_id);
+                        FRAMES_DROPPED.with_label_values(&["db_error"]).inc();
                         continue; // Skip to the next iteration
                     }
+
+                    if !pipes.is_empty() {
+                        pipes.dispatch(PipeEvent::Frame(Arc::new(PipeFrame {
+                            frame_id,
+                            capture: frame,
+                            timestamp: encoded.captured_at,
+                        })));
+                    }
                 }
                 Err(e) => {
                     warn!("Failed to insert frame: {}", e);
+                    FRAMES_DROPPED.with_label_values(&["db_error"]).inc();
                     // Add a small delay before retrying
                     tokio::time::sleep(Duration::from_millis(100)).await;
                     continue; // Skip to the next iteration
                 }
             }
         }
-        tokio::time::sleep(Duration::from_secs_f64(1.0 / fps)).await;
     }
 
     video_capture.stop().await;
@@ -174,6 +254,7 @@ async fn record_audio(
     whisper_sender: UnboundedSender<AudioInput>,
     mut whisper_receiver: UnboundedReceiver<TranscriptionResult>,
     mut audio_devices_control_receiver: Receiver<(AudioDevice, DeviceControl)>,
+    pipes: Arc<PipeManager>,
 ) -> Result<()> {
     let mut handles: HashMap<String, JoinHandle<()>> = HashMap::new();
 
@@ -284,7 +365,7 @@ async fn record_audio(
         // Process whisper results
         while let Ok(transcription) = whisper_receiver.try_recv() {
             info!("Received transcription");
-            process_audio_result(&db, transcription).await;
+            process_audio_result(&db, &pipes, transcription, chunk_duration).await;
         }
 
         // Small delay to prevent busy-waiting
@@ -292,7 +373,16 @@ async fn record_audio(
     }
 }
 
-async fn process_audio_result(db: &DatabaseManager, result: TranscriptionResult) {
+async fn process_audio_result(
+    db: &DatabaseManager,
+    pipes: &PipeManager,
+    result: TranscriptionResult,
+    chunk_duration: Duration,
+) {
+    // Whisper is done with the chunk
+    #[cfg(feature = "encryption")]
+    crate::encryption::encrypt_finalized_chunk(&result.input.path).await;
+
     if result.error.is_some() || result.transcription.is_none() {
         error!(
             "Error in audio recording: {}. Not inserting audio result",
@@ -302,12 +392,29 @@ async fn process_audio_result(db: &DatabaseManager, result: TranscriptionResult)
     }
     info!("Inserting audio chunk: {:?}", result.transcription);
     let transcription = result.transcription.unwrap();
-    match db.insert_audio_chunk(&result.input.path).await {
+    // Chunks stopped early make this an underestimate, close enough for stats
+    let recording_end = result.input.start_time
+        + chrono::Duration::from_std(chunk_duration).unwrap_or_else(|_| chrono::Duration::zero());
+    let transcription_latency = (Utc::now() - recording_end).to_std().unwrap_or_default();
+    TRANSCRIPTION_LATENCY.observe(transcription_latency.as_secs_f64());
+    match db
+        .insert_device_audio_chunk(
+            &result.input.path,
+            &result.input.device,
+            result.input.start_time,
+            transcription_latency,
+        )
+        .await
+    {
         Ok(audio_chunk_id) => {
-            if let Err(e) = db
-                .insert_audio_transcription(audio_chunk_id, &transcription, 0)
-                .await
-            {
+            let inserted = if result.segments.is_empty() {
+                db.insert_audio_transcription(audio_chunk_id, &transcription, 0)
+                    .await
+            } else {
+                db.insert_audio_segments(audio_chunk_id, &result.segments)
+                    .await
+            };
+            if let Err(e) = inserted {
                 error!(
                     "Failed to insert audio transcription for device {}: {}",
                     result.input.device, e
@@ -317,6 +424,13 @@ async fn process_audio_result(db: &DatabaseManager, result: TranscriptionResult)
                     "Inserted audio transcription for chunk {} from device {}",
                     audio_chunk_id, result.input.device
                 );
+                pipes.dispatch(PipeEvent::Transcription(Arc::new(PipeTranscription {
+                    audio_chunk_id,
+                    transcription,
+                    device: result.input.device.clone(),
+                    file_path: result.input.path.clone(),
+                    timestamp: result.input.start_time,
+                })));
             }
         }
         Err(e) => error!(
@@ -1,12 +1,23 @@
 use chrono::{DateTime, Utc};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 use sqlx::migrate::MigrateDatabase;
 use sqlx::{
-    sqlite::{SqlitePool, SqlitePoolOptions},
-    FromRow,
+    sqlite::{SqliteConnectOptions, SqlitePool, SqlitePoolOptions},
+    types::Json,
+    FromRow, QueryBuilder, Row, Sqlite,
 };
+use futures::TryStreamExt;
+use libsqlite3_sys as ffi;
+use screenpipe_audio::TranscriptionSegment;
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::{CStr, CString};
+use std::path::{Path, PathBuf};
+use std::ptr;
+use std::str::FromStr;
 use std::time::Duration;
 use log::{debug, error, info, warn};
+use crate::metrics::{OCR_INSERT_ERRORS, OCR_INSERT_RETRIES};
 use tokio::time::{timeout, Duration as TokioDuration};
 
 #[derive(Debug, Serialize)]
@@ -27,8 +38,27 @@ pub struct OCRResult {
     pub offset_index: i64,
 }
 
-#[derive(Debug, Deserialize, PartialEq, Default, Clone, Copy)]
+/// A word read by OCR, its box in pixels of the frame.
+#[derive(Debug, Clone, PartialEq, Serialize, FromRow)]
+pub struct OcrWord {
+    pub text: String,
+    pub left: i64,
+    pub top: i64,
+    pub width: i64,
+    pub height: i64,
+    pub conf: f64,
+    pub block_num: i64,
+    pub par_num: i64,
+    pub line_num: i64,
+    pub word_num: i64,
+}
+
+/// Words per INSERT, 11 parameters each, well under SQLite's limit of 32766.
+const OCR_WORDS_PER_INSERT: usize = 1000;
+
+#[derive(Debug, Serialize, Deserialize, JsonSchema, sqlx::Type, PartialEq, Default, Clone, Copy)]
 #[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
 pub enum ContentType {
     #[default]
     All,
@@ -36,6 +66,59 @@ pub enum ContentType {
     Audio,
 }
 
+impl FromStr for ContentType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(ContentType::All),
+            "ocr" => Ok(ContentType::OCR),
+            "audio" => Ok(ContentType::Audio),
+            _ => Err(format!("unknown content type {}, expected all, ocr or audio", s)),
+        }
+    }
+}
+
+/// How far ffmpeg got with a video chunk.
+#[derive(Debug, Serialize, Deserialize, JsonSchema, sqlx::Type, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "lowercase")]
+#[sqlx(rename_all = "lowercase")]
+pub enum ChunkStatus {
+    /// Being written
+    Recording,
+    /// ffmpeg exited cleanly
+    Complete,
+    /// ffmpeg crashed or the recording was interrupted, the file holds the frames written
+    /// before that
+    Partial,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct VideoChunk {
+    pub id: i64,
+    pub file_path: String,
+    pub status: ChunkStatus,
+    /// Compaction tier, 0 until the chunk is re-encoded
+    pub tier: i64,
+    /// Recorded frames per frame of the video, above 1 once compaction dropped frames
+    pub frame_step: i64,
+    /// Frame rate the chunk was encoded at, `None` for chunks recorded before it was stored
+    pub fps: Option<f64>,
+}
+
+/// A video chunk placed on the wall-clock timeline by its frames.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct VideoSegment {
+    pub video_chunk_id: i64,
+    pub file_path: String,
+    /// Capture time of the first and last frames in the database
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub frames: i64,
+    /// Frame rate the chunk was encoded at, if known
+    pub fps: Option<f64>,
+}
+
 #[derive(Debug, Serialize, FromRow)]
 pub struct AudioResult {
     pub audio_chunk_id: i64,
@@ -45,8 +128,196 @@ pub struct AudioResult {
     pub offset_index: i64,
 }
 
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct DailyStats {
+    /// UTC date, YYYY-MM-DD
+    pub day: String,
+    pub frames: i64,
+    pub video_chunks: i64,
+    pub audio_chunks: i64,
+    pub transcriptions: i64,
+}
+
+/// Activity in one bucket of the timeline.
+#[derive(Debug, Clone, Default, Serialize, JsonSchema)]
+pub struct TimelineBucket {
+    pub start_time: DateTime<Utc>,
+    pub end_time: DateTime<Utc>,
+    pub frame_count: i64,
+    /// Words transcribed, counted as space separated tokens
+    pub word_count: i64,
+    /// The frame with the most text, for a thumbnail
+    pub frame_id: Option<i64>,
+}
+
+impl TimelineBucket {
+    fn empty(start: i64, bucket_seconds: i64) -> Self {
+        TimelineBucket {
+            start_time: DateTime::from_timestamp(start, 0).unwrap_or_default(),
+            end_time: DateTime::from_timestamp(start + bucket_seconds, 0).unwrap_or_default(),
+            ..Default::default()
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AudioChunk {
+    pub id: i64,
+    pub file_path: String,
+    pub device: Option<String>,
+    pub start_time: Option<DateTime<Utc>>,
+}
+
+/// A transcription with its position in the chunk, in seconds. Transcriptions made before
+/// timing was recorded have no `start_time` / `end_time`.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct AudioSegment {
+    pub audio_chunk_id: i64,
+    pub chunk_start_time: Option<DateTime<Utc>>,
+    pub offset_index: i64,
+    pub transcription: String,
+    pub start_time: Option<f64>,
+    pub end_time: Option<f64>,
+}
+
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct WebhookRule {
+    pub id: i64,
+    pub name: String,
+    pub url: String,
+    /// Only returned when the rule is created, it signs every delivery
+    #[serde(skip_serializing)]
+    pub secret: String,
+    pub keywords: Json<Vec<String>>,
+    pub pattern: Option<String>,
+    pub content_type: ContentType,
+    pub enabled: bool,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct NewWebhookRule {
+    pub name: String,
+    pub url: String,
+    #[serde(default)]
+    pub secret: Option<String>,
+    #[serde(default)]
+    pub keywords: Vec<String>,
+    #[serde(default)]
+    pub pattern: Option<String>,
+    #[serde(default)]
+    pub content_type: ContentType,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct WebhookDeadLetter {
+    pub id: i64,
+    pub rule_id: i64,
+    pub url: String,
+    pub payload: String,
+    pub error: String,
+    pub attempts: i64,
+    pub created_at: DateTime<Utc>,
+}
+
+/// What an API token is allowed to do, `Admin` implies every other scope.
+#[derive(Debug, Serialize, Deserialize, PartialEq, Eq, Clone, Copy)]
+#[serde(rename_all = "kebab-case")]
+pub enum Scope {
+    ReadSearch,
+    ReadMedia,
+    ControlRecording,
+    Admin,
+}
+
+impl Scope {
+    pub const ALL: [Scope; 4] = [
+        Scope::ReadSearch,
+        Scope::ReadMedia,
+        Scope::ControlRecording,
+        Scope::Admin,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Scope::ReadSearch => "read-search",
+            Scope::ReadMedia => "read-media",
+            Scope::ControlRecording => "control-recording",
+            Scope::Admin => "admin",
+        }
+    }
+}
+
+impl std::fmt::Display for Scope {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl std::str::FromStr for Scope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Scope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str() == s)
+            .ok_or_else(|| {
+                format!(
+                    "unknown scope {}, expected one of: read-search, read-media, control-recording, admin",
+                    s
+                )
+            })
+    }
+}
+
+/// How often the last use of an API token is written down.
+const TOKEN_LAST_USED_RESOLUTION_SECS: i64 = 60;
+
+#[derive(Debug, Serialize, FromRow)]
+pub struct ApiToken {
+    pub id: i64,
+    pub name: String,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub scopes: Json<Vec<Scope>>,
+    pub created_at: DateTime<Utc>,
+    pub last_used_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum SortOrder {
+    Ascending,
+    Descending,
+}
+
+impl SortOrder {
+    fn as_sql(&self) -> &'static str {
+        match self {
+            SortOrder::Ascending => "ASC",
+            SortOrder::Descending => "DESC",
+        }
+    }
+}
+
 pub struct DatabaseManager {
     pool: SqlitePool,
+    // SQLCipher key, needed again to open backups and snapshots
+    key_pragma: Option<String>,
+}
+
+/// Rows added by [`DatabaseManager::import`].
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ImportStats {
+    pub video_chunks: u64,
+    pub frames: u64,
+    pub audio_chunks: u64,
+    pub audio_transcriptions: u64,
 }
 
 impl DatabaseManager {
@@ -60,14 +331,37 @@ impl DatabaseManager {
             sqlx::Sqlite::create_database(&connection_string).await?;
         }
 
+        Self::connect(SqliteConnectOptions::from_str(&connection_string)?, None).await
+    }
+
+    /// Opens a database encrypted with SQLCipher, creating it if needed.
+    #[cfg(feature = "encryption")]
+    pub async fn new_encrypted(
+        database_path: &str,
+        key: &crate::encryption::EncryptionKey,
+    ) -> Result<Self, sqlx::Error> {
+        debug!("Initializing encrypted DatabaseManager with database path: {}", database_path);
+        let options = SqliteConnectOptions::new()
+            .filename(database_path)
+            .create_if_missing(true)
+            // SQLCipher needs the key before anything else touches the database
+            .pragma("key", key.sqlcipher_pragma());
+
+        Self::connect(options, Some(key.sqlcipher_pragma())).await
+    }
+
+    async fn connect(
+        options: SqliteConnectOptions,
+        key_pragma: Option<String>,
+    ) -> Result<Self, sqlx::Error> {
         let pool = SqlitePoolOptions::new()
             .max_connections(10)
             .min_connections(3)  // Minimum number of idle connections
             .acquire_timeout(Duration::from_secs(10))
-            .connect(&connection_string)
+            .connect_with(options)
             .await?;
 
-        let db_manager = DatabaseManager { pool };
+        let db_manager = DatabaseManager { pool, key_pragma };
 
         // Run migrations after establishing the connection
         if let Err(e) = Self::run_migrations(&db_manager.pool).await {
@@ -95,6 +389,53 @@ impl DatabaseManager {
         Ok(id)
     }
 
+    /// Inserts a chunk along with the device that recorded it and when, which subtitles need,
+    /// and how long its transcription took after the recording ended.
+    pub async fn insert_device_audio_chunk(
+        &self,
+        file_path: &str,
+        device: &str,
+        start_time: DateTime<Utc>,
+        transcription_latency: Duration,
+    ) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            "INSERT INTO audio_chunks (file_path, device, start_time, transcription_latency_ms) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(file_path)
+        .bind(device)
+        .bind(start_time)
+        .bind(transcription_latency.as_millis() as i64)
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Inserts one transcription per segment, `offset_index` is the position of the segment.
+    pub async fn insert_audio_segments(
+        &self,
+        audio_chunk_id: i64,
+        segments: &[TranscriptionSegment],
+    ) -> Result<(), sqlx::Error> {
+        let mut tx = self.pool.begin().await?;
+        let timestamp = Utc::now();
+        for (offset_index, segment) in segments.iter().enumerate() {
+            sqlx::query(
+                "INSERT INTO audio_transcriptions (audio_chunk_id, transcription, offset_index, timestamp, start_time, end_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(audio_chunk_id)
+            .bind(segment.text.trim())
+            .bind(offset_index as i64)
+            .bind(timestamp)
+            .bind(segment.start)
+            .bind(segment.end)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
     pub async fn insert_audio_transcription(
         &self,
         audio_chunk_id: i64,
@@ -115,68 +456,176 @@ impl DatabaseManager {
         Ok(())
     }
 
-    pub async fn insert_video_chunk(&self, file_path: &str) -> Result<i64, sqlx::Error> {
+    /// Inserts a chunk being recorded, encoded at `fps` frames per second of video.
+    pub async fn insert_video_chunk(&self, file_path: &str, fps: f64) -> Result<i64, sqlx::Error> {
         let mut tx = self.pool.begin().await?;
-        let id = sqlx::query("INSERT INTO video_chunks (file_path) VALUES (?1)")
-            .bind(file_path)
-            .execute(&mut *tx)
-            .await?
-            .last_insert_rowid();
+        let id = sqlx::query(
+            "INSERT INTO video_chunks (file_path, status, fps) VALUES (?1, ?2, ?3)",
+        )
+        .bind(file_path)
+        .bind(ChunkStatus::Recording)
+        .bind(fps)
+        .execute(&mut *tx)
+        .await?
+        .last_insert_rowid();
         tx.commit().await?;
         Ok(id)
     }
 
-    pub async fn insert_frame(&self) -> Result<i64, sqlx::Error> {
-        // debug!("Starting insert_frame");
+    pub async fn set_video_chunk_status(
+        &self,
+        video_chunk_id: i64,
+        status: ChunkStatus,
+    ) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE video_chunks SET status = ?1 WHERE id = ?2")
+            .bind(status)
+            .bind(video_chunk_id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Marks the chunks a previous run left recording as partial, returns how many there were.
+    pub async fn mark_interrupted_video_chunks(&self) -> Result<u64, sqlx::Error> {
+        let result = sqlx::query("UPDATE video_chunks SET status = ?1 WHERE status = ?2")
+            .bind(ChunkStatus::Partial)
+            .bind(ChunkStatus::Recording)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected())
+    }
 
+    /// Finished chunks below `tier` whose last frame is older than `older_than`.
+    pub async fn get_video_chunks_to_compact(
+        &self,
+        tier: i64,
+        older_than: DateTime<Utc>,
+    ) -> Result<Vec<VideoChunk>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT video_chunks.id, video_chunks.file_path, video_chunks.status,
+                    video_chunks.tier, video_chunks.frame_step, video_chunks.fps
+             FROM video_chunks
+             JOIN frames ON frames.video_chunk_id = video_chunks.id
+             WHERE video_chunks.tier < ?1 AND video_chunks.status != ?2
+             GROUP BY video_chunks.id
+             HAVING MAX(frames.timestamp) < ?3
+             ORDER BY video_chunks.id",
+        )
+        .bind(tier)
+        .bind(ChunkStatus::Recording)
+        .bind(older_than)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Records a re-encoded chunk. The video kept one frame out of `step`, frames point at the
+    /// kept frame at or before them.
+    pub async fn set_video_chunk_tier(
+        &self,
+        video_chunk_id: i64,
+        tier: i64,
+        step: i64,
+    ) -> Result<(), sqlx::Error> {
         let mut tx = self.pool.begin().await?;
-        debug!("insert_frame Transaction started");
+        if step > 1 {
+            sqlx::query(
+                "UPDATE frames SET offset_index = offset_index / ?1 WHERE video_chunk_id = ?2",
+            )
+            .bind(step)
+            .bind(video_chunk_id)
+            .execute(&mut *tx)
+            .await?;
+        }
+        sqlx::query(
+            "UPDATE video_chunks SET tier = ?1, frame_step = frame_step * ?2 WHERE id = ?3",
+        )
+        .bind(tier)
+        .bind(step.max(1))
+        .bind(video_chunk_id)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        Ok(())
+    }
 
-        // Get the most recent video_chunk_id
-        let video_chunk_id: Option<i64> =
-            sqlx::query_scalar("SELECT id FROM video_chunks ORDER BY id DESC LIMIT 1")
-                .fetch_optional(&mut *tx)
-                .await?;
-        debug!("Fetched most recent video_chunk_id: {:?}", video_chunk_id);
-
-        // If no video chunk is found, return 0
-        let video_chunk_id = match video_chunk_id {
-            Some(id) => id,
-            None => {
-                debug!("No video chunk found, rolling back transaction");
-                tx.rollback().await?;
-                return Ok(0);
-            }
-        };
+    /// Finished chunks with frames between `start` and `end`, in capture order.
+    pub async fn get_video_segments(
+        &self,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Result<Vec<VideoSegment>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT video_chunks.id AS video_chunk_id, video_chunks.file_path,
+                    MIN(frames.timestamp) AS start_time, MAX(frames.timestamp) AS end_time,
+                    COUNT(*) AS frames, video_chunks.fps
+             FROM video_chunks
+             JOIN frames ON frames.video_chunk_id = video_chunks.id
+             WHERE video_chunks.status != ?1
+             GROUP BY video_chunks.id
+             HAVING MAX(frames.timestamp) >= ?2 AND MIN(frames.timestamp) <= ?3
+             ORDER BY start_time",
+        )
+        .bind(ChunkStatus::Recording)
+        .bind(start)
+        .bind(end)
+        .fetch_all(&self.pool)
+        .await
+    }
 
-        // Calculate the offset_index
-        let offset_index: i64 = sqlx::query_scalar(
-            "SELECT COALESCE(MAX(offset_index), -1) + 1 FROM frames WHERE video_chunk_id = ?1",
+    pub async fn get_video_chunk(&self, id: i64) -> Result<Option<VideoChunk>, sqlx::Error> {
+        sqlx::query_as("SELECT id, file_path, status, tier, frame_step, fps FROM video_chunks WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Capture times of the frames of a chunk, in the order they were recorded in its video.
+    pub async fn get_frame_times(
+        &self,
+        video_chunk_id: i64,
+    ) -> Result<Vec<DateTime<Utc>>, sqlx::Error> {
+        // Compaction points dropped frames at the same offset, the id keeps them in order
+        sqlx::query_scalar(
+            "SELECT timestamp FROM frames WHERE video_chunk_id = ?1 ORDER BY offset_index, id",
         )
         .bind(video_chunk_id)
-        .fetch_one(&mut *tx)
-        .await?;
-        debug!("insert_frame Calculated offset_index: {}", offset_index);
+        .fetch_all(&self.pool)
+        .await
+    }
 
-        // Insert the new frame
+    /// Inserts a frame at a known position of a chunk, with the time it was captured.
+    /// `image_hash` is the frame's perceptual hash, stored bit for bit as a signed integer.
+    pub async fn insert_frame_in_chunk(
+        &self,
+        video_chunk_id: i64,
+        offset_index: i64,
+        timestamp: DateTime<Utc>,
+        ocr_latency: Option<Duration>,
+        image_hash: Option<u64>,
+    ) -> Result<i64, sqlx::Error> {
         let id = sqlx::query(
-            "INSERT INTO frames (video_chunk_id, offset_index, timestamp) VALUES (?1, ?2, ?3)",
+            "INSERT INTO frames (video_chunk_id, offset_index, timestamp, ocr_latency_ms, image_hash) VALUES (?1, ?2, ?3, ?4, ?5)",
         )
         .bind(video_chunk_id)
         .bind(offset_index)
-        .bind(Utc::now())
-        .execute(&mut *tx)
+        .bind(timestamp)
+        .bind(ocr_latency.map(|latency| latency.as_millis() as i64))
+        .bind(image_hash.map(|hash| hash as i64))
+        .execute(&self.pool)
         .await?
         .last_insert_rowid();
-        debug!("insert_frame Inserted new frame with id: {}", id);
-
-        // Commit the transaction
-        tx.commit().await?;
-        // debug!("insert_frame Transaction committed");
-
         Ok(id)
     }
 
+    /// Frames whose perceptual hash is exactly `image_hash`, oldest first.
+    pub async fn get_frames_by_image_hash(&self, image_hash: u64) -> Result<Vec<i64>, sqlx::Error> {
+        sqlx::query_scalar("SELECT id FROM frames WHERE image_hash = ?1 ORDER BY id")
+            .bind(image_hash as i64)
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    /// Inserts the OCR text of a frame and its words, all or nothing.
     pub async fn insert_ocr_text(
         &self,
         frame_id: i64,
@@ -184,6 +633,7 @@ impl DatabaseManager {
         text_json: &str,
         new_text_json_vs_previous_frame: &str,
         raw_data_output_from_ocr: &str,
+        words: &[OcrWord],
     ) -> Result<(), sqlx::Error> {
         const MAX_RETRIES: u32 = 3;
         const TIMEOUT_DURATION: TokioDuration = TokioDuration::from_secs(10);
@@ -198,6 +648,7 @@ impl DatabaseManager {
                 text_json,
                 new_text_json_vs_previous_frame,
                 raw_data_output_from_ocr,
+                words,
             )).await {
                 Ok(Ok(())) => {
                     // Log successful insertion
@@ -213,9 +664,11 @@ impl DatabaseManager {
             }
 
             if attempt < MAX_RETRIES {
+                OCR_INSERT_RETRIES.inc();
                 warn!("Retrying to insert OCR text for frame_id: {} (attempt {}/{})", frame_id, attempt + 1, MAX_RETRIES);
             } else {
                 error!("Failed to insert OCR text for frame_id: {} after {} attempts", frame_id, MAX_RETRIES);
+                OCR_INSERT_ERRORS.inc();
                 return Err(sqlx::Error::PoolTimedOut); // Return error after max retries
             }
         }
@@ -231,6 +684,7 @@ impl DatabaseManager {
         text_json: &str,
         new_text_json_vs_previous_frame: &str,
         raw_data_output_from_ocr: &str,
+        words: &[OcrWord],
     ) -> Result<(), sqlx::Error> {
         // debug!("Starting insert_ocr_text_old for frame_id: {}", frame_id);
         // Function to limit string length
@@ -256,6 +710,25 @@ impl DatabaseManager {
             .bind(raw_data_output_from_ocr)
             .execute(&mut *tx)
             .await?;
+        for words in words.chunks(OCR_WORDS_PER_INSERT) {
+            let mut query = QueryBuilder::<Sqlite>::new(
+                r#"INSERT INTO ocr_words (frame_id, text, "left", top, width, height, conf, block_num, par_num, line_num, word_num) "#,
+            );
+            query.push_values(words, |mut row, word| {
+                row.push_bind(frame_id)
+                    .push_bind(word.text.as_str())
+                    .push_bind(word.left)
+                    .push_bind(word.top)
+                    .push_bind(word.width)
+                    .push_bind(word.height)
+                    .push_bind(word.conf)
+                    .push_bind(word.block_num)
+                    .push_bind(word.par_num)
+                    .push_bind(word.line_num)
+                    .push_bind(word.word_num);
+            });
+            query.build().execute(&mut *tx).await?;
+        }
     
         // Log successful insertion
         // debug!("Successfully inserted OCR text for frame_id: {}", frame_id);
@@ -265,6 +738,19 @@ impl DatabaseManager {
         Ok(())
     }
 
+    /// Words of a frame in reading order, as Tesseract laid them out.
+    pub async fn get_ocr_words(&self, frame_id: i64) -> Result<Vec<OcrWord>, sqlx::Error> {
+        sqlx::query_as(
+            r#"SELECT text, "left", top, width, height, conf, block_num, par_num, line_num, word_num
+             FROM ocr_words
+             WHERE frame_id = ?1
+             ORDER BY id"#,
+        )
+        .bind(frame_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
     pub async fn search(
         &self,
         query: &str,
@@ -277,12 +763,16 @@ impl DatabaseManager {
         let mut results = Vec::new();
 
         if content_type == ContentType::All || content_type == ContentType::OCR {
-            let ocr_results = self.search_ocr(query, limit, offset, start_time, end_time).await?;
+            let ocr_results = self
+                .search_ocr(query, limit, offset, start_time, end_time, SortOrder::Descending)
+                .await?;
             results.extend(ocr_results.into_iter().map(SearchResult::OCR));
         }
 
         if content_type == ContentType::All || content_type == ContentType::Audio {
-            let audio_results = self.search_audio(query, limit, offset, start_time, end_time).await?;
+            let audio_results = self
+                .search_audio(query, limit, offset, start_time, end_time, SortOrder::Descending)
+                .await?;
             results.extend(audio_results.into_iter().map(SearchResult::Audio));
         }
 
@@ -305,15 +795,16 @@ impl DatabaseManager {
         Ok(results)
     }
 
-    async fn search_ocr(
+    pub(crate) async fn search_ocr(
         &self,
         query: &str,
         limit: u32,
         offset: u32,
         start_time: Option<DateTime<Utc>>,
         end_time: Option<DateTime<Utc>>,
+        order: SortOrder,
     ) -> Result<Vec<OCRResult>, sqlx::Error> {
-        sqlx::query_as::<_, OCRResult>(
+        sqlx::query_as::<_, OCRResult>(&format!(
             r#"
             SELECT 
                 ocr_text.frame_id,
@@ -335,10 +826,11 @@ impl DatabaseManager {
                 AND (?2 IS NULL OR frames.timestamp >= ?2)
                 AND (?3 IS NULL OR frames.timestamp <= ?3)
             ORDER BY 
-                frames.timestamp DESC
+                frames.timestamp {order}, frames.id {order}
             LIMIT ?4 OFFSET ?5
             "#,
-        )
+            order = order.as_sql()
+        ))
         .bind(query)
         .bind(start_time)
         .bind(end_time)
@@ -348,15 +840,16 @@ impl DatabaseManager {
         .await
     }
 
-    async fn search_audio(
+    pub(crate) async fn search_audio(
         &self,
         query: &str,
         limit: u32,
         offset: u32,
         start_time: Option<DateTime<Utc>>,
         end_time: Option<DateTime<Utc>>,
+        order: SortOrder,
     ) -> Result<Vec<AudioResult>, sqlx::Error> {
-        sqlx::query_as::<_, AudioResult>(
+        sqlx::query_as::<_, AudioResult>(&format!(
             r#"
             SELECT 
                 audio_transcriptions.audio_chunk_id,
@@ -373,10 +866,11 @@ impl DatabaseManager {
                 AND (?2 IS NULL OR audio_transcriptions.timestamp >= ?2)
                 AND (?3 IS NULL OR audio_transcriptions.timestamp <= ?3)
             ORDER BY 
-                audio_transcriptions.timestamp DESC
+                audio_transcriptions.timestamp {order}, audio_transcriptions.id {order}
             LIMIT ?4 OFFSET ?5
             "#,
-        )
+            order = order.as_sql()
+        ))
         .bind(query)
         .bind(start_time)
         .bind(end_time)
@@ -588,6 +1082,160 @@ impl DatabaseManager {
         Ok(total_count)
     }
 
+    /// Activity between `start_time` and `end_time` grouped in buckets of `bucket_seconds`,
+    /// only buckets with something in them are returned.
+    pub async fn get_timeline(
+        &self,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+        bucket_seconds: i64,
+    ) -> Result<Vec<TimelineBucket>, sqlx::Error> {
+        // The frame with the most text stands for its bucket, SQLite takes the bare column
+        // from the row holding the MAX()
+        let frame_rows = sqlx::query_as::<_, (i64, i64, i64, Option<i64>)>(
+            r#"
+            SELECT
+                CAST(strftime('%s', frames.timestamp) AS INTEGER) / ?3 * ?3 AS bucket,
+                COUNT(DISTINCT frames.id),
+                frames.id,
+                MAX(LENGTH(COALESCE(ocr_text.text, '')))
+            FROM frames
+            LEFT JOIN ocr_text ON ocr_text.frame_id = frames.id
+            WHERE frames.timestamp >= ?1 AND frames.timestamp < ?2
+            GROUP BY bucket
+            "#,
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .bind(bucket_seconds)
+        .fetch_all(&self.pool)
+        .await?;
+
+        // Timed transcriptions are placed where they were said, older ones when they were
+        // inserted. Both are inserted after their chunk is recorded, hence the wider filter.
+        let audio_rows = sqlx::query_as::<_, (i64, i64)>(
+            r#"
+            SELECT
+                said_at / ?5 * ?5 AS bucket,
+                SUM(CASE WHEN text = '' THEN 0
+                         ELSE LENGTH(text) - LENGTH(REPLACE(text, ' ', '')) + 1 END)
+            FROM (
+                SELECT
+                    COALESCE(
+                        CAST(strftime('%s', audio_chunks.start_time) AS INTEGER)
+                            + CAST(audio_transcriptions.start_time AS INTEGER),
+                        CAST(strftime('%s', audio_transcriptions.timestamp) AS INTEGER)
+                    ) AS said_at,
+                    TRIM(REPLACE(REPLACE(audio_transcriptions.transcription, char(10), ' '), char(13), ' ')) AS text
+                FROM audio_transcriptions
+                JOIN audio_chunks ON audio_chunks.id = audio_transcriptions.audio_chunk_id
+                WHERE audio_transcriptions.timestamp >= ?1 AND audio_transcriptions.timestamp < ?2
+            )
+            WHERE said_at >= ?3 AND said_at < ?4
+            GROUP BY bucket
+            "#,
+        )
+        .bind(start_time)
+        .bind(end_time + chrono::Duration::minutes(10))
+        .bind(start_time.timestamp())
+        .bind(end_time.timestamp())
+        .bind(bucket_seconds)
+        .fetch_all(&self.pool)
+        .await?;
+
+        let mut buckets: BTreeMap<i64, TimelineBucket> = BTreeMap::new();
+        for (start, frame_count, frame_id, _) in frame_rows {
+            let entry = buckets
+                .entry(start)
+                .or_insert_with(|| TimelineBucket::empty(start, bucket_seconds));
+            entry.frame_count = frame_count;
+            entry.frame_id = Some(frame_id);
+        }
+        for (start, words) in audio_rows {
+            buckets
+                .entry(start)
+                .or_insert_with(|| TimelineBucket::empty(start, bucket_seconds))
+                .word_count = words;
+        }
+        Ok(buckets.into_values().collect())
+    }
+
+    /// Frames, chunks and transcriptions per day, chunks counted on the day of their first row.
+    pub async fn get_daily_stats(&self) -> Result<Vec<DailyStats>, sqlx::Error> {
+        sqlx::query_as(
+            r#"
+            SELECT
+                day,
+                SUM(frames) AS frames,
+                SUM(video_chunks) AS video_chunks,
+                SUM(audio_chunks) AS audio_chunks,
+                SUM(transcriptions) AS transcriptions
+            FROM (
+                SELECT date(timestamp) AS day, 1 AS frames, 0 AS video_chunks, 0 AS audio_chunks, 0 AS transcriptions
+                FROM frames
+                UNION ALL
+                SELECT date(MIN(timestamp)), 0, 1, 0, 0 FROM frames GROUP BY video_chunk_id
+                UNION ALL
+                SELECT date(MIN(timestamp)), 0, 0, 1, 0 FROM audio_transcriptions GROUP BY audio_chunk_id
+                UNION ALL
+                SELECT date(timestamp), 0, 0, 0, 1 FROM audio_transcriptions
+            )
+            GROUP BY day
+            ORDER BY day
+            "#,
+        )
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Paths of the video and audio chunks recorded since `since`, video chunks dated by their
+    /// first frame.
+    pub async fn get_chunks_recorded_since(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<Vec<String>, sqlx::Error> {
+        sqlx::query_scalar(
+            r#"
+            SELECT video_chunks.file_path
+            FROM video_chunks
+            JOIN frames ON frames.video_chunk_id = video_chunks.id
+            GROUP BY video_chunks.id
+            HAVING MIN(frames.timestamp) >= ?1
+            UNION ALL
+            SELECT file_path FROM audio_chunks WHERE start_time >= ?1
+            "#,
+        )
+        .bind(since)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Average OCR and transcription latencies in milliseconds since `since`.
+    pub async fn get_average_latencies(
+        &self,
+        since: DateTime<Utc>,
+    ) -> Result<(Option<f64>, Option<f64>), sqlx::Error> {
+        let ocr: Option<f64> = sqlx::query_scalar(
+            "SELECT AVG(ocr_latency_ms) FROM frames WHERE timestamp >= ?1",
+        )
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        let transcription: Option<f64> = sqlx::query_scalar(
+            "SELECT AVG(transcription_latency_ms) FROM audio_chunks WHERE start_time >= ?1",
+        )
+        .bind(since)
+        .fetch_one(&self.pool)
+        .await?;
+        Ok((ocr, transcription))
+    }
+
+    /// Path of the database file, `None` for in-memory databases.
+    pub fn database_path(&self) -> Option<PathBuf> {
+        let path = self.pool.connect_options().get_filename().to_path_buf();
+        path.is_file().then_some(path)
+    }
+
     pub async fn get_latest_timestamps(&self) -> Result<(Option<DateTime<Utc>>, Option<DateTime<Utc>>), sqlx::Error> {
         let latest_frame: Option<(DateTime<Utc>,)> = sqlx::query_as(
             "SELECT timestamp FROM frames ORDER BY timestamp DESC LIMIT 1"
@@ -606,13 +1254,638 @@ impl DatabaseManager {
             latest_audio.map(|a| a.0)
         ))
     }
+
+    pub async fn insert_webhook_rule(&self, rule: &NewWebhookRule, secret: &str) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            r#"
+            INSERT INTO webhook_rules (name, url, secret, keywords, pattern, content_type, enabled, created_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+        )
+        .bind(&rule.name)
+        .bind(&rule.url)
+        .bind(secret)
+        .bind(Json(&rule.keywords))
+        .bind(&rule.pattern)
+        .bind(rule.content_type)
+        .bind(rule.enabled)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Returns false if no rule with this id exists.
+    pub async fn update_webhook_rule(&self, id: i64, rule: &NewWebhookRule, secret: &str) -> Result<bool, sqlx::Error> {
+        let rows_affected = sqlx::query(
+            r#"
+            UPDATE webhook_rules
+            SET name = ?2, url = ?3, secret = ?4, keywords = ?5, pattern = ?6, content_type = ?7, enabled = ?8
+            WHERE id = ?1
+            "#,
+        )
+        .bind(id)
+        .bind(&rule.name)
+        .bind(&rule.url)
+        .bind(secret)
+        .bind(Json(&rule.keywords))
+        .bind(&rule.pattern)
+        .bind(rule.content_type)
+        .bind(rule.enabled)
+        .execute(&self.pool)
+        .await?
+        .rows_affected();
+        Ok(rows_affected > 0)
+    }
+
+    /// Returns false if no rule with this id exists.
+    pub async fn delete_webhook_rule(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let rows_affected = sqlx::query("DELETE FROM webhook_rules WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?
+            .rows_affected();
+        Ok(rows_affected > 0)
+    }
+
+    pub async fn get_webhook_rule(&self, id: i64) -> Result<Option<WebhookRule>, sqlx::Error> {
+        sqlx::query_as::<_, WebhookRule>("SELECT * FROM webhook_rules WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    pub async fn get_webhook_rules(&self) -> Result<Vec<WebhookRule>, sqlx::Error> {
+        sqlx::query_as::<_, WebhookRule>("SELECT * FROM webhook_rules ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn insert_webhook_dead_letter(
+        &self,
+        rule_id: i64,
+        url: &str,
+        payload: &str,
+        error: &str,
+        attempts: u32,
+    ) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            "INSERT INTO webhook_dead_letters (rule_id, url, payload, error, attempts, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(rule_id)
+        .bind(url)
+        .bind(payload)
+        .bind(error)
+        .bind(attempts)
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    pub async fn get_webhook_dead_letters(&self, rule_id: i64) -> Result<Vec<WebhookDeadLetter>, sqlx::Error> {
+        sqlx::query_as::<_, WebhookDeadLetter>(
+            "SELECT * FROM webhook_dead_letters WHERE rule_id = ?1 ORDER BY created_at DESC",
+        )
+        .bind(rule_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn insert_pipe_output(
+        &self,
+        pipe_name: &str,
+        content_type: ContentType,
+        source_id: i64,
+        kind: &str,
+        data: &serde_json::Value,
+    ) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            "INSERT INTO pipe_outputs (pipe_name, content_type, source_id, kind, data, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+        )
+        .bind(pipe_name)
+        .bind(content_type)
+        .bind(source_id)
+        .bind(kind)
+        .bind(Json(data))
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    pub async fn insert_api_token(
+        &self,
+        name: &str,
+        token_hash: &str,
+        scopes: &[Scope],
+    ) -> Result<i64, sqlx::Error> {
+        let id = sqlx::query(
+            "INSERT INTO api_tokens (name, token_hash, scopes, created_at) VALUES (?1, ?2, ?3, ?4)",
+        )
+        .bind(name)
+        .bind(token_hash)
+        .bind(Json(scopes))
+        .bind(Utc::now())
+        .execute(&self.pool)
+        .await?
+        .last_insert_rowid();
+        Ok(id)
+    }
+
+    /// Looks up a token by hash and records that it was used. `last_used_at` is only written once
+    /// it is a minute old, every authenticated request would be a write otherwise.
+    pub async fn use_api_token(&self, token_hash: &str) -> Result<Option<ApiToken>, sqlx::Error> {
+        let token = sqlx::query_as::<_, ApiToken>("SELECT * FROM api_tokens WHERE token_hash = ?1")
+            .bind(token_hash)
+            .fetch_optional(&self.pool)
+            .await?;
+        let Some(mut token) = token else {
+            return Ok(None);
+        };
+
+        let now = Utc::now();
+        let stale = token.last_used_at.map_or(true, |last_used_at| {
+            (now - last_used_at).num_seconds() >= TOKEN_LAST_USED_RESOLUTION_SECS
+        });
+        if stale {
+            sqlx::query("UPDATE api_tokens SET last_used_at = ?1 WHERE id = ?2")
+                .bind(now)
+                .bind(token.id)
+                .execute(&self.pool)
+                .await?;
+            token.last_used_at = Some(now);
+        }
+        Ok(Some(token))
+    }
+
+    pub async fn get_api_tokens(&self) -> Result<Vec<ApiToken>, sqlx::Error> {
+        sqlx::query_as::<_, ApiToken>("SELECT * FROM api_tokens ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn count_api_tokens(&self) -> Result<i64, sqlx::Error> {
+        sqlx::query_scalar("SELECT COUNT(*) FROM api_tokens")
+            .fetch_one(&self.pool)
+            .await
+    }
+
+    pub async fn delete_api_token(&self, id: i64) -> Result<bool, sqlx::Error> {
+        let result = sqlx::query("DELETE FROM api_tokens WHERE id = ?1")
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// Copies the whole database to `dest` while the recorder keeps writing, using SQLite's
+    /// online backup API. The copy is encrypted with the same key as this database.
+    pub async fn backup(&self, dest: &Path) -> Result<(), sqlx::Error> {
+        let mut conn = self.pool.acquire().await?;
+        let dest = dest.to_path_buf();
+        let key_pragma = self.key_pragma.clone();
+        let runtime = tokio::runtime::Handle::current();
+        // The copy and its retries block, keep them off the async workers
+        tokio::task::spawn_blocking(move || {
+            let mut handle = runtime.block_on(conn.lock_handle())?;
+            backup_database(
+                handle.as_raw_handle().as_ptr(),
+                &dest,
+                key_pragma.as_deref(),
+            )
+            .map_err(sqlx::Error::Protocol)
+        })
+        .await
+        .map_err(|e| sqlx::Error::Protocol(format!("Backup task failed: {}", e)))?
+    }
+
+    /// Opens another database file, e.g. a backup, with the key of this one.
+    pub async fn open_snapshot(&self, path: &Path) -> Result<Self, sqlx::Error> {
+        let mut options = SqliteConnectOptions::new().filename(path);
+        if let Some(key) = &self.key_pragma {
+            options = options.pragma("key", key.clone());
+        }
+        Self::connect(options, self.key_pragma.clone()).await
+    }
+
+    pub async fn close(&self) {
+        self.pool.close().await;
+    }
+
+    pub async fn get_video_chunks(&self) -> Result<Vec<(i64, String)>, sqlx::Error> {
+        sqlx::query_as("SELECT id, file_path FROM video_chunks ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn get_audio_chunk(&self, id: i64) -> Result<Option<AudioChunk>, sqlx::Error> {
+        sqlx::query_as("SELECT id, file_path, device, start_time FROM audio_chunks WHERE id = ?1")
+            .bind(id)
+            .fetch_optional(&self.pool)
+            .await
+    }
+
+    /// Chunks of every device that started recording between `start_time` and `end_time`, in
+    /// order.
+    pub async fn get_audio_chunks_between(
+        &self,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<AudioChunk>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT id, file_path, device, start_time FROM audio_chunks
+             WHERE start_time >= ?1 AND start_time <= ?2
+             ORDER BY start_time",
+        )
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Transcriptions of a chunk in order.
+    pub async fn get_audio_segments(
+        &self,
+        audio_chunk_id: i64,
+    ) -> Result<Vec<AudioSegment>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT audio_transcriptions.audio_chunk_id, audio_chunks.start_time AS chunk_start_time,
+                    audio_transcriptions.offset_index, audio_transcriptions.transcription,
+                    audio_transcriptions.start_time, audio_transcriptions.end_time
+             FROM audio_transcriptions
+             JOIN audio_chunks ON audio_chunks.id = audio_transcriptions.audio_chunk_id
+             WHERE audio_transcriptions.audio_chunk_id = ?1
+             ORDER BY audio_transcriptions.offset_index",
+        )
+        .bind(audio_chunk_id)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    /// Timed transcriptions of the chunks `device` started recording between `start_time` and
+    /// `end_time`, in order.
+    pub async fn get_device_audio_segments(
+        &self,
+        device: &str,
+        start_time: DateTime<Utc>,
+        end_time: DateTime<Utc>,
+    ) -> Result<Vec<AudioSegment>, sqlx::Error> {
+        sqlx::query_as(
+            "SELECT audio_transcriptions.audio_chunk_id, audio_chunks.start_time AS chunk_start_time,
+                    audio_transcriptions.offset_index, audio_transcriptions.transcription,
+                    audio_transcriptions.start_time, audio_transcriptions.end_time
+             FROM audio_transcriptions
+             JOIN audio_chunks ON audio_chunks.id = audio_transcriptions.audio_chunk_id
+             WHERE audio_chunks.device = ?1
+               AND audio_chunks.start_time >= ?2
+               AND audio_chunks.start_time <= ?3
+               AND audio_transcriptions.start_time IS NOT NULL
+             ORDER BY audio_chunks.start_time, audio_transcriptions.offset_index",
+        )
+        .bind(device)
+        .bind(start_time)
+        .bind(end_time)
+        .fetch_all(&self.pool)
+        .await
+    }
+
+    pub async fn get_audio_chunks(&self) -> Result<Vec<(i64, String)>, sqlx::Error> {
+        sqlx::query_as("SELECT id, file_path FROM audio_chunks ORDER BY id")
+            .fetch_all(&self.pool)
+            .await
+    }
+
+    pub async fn set_video_chunk_path(&self, id: i64, file_path: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE video_chunks SET file_path = ?1 WHERE id = ?2")
+            .bind(file_path)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    pub async fn set_audio_chunk_path(&self, id: i64, file_path: &str) -> Result<(), sqlx::Error> {
+        sqlx::query("UPDATE audio_chunks SET file_path = ?1 WHERE id = ?2")
+            .bind(file_path)
+            .bind(id)
+            .execute(&self.pool)
+            .await?;
+        Ok(())
+    }
+
+    /// Merges everything recorded in `snapshot` into this database in a single transaction.
+    ///
+    /// Chunks get new ids and the paths in `video_paths` / `audio_paths`, keyed by their id in
+    /// the snapshot. Frame ids are shifted past the existing ones so OCR text and pipe outputs
+    /// still point at the right frame.
+    pub async fn import(
+        &self,
+        snapshot: &DatabaseManager,
+        video_paths: &HashMap<i64, String>,
+        audio_paths: &HashMap<i64, String>,
+    ) -> Result<ImportStats, sqlx::Error> {
+        let mut stats = ImportStats::default();
+        let mut tx = self.pool.begin().await?;
+
+        let mut video_chunk_ids = HashMap::new();
+        let video_chunks = sqlx::query_as::<_, VideoChunk>(
+            "SELECT id, file_path, status, tier, frame_step, fps FROM video_chunks ORDER BY id",
+        )
+        .fetch_all(&snapshot.pool)
+        .await?;
+        for chunk in video_chunks {
+            let old_id = chunk.id;
+            let file_path = video_paths.get(&old_id).unwrap_or(&chunk.file_path);
+            let id = sqlx::query(
+                "INSERT INTO video_chunks (file_path, status, tier, frame_step, fps) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(file_path)
+            .bind(chunk.status)
+            .bind(chunk.tier)
+            .bind(chunk.frame_step)
+            .bind(chunk.fps)
+            .execute(&mut *tx)
+                .await?
+                .last_insert_rowid();
+            video_chunk_ids.insert(old_id, id);
+            stats.video_chunks += 1;
+        }
+
+        let mut audio_chunk_ids = HashMap::new();
+        let audio_chunks = sqlx::query_as::<
+            _,
+            (i64, String, Option<String>, Option<DateTime<Utc>>, Option<i64>),
+        >(
+            "SELECT id, file_path, device, start_time, transcription_latency_ms FROM audio_chunks ORDER BY id",
+        )
+        .fetch_all(&snapshot.pool)
+        .await?;
+        for (old_id, file_path, device, start_time, latency) in audio_chunks {
+            let file_path = audio_paths.get(&old_id).unwrap_or(&file_path);
+            let id = sqlx::query(
+                "INSERT INTO audio_chunks (file_path, device, start_time, transcription_latency_ms) VALUES (?1, ?2, ?3, ?4)",
+            )
+            .bind(file_path)
+            .bind(device)
+            .bind(start_time)
+            .bind(latency)
+            .execute(&mut *tx)
+            .await?
+            .last_insert_rowid();
+            audio_chunk_ids.insert(old_id, id);
+            stats.audio_chunks += 1;
+        }
+
+        let frame_offset: i64 = sqlx::query_scalar("SELECT COALESCE(MAX(id), 0) FROM frames")
+            .fetch_one(&mut *tx)
+            .await?;
+
+        let mut frames = sqlx::query_as::<
+            _,
+            (i64, i64, i64, DateTime<Utc>, Option<i64>, Option<i64>),
+        >(
+            "SELECT frames.id, frames.video_chunk_id, frames.offset_index, frames.timestamp,
+                    frames.ocr_latency_ms, frames.image_hash
+             FROM frames
+             JOIN video_chunks ON video_chunks.id = frames.video_chunk_id
+             ORDER BY frames.id",
+        )
+        .fetch(&snapshot.pool);
+        while let Some((
+            id,
+            video_chunk_id,
+            offset_index,
+            timestamp,
+            latency,
+            image_hash,
+        )) = frames.try_next().await?
+        {
+            sqlx::query(
+                "INSERT INTO frames (id, video_chunk_id, offset_index, timestamp, ocr_latency_ms, image_hash) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(id + frame_offset)
+            .bind(video_chunk_ids[&video_chunk_id])
+            .bind(offset_index)
+            .bind(timestamp)
+            .bind(latency)
+            .bind(image_hash)
+            .execute(&mut *tx)
+            .await?;
+            stats.frames += 1;
+        }
+        drop(frames);
+
+        let mut ocr_texts = sqlx::query_as::<
+            _,
+            (i64, String, Option<String>, Option<String>, Option<String>),
+        >(
+            "SELECT ocr_text.frame_id, ocr_text.text, ocr_text.text_json,
+                    ocr_text.new_text_json_vs_previous_frame, ocr_text.raw_data_output_from_ocr
+             FROM ocr_text
+             JOIN frames ON frames.id = ocr_text.frame_id
+             JOIN video_chunks ON video_chunks.id = frames.video_chunk_id",
+        )
+        .fetch(&snapshot.pool);
+        while let Some((frame_id, text, text_json, new_text_json, raw_data)) =
+            ocr_texts.try_next().await?
+        {
+            sqlx::query(
+                "INSERT INTO ocr_text (frame_id, text, text_json, new_text_json_vs_previous_frame, raw_data_output_from_ocr) VALUES (?1, ?2, ?3, ?4, ?5)",
+            )
+            .bind(frame_id + frame_offset)
+            .bind(text)
+            .bind(text_json)
+            .bind(new_text_json)
+            .bind(raw_data)
+            .execute(&mut *tx)
+            .await?;
+        }
+        drop(ocr_texts);
+
+        let mut ocr_words = sqlx::query(
+            r#"SELECT ocr_words.frame_id, ocr_words.text, ocr_words."left", ocr_words.top,
+                    ocr_words.width, ocr_words.height, ocr_words.conf, ocr_words.block_num,
+                    ocr_words.par_num, ocr_words.line_num, ocr_words.word_num
+             FROM ocr_words
+             JOIN frames ON frames.id = ocr_words.frame_id
+             JOIN video_chunks ON video_chunks.id = frames.video_chunk_id
+             ORDER BY ocr_words.id"#,
+        )
+        .fetch(&snapshot.pool);
+        while let Some(row) = ocr_words.try_next().await? {
+            let frame_id: i64 = row.try_get("frame_id")?;
+            let word = OcrWord::from_row(&row)?;
+            sqlx::query(
+                r#"INSERT INTO ocr_words (frame_id, text, "left", top, width, height, conf, block_num, par_num, line_num, word_num) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)"#,
+            )
+            .bind(frame_id + frame_offset)
+            .bind(word.text)
+            .bind(word.left)
+            .bind(word.top)
+            .bind(word.width)
+            .bind(word.height)
+            .bind(word.conf)
+            .bind(word.block_num)
+            .bind(word.par_num)
+            .bind(word.line_num)
+            .bind(word.word_num)
+            .execute(&mut *tx)
+            .await?;
+        }
+        drop(ocr_words);
+
+        let mut transcriptions = sqlx::query_as::<
+            _,
+            (i64, i64, DateTime<Utc>, String, Option<f64>, Option<f64>),
+        >(
+            "SELECT audio_chunk_id, offset_index, timestamp, transcription, start_time, end_time
+             FROM audio_transcriptions
+             ORDER BY id",
+        )
+        .fetch(&snapshot.pool);
+        while let Some((audio_chunk_id, offset_index, timestamp, transcription, start, end)) =
+            transcriptions.try_next().await?
+        {
+            let Some(audio_chunk_id) = audio_chunk_ids.get(&audio_chunk_id) else {
+                continue;
+            };
+            sqlx::query(
+                "INSERT INTO audio_transcriptions (audio_chunk_id, transcription, offset_index, timestamp, start_time, end_time) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(audio_chunk_id)
+            .bind(transcription)
+            .bind(offset_index)
+            .bind(timestamp)
+            .bind(start)
+            .bind(end)
+            .execute(&mut *tx)
+            .await?;
+            stats.audio_transcriptions += 1;
+        }
+        drop(transcriptions);
+
+        let mut outputs = sqlx::query_as::<
+            _,
+            (String, ContentType, i64, String, String, DateTime<Utc>),
+        >(
+            "SELECT pipe_name, content_type, source_id, kind, data, created_at FROM pipe_outputs ORDER BY id",
+        )
+        .fetch(&snapshot.pool);
+        while let Some((pipe_name, content_type, source_id, kind, data, created_at)) =
+            outputs.try_next().await?
+        {
+            let source_id = match content_type {
+                ContentType::Audio => match audio_chunk_ids.get(&source_id) {
+                    Some(id) => *id,
+                    None => continue,
+                },
+                ContentType::OCR => source_id + frame_offset,
+                ContentType::All => source_id,
+            };
+            sqlx::query(
+                "INSERT INTO pipe_outputs (pipe_name, content_type, source_id, kind, data, created_at) VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+            )
+            .bind(pipe_name)
+            .bind(content_type)
+            .bind(source_id)
+            .bind(kind)
+            .bind(data)
+            .bind(created_at)
+            .execute(&mut *tx)
+            .await?;
+        }
+        drop(outputs);
+
+        tx.commit().await?;
+        Ok(stats)
+    }
 }
 
 impl Clone for DatabaseManager {
     fn clone(&self) -> Self {
         DatabaseManager {
             pool: self.pool.clone(),
+            key_pragma: self.key_pragma.clone(),
         }
     }
 }
 
+
+/// Copies the database behind `source` into a new database at `dest`.
+fn backup_database(
+    source: *mut ffi::sqlite3,
+    dest: &Path,
+    key_pragma: Option<&str>,
+) -> Result<(), String> {
+    let dest = CString::new(dest.to_string_lossy().as_bytes()).map_err(|e| e.to_string())?;
+    let key_sql = key_pragma
+        .map(|key| CString::new(format!("PRAGMA key = {};", key)))
+        .transpose()
+        .map_err(|e| e.to_string())?;
+    let main = CString::new("main").expect("no nul byte");
+
+    // SAFETY: `source` is a connection locked by the caller for the whole call, `dest_db` is
+    // closed on every path and the backup handle is always finished.
+    unsafe {
+        let mut dest_db = ptr::null_mut();
+        if ffi::sqlite3_open(dest.as_ptr(), &mut dest_db) != ffi::SQLITE_OK {
+            let error = sqlite_error(dest_db);
+            ffi::sqlite3_close(dest_db);
+            return Err(error);
+        }
+
+        if let Some(key_sql) = &key_sql {
+            let rc = ffi::sqlite3_exec(
+                dest_db,
+                key_sql.as_ptr(),
+                None,
+                ptr::null_mut(),
+                ptr::null_mut(),
+            );
+            if rc != ffi::SQLITE_OK {
+                let error = sqlite_error(dest_db);
+                ffi::sqlite3_close(dest_db);
+                return Err(error);
+            }
+        }
+
+        let backup = ffi::sqlite3_backup_init(dest_db, main.as_ptr(), source, main.as_ptr());
+        if backup.is_null() {
+            let error = sqlite_error(dest_db);
+            ffi::sqlite3_close(dest_db);
+            return Err(error);
+        }
+
+        // Copy everything in one step: stepping a few pages at a time restarts the backup
+        // every time the recorder writes in between
+        let mut rc = ffi::sqlite3_backup_step(backup, -1);
+        let mut retries = 0;
+        while (rc == ffi::SQLITE_BUSY || rc == ffi::SQLITE_LOCKED) && retries < 100 {
+            std::thread::sleep(Duration::from_millis(100));
+            rc = ffi::sqlite3_backup_step(backup, -1);
+            retries += 1;
+        }
+        ffi::sqlite3_backup_finish(backup);
+
+        let result = if rc == ffi::SQLITE_DONE {
+            Ok(())
+        } else {
+            Err(sqlite_error(dest_db))
+        };
+        ffi::sqlite3_close(dest_db);
+        result
+    }
+}
+
+unsafe fn sqlite_error(db: *mut ffi::sqlite3) -> String {
+    if db.is_null() {
+        return "out of memory".to_string();
+    }
+    CStr::from_ptr(ffi::sqlite3_errmsg(db))
+        .to_string_lossy()
+        .into_owned()
+}
@@ -0,0 +1,250 @@
+// Settings of the ffmpeg process encoding screen recordings. Frames are piped as raw RGBA, so
+// ffmpeg needs their size up front and only does the pixel format conversion and scaling.
+use std::fmt;
+use std::path::Path;
+use std::str::FromStr;
+
+/// Longest stretch of a chunk lost when ffmpeg dies, in microseconds. Chunks are fragmented MP4
+/// or WebM, every fragment written is playable without the index ffmpeg writes on exit.
+const FRAGMENT_DURATION_US: u32 = 1_000_000;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum VideoCodec {
+    #[default]
+    X264,
+    X265,
+    Vp9,
+    /// AV1 through SVT-AV1
+    Av1,
+}
+
+impl VideoCodec {
+    /// Name of the ffmpeg encoder.
+    pub fn encoder(&self) -> &'static str {
+        match self {
+            VideoCodec::X264 => "libx264",
+            VideoCodec::X265 => "libx265",
+            VideoCodec::Vp9 => "libvpx-vp9",
+            VideoCodec::Av1 => "libsvtav1",
+        }
+    }
+
+    /// Fastest preset of the encoder, recording has to keep up with capture.
+    pub fn default_preset(&self) -> &'static str {
+        match self {
+            VideoCodec::X264 | VideoCodec::X265 => "ultrafast",
+            // `-cpu-used` for VP9, 0 (slowest) to 8
+            VideoCodec::Vp9 => "8",
+            // 0 (slowest) to 13
+            VideoCodec::Av1 => "12",
+        }
+    }
+
+    /// Extension of the container recordings are written to, VP9 goes to WebM, the rest to MP4.
+    pub fn extension(&self) -> &'static str {
+        match self {
+            VideoCodec::Vp9 => "webm",
+            VideoCodec::X264 | VideoCodec::X265 | VideoCodec::Av1 => "mp4",
+        }
+    }
+
+    /// CRF giving about the quality x264 gets at 25, the scales differ between encoders.
+    pub fn default_crf(&self) -> u32 {
+        match self {
+            VideoCodec::X264 => 25,
+            VideoCodec::X265 => 28,
+            VideoCodec::Vp9 | VideoCodec::Av1 => 35,
+        }
+    }
+}
+
+impl FromStr for VideoCodec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "x264" | "h264" => Ok(VideoCodec::X264),
+            "x265" | "h265" | "hevc" => Ok(VideoCodec::X265),
+            "vp9" => Ok(VideoCodec::Vp9),
+            "av1" | "svtav1" => Ok(VideoCodec::Av1),
+            _ => Err(format!(
+                "unknown codec {}, expected x264, x265, vp9 or av1",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for VideoCodec {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let name = match self {
+            VideoCodec::X264 => "x264",
+            VideoCodec::X265 => "x265",
+            VideoCodec::Vp9 => "vp9",
+            VideoCodec::Av1 => "av1",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Output resolution, relative to the captured screen or absolute.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum VideoScale {
+    /// e.g. `0.5` for half the width and height
+    Factor(f64),
+    /// e.g. `1280x720`
+    Size { width: u32, height: u32 },
+}
+
+impl VideoScale {
+    /// Value of ffmpeg's `scale` filter. Dimensions are kept even, most pixel formats need it.
+    pub fn filter(&self) -> String {
+        match self {
+            VideoScale::Factor(factor) => format!(
+                "scale=trunc(iw*{factor}/2)*2:trunc(ih*{factor}/2)*2",
+                factor = factor
+            ),
+            VideoScale::Size { width, height } => format!("scale={}:{}", width, height),
+        }
+    }
+}
+
+impl FromStr for VideoScale {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "invalid scale {}, expected a factor like 0.5 or a size like 1280x720",
+                s
+            )
+        };
+        match s.split_once('x') {
+            Some((width, height)) => {
+                let width: u32 = width.parse().map_err(|_| invalid())?;
+                let height: u32 = height.parse().map_err(|_| invalid())?;
+                // yuv420p and most encoders need even dimensions
+                if width == 0 || height == 0 || width % 2 != 0 || height % 2 != 0 {
+                    return Err(format!(
+                        "invalid scale {}, width and height must be even and not 0",
+                        s
+                    ));
+                }
+                Ok(VideoScale::Size { width, height })
+            }
+            None => match s.parse::<f64>() {
+                Ok(factor) if factor > 0.0 && factor <= 1.0 => Ok(VideoScale::Factor(factor)),
+                _ => Err(invalid()),
+            },
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct VideoEncoderConfig {
+    pub codec: VideoCodec,
+    /// Encoder speed, the codec's fastest when `None`
+    pub preset: Option<String>,
+    /// Constant quality, lower is better and bigger, the codec's default when `None`
+    pub crf: Option<u32>,
+    /// Pixel format of the output, `yuv420p` when `None`
+    pub pix_fmt: Option<String>,
+    /// Captured size when `None`
+    pub scale: Option<VideoScale>,
+}
+
+impl VideoEncoderConfig {
+    /// Arguments of an ffmpeg reading `width`x`height` RGBA frames from stdin at `fps` and
+    /// encoding them to `output_file`.
+    pub fn ffmpeg_args(&self, width: u32, height: u32, fps: f64, output_file: &str) -> Vec<String> {
+        let mut args: Vec<String> = [
+            "-f",
+            "rawvideo",
+            "-pix_fmt",
+            "rgba",
+            "-s",
+            format!("{}x{}", width, height).as_str(),
+            "-r",
+            fps.to_string().as_str(),
+            "-i",
+            "-",
+        ]
+        .iter()
+        .map(|arg| arg.to_string())
+        .collect();
+        args.extend(self.output_args(&[], output_file));
+        args
+    }
+
+    /// Encoding arguments for `output_file`, after the input ones. `filters` run before the
+    /// scaling.
+    pub fn output_args(&self, filters: &[String], output_file: &str) -> Vec<String> {
+        let mut args = Vec::new();
+        let filters: Vec<String> = filters
+            .iter()
+            .cloned()
+            .chain(self.scale.map(|scale| scale.filter()))
+            .collect();
+        if !filters.is_empty() {
+            args.extend(["-vf".to_string(), filters.join(",")]);
+        }
+
+        let preset = self
+            .preset
+            .clone()
+            .unwrap_or_else(|| self.codec.default_preset().to_string());
+        let crf = self.crf.unwrap_or_else(|| self.codec.default_crf());
+        args.extend(["-vcodec".to_string(), self.codec.encoder().to_string()]);
+        match self.codec {
+            VideoCodec::X264 | VideoCodec::X265 | VideoCodec::Av1 => {
+                args.extend(["-preset".to_string(), preset]);
+            }
+            VideoCodec::Vp9 => {
+                // Constant quality needs a zero bitrate with libvpx
+                args.extend(
+                    [
+                        "-deadline",
+                        "realtime",
+                        "-cpu-used",
+                        preset.as_str(),
+                        "-b:v",
+                        "0",
+                    ]
+                    .iter()
+                    .map(|arg| arg.to_string()),
+                );
+            }
+        }
+        if self.codec == VideoCodec::X265 {
+            // Lets QuickTime and Safari play HEVC in mp4
+            args.extend(["-tag:v".to_string(), "hvc1".to_string()]);
+        }
+        args.extend([
+            "-crf".to_string(),
+            crf.to_string(),
+            "-pix_fmt".to_string(),
+            self.pix_fmt
+                .clone()
+                .unwrap_or_else(|| "yuv420p".to_string()),
+        ]);
+        let webm = Path::new(output_file)
+            .extension()
+            .is_some_and(|extension| extension == "webm");
+        if webm {
+            // WebM is written cluster by cluster, a cluster holds at most this much
+            args.extend([
+                "-cluster_time_limit".to_string(),
+                (FRAGMENT_DURATION_US / 1000).to_string(),
+            ]);
+        } else {
+            args.extend([
+                "-movflags".to_string(),
+                "+empty_moov+default_base_moof".to_string(),
+                "-frag_duration".to_string(),
+                FRAGMENT_DURATION_US.to_string(),
+            ]);
+        }
+        args.push(output_file.to_string());
+        args
+    }
+}
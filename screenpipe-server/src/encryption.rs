@@ -0,0 +1,533 @@
+// Encryption at rest. The database is encrypted with SQLCipher, media chunks with AES-256-GCM
+// once ffmpeg has finalized them (the chunk being recorded stays in plaintext until then).
+// The key is derived from a passphrase (Argon2id) or kept in the OS keyring, and
+// `<data dir>/encryption.json` records which one, so nothing secret is stored next to the data.
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes256Gcm, Nonce};
+use anyhow::{anyhow, bail, Context as _};
+use argon2::Argon2;
+use log::{debug, error, info, warn};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use sqlx::{sqlite::SqliteConnectOptions, ConnectOptions, Connection};
+
+use crate::video::TIMESTAMPS_SUFFIX;
+
+pub const ENCRYPTION_CONFIG_FILE: &str = "encryption.json";
+pub const PASSPHRASE_ENV: &str = "SCREENPIPE_PASSPHRASE";
+
+// Encrypted files start with this, followed by the nonce and the ciphertext
+const MAGIC: &[u8] = b"SPENC1";
+const NONCE_LEN: usize = 12;
+const KEY_CHECK_PLAINTEXT: &[u8] = b"screenpipe";
+const KEYRING_SERVICE: &str = "screenpipe";
+const KEYRING_USER: &str = "data-key";
+const KEYRING_PENDING_USER: &str = "data-key-pending";
+// New key of a rotation that hasn't completed yet
+const PENDING_CONFIG_FILE: &str = "encryption.pending.json";
+
+static MEDIA_KEY: OnceLock<EncryptionKey> = OnceLock::new();
+
+#[derive(Clone)]
+pub struct EncryptionKey([u8; 32]);
+
+impl EncryptionKey {
+    pub fn generate() -> Self {
+        let mut key = [0u8; 32];
+        rand::thread_rng().fill_bytes(&mut key);
+        Self(key)
+    }
+
+    fn from_passphrase(passphrase: &str, salt: &[u8]) -> anyhow::Result<Self> {
+        let mut key = [0u8; 32];
+        Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt, &mut key)
+            .map_err(|e| anyhow!("Failed to derive key from passphrase: {}", e))?;
+        Ok(Self(key))
+    }
+
+    fn from_hex(hex_key: &str) -> anyhow::Result<Self> {
+        let key = hex::decode(hex_key.trim())?;
+        Ok(Self(
+            key.try_into()
+                .map_err(|_| anyhow!("Encryption key must be 32 bytes"))?,
+        ))
+    }
+
+    /// Value of `PRAGMA key` / `PRAGMA rekey`, a raw key so SQLCipher skips its own KDF.
+    pub fn sqlcipher_pragma(&self) -> String {
+        format!("\"x'{}'\"", hex::encode(self.0))
+    }
+
+    pub fn encrypt(&self, plaintext: &[u8]) -> anyhow::Result<Vec<u8>> {
+        let cipher = Aes256Gcm::new_from_slice(&self.0)?;
+        let mut nonce = [0u8; NONCE_LEN];
+        rand::thread_rng().fill_bytes(&mut nonce);
+        let ciphertext = cipher
+            .encrypt(Nonce::from_slice(&nonce), plaintext)
+            .map_err(|_| anyhow!("Failed to encrypt"))?;
+
+        let mut output = Vec::with_capacity(MAGIC.len() + NONCE_LEN + ciphertext.len());
+        output.extend_from_slice(MAGIC);
+        output.extend_from_slice(&nonce);
+        output.extend_from_slice(&ciphertext);
+        Ok(output)
+    }
+
+    pub fn decrypt(&self, data: &[u8]) -> anyhow::Result<Vec<u8>> {
+        if !is_encrypted(data) || data.len() < MAGIC.len() + NONCE_LEN {
+            bail!("Data is not encrypted");
+        }
+        let (nonce, ciphertext) = data[MAGIC.len()..].split_at(NONCE_LEN);
+        let cipher = Aes256Gcm::new_from_slice(&self.0)?;
+        cipher
+            .decrypt(Nonce::from_slice(nonce), ciphertext)
+            .map_err(|_| anyhow!("Failed to decrypt, wrong key or corrupted data"))
+    }
+}
+
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum KeySource {
+    Passphrase,
+    Keyring,
+}
+
+impl std::str::FromStr for KeySource {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "passphrase" => Ok(KeySource::Passphrase),
+            "keyring" => Ok(KeySource::Keyring),
+            _ => Err(format!(
+                "unknown key source {}, expected passphrase or keyring",
+                s
+            )),
+        }
+    }
+}
+
+/// `<data dir>/encryption.json`, present once encryption is enabled.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct EncryptionConfig {
+    pub key_source: KeySource,
+    /// Hex-encoded Argon2 salt, for passphrase keys
+    #[serde(default)]
+    pub salt: Option<String>,
+    /// Known plaintext encrypted with the key, to tell a wrong passphrase from corrupted data
+    pub key_check: String,
+}
+
+impl EncryptionConfig {
+    pub fn load(base_dir: &Path) -> anyhow::Result<Option<Self>> {
+        Self::load_file(&base_dir.join(ENCRYPTION_CONFIG_FILE))
+    }
+
+    fn load_file(path: &Path) -> anyhow::Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Ok(Some(
+            serde_json::from_str(&content)
+                .with_context(|| format!("Invalid {}", path.display()))?,
+        ))
+    }
+
+    fn save(&self, path: &Path) -> anyhow::Result<()> {
+        let tmp_path = path.with_extension("json.tmp");
+        std::fs::write(&tmp_path, serde_json::to_string_pretty(self)?)?;
+        std::fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+
+    /// Gets the key from the passphrase (`SCREENPIPE_PASSPHRASE` or prompted) or the keyring.
+    pub fn unlock(&self) -> anyhow::Result<EncryptionKey> {
+        let key = match self.key_source {
+            KeySource::Passphrase => {
+                let passphrase = match std::env::var(PASSPHRASE_ENV) {
+                    Ok(passphrase) => passphrase,
+                    Err(_) => rpassword::prompt_password("Passphrase: ")?,
+                };
+                self.derive_key(&passphrase)?
+            }
+            KeySource::Keyring => self.keyring_key(KEYRING_USER)?,
+        };
+        self.check_key(key)
+    }
+
+    fn unlock_pending(&self) -> anyhow::Result<EncryptionKey> {
+        let key = match self.key_source {
+            KeySource::Passphrase => {
+                self.derive_key(&rpassword::prompt_password("New passphrase: ")?)?
+            }
+            KeySource::Keyring => self.keyring_key(KEYRING_PENDING_USER)?,
+        };
+        self.check_key(key)
+    }
+
+    fn derive_key(&self, passphrase: &str) -> anyhow::Result<EncryptionKey> {
+        let salt = hex::decode(self.salt.as_deref().unwrap_or_default())?;
+        EncryptionKey::from_passphrase(passphrase, &salt)
+    }
+
+    fn keyring_key(&self, user: &str) -> anyhow::Result<EncryptionKey> {
+        EncryptionKey::from_hex(&keyring_entry(user)?.get_password()?)
+    }
+
+    fn check_key(&self, key: EncryptionKey) -> anyhow::Result<EncryptionKey> {
+        match key.decrypt(&hex::decode(&self.key_check)?) {
+            Ok(check) if check == KEY_CHECK_PLAINTEXT => Ok(key),
+            _ => bail!("Wrong passphrase or encryption key"),
+        }
+    }
+
+    /// Creates a new key from the given source, storing it in the keyring under `keyring_user`
+    /// if needed.
+    fn create(key_source: KeySource, keyring_user: &str) -> anyhow::Result<(Self, EncryptionKey)> {
+        let (key, salt) = match key_source {
+            KeySource::Passphrase => {
+                let passphrase = read_new_passphrase()?;
+                let mut salt = [0u8; 16];
+                rand::thread_rng().fill_bytes(&mut salt);
+                (
+                    EncryptionKey::from_passphrase(&passphrase, &salt)?,
+                    Some(hex::encode(salt)),
+                )
+            }
+            KeySource::Keyring => {
+                let key = EncryptionKey::generate();
+                keyring_entry(keyring_user)?.set_password(&hex::encode(key.0))?;
+                (key, None)
+            }
+        };
+
+        let config = Self {
+            key_source,
+            salt,
+            key_check: hex::encode(key.encrypt(KEY_CHECK_PLAINTEXT)?),
+        };
+        Ok((config, key))
+    }
+}
+
+fn keyring_entry(user: &str) -> anyhow::Result<keyring::Entry> {
+    Ok(keyring::Entry::new(KEYRING_SERVICE, user)?)
+}
+
+fn read_new_passphrase() -> anyhow::Result<String> {
+    let passphrase = rpassword::prompt_password("New passphrase: ")?;
+    if passphrase.is_empty() {
+        bail!("Passphrase can't be empty");
+    }
+    if rpassword::prompt_password("Confirm passphrase: ")? != passphrase {
+        bail!("Passphrases don't match");
+    }
+    Ok(passphrase)
+}
+
+/// Encrypts media chunks from now on and lets readers decrypt them.
+pub fn enable_media_encryption(key: EncryptionKey) {
+    if MEDIA_KEY.set(key).is_err() {
+        warn!("Media encryption was already enabled");
+    }
+}
+
+/// Encrypts a chunk in place once nothing writes to it anymore. Does nothing when media
+/// encryption is disabled.
+pub async fn encrypt_finalized_chunk(path: &str) {
+    let Some(key) = MEDIA_KEY.get() else {
+        return;
+    };
+    let path = PathBuf::from(path);
+    let result = tokio::task::spawn_blocking({
+        let path = path.clone();
+        move || encrypt_file(key, &path)
+    })
+    .await;
+
+    match result {
+        Ok(Ok(())) => debug!("Encrypted {}", path.display()),
+        Ok(Err(e)) => error!("Failed to encrypt {}: {}", path.display(), e),
+        Err(e) => error!("Failed to encrypt {}: {}", path.display(), e),
+    }
+}
+
+fn encrypt_file(key: &EncryptionKey, path: &Path) -> anyhow::Result<()> {
+    let data = std::fs::read(path)?;
+    if is_encrypted(&data) {
+        return Ok(());
+    }
+    replace_file(path, &key.encrypt(&data)?)
+}
+
+// Write next to the original and rename, so a crash never leaves a half written chunk
+fn replace_file(path: &Path, data: &[u8]) -> anyhow::Result<()> {
+    let mut tmp_path = path.as_os_str().to_owned();
+    tmp_path.push(".tmp");
+    let tmp_path = PathBuf::from(tmp_path);
+    std::fs::write(&tmp_path, data)?;
+    std::fs::rename(&tmp_path, path)?;
+    Ok(())
+}
+
+/// A media file ready to be read by ffmpeg: the chunk itself, or a decrypted copy in the temp
+/// directory that is removed when this is dropped.
+pub struct MediaFile {
+    path: PathBuf,
+    temporary: bool,
+}
+
+impl MediaFile {
+    pub fn path(&self) -> &str {
+        self.path.to_str().unwrap_or_default()
+    }
+}
+
+impl Drop for MediaFile {
+    fn drop(&mut self) {
+        if self.temporary {
+            if let Err(e) = std::fs::remove_file(&self.path) {
+                warn!("Failed to remove {}: {}", self.path.display(), e);
+            }
+        }
+    }
+}
+
+/// Reads a whole media file, decrypting it if needed.
+pub async fn read_media(path: &str) -> anyhow::Result<Vec<u8>> {
+    let data = tokio::fs::read(path).await?;
+    if !is_encrypted(&data) {
+        return Ok(data);
+    }
+    let key = MEDIA_KEY
+        .get()
+        .ok_or_else(|| anyhow!("{} is encrypted but no key was provided", path))?;
+    key.decrypt(&data)
+}
+
+pub async fn open_media(path: &str) -> anyhow::Result<MediaFile> {
+    let path = PathBuf::from(path);
+    tokio::task::spawn_blocking(move || {
+        let data = std::fs::read(&path)?;
+        if !is_encrypted(&data) {
+            return Ok(MediaFile {
+                path,
+                temporary: false,
+            });
+        }
+        let key = MEDIA_KEY
+            .get()
+            .ok_or_else(|| anyhow!("{} is encrypted but no key was provided", path.display()))?;
+
+        let extension = path
+            .extension()
+            .and_then(|ext| ext.to_str())
+            .unwrap_or("mp4");
+        let mut name = [0u8; 16];
+        rand::thread_rng().fill_bytes(&mut name);
+        let tmp_path =
+            std::env::temp_dir().join(format!("screenpipe-{}.{}", hex::encode(name), extension));
+        let mut file = create_private_file(&tmp_path)?;
+        let media = MediaFile {
+            path: tmp_path,
+            temporary: true,
+        };
+        file.write_all(&key.decrypt(&data)?)?;
+        Ok(media)
+    })
+    .await?
+}
+
+fn create_private_file(path: &Path) -> std::io::Result<std::fs::File> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(path)
+}
+
+/// Media chunks and the frame timestamps of the video chunks, encrypted alike.
+fn media_files(data_dir: &Path) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    if !data_dir.exists() {
+        return Ok(files);
+    }
+    for entry in std::fs::read_dir(data_dir)? {
+        let path = entry?.path();
+        let is_chunk = matches!(
+            path.extension().and_then(|ext| ext.to_str()),
+            Some("mp4" | "webm")
+        );
+        let is_timestamps = path
+            .file_name()
+            .is_some_and(|name| name.to_string_lossy().ends_with(TIMESTAMPS_SUFFIX));
+        if is_chunk || is_timestamps {
+            files.push(path);
+        }
+    }
+    Ok(files)
+}
+
+/// Turns on encryption for an existing data directory: creates the key, encrypts the
+/// database and the media chunks recorded so far, then writes `encryption.json`.
+/// Must run while screenpipe isn't recording.
+pub async fn enable_encryption(base_dir: &Path, key_source: KeySource) -> anyhow::Result<()> {
+    if EncryptionConfig::load(base_dir)?.is_some() {
+        bail!("Encryption is already enabled");
+    }
+    let (config, key) = EncryptionConfig::create(key_source, KEYRING_USER)?;
+
+    let db_path = base_dir.join("db.sqlite");
+    if db_path.exists() {
+        encrypt_database(&db_path, &key).await?;
+        info!("Encrypted {}", db_path.display());
+    }
+
+    let files = media_files(&base_dir.join("data"))?;
+    let count = files.len();
+    for path in files {
+        encrypt_file(&key, &path)
+            .with_context(|| format!("Failed to encrypt {}", path.display()))?;
+    }
+    info!("Encrypted {} media files", count);
+
+    config.save(&base_dir.join(ENCRYPTION_CONFIG_FILE))
+}
+
+async fn encrypt_database(db_path: &Path, key: &EncryptionKey) -> anyhow::Result<()> {
+    let encrypted_path = db_path.with_extension("sqlite.encrypted");
+    if encrypted_path.exists() {
+        std::fs::remove_file(&encrypted_path)?;
+    }
+
+    let mut conn = SqliteConnectOptions::new()
+        .filename(db_path)
+        .connect()
+        .await?;
+    sqlx::query(&format!(
+        "ATTACH DATABASE ?1 AS encrypted KEY {}",
+        key.sqlcipher_pragma()
+    ))
+    .bind(encrypted_path.to_string_lossy())
+    .execute(&mut conn)
+    .await?;
+    sqlx::query("SELECT sqlcipher_export('encrypted')")
+        .execute(&mut conn)
+        .await?;
+    sqlx::query("DETACH DATABASE encrypted")
+        .execute(&mut conn)
+        .await?;
+    conn.close().await?;
+
+    std::fs::rename(&encrypted_path, db_path)?;
+    for suffix in ["sqlite-wal", "sqlite-shm"] {
+        let path = db_path.with_extension(suffix);
+        if path.exists() {
+            std::fs::remove_file(path)?;
+        }
+    }
+    Ok(())
+}
+
+/// Replaces the key: re-encrypts the media chunks, rekeys the database and updates
+/// `encryption.json`. The new key is recorded before anything is touched, so an interrupted
+/// rotation is resumed by running it again. Must run while screenpipe isn't recording.
+pub async fn rotate_key(base_dir: &Path, key_source: Option<KeySource>) -> anyhow::Result<()> {
+    let Some(old_config) = EncryptionConfig::load(base_dir)? else {
+        bail!("Encryption isn't enabled");
+    };
+    let old_key = old_config.unlock()?;
+
+    let pending_path = base_dir.join(PENDING_CONFIG_FILE);
+    let (config, new_key) = match EncryptionConfig::load_file(&pending_path)? {
+        Some(config) => {
+            info!("Resuming interrupted key rotation");
+            let key = config.unlock_pending()?;
+            (config, key)
+        }
+        None => {
+            let source = key_source.unwrap_or(old_config.key_source);
+            let (config, key) = EncryptionConfig::create(source, KEYRING_PENDING_USER)?;
+            config.save(&pending_path)?;
+            (config, key)
+        }
+    };
+
+    let count = reencrypt_media(&base_dir.join("data"), &old_key, &new_key)?;
+    info!("Re-encrypted {} media files", count);
+
+    let db_path = base_dir.join("db.sqlite");
+    if open_encrypted_database(&db_path, &new_key).await.is_err() {
+        let mut conn = open_encrypted_database(&db_path, &old_key).await?;
+        sqlx::query(&format!("PRAGMA rekey = {}", new_key.sqlcipher_pragma()))
+            .execute(&mut conn)
+            .await?;
+        conn.close().await?;
+        info!("Rekeyed the database");
+    }
+
+    if config.key_source == KeySource::Keyring {
+        keyring_entry(KEYRING_USER)?.set_password(&hex::encode(new_key.0))?;
+        if let Err(e) = keyring_entry(KEYRING_PENDING_USER)?.delete_password() {
+            warn!("Failed to remove the pending key from the keyring: {}", e);
+        }
+    }
+    std::fs::rename(&pending_path, base_dir.join(ENCRYPTION_CONFIG_FILE))?;
+    Ok(())
+}
+
+/// Encrypts the media files of `data_dir` with `new_key`, decrypting them with `old_key` first if
+/// needed. Files already under `new_key` are left as they are. Returns the number of files.
+pub fn reencrypt_media(
+    data_dir: &Path,
+    old_key: &EncryptionKey,
+    new_key: &EncryptionKey,
+) -> anyhow::Result<usize> {
+    let files = media_files(data_dir)?;
+    let count = files.len();
+    for path in files {
+        let data = std::fs::read(&path)?;
+        let plaintext = if !is_encrypted(&data) {
+            data
+        } else if let Ok(plaintext) = old_key.decrypt(&data) {
+            plaintext
+        } else if new_key.decrypt(&data).is_ok() {
+            // Rotated before the interruption
+            continue;
+        } else {
+            bail!("Failed to decrypt {} with either key", path.display());
+        };
+        replace_file(&path, &new_key.encrypt(&plaintext)?)?;
+    }
+    Ok(count)
+}
+
+async fn open_encrypted_database(
+    db_path: &Path,
+    key: &EncryptionKey,
+) -> anyhow::Result<sqlx::SqliteConnection> {
+    let mut conn = SqliteConnectOptions::new()
+        .filename(db_path)
+        .pragma("key", key.sqlcipher_pragma())
+        .connect()
+        .await?;
+    // SQLCipher only checks the key on first access
+    sqlx::query("SELECT count(*) FROM sqlite_master")
+        .execute(&mut conn)
+        .await?;
+    Ok(conn)
+}
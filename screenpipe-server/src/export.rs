@@ -0,0 +1,548 @@
+// Export of search results as JSONL, CSV or a Markdown timeline, optionally bundled in a zip
+// with frame thumbnails and audio chunks.
+use std::collections::{HashSet, VecDeque};
+use std::io::{Seek, SeekFrom, Write};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use axum::{
+    body::Body,
+    extract::{Extension, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json as JsonResponse, Response},
+};
+use chrono::{DateTime, NaiveDate, Utc};
+use futures::{Stream, StreamExt};
+use image::{DynamicImage, ImageFormat};
+use log::{error, info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use tokio_util::io::ReaderStream;
+use zip::{write::FileOptions, CompressionMethod, ZipWriter};
+
+use crate::auth::GrantedScopes;
+use crate::db::{AudioResult, OCRResult, Scope, SortOrder};
+use crate::server::{into_content_item, AppState};
+use crate::video::extract_frame;
+use crate::{ContentType, DatabaseManager, SearchResult};
+
+const PAGE_SIZE: u32 = 500;
+const THUMBNAIL_SIZE: u32 = 480;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    #[default]
+    Jsonl,
+    Csv,
+    Markdown,
+}
+
+impl ExportFormat {
+    fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "jsonl",
+            ExportFormat::Csv => "csv",
+            ExportFormat::Markdown => "md",
+        }
+    }
+
+    fn mime_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Jsonl => "application/x-ndjson",
+            ExportFormat::Csv => "text/csv; charset=utf-8",
+            ExportFormat::Markdown => "text/markdown; charset=utf-8",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "jsonl" => Ok(ExportFormat::Jsonl),
+            "csv" => Ok(ExportFormat::Csv),
+            "markdown" | "md" => Ok(ExportFormat::Markdown),
+            _ => Err(format!(
+                "unknown format {}, expected jsonl, csv or markdown",
+                s
+            )),
+        }
+    }
+}
+
+/// Same filters as `/search`, but results come oldest first and `limit` defaults to everything.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ExportOptions {
+    #[serde(default)]
+    pub q: Option<String>,
+    #[serde(default)]
+    pub content_type: ContentType,
+    #[serde(default)]
+    pub start_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub end_time: Option<DateTime<Utc>>,
+    #[serde(default)]
+    pub limit: Option<u32>,
+    #[serde(default)]
+    pub offset: u32,
+    #[serde(default)]
+    pub format: ExportFormat,
+    /// Bundle the export in a zip archive
+    #[serde(default)]
+    pub zip: bool,
+    /// Add a thumbnail of every frame to the zip
+    #[serde(default)]
+    pub thumbnails: bool,
+    /// Add the audio chunks to the zip
+    #[serde(default)]
+    pub audio: bool,
+}
+
+impl ExportOptions {
+    pub fn validate(&self) -> Result<(), String> {
+        if (self.thumbnails || self.audio) && !self.zip {
+            return Err("thumbnails and audio can only be exported in a zip".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn file_name(&self) -> String {
+        if self.zip {
+            "screenpipe-export.zip".to_string()
+        } else {
+            format!("screenpipe-export.{}", self.format.extension())
+        }
+    }
+}
+
+/// Pages through OCR and audio results in parallel and merges them chronologically.
+struct ExportCursor {
+    db: Arc<DatabaseManager>,
+    options: ExportOptions,
+    ocr: VecDeque<OCRResult>,
+    ocr_offset: Option<u32>,
+    audio: VecDeque<AudioResult>,
+    audio_offset: Option<u32>,
+    skipped: u32,
+    returned: u32,
+}
+
+impl ExportCursor {
+    fn new(db: Arc<DatabaseManager>, options: ExportOptions) -> Self {
+        let with_ocr = matches!(options.content_type, ContentType::All | ContentType::OCR);
+        let with_audio = matches!(options.content_type, ContentType::All | ContentType::Audio);
+        Self {
+            db,
+            options,
+            ocr: VecDeque::new(),
+            // `None` once there is nothing left to fetch
+            ocr_offset: with_ocr.then_some(0),
+            audio: VecDeque::new(),
+            audio_offset: with_audio.then_some(0),
+            skipped: 0,
+            returned: 0,
+        }
+    }
+
+    async fn next_result(&mut self) -> Result<Option<SearchResult>, sqlx::Error> {
+        loop {
+            if self
+                .options
+                .limit
+                .is_some_and(|limit| self.returned >= limit)
+            {
+                return Ok(None);
+            }
+            let Some(result) = self.next_merged().await? else {
+                return Ok(None);
+            };
+            if self.skipped < self.options.offset {
+                self.skipped += 1;
+                continue;
+            }
+            self.returned += 1;
+            return Ok(Some(result));
+        }
+    }
+
+    async fn next_merged(&mut self) -> Result<Option<SearchResult>, sqlx::Error> {
+        let query = self.options.q.as_deref().unwrap_or("");
+        if self.ocr.is_empty() {
+            if let Some(offset) = self.ocr_offset {
+                let page = self
+                    .db
+                    .search_ocr(
+                        query,
+                        PAGE_SIZE,
+                        offset,
+                        self.options.start_time,
+                        self.options.end_time,
+                        SortOrder::Ascending,
+                    )
+                    .await?;
+                self.ocr_offset = (page.len() as u32 == PAGE_SIZE).then_some(offset + PAGE_SIZE);
+                self.ocr.extend(page);
+            }
+        }
+        if self.audio.is_empty() {
+            if let Some(offset) = self.audio_offset {
+                let page = self
+                    .db
+                    .search_audio(
+                        query,
+                        PAGE_SIZE,
+                        offset,
+                        self.options.start_time,
+                        self.options.end_time,
+                        SortOrder::Ascending,
+                    )
+                    .await?;
+                self.audio_offset = (page.len() as u32 == PAGE_SIZE).then_some(offset + PAGE_SIZE);
+                self.audio.extend(page);
+            }
+        }
+
+        let take_ocr = match (self.ocr.front(), self.audio.front()) {
+            (Some(ocr), Some(audio)) => ocr.timestamp <= audio.timestamp,
+            (Some(_), None) => true,
+            (None, Some(_)) => false,
+            (None, None) => return Ok(None),
+        };
+        Ok(if take_ocr {
+            self.ocr.pop_front().map(SearchResult::OCR)
+        } else {
+            self.audio.pop_front().map(SearchResult::Audio)
+        })
+    }
+}
+
+struct Formatter {
+    format: ExportFormat,
+    // Markdown links to the files bundled in the zip
+    link_thumbnails: bool,
+    link_audio: bool,
+    current_day: Option<NaiveDate>,
+}
+
+impl Formatter {
+    fn new(options: &ExportOptions) -> Self {
+        Self {
+            format: options.format,
+            link_thumbnails: options.thumbnails,
+            link_audio: options.audio,
+            current_day: None,
+        }
+    }
+
+    fn header(&self, options: &ExportOptions) -> String {
+        match self.format {
+            ExportFormat::Jsonl => String::new(),
+            ExportFormat::Csv => "type,id,timestamp,text,file_path,offset_index\n".to_string(),
+            ExportFormat::Markdown => {
+                let mut header = "# Screenpipe export\n\n".to_string();
+                if let Some(q) = options.q.as_deref().filter(|q| !q.is_empty()) {
+                    header.push_str(&format!("Search: `{}`\n\n", q));
+                }
+                if options.start_time.is_some() || options.end_time.is_some() {
+                    let format_time = |time: Option<DateTime<Utc>>| {
+                        time.map_or_else(|| "…".to_string(), |t| t.to_rfc3339())
+                    };
+                    header.push_str(&format!(
+                        "From {} to {}\n\n",
+                        format_time(options.start_time),
+                        format_time(options.end_time)
+                    ));
+                }
+                header
+            }
+        }
+    }
+
+    fn item(&mut self, result: SearchResult) -> String {
+        match self.format {
+            ExportFormat::Jsonl => {
+                let mut line =
+                    serde_json::to_string(&into_content_item(result)).unwrap_or_default();
+                line.push('\n');
+                line
+            }
+            ExportFormat::Csv => {
+                let (kind, id, timestamp, text, file_path, offset_index) = match &result {
+                    SearchResult::OCR(ocr) => (
+                        "ocr",
+                        ocr.frame_id,
+                        ocr.timestamp,
+                        &ocr.ocr_text,
+                        &ocr.file_path,
+                        ocr.offset_index,
+                    ),
+                    SearchResult::Audio(audio) => (
+                        "audio",
+                        audio.audio_chunk_id,
+                        audio.timestamp,
+                        &audio.transcription,
+                        &audio.file_path,
+                        audio.offset_index,
+                    ),
+                };
+                format!(
+                    "{},{},{},{},{},{}\n",
+                    kind,
+                    id,
+                    timestamp.to_rfc3339(),
+                    csv_field(text),
+                    csv_field(file_path),
+                    offset_index
+                )
+            }
+            ExportFormat::Markdown => {
+                let timestamp = match &result {
+                    SearchResult::OCR(ocr) => ocr.timestamp,
+                    SearchResult::Audio(audio) => audio.timestamp,
+                };
+                let mut output = String::new();
+                let day = timestamp.date_naive();
+                if self.current_day != Some(day) {
+                    self.current_day = Some(day);
+                    output.push_str(&format!("## {}\n\n", day));
+                }
+
+                let time = timestamp.format("%H:%M:%S");
+                match &result {
+                    SearchResult::OCR(ocr) => {
+                        output.push_str(&format!(
+                            "- **{}** screen: {}\n",
+                            time,
+                            single_line(&ocr.ocr_text)
+                        ));
+                        if self.link_thumbnails {
+                            output.push_str(&format!(
+                                "\n  ![frame {}]({})\n\n",
+                                ocr.frame_id,
+                                thumbnail_path(ocr.frame_id)
+                            ));
+                        }
+                    }
+                    SearchResult::Audio(audio) => {
+                        output.push_str(&format!(
+                            "- **{}** audio: {}",
+                            time,
+                            single_line(&audio.transcription)
+                        ));
+                        if self.link_audio {
+                            output.push_str(&format!(
+                                " ([listen]({}))",
+                                audio_path(audio.audio_chunk_id, &audio.file_path)
+                            ));
+                        }
+                        output.push('\n');
+                    }
+                }
+                output
+            }
+        }
+    }
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn single_line(text: &str) -> String {
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+fn thumbnail_path(frame_id: i64) -> String {
+    format!("frames/{}.jpg", frame_id)
+}
+
+fn audio_path(audio_chunk_id: i64, file_path: &str) -> String {
+    let extension = std::path::Path::new(file_path)
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .unwrap_or("mp4");
+    format!("audio/{}.{}", audio_chunk_id, extension)
+}
+
+fn results(
+    db: Arc<DatabaseManager>,
+    options: ExportOptions,
+) -> impl Stream<Item = Result<SearchResult, sqlx::Error>> + Send + 'static {
+    futures::stream::try_unfold(ExportCursor::new(db, options), |mut cursor| async move {
+        Ok(cursor.next_result().await?.map(|result| (result, cursor)))
+    })
+}
+
+/// Formatted export, streamed page by page.
+pub fn export_stream(
+    db: Arc<DatabaseManager>,
+    options: ExportOptions,
+) -> impl Stream<Item = Result<String, anyhow::Error>> + Send + 'static {
+    let mut formatter = Formatter::new(&options);
+    let header = formatter.header(&options);
+    futures::stream::once(async move { Ok(header) }).chain(results(db, options).map(
+        move |result| {
+            result
+                .map(|result| formatter.item(result))
+                .map_err(anyhow::Error::from)
+        },
+    ))
+}
+
+/// Writes the export, and the thumbnails and audio chunks it asks for, as a zip archive.
+pub async fn export_zip<W: Write + Seek>(
+    db: Arc<DatabaseManager>,
+    options: ExportOptions,
+    writer: W,
+) -> anyhow::Result<W> {
+    let mut zip = ZipWriter::new(writer);
+    let deflated = FileOptions::default().compression_method(CompressionMethod::Deflated);
+    // Media is already compressed
+    let stored = FileOptions::default().compression_method(CompressionMethod::Stored);
+
+    let mut formatter = Formatter::new(&options);
+    let mut frames = Vec::new();
+    let mut audio_chunks = Vec::new();
+    let mut seen_audio_chunks = HashSet::new();
+
+    zip.start_file(format!("export.{}", options.format.extension()), deflated)?;
+    zip.write_all(formatter.header(&options).as_bytes())?;
+    let mut stream = Box::pin(results(Arc::clone(&db), options.clone()));
+    while let Some(result) = stream.next().await {
+        let result = result?;
+        match &result {
+            SearchResult::OCR(ocr) if options.thumbnails => {
+                frames.push((ocr.frame_id, ocr.file_path.clone(), ocr.offset_index));
+            }
+            SearchResult::Audio(audio)
+                if options.audio && seen_audio_chunks.insert(audio.audio_chunk_id) =>
+            {
+                audio_chunks.push((audio.audio_chunk_id, audio.file_path.clone()));
+            }
+            _ => {}
+        }
+        zip.write_all(formatter.item(result).as_bytes())?;
+    }
+
+    for (frame_id, file_path, offset_index) in frames {
+        match thumbnail(&file_path, offset_index).await {
+            Ok(jpeg) => {
+                zip.start_file(thumbnail_path(frame_id), stored)?;
+                zip.write_all(&jpeg)?;
+            }
+            Err(e) => warn!("Skipping thumbnail of frame {}: {}", frame_id, e),
+        }
+    }
+
+    for (audio_chunk_id, file_path) in audio_chunks {
+        #[cfg(feature = "encryption")]
+        let data = crate::encryption::read_media(&file_path).await;
+        #[cfg(not(feature = "encryption"))]
+        let data = tokio::fs::read(&file_path)
+            .await
+            .map_err(anyhow::Error::from);
+        match data {
+            Ok(data) => {
+                zip.start_file(audio_path(audio_chunk_id, &file_path), stored)?;
+                zip.write_all(&data)?;
+            }
+            Err(e) => warn!("Skipping audio chunk {}: {}", audio_chunk_id, e),
+        }
+    }
+
+    Ok(zip.finish()?)
+}
+
+async fn thumbnail(file_path: &str, offset_index: i64) -> anyhow::Result<Vec<u8>> {
+    let png = extract_frame(file_path, offset_index).await?;
+    tokio::task::spawn_blocking(move || {
+        let image = image::load_from_memory_with_format(&png, ImageFormat::Png)?;
+        let image = if image.width() > THUMBNAIL_SIZE || image.height() > THUMBNAIL_SIZE {
+            image.thumbnail(THUMBNAIL_SIZE, THUMBNAIL_SIZE)
+        } else {
+            image
+        };
+        let mut jpeg = Vec::new();
+        // JPEG has no alpha channel
+        DynamicImage::ImageRgb8(image.to_rgb8())
+            .write_to(&mut std::io::Cursor::new(&mut jpeg), ImageFormat::Jpeg)?;
+        Ok(jpeg)
+    })
+    .await?
+}
+
+pub(crate) async fn export(
+    State(state): State<Arc<AppState>>,
+    scopes: Option<Extension<GrantedScopes>>,
+    Query(options): Query<ExportOptions>,
+) -> Response {
+    if let Err(message) = options.validate() {
+        return (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(json!({ "error": message })),
+        )
+            .into_response();
+    }
+    let with_media = options.thumbnails || options.audio;
+    if with_media && !scopes.map_or(true, |Extension(scopes)| scopes.allows(Scope::ReadMedia)) {
+        return (
+            StatusCode::FORBIDDEN,
+            JsonResponse(json!({ "error": "Token lacks the read-media scope" })),
+        )
+            .into_response();
+    }
+
+    info!("Exporting {:?}", options);
+    let disposition = format!("attachment; filename=\"{}\"", options.file_name());
+
+    if !options.zip {
+        let content_type = options.format.mime_type();
+        let body = Body::from_stream(export_stream(Arc::clone(&state.db), options));
+        return (
+            [
+                (header::CONTENT_TYPE, content_type.to_string()),
+                (header::CONTENT_DISPOSITION, disposition),
+            ],
+            body,
+        )
+            .into_response();
+    }
+
+    // A zip can't be streamed while it's written, build it in an anonymous temporary file
+    let file = match tempfile::tempfile() {
+        Ok(file) => file,
+        Err(e) => return export_error(e.into()),
+    };
+    let mut file = match export_zip(Arc::clone(&state.db), options, file).await {
+        Ok(file) => file,
+        Err(e) => return export_error(e),
+    };
+    if let Err(e) = file.seek(SeekFrom::Start(0)) {
+        return export_error(e.into());
+    }
+
+    let body = Body::from_stream(ReaderStream::new(tokio::fs::File::from_std(file)));
+    (
+        [
+            (header::CONTENT_TYPE, "application/zip".to_string()),
+            (header::CONTENT_DISPOSITION, disposition),
+        ],
+        body,
+    )
+        .into_response()
+}
+
+fn export_error(e: anyhow::Error) -> Response {
+    error!("Failed to export: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        JsonResponse(json!({ "error": format!("Failed to export: {}", e) })),
+    )
+        .into_response()
+}
@@ -0,0 +1,294 @@
+// HLS playlist over the recorded chunks, so a player scrubs through a day as one video. Each chunk
+// is a segment of its own, served from the fragmented MP4 it was recorded as or remuxed to one,
+// and tagged with the capture time of its first frame to map playback time back to wall-clock
+// time.
+use std::collections::VecDeque;
+use std::process::Stdio;
+use std::sync::{Arc, Mutex};
+
+use anyhow::anyhow;
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json as JsonResponse, Response},
+};
+use chrono::{DateTime, SecondsFormat, Utc};
+use log::error;
+use screenpipe_core::find_ffmpeg_path;
+use serde::Deserialize;
+use serde_json::json;
+use tokio::process::Command;
+
+use crate::db::{VideoChunk, VideoSegment};
+use crate::server::AppState;
+
+/// Duration of a segment with a single frame, the interval it was captured at is unknown.
+const SINGLE_FRAME_SECONDS: f64 = 1.0;
+
+/// Chunks kept split into segments, a player asks for the init section and the fragments of a
+/// chunk one after the other.
+const SEGMENT_CACHE_SIZE: usize = 4;
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct PlaylistQuery {
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+}
+
+/// Playback duration of a chunk. Every frame in the database is a frame of the video, at the
+/// frame rate it was encoded at, whenever it was captured. Chunks recorded before the frame rate
+/// was stored are estimated from the span of their frames plus the interval after the last one.
+pub(crate) fn segment_duration(segment: &VideoSegment) -> f64 {
+    if let Some(fps) = segment.fps.filter(|fps| *fps > 0.0) {
+        return segment.frames as f64 / fps;
+    }
+    if segment.frames < 2 {
+        return SINGLE_FRAME_SECONDS;
+    }
+    let span = (segment.end_time - segment.start_time).num_milliseconds() as f64 / 1000.0;
+    span * segment.frames as f64 / (segment.frames - 1) as f64
+}
+
+/// Renders an HLS media playlist with one fragmented MP4 segment per chunk. Chunks start over
+/// at timestamp zero and may leave gaps, so every segment follows a discontinuity.
+pub fn render_playlist(segments: &[VideoSegment]) -> String {
+    let target_duration = segments
+        .iter()
+        .map(segment_duration)
+        .fold(SINGLE_FRAME_SECONDS, f64::max)
+        .ceil() as u64;
+
+    let mut playlist = format!(
+        "#EXTM3U\n#EXT-X-VERSION:7\n#EXT-X-TARGETDURATION:{}\n#EXT-X-MEDIA-SEQUENCE:0\n#EXT-X-PLAYLIST-TYPE:VOD\n#EXT-X-INDEPENDENT-SEGMENTS\n",
+        target_duration
+    );
+    for (index, segment) in segments.iter().enumerate() {
+        if index > 0 {
+            playlist.push_str("#EXT-X-DISCONTINUITY\n");
+        }
+        playlist.push_str(&format!(
+            "#EXT-X-MAP:URI=\"segments/{id}/init.mp4\"\n#EXT-X-PROGRAM-DATE-TIME:{start}\n#EXTINF:{duration:.3},\nsegments/{id}/media.m4s\n",
+            id = segment.video_chunk_id,
+            start = segment
+                .start_time
+                .to_rfc3339_opts(SecondsFormat::Millis, true),
+            duration = segment_duration(segment),
+        ));
+    }
+    playlist.push_str("#EXT-X-ENDLIST\n");
+    playlist
+}
+
+/// Length of the init section (`ftyp` and `moov`) of a fragmented MP4, the offset of its first
+/// `moof` box. `None` if the data holds no fragment.
+pub fn split_init_segment(data: &[u8]) -> Option<usize> {
+    let mut offset = 0;
+    while offset + 8 <= data.len() {
+        let size = u32::from_be_bytes(data[offset..offset + 4].try_into().ok()?) as u64;
+        let kind = &data[offset + 4..offset + 8];
+        if kind == b"moof" {
+            return Some(offset);
+        }
+        let size = match size {
+            // Box extends to the end of the file
+            0 => return None,
+            // 64-bit size right after the type
+            1 => u64::from_be_bytes(data.get(offset + 8..offset + 16)?.try_into().ok()?),
+            size => size,
+        };
+        if size < 8 {
+            return None;
+        }
+        offset = offset.checked_add(usize::try_from(size).ok()?)?;
+    }
+    None
+}
+
+/// A chunk as fragmented MP4, split into its init section and its fragments.
+#[derive(Clone)]
+struct SegmentParts {
+    init: Bytes,
+    fragments: Bytes,
+}
+
+/// The last chunks served, by chunk id, path and compaction tier. A compacted chunk keeps its
+/// path but not its tier.
+#[derive(Default)]
+pub(crate) struct SegmentCache {
+    entries: Mutex<VecDeque<((i64, String, i64), SegmentParts)>>,
+}
+
+impl SegmentCache {
+    fn key(chunk: &VideoChunk) -> (i64, String, i64) {
+        (chunk.id, chunk.file_path.clone(), chunk.tier)
+    }
+
+    fn get(&self, chunk: &VideoChunk) -> Option<SegmentParts> {
+        let key = Self::key(chunk);
+        self.entries
+            .lock()
+            .unwrap()
+            .iter()
+            .find(|(entry, _)| *entry == key)
+            .map(|(_, parts)| parts.clone())
+    }
+
+    fn insert(&self, chunk: &VideoChunk, parts: SegmentParts) {
+        let mut entries = self.entries.lock().unwrap();
+        entries.retain(|((id, _, _), _)| *id != chunk.id);
+        if entries.len() >= SEGMENT_CACHE_SIZE {
+            entries.pop_front();
+        }
+        entries.push_back((Self::key(chunk), parts));
+    }
+}
+
+/// Splits a chunk into segment parts. Chunks recorded as fragmented MP4 are served as they are,
+/// WebM chunks and MP4 ones written before recordings were fragmented are remuxed.
+async fn fragment_chunk(file_path: &str) -> anyhow::Result<SegmentParts> {
+    #[cfg(feature = "encryption")]
+    let media = crate::encryption::open_media(file_path).await?;
+    #[cfg(feature = "encryption")]
+    let input = media.path();
+    #[cfg(not(feature = "encryption"))]
+    let input = file_path;
+
+    let mut data = if file_path.ends_with(".mp4") {
+        tokio::fs::read(input).await?
+    } else {
+        Vec::new()
+    };
+    if split_init_segment(&data).is_none() {
+        data = remux_chunk(input, file_path).await?;
+    }
+
+    let init_len =
+        split_init_segment(&data).ok_or_else(|| anyhow!("{} has no video fragment", file_path))?;
+    let fragments = data.split_off(init_len);
+    Ok(SegmentParts {
+        init: data.into(),
+        fragments: fragments.into(),
+    })
+}
+
+/// Remuxes `input`, the media of the chunk at `file_path`, to fragmented MP4.
+async fn remux_chunk(input: &str, file_path: &str) -> anyhow::Result<Vec<u8>> {
+    let ffmpeg_path = find_ffmpeg_path().ok_or_else(|| anyhow!("ffmpeg not found"))?;
+    let output = Command::new(ffmpeg_path)
+        .args([
+            "-i",
+            input,
+            "-c",
+            "copy",
+            "-f",
+            "mp4",
+            "-movflags",
+            "+frag_keyframe+empty_moov+default_base_moof",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+    if !output.status.success() {
+        return Err(anyhow!(
+            "Failed to remux {}: {}",
+            file_path,
+            String::from_utf8_lossy(&output.stderr)
+        ));
+    }
+    Ok(output.stdout)
+}
+
+pub(crate) async fn get_playlist(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<PlaylistQuery>,
+) -> Response {
+    if query.end_time <= query.start_time {
+        return (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(json!({ "error": "end_time must be after start_time" })),
+        )
+            .into_response();
+    }
+
+    match state
+        .db
+        .get_video_segments(query.start_time, query.end_time)
+        .await
+    {
+        Ok(segments) => (
+            [(header::CONTENT_TYPE, "application/vnd.apple.mpegurl")],
+            render_playlist(&segments),
+        )
+            .into_response(),
+        Err(e) => {
+            error!("Failed to list video segments: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({ "error": format!("Failed to list video segments: {}", e) })),
+            )
+                .into_response()
+        }
+    }
+}
+
+pub(crate) async fn get_segment_init(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    segment_response(&state, id, "video/mp4", |parts| parts.init).await
+}
+
+pub(crate) async fn get_segment_media(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    segment_response(&state, id, "video/iso.segment", |parts| parts.fragments).await
+}
+
+async fn segment_response(
+    state: &AppState,
+    id: i64,
+    content_type: &'static str,
+    part: fn(SegmentParts) -> Bytes,
+) -> Response {
+    let chunk = match state.db.get_video_chunk(id).await {
+        Ok(Some(chunk)) => chunk,
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                JsonResponse(json!({ "error": "Video chunk not found" })),
+            )
+                .into_response()
+        }
+        Err(e) => {
+            error!("Failed to get video chunk {}: {}", id, e);
+            return (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({ "error": format!("Failed to get video chunk: {}", e) })),
+            )
+                .into_response();
+        }
+    };
+
+    if let Some(parts) = state.segments.get(&chunk) {
+        return ([(header::CONTENT_TYPE, content_type)], part(parts)).into_response();
+    }
+    match fragment_chunk(&chunk.file_path).await {
+        Ok(parts) => {
+            state.segments.insert(&chunk, parts.clone());
+            ([(header::CONTENT_TYPE, content_type)], part(parts)).into_response()
+        }
+        Err(e) => {
+            error!("Failed to serve segment {}: {}", id, e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({ "error": format!("Failed to serve segment: {}", e) })),
+            )
+                .into_response()
+        }
+    }
+}
@@ -1,14 +1,71 @@
 pub mod core;
+mod auth;
+mod backup;
+mod clip;
+mod compaction;
 mod db;
+mod encoder;
+mod export;
+mod hls;
+#[cfg(feature = "encryption")]
+mod encryption;
+mod mcp;
+mod metrics;
+mod pipes;
 mod plugin;
+mod queue;
 mod resource_monitor;
 mod server;
+mod stats;
+mod subtitles;
+mod timeline;
 mod video;
+#[cfg(feature = "wasm-pipes")]
+mod wasm_pipes;
+mod webhooks;
+mod words;
 pub mod logs;
 
+pub use auth::{generate_token, hash_token, required_scope, AuthPlugin, GrantedScopes};
+pub use backup::{
+    create_backup, import_backup, verify_backup, BackupFile, BackupManifest, MANIFEST_FILE,
+};
+pub use clip::{
+    clip_offset, concat_script, export_clip, plan_clip, video_filter, ClipChunk, ClipOptions,
+    ClipPiece,
+};
+pub use compaction::{CompactionStats, CompactionTier, Compactor};
 pub use core::{start_continuous_recording, RecorderControl};
-pub use db::{ContentType, DatabaseManager, SearchResult};
+pub use pipes::{
+    Pipe, PipeContext, PipeEvent, PipeFrame, PipeManager, PipeTranscription, DEFAULT_QUEUE_CAPACITY,
+};
+pub use plugin::{ApiPlugin, FnPlugin, PluginDecision, PluginRegistry, RequestInfo};
+pub use queue::{BoundedQueue, DropPolicy};
+pub use db::{
+    ApiToken, AudioChunk, AudioSegment, ChunkStatus, ContentType, DailyStats, DatabaseManager,
+    ImportStats, NewWebhookRule, OcrWord, Scope, SearchResult, TimelineBucket, VideoChunk, VideoSegment,
+    WebhookRule,
+};
+#[cfg(feature = "encryption")]
+pub use encryption::{
+    enable_encryption, enable_media_encryption, is_encrypted, reencrypt_media, rotate_key,
+    EncryptionConfig, EncryptionKey, KeySource, ENCRYPTION_CONFIG_FILE,
+};
+pub use encoder::{VideoCodec, VideoEncoderConfig, VideoScale};
+pub use hls::{render_playlist, split_init_segment};
+pub use export::{export_stream, export_zip, ExportFormat, ExportOptions};
 pub use resource_monitor::ResourceMonitor;
 pub use server::Server;
-pub use video::VideoCapture;
+pub use metrics::render_metrics;
+pub use stats::{collect_stats, format_stats, DiskUsage, UsageStats};
+pub use subtitles::{chunk_cues, render_subtitles, stitch_cues, Cue, SubtitleFormat};
+pub use timeline::BucketSize;
+pub use video::{
+    extract_frame, read_frame_timestamps, timestamps_path, ChunkFinishedCallback, EncodedFrame,
+    NewChunkCallback, VideoCapture, TIMESTAMPS_SUFFIX,
+};
+pub use webhooks::{sign_payload, NewContent, WebhookEngine, SIGNATURE_HEADER};
+pub use words::{matching_words, ocr_layout, ocr_words};
+#[cfg(feature = "wasm-pipes")]
+pub use wasm_pipes::{load_wasm_pipes, wasm_engine, WasmPipe, WasmPipeConfig, WasmPipesConfig};
 pub use logs::MultiWriter;
@@ -0,0 +1,308 @@
+// Model Context Protocol (https://modelcontextprotocol.io) endpoint so local AI assistants
+// can query screen and audio history as tools. JSON-RPC 2.0 over HTTP POST on /mcp.
+use std::sync::{atomic::Ordering, Arc};
+
+use axum::{
+    extract::{Extension, Json as JsonExt, State},
+    http::StatusCode,
+    response::{IntoResponse, Json as JsonResponse, Response},
+};
+use base64::{engine::general_purpose::STANDARD, Engine};
+use chrono::{Duration, Utc};
+use log::{debug, error, info};
+use schemars::{gen::SchemaSettings, JsonSchema};
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::auth::GrantedScopes;
+use crate::db::Scope;
+use crate::server::{into_content_item, AppState, SearchQuery};
+use crate::video::extract_frame;
+
+const PROTOCOL_VERSION: &str = "2024-11-05";
+
+// JSON-RPC error codes
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+#[derive(Deserialize)]
+pub(crate) struct JsonRpcRequest {
+    #[serde(default)]
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Option<Value>,
+}
+
+#[derive(Deserialize)]
+struct ToolCallParams {
+    name: String,
+    #[serde(default)]
+    arguments: Option<Value>,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct GetFrameArgs {
+    /// Id of the frame, as returned by the search tool
+    frame_id: i64,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct RecentActivityArgs {
+    /// How far back to look, in minutes
+    #[serde(default = "default_recent_minutes")]
+    minutes: u32,
+    /// Maximum number of results to return
+    #[serde(default = "default_recent_limit")]
+    limit: u32,
+}
+
+#[derive(Deserialize, JsonSchema)]
+struct NoArgs {}
+
+fn default_recent_minutes() -> u32 {
+    5
+}
+
+fn default_recent_limit() -> u32 {
+    20
+}
+
+type ToolResult = Result<Value, String>;
+
+pub(crate) async fn handle_mcp(
+    State(state): State<Arc<AppState>>,
+    scopes: Option<Extension<GrantedScopes>>,
+    JsonExt(request): JsonExt<JsonRpcRequest>,
+) -> Response {
+    // No auth plugin in front of the server, every tool is allowed
+    let scopes = scopes.map_or_else(GrantedScopes::all, |Extension(scopes)| scopes);
+
+    // Notifications (e.g. notifications/initialized) carry no id and expect no response
+    let Some(id) = request.id else {
+        debug!("Received MCP notification: {}", request.method);
+        return StatusCode::ACCEPTED.into_response();
+    };
+
+    info!("Received MCP request: {}", request.method);
+    let result = match request.method.as_str() {
+        "initialize" => Ok(json!({
+            "protocolVersion": PROTOCOL_VERSION,
+            "capabilities": { "tools": {} },
+            "serverInfo": {
+                "name": "screenpipe",
+                "version": env!("CARGO_PKG_VERSION"),
+            },
+        })),
+        "ping" => Ok(json!({})),
+        "tools/list" => Ok(json!({ "tools": list_tools() })),
+        "tools/call" => match request
+            .params
+            .map(serde_json::from_value::<ToolCallParams>)
+        {
+            Some(Ok(params)) => call_tool(&state, &scopes, params).await,
+            Some(Err(e)) => Err((INVALID_PARAMS, format!("Invalid tool call: {}", e))),
+            None => Err((INVALID_PARAMS, "Missing tool call params".to_string())),
+        },
+        method => Err((METHOD_NOT_FOUND, format!("Method not found: {}", method))),
+    };
+
+    let body = match result {
+        Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+        Err((code, message)) => json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "error": { "code": code, "message": message },
+        }),
+    };
+    JsonResponse(body).into_response()
+}
+
+fn list_tools() -> Vec<Value> {
+    vec![
+        tool::<SearchQuery>(
+            "search",
+            "Search OCR text captured from the screen and audio transcriptions",
+        ),
+        tool::<GetFrameArgs>(
+            "get_frame",
+            "Get the screenshot of a frame returned by search, as a PNG image",
+        ),
+        tool::<RecentActivityArgs>(
+            "recent_activity",
+            "Get what was on screen and said in the last few minutes",
+        ),
+        tool::<NoArgs>(
+            "list_audio_devices",
+            "List the audio devices screenpipe knows about and whether they are recording",
+        ),
+        tool::<NoArgs>("pause_recording", "Pause screen recording"),
+    ]
+}
+
+fn tool<T: JsonSchema>(name: &str, description: &str) -> Value {
+    let generator = SchemaSettings::draft07()
+        .with(|settings| {
+            settings.inline_subschemas = true;
+            settings.meta_schema = None;
+        })
+        .into_generator();
+    let mut input_schema =
+        serde_json::to_value(generator.into_root_schema_for::<T>()).unwrap_or_default();
+    if let Some(schema) = input_schema.as_object_mut() {
+        schema.remove("title");
+    }
+
+    json!({
+        "name": name,
+        "description": description,
+        "inputSchema": input_schema,
+    })
+}
+
+fn tool_scope(name: &str) -> Scope {
+    match name {
+        "get_frame" => Scope::ReadMedia,
+        "pause_recording" => Scope::ControlRecording,
+        _ => Scope::ReadSearch,
+    }
+}
+
+async fn call_tool(
+    state: &AppState,
+    scopes: &GrantedScopes,
+    params: ToolCallParams,
+) -> Result<Value, (i64, String)> {
+    let scope = tool_scope(&params.name);
+    if !scopes.allows(scope) {
+        return Ok(text_result(
+            &json!({ "error": format!("Token lacks the {} scope", scope) }),
+            true,
+        ));
+    }
+
+    let arguments = params.arguments.unwrap_or_else(|| json!({}));
+    let result = match params.name.as_str() {
+        "search" => match serde_json::from_value(arguments) {
+            Ok(args) => search(state, args).await,
+            Err(e) => Err(format!("Invalid arguments: {}", e)),
+        },
+        "get_frame" => match serde_json::from_value(arguments) {
+            Ok(args) => return Ok(get_frame(state, args).await),
+            Err(e) => Err(format!("Invalid arguments: {}", e)),
+        },
+        "recent_activity" => match serde_json::from_value(arguments) {
+            Ok(args) => recent_activity(state, args).await,
+            Err(e) => Err(format!("Invalid arguments: {}", e)),
+        },
+        "list_audio_devices" => Ok(list_audio_devices(state)),
+        "pause_recording" => Ok(pause_recording(state)),
+        name => return Err((INVALID_PARAMS, format!("Unknown tool: {}", name))),
+    };
+
+    Ok(match result {
+        Ok(value) => text_result(&value, false),
+        Err(message) => {
+            error!("MCP tool {} failed: {}", params.name, message);
+            text_result(&json!({ "error": message }), true)
+        }
+    })
+}
+
+fn text_result(value: &Value, is_error: bool) -> Value {
+    json!({
+        "content": [{ "type": "text", "text": value.to_string() }],
+        "isError": is_error,
+    })
+}
+
+async fn search(state: &AppState, query: SearchQuery) -> ToolResult {
+    let query_str = query.q.as_deref().unwrap_or("");
+    let results = state
+        .db
+        .search(
+            query_str,
+            query.content_type,
+            query.pagination.limit,
+            query.pagination.offset,
+            query.start_time,
+            query.end_time,
+        )
+        .await
+        .map_err(|e| format!("Failed to search for content: {}", e))?;
+
+    let items: Vec<_> = results.into_iter().map(into_content_item).collect();
+    serde_json::to_value(items).map_err(|e| e.to_string())
+}
+
+async fn get_frame(state: &AppState, args: GetFrameArgs) -> Value {
+    let (file_path, offset_index) = match state.db.get_frame(args.frame_id).await {
+        Ok(Some(frame)) => frame,
+        Ok(None) => {
+            return text_result(
+                &json!({ "error": format!("Frame {} not found", args.frame_id) }),
+                true,
+            )
+        }
+        Err(e) => {
+            return text_result(
+                &json!({ "error": format!("Failed to get frame: {}", e) }),
+                true,
+            )
+        }
+    };
+
+    let metadata = json!({
+        "frame_id": args.frame_id,
+        "file_path": file_path,
+        "offset_index": offset_index,
+    });
+    match extract_frame(&file_path, offset_index).await {
+        Ok(png) => json!({
+            "content": [
+                { "type": "text", "text": metadata.to_string() },
+                { "type": "image", "data": STANDARD.encode(png), "mimeType": "image/png" },
+            ],
+            "isError": false,
+        }),
+        Err(e) => {
+            error!("Failed to extract frame {}: {}", args.frame_id, e);
+            text_result(
+                &json!({ "error": format!("Failed to extract frame: {}", e), "frame": metadata }),
+                true,
+            )
+        }
+    }
+}
+
+async fn recent_activity(state: &AppState, args: RecentActivityArgs) -> ToolResult {
+    let start_time = Utc::now() - Duration::minutes(args.minutes as i64);
+    let results = state
+        .db
+        .get_recent_results(args.limit, 0, Some(start_time), None)
+        .await
+        .map_err(|e| format!("Failed to get recent activity: {}", e))?;
+
+    let items: Vec<_> = results.into_iter().map(into_content_item).collect();
+    serde_json::to_value(items).map_err(|e| e.to_string())
+}
+
+fn list_audio_devices(state: &AppState) -> Value {
+    let devices: Vec<Value> = state
+        .devices_status
+        .iter()
+        .map(|(audio_device, device_control)| {
+            json!({
+                "id": audio_device.to_string(),
+                "is_running": device_control.is_running,
+            })
+        })
+        .collect();
+    json!(devices)
+}
+
+fn pause_recording(state: &AppState) -> Value {
+    state.vision_control.store(false, Ordering::SeqCst);
+    info!("Screen recording paused by MCP client");
+    json!({ "is_running": false })
+}
@@ -0,0 +1,104 @@
+// Prometheus metrics of the recording pipeline. Capture and OCR series live in
+// screenpipe-vision, the Whisper queue in screenpipe-audio, all of them register in the default
+// registry rendered by `/metrics`.
+use axum::{
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+};
+use lazy_static::lazy_static;
+use log::error;
+use prometheus::{
+    register_gauge, register_histogram, register_int_counter, register_int_counter_vec,
+    register_int_gauge, register_int_gauge_vec, Encoder, Gauge, Histogram, IntCounter,
+    IntCounterVec, IntGauge, IntGaugeVec, TextEncoder,
+};
+
+lazy_static! {
+    pub static ref QUEUE_LENGTH: IntGaugeVec = register_int_gauge_vec!(
+        "screenpipe_queue_length",
+        "Frames waiting in the video capture queues",
+        &["queue"]
+    )
+    .unwrap();
+    pub static ref CAPTURE_INTERVAL: Gauge = register_gauge!(
+        "screenpipe_capture_interval_seconds",
+        "Time between screenshots, longer than 1/fps while the queues are backed up"
+    )
+    .unwrap();
+    pub static ref FFMPEG_RESTARTS: IntCounter = register_int_counter!(
+        "screenpipe_ffmpeg_restarts_total",
+        "FFmpeg processes started again after the previous one failed"
+    )
+    .unwrap();
+    pub static ref TRANSCRIPTION_LATENCY: Histogram = register_histogram!(
+        "screenpipe_transcription_latency_seconds",
+        "Time from the end of an audio chunk to its transcription",
+        vec![1.0, 2.5, 5.0, 10.0, 30.0, 60.0, 120.0, 300.0, 600.0]
+    )
+    .unwrap();
+    pub static ref OCR_INSERT_RETRIES: IntCounter = register_int_counter!(
+        "screenpipe_ocr_insert_retries_total",
+        "OCR text inserts attempted again after an error or timeout"
+    )
+    .unwrap();
+    pub static ref OCR_INSERT_ERRORS: IntCounter = register_int_counter!(
+        "screenpipe_ocr_insert_errors_total",
+        "OCR text inserts that failed after every retry"
+    )
+    .unwrap();
+    pub static ref PIPE_EVENTS_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "screenpipe_pipe_events_dropped_total",
+        "Frames and transcriptions a pipe missed because its queue was full",
+        &["pipe"]
+    )
+    .unwrap();
+    pub static ref PROCESS_MEMORY: IntGauge = register_int_gauge!(
+        "screenpipe_process_memory_bytes",
+        "Memory used by screenpipe and its child processes"
+    )
+    .unwrap();
+    pub static ref PROCESS_CPU: Gauge = register_gauge!(
+        "screenpipe_process_cpu_percent",
+        "CPU used by screenpipe and its child processes, 100 per core"
+    )
+    .unwrap();
+}
+
+/// Registers every series up front, so they are scraped as zero before anything is recorded.
+fn register_all() {
+    use screenpipe_audio::metrics as audio;
+    use screenpipe_vision::metrics as vision;
+
+    lazy_static::initialize(&vision::FRAMES_CAPTURED);
+    lazy_static::initialize(&vision::FRAMES_OCRED);
+    lazy_static::initialize(&vision::FRAMES_DROPPED);
+    lazy_static::initialize(&vision::OCR_DURATION);
+    lazy_static::initialize(&audio::WHISPER_QUEUE_DEPTH);
+    lazy_static::initialize(&QUEUE_LENGTH);
+    lazy_static::initialize(&CAPTURE_INTERVAL);
+    lazy_static::initialize(&FFMPEG_RESTARTS);
+    lazy_static::initialize(&TRANSCRIPTION_LATENCY);
+    lazy_static::initialize(&OCR_INSERT_RETRIES);
+    lazy_static::initialize(&OCR_INSERT_ERRORS);
+    lazy_static::initialize(&PIPE_EVENTS_DROPPED);
+    lazy_static::initialize(&PROCESS_MEMORY);
+    lazy_static::initialize(&PROCESS_CPU);
+}
+
+/// Renders the default registry in the Prometheus text format.
+pub fn render_metrics() -> Result<String, prometheus::Error> {
+    register_all();
+    let mut buffer = Vec::new();
+    TextEncoder::new().encode(&prometheus::gather(), &mut buffer)?;
+    Ok(String::from_utf8_lossy(&buffer).into_owned())
+}
+
+pub(crate) async fn get_metrics() -> Response {
+    match render_metrics() {
+        Ok(body) => ([(header::CONTENT_TYPE, prometheus::TEXT_FORMAT)], body).into_response(),
+        Err(e) => {
+            error!("Failed to render metrics: {}", e);
+            (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()).into_response()
+        }
+    }
+}
@@ -0,0 +1,26 @@
+-- Create webhook_rules table
+CREATE TABLE IF NOT EXISTS webhook_rules (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    url TEXT NOT NULL,
+    secret TEXT NOT NULL,
+    keywords TEXT NOT NULL DEFAULT '[]',
+    pattern TEXT,
+    content_type TEXT NOT NULL DEFAULT 'all',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_at TIMESTAMP NOT NULL
+);
+
+-- Create webhook_dead_letters table for deliveries that failed after all retries
+CREATE TABLE IF NOT EXISTS webhook_dead_letters (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    rule_id INTEGER NOT NULL,
+    url TEXT NOT NULL,
+    payload TEXT NOT NULL,
+    error TEXT NOT NULL,
+    attempts INTEGER NOT NULL,
+    created_at TIMESTAMP NOT NULL,
+    FOREIGN KEY (rule_id) REFERENCES webhook_rules(id) ON DELETE CASCADE
+);
+
+CREATE INDEX IF NOT EXISTS idx_webhook_dead_letters_rule_id ON webhook_dead_letters(rule_id);
@@ -0,0 +1,13 @@
+-- Create pipe_outputs table for records derived by in-process pipes
+CREATE TABLE IF NOT EXISTS pipe_outputs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    pipe_name TEXT NOT NULL,
+    content_type TEXT NOT NULL,
+    source_id INTEGER NOT NULL,
+    kind TEXT NOT NULL,
+    data TEXT NOT NULL,
+    created_at TIMESTAMP NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_pipe_outputs_pipe_name_kind ON pipe_outputs(pipe_name, kind);
+CREATE INDEX IF NOT EXISTS idx_pipe_outputs_content_type_source_id ON pipe_outputs(content_type, source_id);
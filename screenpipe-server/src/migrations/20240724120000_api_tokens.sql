@@ -0,0 +1,9 @@
+-- Create api_tokens table, only the SHA-256 of each token is stored
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scopes TEXT NOT NULL DEFAULT '[]',
+    created_at TIMESTAMP NOT NULL,
+    last_used_at TIMESTAMP
+);
@@ -0,0 +1,7 @@
+-- Device and start time of every audio chunk, and where each transcription falls in its chunk
+ALTER TABLE audio_chunks ADD COLUMN device TEXT;
+ALTER TABLE audio_chunks ADD COLUMN start_time TIMESTAMP;
+ALTER TABLE audio_transcriptions ADD COLUMN start_time REAL;
+ALTER TABLE audio_transcriptions ADD COLUMN end_time REAL;
+
+CREATE INDEX IF NOT EXISTS idx_audio_chunks_device_start_time ON audio_chunks(device, start_time);
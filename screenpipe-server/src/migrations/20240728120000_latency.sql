@@ -0,0 +1,4 @@
+-- How long the OCR of a frame took, and a chunk from the end of its recording to its
+-- transcription
+ALTER TABLE frames ADD COLUMN ocr_latency_ms INTEGER;
+ALTER TABLE audio_chunks ADD COLUMN transcription_latency_ms INTEGER;
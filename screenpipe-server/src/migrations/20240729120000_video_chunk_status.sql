@@ -0,0 +1,3 @@
+-- Whether ffmpeg finished a chunk. Chunks recorded before this column were complete as far as we
+-- know, chunks still "recording" at startup were cut short by a crash
+ALTER TABLE video_chunks ADD COLUMN status TEXT NOT NULL DEFAULT 'complete';
@@ -0,0 +1,4 @@
+-- Compaction tier of a chunk, 0 as recorded, and how many recorded frames each frame of the
+-- re-encoded video stands for
+ALTER TABLE video_chunks ADD COLUMN tier INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE video_chunks ADD COLUMN frame_step INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,4 @@
+-- Perceptual hash (dHash) of a frame as a signed 64-bit integer, NULL for frames recorded before
+-- it was stored
+ALTER TABLE frames ADD COLUMN image_hash INTEGER;
+CREATE INDEX IF NOT EXISTS idx_frames_image_hash ON frames(image_hash);
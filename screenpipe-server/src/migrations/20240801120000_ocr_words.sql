@@ -0,0 +1,19 @@
+-- Words read by OCR with their box on the frame, in pixels, and their place in Tesseract's
+-- block / paragraph / line layout
+CREATE TABLE IF NOT EXISTS ocr_words (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    frame_id INTEGER NOT NULL,
+    text TEXT NOT NULL,
+    "left" INTEGER NOT NULL,
+    top INTEGER NOT NULL,
+    width INTEGER NOT NULL,
+    height INTEGER NOT NULL,
+    conf REAL NOT NULL,
+    block_num INTEGER NOT NULL,
+    par_num INTEGER NOT NULL,
+    line_num INTEGER NOT NULL,
+    word_num INTEGER NOT NULL,
+    FOREIGN KEY (frame_id) REFERENCES frames(id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_ocr_words_frame_id ON ocr_words(frame_id);
@@ -0,0 +1,3 @@
+-- Frame rate a chunk was encoded at, the video time of a frame is its offset divided by it.
+-- Unknown for chunks recorded before it was stored
+ALTER TABLE video_chunks ADD COLUMN fps REAL;
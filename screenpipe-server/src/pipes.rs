@@ -0,0 +1,175 @@
+use std::panic::AssertUnwindSafe;
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use futures::FutureExt;
+use log::{debug, error, info, warn};
+use prometheus::IntCounter;
+use screenpipe_vision::CaptureResult;
+use tokio::sync::mpsc::{self, error::TrySendError};
+
+use crate::metrics::PIPE_EVENTS_DROPPED;
+use crate::{ContentType, DatabaseManager};
+
+pub const DEFAULT_QUEUE_CAPACITY: usize = 64;
+
+/// A frame and its OCR result, once stored in the database.
+pub struct PipeFrame {
+    pub frame_id: i64,
+    pub capture: CaptureResult,
+    /// When the frame was captured
+    pub timestamp: DateTime<Utc>,
+}
+
+/// A transcription, once stored in the database.
+pub struct PipeTranscription {
+    pub audio_chunk_id: i64,
+    pub transcription: String,
+    pub device: String,
+    pub file_path: String,
+    /// When the audio chunk started recording
+    pub timestamp: DateTime<Utc>,
+}
+
+#[derive(Clone)]
+pub enum PipeEvent {
+    Frame(Arc<PipeFrame>),
+    Transcription(Arc<PipeTranscription>),
+}
+
+/// In-process consumer of captured data, e.g. to extract TODOs or tag meetings.
+///
+/// Every pipe runs on its own task with its own bounded queue: a slow pipe drops its own
+/// events without holding back recording or other pipes, and errors or panics are logged
+/// and don't stop it from receiving the next event.
+#[async_trait]
+pub trait Pipe: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    /// Number of events buffered for this pipe before new ones are dropped.
+    fn queue_capacity(&self) -> usize {
+        DEFAULT_QUEUE_CAPACITY
+    }
+
+    async fn on_frame(&self, _frame: &PipeFrame, _ctx: &PipeContext) -> anyhow::Result<()> {
+        Ok(())
+    }
+
+    async fn on_transcription(
+        &self,
+        _transcription: &PipeTranscription,
+        _ctx: &PipeContext,
+    ) -> anyhow::Result<()> {
+        Ok(())
+    }
+}
+
+/// Handed to pipes so they can read the database and store what they derive.
+pub struct PipeContext {
+    pipe_name: String,
+    db: Arc<DatabaseManager>,
+}
+
+impl PipeContext {
+    pub fn db(&self) -> &DatabaseManager {
+        &self.db
+    }
+
+    /// Stores a derived record in `pipe_outputs`, attached to the frame (OCR) or audio chunk (audio) it came from.
+    pub async fn write_output(
+        &self,
+        content_type: ContentType,
+        source_id: i64,
+        kind: &str,
+        data: &serde_json::Value,
+    ) -> Result<i64, sqlx::Error> {
+        self.db
+            .insert_pipe_output(&self.pipe_name, content_type, source_id, kind, data)
+            .await
+    }
+}
+
+struct PipeWorker {
+    name: String,
+    sender: mpsc::Sender<PipeEvent>,
+    /// Events dropped because the queue was full, in `screenpipe_pipe_events_dropped_total`
+    dropped: IntCounter,
+}
+
+/// Fans new frames and transcriptions out to the pipes registered at startup.
+pub struct PipeManager {
+    workers: Vec<PipeWorker>,
+}
+
+impl PipeManager {
+    pub fn new(db: Arc<DatabaseManager>, pipes: Vec<Arc<dyn Pipe>>) -> Arc<Self> {
+        let workers = pipes
+            .into_iter()
+            .map(|pipe| {
+                let name = pipe.name().to_string();
+                let (sender, receiver) = mpsc::channel(pipe.queue_capacity().max(1));
+                let ctx = PipeContext {
+                    pipe_name: name.clone(),
+                    db: Arc::clone(&db),
+                };
+                info!("Starting pipe {}", name);
+                tokio::spawn(run_pipe(pipe, ctx, receiver));
+                PipeWorker {
+                    dropped: PIPE_EVENTS_DROPPED.with_label_values(&[&name]),
+                    name,
+                    sender,
+                }
+            })
+            .collect();
+        Arc::new(Self { workers })
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.workers.is_empty()
+    }
+
+    pub fn dispatch(&self, event: PipeEvent) {
+        for worker in &self.workers {
+            match worker.sender.try_send(event.clone()) {
+                Ok(()) => {}
+                Err(TrySendError::Full(_)) => {
+                    worker.dropped.inc();
+                    let dropped = worker.dropped.get();
+                    // Don't flood the logs when a pipe is stuck
+                    if dropped == 1 || dropped % 100 == 0 {
+                        warn!(
+                            "Pipe {} is lagging behind, dropped {} events so far",
+                            worker.name, dropped
+                        );
+                    }
+                }
+                Err(TrySendError::Closed(_)) => {
+                    debug!("Pipe {} has stopped, not sending event", worker.name);
+                }
+            }
+        }
+    }
+}
+
+async fn run_pipe(pipe: Arc<dyn Pipe>, ctx: PipeContext, mut receiver: mpsc::Receiver<PipeEvent>) {
+    while let Some(event) = receiver.recv().await {
+        let result = match &event {
+            PipeEvent::Frame(frame) => AssertUnwindSafe(pipe.on_frame(frame, &ctx))
+                .catch_unwind()
+                .await,
+            PipeEvent::Transcription(transcription) => {
+                AssertUnwindSafe(pipe.on_transcription(transcription, &ctx))
+                    .catch_unwind()
+                    .await
+            }
+        };
+
+        match result {
+            Ok(Ok(())) => {}
+            Ok(Err(e)) => error!("Pipe {} failed to process event: {}", ctx.pipe_name, e),
+            Err(_) => error!("Pipe {} panicked while processing event", ctx.pipe_name),
+        }
+    }
+    info!("Pipe {} stopped", ctx.pipe_name);
+}
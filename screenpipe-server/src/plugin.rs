@@ -1,49 +1,154 @@
+use async_trait::async_trait;
+use axum::{
+    body::Body,
+    extract::Request,
+    http::{Extensions, HeaderMap, Method, Uri},
+    response::Response,
+};
 use futures::future::BoxFuture;
+use log::debug;
+use std::sync::Arc;
 use std::task::{Context, Poll};
+use std::time::Instant;
 use tower::{Layer, Service};
 
-#[derive(Clone)]
-pub struct ApiPluginLayer<F> {
-    plugin: F,
+/// What a plugin decided to do with an incoming request.
+pub enum PluginDecision {
+    /// Pass the request on. Plugins modify it in place (headers, extensions, ...) before allowing it.
+    Allow,
+    /// Answer with this response instead, later plugins and the handler are skipped.
+    Deny(Response),
+}
+
+/// Request details kept around for the response hooks, the request itself is consumed by the handler.
+pub struct RequestInfo {
+    pub method: Method,
+    pub uri: Uri,
+    pub headers: HeaderMap,
+    pub extensions: Extensions,
+    pub started_at: Instant,
 }
 
-impl<F> ApiPluginLayer<F>
+impl RequestInfo {
+    fn new(request: &Request<Body>, started_at: Instant) -> Self {
+        Self {
+            method: request.method().clone(),
+            uri: request.uri().clone(),
+            headers: request.headers().clone(),
+            extensions: request.extensions().clone(),
+            started_at,
+        }
+    }
+}
+
+/// Hooks into every API request, e.g. for auth, auditing or analytics.
+///
+/// Plugins run in registration order for requests and responses alike.
+#[async_trait]
+pub trait ApiPlugin: Send + Sync + 'static {
+    fn name(&self) -> &str;
+
+    async fn on_request(&self, _request: &mut Request<Body>) -> PluginDecision {
+        PluginDecision::Allow
+    }
+
+    /// Called for every response, including the ones produced by a denying plugin.
+    async fn on_response(&self, _request: &RequestInfo, _response: &mut Response) {}
+
+    /// Called after `on_response` when the response is a server error.
+    async fn on_error(&self, _request: &RequestInfo, _response: &mut Response) {}
+}
+
+/// Adapts a synchronous `Fn(&Request)` into a plugin that only observes requests.
+pub struct FnPlugin<F> {
+    name: String,
+    f: F,
+}
+
+impl<F> FnPlugin<F>
 where
-    F: Clone,
+    F: Fn(&Request<Body>) + Send + Sync + 'static,
 {
-    pub fn new(plugin: F) -> Self {
-        Self { plugin }
+    pub fn new(name: impl Into<String>, f: F) -> Self {
+        Self {
+            name: name.into(),
+            f,
+        }
     }
 }
 
-impl<S, F> Layer<S> for ApiPluginLayer<F>
+#[async_trait]
+impl<F> ApiPlugin for FnPlugin<F>
 where
-    F: Clone + Send + Sync + 'static,
+    F: Fn(&Request<Body>) + Send + Sync + 'static,
 {
-    type Service = ApiPluginService<S, F>;
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn on_request(&self, request: &mut Request<Body>) -> PluginDecision {
+        (self.f)(request);
+        PluginDecision::Allow
+    }
+}
+
+#[derive(Clone, Default)]
+pub struct PluginRegistry {
+    plugins: Vec<Arc<dyn ApiPlugin>>,
+}
+
+impl PluginRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn register(&mut self, plugin: impl ApiPlugin) -> &mut Self {
+        debug!("Registering API plugin {}", plugin.name());
+        self.plugins.push(Arc::new(plugin));
+        self
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.plugins.is_empty()
+    }
+}
+
+#[derive(Clone)]
+pub struct ApiPluginLayer {
+    registry: Arc<PluginRegistry>,
+}
+
+impl ApiPluginLayer {
+    pub fn new(registry: PluginRegistry) -> Self {
+        Self {
+            registry: Arc::new(registry),
+        }
+    }
+}
+
+impl<S> Layer<S> for ApiPluginLayer {
+    type Service = ApiPluginService<S>;
 
     fn layer(&self, service: S) -> <Self as Layer<S>>::Service {
         ApiPluginService {
             inner: service,
-            plugin: self.plugin.clone(),
+            registry: Arc::clone(&self.registry),
         }
     }
 }
 
 #[derive(Clone)]
-pub struct ApiPluginService<S, F> {
+pub struct ApiPluginService<S> {
     inner: S,
-    plugin: F,
+    registry: Arc<PluginRegistry>,
 }
 
-impl<S, F, R> Service<R> for ApiPluginService<S, F>
+impl<S> Service<Request<Body>> for ApiPluginService<S>
 where
-    S: Service<R> + Clone + Send + 'static,
-    F: Fn(&R) + Clone + Send + Sync + 'static,
-    R: Send + 'static,
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
     S::Future: Send,
 {
-    type Response = S::Response;
+    type Response = Response;
     type Error = S::Error;
     type Future = BoxFuture<'static, Result<Self::Response, Self::Error>>;
 
@@ -51,10 +156,45 @@ where
         self.inner.poll_ready(cx)
     }
 
-    fn call(&mut self, request: R) -> Self::Future {
-        let plugin = self.plugin.clone();
-        let mut inner = self.inner.clone();
-        plugin(&request);
-        Box::pin(async move { inner.call(request).await })
+    fn call(&mut self, mut request: Request<Body>) -> Self::Future {
+        let registry = Arc::clone(&self.registry);
+        // The clone may not be ready yet, keep it and use the service that was polled
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+
+        Box::pin(async move {
+            let started_at = Instant::now();
+
+            let mut denied = None;
+            for plugin in registry.plugins.iter() {
+                if let PluginDecision::Deny(response) = plugin.on_request(&mut request).await {
+                    debug!(
+                        "Plugin {} denied {} {}",
+                        plugin.name(),
+                        request.method(),
+                        request.uri()
+                    );
+                    denied = Some(response);
+                    break;
+                }
+            }
+
+            let info = RequestInfo::new(&request, started_at);
+            let mut response = match denied {
+                Some(response) => response,
+                None => inner.call(request).await?,
+            };
+
+            for plugin in registry.plugins.iter() {
+                plugin.on_response(&info, &mut response).await;
+            }
+            if response.status().is_server_error() {
+                for plugin in registry.plugins.iter() {
+                    plugin.on_error(&info, &mut response).await;
+                }
+            }
+
+            Ok(response)
+        })
     }
 }
@@ -0,0 +1,112 @@
+// Bounded queues between the stages of the video pipeline. Pushing never waits: a full queue
+// drops an item according to its policy and counts it, so a slow consumer costs frames instead
+// of memory.
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use log::warn;
+use tokio::sync::Notify;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DropPolicy {
+    /// Make room by dropping the oldest item, the consumer always gets the latest data
+    DropOldest,
+    /// Reject the new item, the consumer gets a gapless run until it catches up
+    DropNewest,
+}
+
+pub struct BoundedQueue<T> {
+    name: &'static str,
+    capacity: usize,
+    policy: DropPolicy,
+    items: Mutex<VecDeque<T>>,
+    dropped: AtomicU64,
+    notify: Notify,
+}
+
+impl<T> BoundedQueue<T> {
+    pub fn new(name: &'static str, capacity: usize, policy: DropPolicy) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            name,
+            capacity,
+            policy,
+            items: Mutex::new(VecDeque::with_capacity(capacity)),
+            dropped: AtomicU64::new(0),
+            notify: Notify::new(),
+        }
+    }
+
+    /// Queues `item`, returns `false` if an item had to be dropped to stay within capacity.
+    pub fn push(&self, item: T) -> bool {
+        let accepted = {
+            let mut items = self.items.lock().unwrap();
+            if items.len() < self.capacity {
+                items.push_back(item);
+                true
+            } else {
+                if self.policy == DropPolicy::DropOldest {
+                    items.pop_front();
+                    items.push_back(item);
+                }
+                false
+            }
+        };
+        self.notify.notify_one();
+
+        if !accepted {
+            let dropped = self.dropped.fetch_add(1, Ordering::Relaxed) + 1;
+            // Don't flood the logs when a consumer is stuck
+            if dropped == 1 || dropped % 100 == 0 {
+                warn!(
+                    "Queue {} is full, dropped {} items so far",
+                    self.name, dropped
+                );
+            }
+        }
+        accepted
+    }
+
+    pub fn try_pop(&self) -> Option<T> {
+        self.items.lock().unwrap().pop_front()
+    }
+
+    /// Waits for the next item, `None` if nothing came in within `timeout`.
+    pub async fn pop_timeout(&self, timeout: Duration) -> Option<T> {
+        tokio::time::timeout(timeout, async {
+            loop {
+                if let Some(item) = self.try_pop() {
+                    return item;
+                }
+                // A push between try_pop and here leaves a permit, nothing is missed
+                self.notify.notified().await;
+            }
+        })
+        .await
+        .ok()
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// How full the queue is, from 0 to 1.
+    pub fn fill_ratio(&self) -> f64 {
+        self.len() as f64 / self.capacity as f64
+    }
+
+    /// Number of items dropped because the queue was full.
+    pub fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Relaxed)
+    }
+}
@@ -5,6 +5,8 @@ use std::thread;
 use std::time::{Duration, Instant};
 use sysinfo::{PidExt, ProcessExt, System, SystemExt};
 
+use crate::metrics::{PROCESS_CPU, PROCESS_MEMORY};
+
 pub struct ResourceMonitor {
     start_time: Instant,
     memory_threshold: f64,
@@ -45,6 +47,9 @@ impl ResourceMonitor {
                 }
             }
 
+            PROCESS_MEMORY.set(total_memory as i64);
+            PROCESS_CPU.set(total_cpu as f64);
+
             let total_memory_gb = total_memory / 1048576000.0;
             let system_total_memory = sys.total_memory() as f64 / 1048576000.0;
             let memory_usage_percent = (total_memory_gb / system_total_memory) * 100.0;
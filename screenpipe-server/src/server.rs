@@ -10,6 +10,7 @@ use tracing::Level;
 use crate::{ContentType, DatabaseManager, SearchResult};
 use chrono::{DateTime, Utc};
 use log::{error, info};
+use schemars::JsonSchema;
 use screenpipe_audio::{AudioDevice, DeviceControl};
 use serde::{Deserialize, Serialize};
 use serde_json::json;
@@ -25,19 +26,34 @@ use std::{
 use tokio::{net::TcpListener, sync::mpsc::Sender};
 use tower_http::trace::TraceLayer;
 use tower_http::{
-    cors::CorsLayer,
     trace::{DefaultMakeSpan, DefaultOnRequest, DefaultOnResponse},
     LatencyUnit,
 };
 
-use crate::plugin::ApiPluginLayer;
+use crate::auth::cors_layer;
+use crate::clip::export_video;
+use crate::export::export;
+use crate::hls::{get_playlist, get_segment_init, get_segment_media, SegmentCache};
+use crate::mcp::handle_mcp;
+use crate::plugin::{ApiPluginLayer, PluginRegistry};
+use crate::metrics::get_metrics;
+use crate::stats::get_stats;
+use crate::subtitles::{chunk_subtitles, device_subtitles};
+use crate::timeline::get_timeline;
+use crate::webhooks::{
+    create_webhook, delete_webhook, get_webhook, list_webhook_dead_letters, list_webhooks,
+    update_webhook, WebhookEngine,
+};
+use crate::words::{get_frame_layout, get_frame_words};
 
 pub(crate) struct AppState {
-    db: Arc<DatabaseManager>,
-    vision_control: Arc<AtomicBool>,
-    audio_devices_control_sender: Sender<(AudioDevice, DeviceControl)>,
-    devices_status: HashMap<AudioDevice, DeviceControl>,
-    app_start_time: DateTime<Utc>,
+    pub(crate) db: Arc<DatabaseManager>,
+    pub(crate) vision_control: Arc<AtomicBool>,
+    pub(crate) audio_devices_control_sender: Sender<(AudioDevice, DeviceControl)>,
+    pub(crate) devices_status: HashMap<AudioDevice, DeviceControl>,
+    pub(crate) app_start_time: DateTime<Utc>,
+    pub(crate) webhooks: Arc<WebhookEngine>,
+    pub(crate) segments: SegmentCache,
 }
 
 #[derive(Deserialize)]
@@ -46,35 +62,52 @@ pub(crate) struct DeviceRequest {
 }
 
 // Request structs
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub(crate) struct SearchQuery {
-    q: Option<String>,
+    /// Text to look for in OCR text and audio transcriptions
+    pub(crate) q: Option<String>,
     #[serde(flatten)]
-    pagination: PaginationQuery,
+    pub(crate) pagination: PaginationQuery,
     #[serde(default)]
-    content_type: ContentType,
+    pub(crate) content_type: ContentType,
+    /// Only return content captured at or after this time (RFC 3339)
     #[serde(default)]
-    start_time: Option<DateTime<Utc>>,
+    pub(crate) start_time: Option<DateTime<Utc>>,
+    /// Only return content captured at or before this time (RFC 3339)
     #[serde(default)]
-    end_time: Option<DateTime<Utc>>,
+    pub(crate) end_time: Option<DateTime<Utc>>,
 }
 
-#[derive(Deserialize)]
+#[derive(Deserialize, JsonSchema)]
 pub(crate) struct PaginationQuery {
+    /// Maximum number of results to return
     #[serde(default = "default_limit")]
     #[serde(deserialize_with = "deserialize_number_from_string")]
-    limit: u32,
+    #[schemars(with = "u32")]
+    pub(crate) limit: u32,
+    /// Number of results to skip
     #[serde(default)]
     #[serde(deserialize_with = "deserialize_number_from_string")]
-    offset: u32,
+    #[schemars(with = "u32")]
+    pub(crate) offset: u32,
 }
 
+// Query strings always carry numbers as strings, JSON bodies (e.g. MCP tool calls) don't
 fn deserialize_number_from_string<'de, D>(deserializer: D) -> Result<u32, D::Error>
 where
     D: serde::Deserializer<'de>,
 {
-    let s: String = serde::Deserialize::deserialize(deserializer)?;
-    s.parse().map_err(serde::de::Error::custom)
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum StringOrNumber {
+        String(String),
+        Number(u32),
+    }
+
+    match StringOrNumber::deserialize(deserializer)? {
+        StringOrNumber::String(s) => s.parse().map_err(serde::de::Error::custom),
+        StringOrNumber::Number(n) => Ok(n),
+    }
 }
 
 #[derive(Deserialize)]
@@ -118,12 +151,14 @@ pub(crate) struct OCRContent {
     offset_index: i64,
 }
 
+/// A transcribed segment of an audio chunk, chunks transcribed with timings match per segment.
 #[derive(Serialize)]
 pub(crate) struct AudioContent {
     chunk_id: i64,
     transcription: String,
     timestamp: DateTime<Utc>,
     file_path: String,
+    /// Position of the segment in the chunk, 0 for chunks transcribed as a whole
     offset_index: i64,
 }
 
@@ -438,7 +473,7 @@ pub(crate) async fn health_check(
 }
 
 // Helper functions
-fn into_content_item(result: SearchResult) -> ContentItem {
+pub(crate) fn into_content_item(result: SearchResult) -> ContentItem {
     match result {
         SearchResult::OCR(ocr) => ContentItem::OCR(OCRContent {
             frame_id: ocr.frame_id,
@@ -462,6 +497,8 @@ pub struct Server {
     addr: SocketAddr,
     vision_control: Arc<AtomicBool>,
     audio_devices_control_sender: Sender<(AudioDevice, DeviceControl)>,
+    webhooks: Arc<WebhookEngine>,
+    cors_origins: Vec<String>,
 }
 
 impl Server {
@@ -470,23 +507,52 @@ impl Server {
         addr: SocketAddr,
         vision_control: Arc<AtomicBool>,
         audio_devices_control_sender: Sender<(AudioDevice, DeviceControl)>,
+        webhooks: Arc<WebhookEngine>,
+        cors_origins: Vec<String>,
     ) -> Self {
         Server {
             db,
             addr,
             vision_control,
             audio_devices_control_sender,
+            webhooks,
+            cors_origins,
         }
     }
 
-    pub async fn start<F>(
+    pub async fn start(
         self,
         device_status: HashMap<AudioDevice, DeviceControl>,
-        api_plugin: F,
-    ) -> Result<(), std::io::Error>
-    where
-        F: Fn(&axum::http::Request<axum::body::Body>) + Clone + Send + Sync + 'static,
-    {
+        plugins: PluginRegistry,
+    ) -> Result<(), std::io::Error> {
+        let addr = self.addr;
+        let app = self.router(device_status, plugins);
+
+        info!("Starting server on {}", addr);
+        // info!("Audio devices:");
+        // for (device, control) in device_status.iter() {
+        //     info!("{}: {}", device, control.is_running);
+        // }
+
+        match serve(TcpListener::bind(addr).await?, app.into_make_service()).await {
+            Ok(_) => {
+                info!("Server stopped gracefully");
+                Ok(())
+            }
+            Err(e) => {
+                error!("Server error: {}", e);
+                Err(e)
+            }
+        }
+    }
+
+    /// The API routes with their plugins, without binding to an address, e.g. to serve them on
+    /// a listener of your own.
+    pub fn router(
+        self,
+        device_status: HashMap<AudioDevice, DeviceControl>,
+        plugins: PluginRegistry,
+    ) -> Router {
         // TODO could init w audio devices
         let app_state = Arc::new(AppState {
             db: self.db,
@@ -494,21 +560,42 @@ impl Server {
             audio_devices_control_sender: self.audio_devices_control_sender,
             devices_status: device_status,
             app_start_time: Utc::now(),
+            webhooks: self.webhooks,
+            segments: SegmentCache::default(),
         });
 
         // https://github.com/tokio-rs/console
-        let app = Router::new()
+        Router::new()
             .route("/search", get(search))
+            .route("/export", get(export))
+            .route("/timeline", get(get_timeline))
+            .route("/stats", get(get_stats))
+            .route("/metrics", get(get_metrics))
+            .route("/frames/:id/words", get(get_frame_words))
+            .route("/frames/:id/layout", get(get_frame_layout))
+            .route("/video/export", post(export_video))
+            .route("/video/playlist.m3u8", get(get_playlist))
+            .route("/video/segments/:id/init.mp4", get(get_segment_init))
+            .route("/video/segments/:id/media.m4s", get(get_segment_media))
             .route("/audio/start", post(start_device))
             .route("/audio/stop", post(stop_device))
             .route("/audio/status", post(get_device_status))
             .route("/audio/list", get(get_devices))
+            .route("/audio/chunks/:id/subtitles", get(chunk_subtitles))
+            .route("/audio/subtitles", get(device_subtitles))
             .route("/vision/start", post(start_recording))
             .route("/vision/stop", post(stop_recording))
             .route("/vision/status", get(get_recording_status))
             .route("/health", get(health_check))
-            .layer(ApiPluginLayer::new(api_plugin))
-            .layer(CorsLayer::permissive())
+            .route("/mcp", post(handle_mcp))
+            .route("/webhooks", get(list_webhooks).post(create_webhook))
+            .route(
+                "/webhooks/:id",
+                get(get_webhook).put(update_webhook).delete(delete_webhook),
+            )
+            .route("/webhooks/:id/dead_letters", get(list_webhook_dead_letters))
+            .layer(ApiPluginLayer::new(plugins))
+            .layer(cors_layer(&self.cors_origins))
             .layer(
                 // https://github.com/tokio-rs/axum/blob/main/examples/tracing-aka-logging/src/main.rs
                 TraceLayer::new_for_http()
@@ -520,24 +607,7 @@ impl Server {
                             .latency_unit(LatencyUnit::Micros),
                     ),
             )
-            .with_state(app_state);
-
-        info!("Starting server on {}", self.addr);
-        // info!("Audio devices:");
-        // for (device, control) in device_status.iter() {
-        //     info!("{}: {}", device, control.is_running);
-        // }
-
-        match serve(TcpListener::bind(self.addr).await?, app.into_make_service()).await {
-            Ok(_) => {
-                info!("Server stopped gracefully");
-                Ok(())
-            }
-            Err(e) => {
-                error!("Server error: {}", e);
-                Err(e)
-            }
-        }
+            .with_state(app_state)
     }
 }
 
@@ -585,6 +655,14 @@ impl Server {
 // # 12. Get recording status
 // # curl "http://localhost:3030/vision/status"
 
+// # 13. List the tools exposed to MCP clients
+// # curl -X POST "http://localhost:3030/mcp" -H "Content-Type: application/json" -d '{"jsonrpc": "2.0", "id": 1, "method": "tools/list"}' | jq
+
+// # 14. Get a signed POST whenever an incident id shows up on screen or in audio
+// # curl -X POST "http://localhost:3030/webhooks" -H "Content-Type: application/json" -d '{"name": "incidents", "url": "http://localhost:8080/hook", "pattern": "INC-[0-9]+"}' | jq
+// # curl "http://localhost:3030/webhooks" | jq
+// # curl -X DELETE "http://localhost:3030/webhooks/1"
+
 /*
 
 echo "Listing audio devices:"
@@ -643,5 +721,39 @@ curl "http://localhost:3030/search?limit=5&offset=0&content_type=ocr&start_time=
 # Search for audio content with a keyword from the beginning of the current month
 curl "http://localhost:3030/search?q=libmp3&limit=5&offset=0&content_type=audio&start_time=$(date -u -v1d -v0H -v0M -v0S +%Y-%m-01T%H:%M:%SZ)" | jq
 
+# Export today's transcripts as a Markdown timeline
+curl "http://localhost:3030/export?content_type=audio&format=markdown&start_time=$(date -u -v0H -v0M -v0S +%Y-%m-%dT%H:%M:%SZ)" -o transcript.md
+
+# Export a project's OCR history with frame thumbnails
+curl "http://localhost:3030/export?q=screenpipe&content_type=ocr&format=markdown&zip=true&thumbnails=true" -o export.zip
+
+# Subtitles of an audio chunk
+curl "http://localhost:3030/audio/chunks/1/subtitles?format=vtt" -o chunk.vtt
+
+# Recording stats: per day counts, disk usage, latencies and when the disk will be full
+curl "http://localhost:3030/stats" | jq
+
+# Prometheus metrics of the recording pipeline
+curl "http://localhost:3030/metrics"
+
+# Today's activity in 5 minute buckets
+curl "http://localhost:3030/timeline?bucket=5m&start_time=$(date -u -v0H -v0M -v0S +%Y-%m-%dT%H:%M:%SZ)&end_time=$(date -u +%Y-%m-%dT%H:%M:%SZ)" | jq
+
+# Subtitles of the last hour of a meeting, stitched from every chunk of the microphone
+curl "http://localhost:3030/audio/subtitles?device=MacBook%20Pro%20Microphone%20(input)&format=srt&start_time=$(date -u -v-1H +%Y-%m-%dT%H:%M:%SZ)&end_time=$(date -u +%Y-%m-%dT%H:%M:%SZ)" -o meeting.srt
+
+# Today's screen recording as one HLS stream, e.g. for hls.js or Safari
+ffplay "http://localhost:3030/video/playlist.m3u8?start_time=$(date -u -v0H -v0M -v0S +%Y-%m-%dT%H:%M:%SZ)&end_time=$(date -u +%Y-%m-%dT%H:%M:%SZ)"
+
+# Last 10 minutes as an mp4 with timestamps and audio, e.g. to attach to a bug report
+curl -X POST "http://localhost:3030/video/export" -H "Content-Type: application/json" \
+  -d "{\"start_time\": \"$(date -u -v-10M +%Y-%m-%dT%H:%M:%SZ)\", \"end_time\": \"$(date -u +%Y-%m-%dT%H:%M:%SZ)\", \"timestamps\": true, \"audio\": true}" -o replay.mp4
+
+# Boxes of the words of a frame matching a search, to highlight them over the frame image
+curl "http://localhost:3030/frames/42/words?q=screenpipe" | jq
+
+# Text of a frame in blocks and lines, e.g. to copy a paragraph as it was laid out
+curl "http://localhost:3030/frames/42/layout" | jq
+
 
 */
@@ -0,0 +1,298 @@
+// Usage statistics: what was recorded per day, where the disk space goes, how fast OCR and
+// transcription keep up, and when the disk will be full at the current pace.
+use std::collections::HashSet;
+use std::fmt::Write;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use axum::{extract::State, http::StatusCode, response::Json as JsonResponse};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Serialize;
+use serde_json::json;
+use sysinfo::{DiskExt, System, SystemExt};
+
+use crate::db::DailyStats;
+use crate::server::AppState;
+use crate::video::TIMESTAMPS_SUFFIX;
+use crate::DatabaseManager;
+
+/// Window used for the latency averages and the growth rate.
+const RECENT_DAYS: i64 = 7;
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct DiskUsage {
+    pub video_bytes: u64,
+    pub audio_bytes: u64,
+    /// The database file with its journal
+    pub database_bytes: u64,
+    /// Files in the data directory that no chunk points at
+    pub other_bytes: u64,
+    pub total_bytes: u64,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct UsageStats {
+    pub generated_at: DateTime<Utc>,
+    pub days: Vec<DailyStats>,
+    pub disk_usage: DiskUsage,
+    /// Average time the OCR of a frame took over the last week, in milliseconds
+    pub ocr_latency_ms: Option<f64>,
+    /// Average time from the end of a recording to its transcription over the last week
+    pub transcription_latency_ms: Option<f64>,
+    /// Media recorded per day over the last week, plus the average growth of the database since
+    /// the first recording
+    pub growth_bytes_per_day: Option<f64>,
+    /// Free space on the disk holding the data directory
+    pub free_bytes: Option<u64>,
+    /// When the disk fills up at the current growth rate
+    pub disk_full_at: Option<DateTime<Utc>>,
+}
+
+/// Collects the stats of the data directory holding the database.
+pub async fn collect_stats(db: &DatabaseManager) -> anyhow::Result<UsageStats> {
+    let now = Utc::now();
+    let recent = now - chrono::Duration::days(RECENT_DAYS);
+    let days = db.get_daily_stats().await?;
+    let (ocr_latency_ms, transcription_latency_ms) = db.get_average_latencies(recent).await?;
+
+    let chunk_names =
+        |chunks: Vec<(i64, String)>| file_names(chunks.into_iter().map(|(_, path)| path));
+    let video_files = chunk_names(db.get_video_chunks().await?);
+    let audio_files = chunk_names(db.get_audio_chunks().await?);
+    // Dated by when they were recorded, compaction and encryption rewrite older files
+    let recent_files = file_names(db.get_chunks_recorded_since(recent).await?);
+    let database_path = db.database_path();
+
+    let (disk_usage, recent_media_bytes, free_bytes) = tokio::task::spawn_blocking(move || {
+        let Some(database_path) = database_path else {
+            return Ok::<_, io::Error>((DiskUsage::default(), 0, None));
+        };
+        let base_dir = database_path
+            .parent()
+            .unwrap_or(Path::new("."))
+            .to_path_buf();
+        let mut usage = DiskUsage::default();
+        let mut recent_media_bytes = 0;
+
+        let database_name = file_name(&database_path).unwrap_or_default();
+        for entry in fs::read_dir(&base_dir)? {
+            let entry = entry?;
+            let name = entry.file_name().to_string_lossy().into_owned();
+            // db.sqlite, db.sqlite-wal, db.sqlite-shm, db.sqlite-journal
+            if name.starts_with(&database_name) && entry.file_type()?.is_file() {
+                usage.database_bytes += entry.metadata()?.len();
+            }
+        }
+
+        for (path, size) in walk(&base_dir.join("data"))? {
+            let name = file_name(&path).unwrap_or_default();
+            // Frame timestamps count with the video they describe
+            let chunk_name = name.strip_suffix(TIMESTAMPS_SUFFIX).unwrap_or(&name);
+            if video_files.contains(chunk_name) {
+                usage.video_bytes += size;
+            } else if audio_files.contains(&name) {
+                usage.audio_bytes += size;
+            } else {
+                usage.other_bytes += size;
+                continue;
+            }
+            if recent_files.contains(chunk_name) {
+                recent_media_bytes += size;
+            }
+        }
+        usage.total_bytes =
+            usage.video_bytes + usage.audio_bytes + usage.database_bytes + usage.other_bytes;
+
+        Ok((usage, recent_media_bytes, free_space(&base_dir)))
+    })
+    .await??;
+
+    let total_days = days
+        .first()
+        .and_then(|first| first.day.parse::<chrono::NaiveDate>().ok())
+        .map(|first| (now.date_naive() - first).num_days() + 1)
+        .unwrap_or(0);
+    // Data younger than the window grows faster than it looks over the whole window
+    let recorded_days = total_days.min(RECENT_DAYS);
+    // Rows aren't dated by size, the database is assumed to grow at its average pace
+    let growth_bytes_per_day = (recorded_days > 0).then(|| {
+        recent_media_bytes as f64 / recorded_days as f64
+            + disk_usage.database_bytes as f64 / total_days as f64
+    });
+    let disk_full_at = match (growth_bytes_per_day, free_bytes) {
+        (Some(growth), Some(free)) if growth > 0.0 => {
+            // Capped to a century, a nearly idle recorder would overflow the date
+            let seconds = (free as f64 / growth * 24.0 * 3600.0).min(100.0 * 365.0 * 24.0 * 3600.0);
+            Some(now + chrono::Duration::seconds(seconds as i64))
+        }
+        _ => None,
+    };
+
+    Ok(UsageStats {
+        generated_at: now,
+        days,
+        disk_usage,
+        ocr_latency_ms,
+        transcription_latency_ms,
+        growth_bytes_per_day,
+        free_bytes,
+        disk_full_at,
+    })
+}
+
+fn file_names(paths: impl IntoIterator<Item = String>) -> HashSet<String> {
+    paths
+        .into_iter()
+        .filter_map(|path| file_name(Path::new(&path)))
+        .collect()
+}
+
+fn file_name(path: &Path) -> Option<String> {
+    path.file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+}
+
+/// Every file under `dir` with its size, nothing if `dir` doesn't exist.
+fn walk(dir: &Path) -> io::Result<Vec<(PathBuf, u64)>> {
+    let mut files = Vec::new();
+    let mut pending = vec![dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let entries = match fs::read_dir(&dir) {
+            Ok(entries) => entries,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => continue,
+            Err(e) => return Err(e),
+        };
+        for entry in entries {
+            let entry = entry?;
+            let metadata = entry.metadata()?;
+            if metadata.is_dir() {
+                pending.push(entry.path());
+            } else if metadata.is_file() {
+                files.push((entry.path(), metadata.len()));
+            }
+        }
+    }
+    Ok(files)
+}
+
+/// Available space on the disk with the longest mount point containing `path`.
+fn free_space(path: &Path) -> Option<u64> {
+    let path = path.canonicalize().ok()?;
+    let mut sys = System::new();
+    sys.refresh_disks_list();
+    sys.disks()
+        .iter()
+        .filter(|disk| path.starts_with(disk.mount_point()))
+        .max_by_key(|disk| disk.mount_point().as_os_str().len())
+        .map(|disk| disk.available_space())
+}
+
+/// Plain text report for the terminal.
+pub fn format_stats(stats: &UsageStats) -> String {
+    let mut output = String::new();
+    let _ = writeln!(output, "Recorded per day (UTC):");
+    let _ = writeln!(
+        output,
+        "  {:<12}{:>10}{:>14}{:>14}{:>16}",
+        "day", "frames", "video chunks", "audio chunks", "transcriptions"
+    );
+    for day in &stats.days {
+        let _ = writeln!(
+            output,
+            "  {:<12}{:>10}{:>14}{:>14}{:>16}",
+            day.day, day.frames, day.video_chunks, day.audio_chunks, day.transcriptions
+        );
+    }
+
+    let usage = &stats.disk_usage;
+    let _ = writeln!(output, "\nDisk usage:");
+    let _ = writeln!(
+        output,
+        "  video     {}",
+        format_bytes(usage.video_bytes as f64)
+    );
+    let _ = writeln!(
+        output,
+        "  audio     {}",
+        format_bytes(usage.audio_bytes as f64)
+    );
+    let _ = writeln!(
+        output,
+        "  database  {}",
+        format_bytes(usage.database_bytes as f64)
+    );
+    let _ = writeln!(
+        output,
+        "  other     {}",
+        format_bytes(usage.other_bytes as f64)
+    );
+    let _ = writeln!(
+        output,
+        "  total     {}",
+        format_bytes(usage.total_bytes as f64)
+    );
+
+    let latency =
+        |ms: Option<f64>| ms.map_or_else(|| "n/a".to_string(), |ms| format!("{:.0} ms", ms));
+    let _ = writeln!(
+        output,
+        "\nAverage latency over the last {} days:",
+        RECENT_DAYS
+    );
+    let _ = writeln!(output, "  OCR            {}", latency(stats.ocr_latency_ms));
+    let _ = writeln!(
+        output,
+        "  transcription  {}",
+        latency(stats.transcription_latency_ms)
+    );
+
+    let _ = writeln!(output, "\nGrowth:");
+    match stats.growth_bytes_per_day {
+        Some(growth) => {
+            let _ = writeln!(output, "  {} per day", format_bytes(growth));
+        }
+        None => {
+            let _ = writeln!(output, "  n/a");
+        }
+    }
+    if let Some(free) = stats.free_bytes {
+        let _ = writeln!(output, "  {} free", format_bytes(free as f64));
+    }
+    if let Some(disk_full_at) = stats.disk_full_at {
+        let _ = writeln!(
+            output,
+            "  disk full around {}",
+            disk_full_at.format("%Y-%m-%d")
+        );
+    }
+    output
+}
+
+fn format_bytes(bytes: f64) -> String {
+    const UNITS: [&str; 5] = ["B", "KB", "MB", "GB", "TB"];
+    let mut value = bytes;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+pub(crate) async fn get_stats(
+    State(state): State<Arc<AppState>>,
+) -> Result<JsonResponse<UsageStats>, (StatusCode, JsonResponse<serde_json::Value>)> {
+    collect_stats(&state.db)
+        .await
+        .map(JsonResponse)
+        .map_err(|e| {
+            error!("Failed to collect stats: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({ "error": format!("Failed to collect stats: {}", e) })),
+            )
+        })
+}
@@ -0,0 +1,238 @@
+// SRT and WebVTT subtitles built from timed audio transcriptions, either for one chunk or for
+// every chunk a device recorded over a time range, stitched into a single track.
+use std::fmt::Write;
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Json as JsonResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::db::AudioSegment;
+use crate::server::AppState;
+
+/// Length given to transcriptions recorded before timing was stored, the default chunk duration.
+const UNTIMED_CUE_SECONDS: f64 = 30.0;
+/// Chunks starting this long before a time range are looked at for cues inside the range.
+pub(crate) const MAX_CHUNK_SECONDS: i64 = 600;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum SubtitleFormat {
+    #[default]
+    Srt,
+    Vtt,
+}
+
+impl SubtitleFormat {
+    pub fn mime_type(&self) -> &'static str {
+        match self {
+            SubtitleFormat::Srt => "application/x-subrip; charset=utf-8",
+            SubtitleFormat::Vtt => "text/vtt; charset=utf-8",
+        }
+    }
+}
+
+/// A caption, `start` and `end` in seconds from the start of the track.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: f64,
+    pub end: f64,
+    pub text: String,
+}
+
+/// Cues of a single chunk, relative to the start of the chunk.
+pub fn chunk_cues(segments: &[AudioSegment]) -> Vec<Cue> {
+    let mut cues: Vec<Cue> = Vec::new();
+    for segment in segments {
+        let (start, end) = match (segment.start_time, segment.end_time) {
+            (Some(start), Some(end)) => (start, end),
+            _ => {
+                let start = cues.last().map_or(0.0, |cue| cue.end);
+                (start, start + UNTIMED_CUE_SECONDS)
+            }
+        };
+        push_cue(&mut cues, start, end, &segment.transcription);
+    }
+    cues
+}
+
+/// Cues of consecutive chunks, relative to `start_time` and cut at `end_time`.
+pub fn stitch_cues(
+    segments: &[AudioSegment],
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+) -> Vec<Cue> {
+    let length = seconds_between(start_time, end_time);
+    let mut cues = Vec::new();
+    for segment in segments {
+        let (Some(chunk_start), Some(start), Some(end)) = (
+            segment.chunk_start_time,
+            segment.start_time,
+            segment.end_time,
+        ) else {
+            continue;
+        };
+        let offset = seconds_between(start_time, chunk_start);
+        let (start, end) = (offset + start, offset + end);
+        if end <= 0.0 || start >= length {
+            continue;
+        }
+        push_cue(
+            &mut cues,
+            start.max(0.0),
+            end.min(length),
+            &segment.transcription,
+        );
+    }
+    cues
+}
+
+fn push_cue(cues: &mut Vec<Cue>, start: f64, end: f64, text: &str) {
+    // Blank lines end a cue in both formats
+    let text = text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n");
+    if text.is_empty() || end <= start {
+        return;
+    }
+    cues.push(Cue { start, end, text });
+}
+
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+    (to - from).num_milliseconds() as f64 / 1000.0
+}
+
+pub fn render_subtitles(format: SubtitleFormat, cues: &[Cue]) -> String {
+    let mut output = String::new();
+    if format == SubtitleFormat::Vtt {
+        output.push_str("WEBVTT\n\n");
+    }
+    for (i, cue) in cues.iter().enumerate() {
+        let (start, end, text) = match format {
+            SubtitleFormat::Srt => (
+                timestamp(cue.start, ','),
+                timestamp(cue.end, ','),
+                cue.text.clone(),
+            ),
+            SubtitleFormat::Vtt => (
+                timestamp(cue.start, '.'),
+                timestamp(cue.end, '.'),
+                escape_vtt(&cue.text),
+            ),
+        };
+        let _ = write!(output, "{}\n{} --> {}\n{}\n\n", i + 1, start, end, text);
+    }
+    output
+}
+
+/// `HH:MM:SS,mmm` for SRT, `HH:MM:SS.mmm` for WebVTT.
+fn timestamp(seconds: f64, separator: char) -> String {
+    let millis = (seconds.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        separator,
+        millis % 1000
+    )
+}
+
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct SubtitleQuery {
+    #[serde(default)]
+    format: SubtitleFormat,
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct DeviceSubtitleQuery {
+    device: String,
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    #[serde(default)]
+    format: SubtitleFormat,
+}
+
+pub(crate) async fn chunk_subtitles(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Query(query): Query<SubtitleQuery>,
+) -> Response {
+    match state.db.get_audio_chunk(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                JsonResponse(json!({ "error": "Audio chunk not found" })),
+            )
+                .into_response()
+        }
+        Err(e) => return subtitles_error(e),
+    }
+
+    match state.db.get_audio_segments(id).await {
+        Ok(segments) => subtitles_response(query.format, &chunk_cues(&segments)),
+        Err(e) => subtitles_error(e),
+    }
+}
+
+pub(crate) async fn device_subtitles(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<DeviceSubtitleQuery>,
+) -> Response {
+    if query.end_time <= query.start_time {
+        return (
+            StatusCode::BAD_REQUEST,
+            JsonResponse(json!({ "error": "end_time must be after start_time" })),
+        )
+            .into_response();
+    }
+
+    let segments = state
+        .db
+        .get_device_audio_segments(
+            &query.device,
+            query.start_time - chrono::Duration::seconds(MAX_CHUNK_SECONDS),
+            query.end_time,
+        )
+        .await;
+    match segments {
+        Ok(segments) => subtitles_response(
+            query.format,
+            &stitch_cues(&segments, query.start_time, query.end_time),
+        ),
+        Err(e) => subtitles_error(e),
+    }
+}
+
+fn subtitles_response(format: SubtitleFormat, cues: &[Cue]) -> Response {
+    (
+        [(header::CONTENT_TYPE, format.mime_type())],
+        render_subtitles(format, cues),
+    )
+        .into_response()
+}
+
+fn subtitles_error(e: sqlx::Error) -> Response {
+    error!("Failed to build subtitles: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        JsonResponse(json!({ "error": format!("Failed to build subtitles: {}", e) })),
+    )
+        .into_response()
+}
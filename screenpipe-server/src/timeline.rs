@@ -0,0 +1,97 @@
+// Activity aggregated in time buckets, enough to draw a "day at a glance" view without pulling
+// every frame and transcription.
+use std::sync::Arc;
+
+use axum::{
+    extract::{Query, State},
+    http::StatusCode,
+    response::Json as JsonResponse,
+};
+use chrono::{DateTime, Utc};
+use log::error;
+use serde::{Deserialize, Serialize};
+use serde_json::json;
+
+use crate::db::TimelineBucket;
+use crate::server::AppState;
+
+/// Keeps a single request bounded, a week of 1m buckets.
+const MAX_BUCKETS: i64 = 7 * 24 * 60;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub enum BucketSize {
+    #[serde(rename = "1m")]
+    OneMinute,
+    #[default]
+    #[serde(rename = "5m")]
+    FiveMinutes,
+    #[serde(rename = "1h")]
+    OneHour,
+}
+
+impl BucketSize {
+    pub fn seconds(&self) -> i64 {
+        match self {
+            BucketSize::OneMinute => 60,
+            BucketSize::FiveMinutes => 5 * 60,
+            BucketSize::OneHour => 60 * 60,
+        }
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct TimelineQuery {
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    #[serde(default)]
+    bucket: BucketSize,
+}
+
+#[derive(Debug, Serialize)]
+pub(crate) struct TimelineResponse {
+    start_time: DateTime<Utc>,
+    end_time: DateTime<Utc>,
+    bucket: BucketSize,
+    /// Only buckets with activity
+    buckets: Vec<TimelineBucket>,
+}
+
+pub(crate) async fn get_timeline(
+    State(state): State<Arc<AppState>>,
+    Query(query): Query<TimelineQuery>,
+) -> Result<JsonResponse<TimelineResponse>, (StatusCode, JsonResponse<serde_json::Value>)> {
+    if query.end_time <= query.start_time {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(json!({ "error": "end_time must be after start_time" })),
+        ));
+    }
+    let seconds = query.bucket.seconds();
+    if (query.end_time - query.start_time).num_seconds() / seconds > MAX_BUCKETS {
+        return Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(
+                json!({ "error": format!("Time range too long, at most {} buckets", MAX_BUCKETS) }),
+            ),
+        ));
+    }
+
+    let buckets = state
+        .db
+        .get_timeline(query.start_time, query.end_time, seconds)
+        .await
+        .map_err(|e| {
+            error!("Failed to build the timeline: {}", e);
+            (
+                StatusCode::INTERNAL_SERVER_ERROR,
+                JsonResponse(json!({ "error": format!("Failed to build the timeline: {}", e) })),
+            )
+        })?;
+
+    Ok(JsonResponse(TimelineResponse {
+        start_time: query.start_time,
+        end_time: query.end_time,
+        bucket: query.bucket,
+        buckets,
+    }))
+}
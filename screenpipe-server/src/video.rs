@@ -1,58 +1,119 @@
-use chrono::Utc;
-use image::ImageFormat::{self};
+use crate::db::ChunkStatus;
+use crate::encoder::VideoEncoderConfig;
+use crate::metrics::{CAPTURE_INTERVAL, FFMPEG_RESTARTS, QUEUE_LENGTH};
+use crate::queue::{BoundedQueue, DropPolicy};
+use chrono::{DateTime, SecondsFormat, Utc};
+use futures::future::BoxFuture;
+use image::DynamicImage;
 use log::{debug, error, info, warn};
 use screenpipe_core::find_ffmpeg_path;
-use screenpipe_vision::{continuous_capture, CaptureResult, ControlMessage};
-use std::collections::VecDeque;
+use screenpipe_vision::metrics::FRAMES_DROPPED;
+use screenpipe_vision::{continuous_capture, CaptureInterval, CaptureResult, ControlMessage};
+use std::borrow::Cow;
 use std::process::Stdio;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::Instant;
 use tokio::io::AsyncWriteExt;
-use tokio::io::{AsyncBufReadExt, BufReader};
+use tokio::io::{AsyncBufReadExt, BufReader, BufWriter};
 use tokio::process::{Child, ChildStdin, Command};
-use tokio::sync::mpsc::{channel, Receiver, Sender};
+use tokio::sync::mpsc::{channel, Sender};
 use tokio::sync::Mutex;
 
 use std::time::Duration;
 
 const MAX_FPS: f64 = 30.0; // Adjust based on your needs
+/// Frames waiting for the encoder. Large, the video should keep every frame while ffmpeg keeps up.
+const VIDEO_QUEUE_CAPACITY: usize = 128;
+/// Frames waiting for the database. Kept short: when inserts lag, frames skip OCR storage but
+/// still make it into the video.
+const OCR_QUEUE_CAPACITY: usize = 32;
+/// Wait before starting ffmpeg again after a failure, doubled on every failure in a row.
+const MIN_RESTART_DELAY: Duration = Duration::from_secs(1);
+const MAX_RESTART_DELAY: Duration = Duration::from_secs(60);
+/// Capture slows down to at most this many times the configured interval.
+const MAX_SLOWDOWN: u32 = 8;
+/// Appended to the path of a chunk for its sidecar listing the capture time of every frame.
+pub const TIMESTAMPS_SUFFIX: &str = ".timestamps";
+
+/// Registers a new chunk file, e.g. in the database, and returns its id.
+pub type NewChunkCallback = Arc<dyn Fn(&str) -> BoxFuture<'static, Option<i64>> + Send + Sync>;
+/// Records how a registered chunk ended, complete or partial.
+pub type ChunkFinishedCallback =
+    Arc<dyn Fn(i64, ChunkStatus) -> BoxFuture<'static, ()> + Send + Sync>;
+
+/// A frame written to a chunk, along with where it ended up in the video.
+pub struct EncodedFrame {
+    pub frame: CaptureResult,
+    /// Id given by the new chunk callback, `None` if the chunk could not be registered
+    pub video_chunk_id: Option<i64>,
+    pub offset_index: i64,
+    pub captured_at: DateTime<Utc>,
+}
 
 pub struct VideoCapture {
     control_tx: Sender<ControlMessage>,
-    frame_queue: Arc<Mutex<VecDeque<CaptureResult>>>,
-    video_frame_queue: Arc<Mutex<VecDeque<CaptureResult>>>,
-    pub ocr_frame_queue: Arc<Mutex<VecDeque<CaptureResult>>>,
+    latest_frame: Arc<StdMutex<Option<CaptureResult>>>,
+    video_frame_queue: Arc<BoundedQueue<CaptureResult>>,
+    /// Frames once they are in a chunk, waiting to be stored with their OCR results
+    pub ocr_frame_queue: Arc<BoundedQueue<EncodedFrame>>,
+    capture_interval: CaptureInterval,
     ffmpeg_handle: Arc<Mutex<Option<Child>>>,
     is_running: Arc<Mutex<bool>>,
 }
 
 impl VideoCapture {
+    /// Records the screen into chunks of `chunk_duration` each. `new_chunk_callback` is awaited
+    /// before the first frame of a chunk is written, its id is carried by the frames of the chunk
+    /// and handed to `chunk_finished_callback` once ffmpeg is done with it. Frames whose perceptual
+    /// hash is within `dedup_threshold` bits of the last kept one are skipped, 0 keeps them all.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         output_path: &str,
         fps: f64,
-        new_chunk_callback: impl Fn(&str) + Send + Sync + 'static,
+        chunk_duration: Duration,
+        encoder: VideoEncoderConfig,
+        new_chunk_callback: impl Fn(&str) -> BoxFuture<'static, Option<i64>> + Send + Sync + 'static,
+        chunk_finished_callback: impl Fn(i64, ChunkStatus) -> BoxFuture<'static, ()>
+            + Send
+            + Sync
+            + 'static,
         save_text_files: bool,
+        dedup_threshold: u32,
+        ocr_workers: usize,
     ) -> Self {
         info!("Starting new video capture");
         let (control_tx, mut control_rx) = channel(512);
-        let frame_queue = Arc::new(Mutex::new(VecDeque::new()));
-        let video_frame_queue = Arc::new(Mutex::new(VecDeque::new()));
-        let ocr_frame_queue = Arc::new(Mutex::new(VecDeque::new()));
+        let latest_frame = Arc::new(StdMutex::new(None));
+        let video_frame_queue = Arc::new(BoundedQueue::new(
+            "video_frame",
+            VIDEO_QUEUE_CAPACITY,
+            DropPolicy::DropOldest,
+        ));
+        let ocr_frame_queue = Arc::new(BoundedQueue::new(
+            "ocr_frame",
+            OCR_QUEUE_CAPACITY,
+            DropPolicy::DropOldest,
+        ));
+        let base_interval = Duration::from_secs_f64(1.0 / fps);
+        let capture_interval = CaptureInterval::new(base_interval);
         let ffmpeg_handle = Arc::new(Mutex::new(None));
         let is_running = Arc::new(Mutex::new(true));
-        let new_chunk_callback = Arc::new(new_chunk_callback);
-        let new_chunk_callback_clone = Arc::clone(&new_chunk_callback);
+        let new_chunk_callback: NewChunkCallback = Arc::new(new_chunk_callback);
+        let chunk_finished_callback: ChunkFinishedCallback = Arc::new(chunk_finished_callback);
 
-        let capture_frame_queue = frame_queue.clone();
+        let capture_latest_frame = latest_frame.clone();
         let capture_video_frame_queue = video_frame_queue.clone();
-        let capture_ocr_frame_queue = ocr_frame_queue.clone();
         let capture_thread_is_running = is_running.clone();
         let (result_sender, mut result_receiver) = channel(512);
+        let capture_thread_interval = capture_interval.clone();
         let _capture_thread = tokio::spawn(async move {
             continuous_capture(
                 &mut control_rx,
                 result_sender,
-                Duration::from_secs_f64(1.0 / fps),
+                capture_thread_interval,
                 save_text_files,
+                dedup_threshold,
+                ocr_workers,
             )
             .await;
         });
@@ -65,231 +126,515 @@ impl VideoCapture {
                 if let Some(result) = result_receiver.recv().await {
                     let frame_number = result.frame_number;
                     debug!("Received frame {} for queueing", frame_number);
-                    let mut queue = capture_frame_queue.lock().await;
-                    let mut video_queue = capture_video_frame_queue.lock().await;
-                    let mut ocr_queue = capture_ocr_frame_queue.lock().await;
-                    queue.push_back(result.clone());
-                    video_queue.push_back(result.clone());
-                    ocr_queue.push_back(result);
-                    debug!("Frame {} pushed to queues. Queue length: {}, Video queue length: {}, OCR queue length: {}", frame_number, queue.len(), video_queue.len(), ocr_queue.len());
-
-                    // Clear the old queue after processing
-                    if queue.len() > 1 {
-                        queue.pop_front();
-                    }
+                    *capture_latest_frame.lock().unwrap() = Some(result.clone());
+                    queue_frame(&capture_video_frame_queue, result);
+                    debug!(
+                        "Frame {} pushed to queues. Video queue length: {}",
+                        frame_number,
+                        capture_video_frame_queue.len()
+                    );
+                }
+            }
+        });
+
+        // Sample the queues for /metrics and slow capture down while a consumer lags
+        let monitor_latest_frame = latest_frame.clone();
+        let monitor_video_frame_queue = video_frame_queue.clone();
+        let monitor_ocr_frame_queue = ocr_frame_queue.clone();
+        let monitor_interval = capture_interval.clone();
+        let monitor_thread_is_running = is_running.clone();
+        let _monitor_thread = tokio::spawn(async move {
+            while *monitor_thread_is_running.lock().await {
+                let has_latest_frame = monitor_latest_frame.lock().unwrap().is_some();
+                QUEUE_LENGTH
+                    .with_label_values(&["frame"])
+                    .set(has_latest_frame as i64);
+                QUEUE_LENGTH
+                    .with_label_values(&[monitor_video_frame_queue.name()])
+                    .set(monitor_video_frame_queue.len() as i64);
+                QUEUE_LENGTH
+                    .with_label_values(&[monitor_ocr_frame_queue.name()])
+                    .set(monitor_ocr_frame_queue.len() as i64);
+                let lag = monitor_video_frame_queue
+                    .fill_ratio()
+                    .max(monitor_ocr_frame_queue.fill_ratio());
+
+                let current = monitor_interval.get();
+                let next = next_capture_interval(current, base_interval, lag);
+                if next != current {
+                    info!(
+                        "Frame queues {:.0}% full, capturing every {:?}",
+                        lag * 100.0,
+                        next
+                    );
+                    monitor_interval.set(next);
                 }
+                CAPTURE_INTERVAL.set(next.as_secs_f64());
+                tokio::time::sleep(Duration::from_secs(1)).await;
             }
         });
 
         let video_frame_queue_clone = video_frame_queue.clone();
+        let ocr_frame_queue_clone = ocr_frame_queue.clone();
         let video_thread_is_running = is_running.clone();
         let output_path = output_path.to_string();
         let _video_thread = tokio::spawn(async move {
             save_frames_as_video(
                 &video_frame_queue_clone,
+                &ocr_frame_queue_clone,
                 &output_path,
                 fps,
+                chunk_duration,
+                &encoder,
                 video_thread_is_running,
-                new_chunk_callback_clone,
+                new_chunk_callback,
+                chunk_finished_callback,
             )
             .await;
         });
 
         VideoCapture {
             control_tx,
-            frame_queue,
+            latest_frame,
             video_frame_queue,
             ocr_frame_queue,
+            capture_interval,
             ffmpeg_handle,
             is_running,
         }
     }
 
     pub async fn pause(&self) {
-        self.control_tx.send(ControlMessage::Pause).await.unwrap();
+        self.send_control(ControlMessage::Pause).await;
     }
 
     pub async fn resume(&self) {
-        self.control_tx.send(ControlMessage::Resume).await.unwrap();
+        self.send_control(ControlMessage::Resume).await;
     }
 
     pub async fn stop(&self) {
-        self.control_tx.send(ControlMessage::Stop).await.unwrap();
+        self.send_control(ControlMessage::Stop).await;
         *self.is_running.lock().await = false;
         if let Some(mut child) = self.ffmpeg_handle.lock().await.take() {
-            child
-                .wait()
-                .await
-                .expect("Failed to wait for ffmpeg process");
+            if let Err(e) = child.wait().await {
+                error!("Failed to wait for ffmpeg process: {}", e);
+            }
+        }
+    }
+
+    async fn send_control(&self, message: ControlMessage) {
+        if self.control_tx.send(message).await.is_err() {
+            warn!("Capture already stopped");
         }
     }
 
     pub async fn get_latest_frame(&self) -> Option<CaptureResult> {
-        let mut queue = self.frame_queue.lock().await;
-        let queue_length = queue.len();
-        debug!("Number of frames in queue before popping: {}", queue_length);
-        queue.pop_front()
+        self.latest_frame.lock().unwrap().take()
     }
 
-    pub fn get_video_frame_queue(&self) -> Arc<Mutex<VecDeque<CaptureResult>>> {
+    pub fn get_video_frame_queue(&self) -> Arc<BoundedQueue<CaptureResult>> {
         Arc::clone(&self.video_frame_queue)
     }
+
+    /// Number of frames each queue dropped because its consumer was behind.
+    pub fn dropped_frames(&self) -> Vec<(&'static str, u64)> {
+        vec![
+            (
+                self.video_frame_queue.name(),
+                self.video_frame_queue.dropped(),
+            ),
+            (self.ocr_frame_queue.name(), self.ocr_frame_queue.dropped()),
+        ]
+    }
+
+    /// Current time between screenshots, longer than `1 / fps` while consumers lag.
+    pub fn capture_interval(&self) -> Duration {
+        self.capture_interval.get()
+    }
+}
+
+fn queue_frame<T>(queue: &BoundedQueue<T>, frame: T) {
+    if !queue.push(frame) {
+        let reason = format!("{}_queue_full", queue.name());
+        FRAMES_DROPPED.with_label_values(&[reason.as_str()]).inc();
+    }
+}
+
+/// Doubles the interval while a queue is at least half full, halves it back once they drain.
+fn next_capture_interval(current: Duration, base: Duration, lag: f64) -> Duration {
+    if lag >= 0.5 {
+        (current * 2).min(base * MAX_SLOWDOWN)
+    } else if lag <= 0.1 {
+        (current / 2).max(base)
+    } else {
+        current
+    }
+}
+
+/// Path of the sidecar holding the capture time of each frame of a chunk, one RFC 3339
+/// timestamp per line in frame order.
+pub fn timestamps_path(chunk_path: &str) -> String {
+    format!("{}{}", chunk_path, TIMESTAMPS_SUFFIX)
+}
+
+/// Delay before the next ffmpeg start after failures.
+struct RestartBackoff {
+    delay: Duration,
+    retry_at: Option<Instant>,
+}
+
+impl RestartBackoff {
+    fn new() -> Self {
+        Self {
+            delay: MIN_RESTART_DELAY,
+            retry_at: None,
+        }
+    }
+
+    fn failed(&mut self) {
+        warn!("Starting FFmpeg again in {:?}", self.delay);
+        self.retry_at = Some(Instant::now() + self.delay);
+        self.delay = (self.delay * 2).min(MAX_RESTART_DELAY);
+    }
+
+    /// Whether starting ffmpeg has to wait longer.
+    fn waiting(&self) -> bool {
+        self.retry_at.is_some_and(|retry_at| Instant::now() < retry_at)
+    }
+
+    /// Called when ffmpeg started, returns whether it was a restart after a failure.
+    fn started(&mut self) -> bool {
+        self.retry_at.take().is_some()
+    }
+
+    /// Called when ffmpeg finished a chunk cleanly.
+    fn reset(&mut self) {
+        self.delay = MIN_RESTART_DELAY;
+    }
+}
+
+/// A chunk being written: the ffmpeg process encoding it and its timestamps sidecar.
+struct ChunkWriter {
+    file_path: String,
+    video_chunk_id: Option<i64>,
+    ffmpeg: Child,
+    stdin: ChildStdin,
+    /// Size of the raw frames ffmpeg expects
+    width: u32,
+    height: u32,
+    timestamps: BufWriter<tokio::fs::File>,
+    started_at: Instant,
+    frames: i64,
+    flush_every: i64,
+    chunk_finished_callback: ChunkFinishedCallback,
+}
+
+impl ChunkWriter {
+    async fn open(
+        output_path: &str,
+        fps: f64,
+        width: u32,
+        height: u32,
+        encoder: &VideoEncoderConfig,
+        new_chunk_callback: &NewChunkCallback,
+        chunk_finished_callback: &ChunkFinishedCallback,
+    ) -> Result<Self, anyhow::Error> {
+        let formatted_time = Utc::now().format("%Y-%m-%d_%H-%M-%S").to_string();
+        let file_path = format!(
+            "{}/{}.{}",
+            output_path,
+            formatted_time,
+            encoder.codec.extension()
+        );
+
+        let mut ffmpeg = start_ffmpeg_process(&file_path, fps, width, height, encoder).await?;
+        let (Some(stdin), Some(stderr), Some(stdout)) =
+            (ffmpeg.stdin.take(), ffmpeg.stderr.take(), ffmpeg.stdout.take())
+        else {
+            return Err(anyhow::anyhow!("Failed to open FFmpeg pipes"));
+        };
+
+        // Spawn a task to log FFmpeg's stderr
+        tokio::spawn(async move {
+            let reader = BufReader::new(stderr);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("FFmpeg: {}", line);
+            }
+        });
+
+        // Log FFmpeg's stdout
+        tokio::spawn(async move {
+            let reader = BufReader::new(stdout);
+            let mut lines = reader.lines();
+            while let Ok(Some(line)) = lines.next_line().await {
+                debug!("FFmpeg: {}", line);
+            }
+        });
+
+        let timestamps = tokio::fs::File::create(timestamps_path(&file_path)).await?;
+        // Registered once ffmpeg runs, a failed start leaves no chunk without frames behind
+        let video_chunk_id = new_chunk_callback(&file_path).await;
+        debug!("New FFmpeg process started for file: {}", file_path);
+
+        Ok(Self {
+            file_path,
+            video_chunk_id,
+            ffmpeg,
+            stdin,
+            width,
+            height,
+            timestamps: BufWriter::new(timestamps),
+            started_at: Instant::now(),
+            frames: 0,
+            flush_every: (fps.round() as i64).max(1),
+            chunk_finished_callback: chunk_finished_callback.clone(),
+        })
+    }
+
+    /// Writes a frame of the chunk's size as raw RGBA and returns its offset in the chunk.
+    async fn write(&mut self, rgba: &[u8], captured_at: DateTime<Utc>) -> std::io::Result<i64> {
+        self.stdin.write_all(rgba).await?;
+        let offset_index = self.frames;
+        self.frames += 1;
+        self.timestamps
+            .write_all(
+                format!(
+                    "{}\n",
+                    captured_at.to_rfc3339_opts(SecondsFormat::Millis, true)
+                )
+                .as_bytes(),
+            )
+            .await?;
+
+        // Flush every second
+        if self.frames % self.flush_every == 0 {
+            debug!("Flushing FFmpeg input");
+            self.stdin.flush().await?;
+            self.timestamps.flush().await?;
+        }
+        Ok(offset_index)
+    }
+
+    /// Closes ffmpeg's input and waits for it, returns whether it exited cleanly.
+    async fn finish(mut self) -> bool {
+        if let Err(e) = self.timestamps.flush().await {
+            error!("Failed to write frame timestamps: {}", e);
+        }
+        drop(self.stdin); // Ensure stdin is closed
+        let success = match self.ffmpeg.wait().await {
+            Ok(status) => {
+                debug!("FFmpeg process exited with status: {}", status);
+                status.success()
+            }
+            Err(e) => {
+                error!("Failed to wait for ffmpeg: {}", e);
+                false
+            }
+        };
+        if !success {
+            error!(
+                "FFmpeg failed to finish {}, keeping its first {} frames at most",
+                self.file_path, self.frames
+            );
+        }
+        if let Some(video_chunk_id) = self.video_chunk_id {
+            let status = if success {
+                ChunkStatus::Complete
+            } else {
+                ChunkStatus::Partial
+            };
+            (self.chunk_finished_callback)(video_chunk_id, status).await;
+        }
+        finalize_chunk(timestamps_path(&self.file_path));
+        finalize_chunk(self.file_path);
+        success
+    }
 }
+
 async fn save_frames_as_video(
-    frame_queue: &Arc<Mutex<VecDeque<CaptureResult>>>,
+    frame_queue: &BoundedQueue<CaptureResult>,
+    ocr_frame_queue: &BoundedQueue<EncodedFrame>,
     output_path: &str,
     fps: f64,
+    chunk_duration: Duration,
+    encoder: &VideoEncoderConfig,
     is_running: Arc<Mutex<bool>>,
-    new_chunk_callback: Arc<dyn Fn(&str) + Send + Sync>,
+    new_chunk_callback: NewChunkCallback,
+    chunk_finished_callback: ChunkFinishedCallback,
 ) {
     debug!("Starting save_frames_as_video function");
-    let frames_per_video = 30; // Adjust this value as needed
-    let mut frame_count = 0;
-    let (sender, mut receiver): (Sender<Vec<u8>>, Receiver<Vec<u8>>) = channel(512);
-    let sender = Arc::new(sender);
-    let mut current_ffmpeg: Option<Child> = None;
-    let mut current_stdin: Option<ChildStdin> = None;
+    let mut chunk: Option<ChunkWriter> = None;
+    // Armed when ffmpeg failed to start, exited with an error or stopped reading frames
+    let mut backoff = RestartBackoff::new();
 
     while *is_running.lock().await {
-        if frame_count % frames_per_video == 0 || current_ffmpeg.is_none() {
-            debug!("Starting new FFmpeg process");
-            // Close previous FFmpeg process if exists
-            if let Some(child) = current_ffmpeg.take() {
-                drop(current_stdin.take()); // Ensure stdin is closed
-                let output = child
-                    .wait_with_output()
-                    .await
-                    .expect("ffmpeg process failed");
-                debug!("FFmpeg process exited with status: {}", output.status);
-                if !output.status.success() {
-                    error!("FFmpeg stderr: {}", String::from_utf8_lossy(&output.stderr));
+        // Notice a crash between frames, not only on the next write
+        if let Some(current) = chunk.as_mut() {
+            if let Ok(Some(status)) = current.ffmpeg.try_wait() {
+                error!(
+                    "FFmpeg exited while recording {}: {}",
+                    current.file_path, status
+                );
+                if let Some(chunk) = chunk.take() {
+                    finish_chunk(chunk, &mut backoff).await;
                 }
             }
+        }
 
-            // Wait for at least one frame before starting a new FFmpeg process
-            let first_frame = loop {
-                if let Some(result) = frame_queue.lock().await.pop_front() {
-                    debug!("Got first frame for new chunk");
-                    break result;
-                }
-                tokio::time::sleep(Duration::from_millis(10)).await;
-            };
-
-            // Encode the first frame
-            let mut buffer = Vec::new();
-            first_frame
-                .image
-                .write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
-                .expect("Failed to encode first frame");
-
-            let time = Utc::now();
-            let formatted_time = time.format("%Y-%m-%d_%H-%M-%S").to_string();
-            // Start new FFmpeg process with a new output file
-            let output_file = format!("{}/{}.mp4", output_path, formatted_time);
-
-            // Call the callback with the new video chunk file path
-            new_chunk_callback(&output_file);
-
-            match start_ffmpeg_process(&output_file, fps).await {
-                Ok(mut child) => {
-                    let mut stdin = child.stdin.take().expect("Failed to open stdin");
-                    let stderr = child.stderr.take().expect("Failed to open stderr");
-                    let stdout = child.stdout.take().expect("Failed to open stdout");
-
-                    // Write the first frame to FFmpeg
-                    stdin
-                        .write_all(&buffer)
-                        .await
-                        .expect("Failed to write first frame to ffmpeg");
-                    frame_count += 1;
-
-                    // Spawn a task to log FFmpeg's stderr
-                    tokio::spawn(async move {
-                        let reader = BufReader::new(stderr);
-                        let mut lines = reader.lines();
-                        while let Ok(Some(line)) = lines.next_line().await {
-                            debug!("FFmpeg: {}", line);
-                        }
-                    });
-
-                    // Log FFmpeg's stdout
-                    tokio::spawn(async move {
-                        let reader = BufReader::new(stdout);
-                        let mut lines = reader.lines();
-                        while let Ok(Some(line)) = lines.next_line().await {
-                            debug!("FFmpeg: {}", line);
-                        }
-                    });
-
-                    current_ffmpeg = Some(child);
-                    current_stdin = Some(stdin);
-                    debug!("New FFmpeg process started for file: {}", output_file);
-                }
-                Err(e) => {
-                    error!("Failed to start FFmpeg process: {}", e);
-                    // Handle the error appropriately, maybe try to restart or exit
-                }
+        // Rotate on wall-clock time, whatever the frame rate the capture ended up at
+        if chunk
+            .as_ref()
+            .is_some_and(|chunk| chunk.started_at.elapsed() >= chunk_duration)
+        {
+            if let Some(chunk) = chunk.take() {
+                finish_chunk(chunk, &mut backoff).await;
             }
         }
 
-        if let Some(result) = frame_queue.lock().await.pop_front() {
-            debug!("Processing frame in video.rs"); // {}", frame_count + 1
-            let sender = Arc::clone(&sender);
-
-            tokio::spawn(async move {
-                let mut buffer = Vec::new();
-                match result
-                    .image
-                    .write_to(&mut std::io::Cursor::new(&mut buffer), ImageFormat::Png)
-                {
-                    Ok(_) => {
-                        sender
-                            .send(buffer)
-                            .await
-                            .expect("Failed to send encoded frame");
-                    }
-                    Err(e) => error!("Failed to encode image as PNG: {}", e),
-                }
-            });
-        } else {
-            // debug!("No frames in queue, waiting...");
-            tokio::time::sleep(Duration::from_millis(10)).await;
+        let Some(frame) = frame_queue.pop_timeout(Duration::from_millis(100)).await else {
+            continue;
+        };
+        debug!("Processing frame in video.rs");
+        let (width, height) = (frame.image.width(), frame.image.height());
+
+        // ffmpeg reads raw frames of a fixed size, a resized screen starts a new chunk
+        if chunk
+            .as_ref()
+            .is_some_and(|chunk| (chunk.width, chunk.height) != (width, height))
+        {
+            info!(
+                "Screen size changed to {}x{}, starting a new chunk",
+                width, height
+            );
+            if let Some(chunk) = chunk.take() {
+                finish_chunk(chunk, &mut backoff).await;
+            }
         }
 
-        // Write encoded frames to FFmpeg
-        while let Ok(buffer) = receiver.try_recv() {
-            if let Some(stdin) = current_stdin.as_mut() {
-                if let Err(e) = stdin.write_all(buffer.as_slice()).await {
-                    error!("Failed to write frame to ffmpeg: {}", e);
-                    break;
-                }
-                frame_count += 1;
-                debug!("Wrote frame {} to FFmpeg", frame_count);
-
-                // Flush every second
-                if frame_count % fps as usize == 0 {
-                    debug!("Flushing FFmpeg input");
-                    if let Err(e) = stdin.flush().await {
-                        error!("Failed to flush FFmpeg input: {}", e);
+        if chunk.is_none() && backoff.waiting() {
+            FRAMES_DROPPED.with_label_values(&["ffmpeg_error"]).inc();
+            continue;
+        }
+        if chunk.is_none() {
+            debug!("Starting new FFmpeg process");
+            let opened = ChunkWriter::open(
+                output_path,
+                fps,
+                width,
+                height,
+                encoder,
+                &new_chunk_callback,
+                &chunk_finished_callback,
+            )
+            .await;
+            match opened {
+                Ok(new_chunk) => {
+                    if backoff.started() {
+                        FFMPEG_RESTARTS.inc();
                     }
+                    chunk = Some(new_chunk);
+                }
+                Err(e) => {
+                    error!("Failed to start FFmpeg process: {}", e);
+                    backoff.failed();
+                    FRAMES_DROPPED.with_label_values(&["ffmpeg_error"]).inc();
+                    continue;
                 }
             }
         }
-
-        // Yield to other tasks periodically
-        if frame_count % 100 == 0 {
-            tokio::task::yield_now().await;
+        let Some(current) = chunk.as_mut() else {
+            continue;
+        };
+
+        let captured_at = Utc::now()
+            - chrono::Duration::from_std(frame.timestamp.elapsed())
+                .unwrap_or_else(|_| chrono::Duration::zero());
+        // The pixels borrow the frame, which then moves on to the OCR queue
+        let written = current.write(&rgba_bytes(&frame.image), captured_at).await;
+        match written {
+            Ok(offset_index) => {
+                debug!(
+                    "Wrote frame {} to FFmpeg for {}",
+                    offset_index, current.file_path
+                );
+                queue_frame(
+                    ocr_frame_queue,
+                    EncodedFrame {
+                        frame,
+                        video_chunk_id: current.video_chunk_id,
+                        offset_index,
+                        captured_at,
+                    },
+                );
+            }
+            Err(e) => {
+                error!("Failed to write frame to ffmpeg: {}", e);
+                FRAMES_DROPPED.with_label_values(&["ffmpeg_error"]).inc();
+                if let Some(chunk) = chunk.take() {
+                    // ffmpeg is gone or stuck, the chunk can't end cleanly
+                    chunk.finish().await;
+                }
+                backoff.failed();
+            }
         }
     }
 
     // Close the final FFmpeg process
-    if let Some(mut child) = current_ffmpeg.take() {
-        drop(current_stdin.take()); // Ensure stdin is closed
-        child.wait().await.expect("ffmpeg process failed");
+    if let Some(chunk) = chunk.take() {
+        chunk.finish().await;
     }
 }
 
-async fn start_ffmpeg_process(output_file: &str, fps: f64) -> Result<Child, anyhow::Error> {
+/// Closes a chunk and arms the backoff if ffmpeg did not exit cleanly.
+async fn finish_chunk(chunk: ChunkWriter, backoff: &mut RestartBackoff) {
+    if chunk.finish().await {
+        backoff.reset();
+    } else {
+        backoff.failed();
+    }
+}
+
+/// Pixels of `image` as RGBA, without a copy when the capture already is.
+fn rgba_bytes(image: &DynamicImage) -> Cow<'_, [u8]> {
+    match image.as_rgba8() {
+        Some(rgba) => Cow::Borrowed(rgba.as_raw()),
+        None => Cow::Owned(image.to_rgba8().into_raw()),
+    }
+}
+
+/// Called once ffmpeg is done writing a chunk.
+#[allow(unused_variables)]
+pub(crate) fn finalize_chunk(file: String) {
+    #[cfg(feature = "encryption")]
+    tokio::spawn(async move {
+        crate::encryption::encrypt_finalized_chunk(&file).await;
+    });
+}
+
+/// Reads the capture time of every frame of a chunk from its sidecar, in frame order.
+pub async fn read_frame_timestamps(file_path: &str) -> Result<Vec<DateTime<Utc>>, anyhow::Error> {
+    let path = timestamps_path(file_path);
+    #[cfg(feature = "encryption")]
+    let media = crate::encryption::open_media(&path).await?;
+    #[cfg(feature = "encryption")]
+    let path = media.path().to_string();
+
+    let content = tokio::fs::read_to_string(&path).await?;
+    content
+        .lines()
+        .filter(|line| !line.is_empty())
+        .map(|line| Ok(DateTime::parse_from_rfc3339(line)?.with_timezone(&Utc)))
+        .collect()
+}
+
+async fn start_ffmpeg_process(
+    output_file: &str,
+    fps: f64,
+    width: u32,
+    height: u32,
+    encoder: &VideoEncoderConfig,
+) -> Result<Child, anyhow::Error> {
     // Overriding fps with max fps if over the max and warning user
     let fps = if fps > MAX_FPS {
         warn!("Overriding FPS from {} to {}", fps, MAX_FPS);
@@ -298,28 +643,16 @@ async fn start_ffmpeg_process(output_file: &str, fps: f64) -> Result<Child, anyh
         fps
     };
 
-    info!("Starting FFmpeg process for file: {}", output_file);
-    let mut command = Command::new(find_ffmpeg_path().unwrap());
+    info!(
+        "Starting FFmpeg process for file: {} ({})",
+        output_file, encoder.codec
+    );
+    let ffmpeg_path = find_ffmpeg_path().ok_or_else(|| anyhow::anyhow!("ffmpeg not found"))?;
+    let mut command = Command::new(ffmpeg_path);
     command
-        .args(&[
-            "-f",
-            "image2pipe",
-            "-vcodec",
-            "png",
-            "-r",
-            &fps.to_string(),
-            "-i",
-            "-",
-            "-vcodec",
-            "libx264",
-            "-preset",
-            "ultrafast",
-            "-pix_fmt",
-            "yuv420p",
-            "-crf",
-            "25",
-            output_file,
-        ])
+        .args(encoder.ffmpeg_args(width, height, fps, output_file))
+        // A chunk dropped without finishing, e.g. when startup fails halfway, takes ffmpeg along
+        .kill_on_drop(true)
         .stdin(Stdio::piped())
         .stdout(Stdio::piped())
         .stderr(Stdio::piped());
@@ -330,4 +663,50 @@ async fn start_ffmpeg_process(output_file: &str, fps: f64) -> Result<Child, anyh
     debug!("FFmpeg process spawned");
 
     Ok(child)
-}
\ No newline at end of file
+}
+
+/// Decodes the frame at `offset_index` of a video chunk and returns it PNG-encoded.
+pub async fn extract_frame(file_path: &str, offset_index: i64) -> Result<Vec<u8>, anyhow::Error> {
+    let ffmpeg_path =
+        find_ffmpeg_path().ok_or_else(|| anyhow::anyhow!("ffmpeg not found"))?;
+    let select_filter = format!("select=eq(n\\,{})", offset_index);
+
+    debug!("Extracting frame {} from {}", offset_index, file_path);
+    #[cfg(feature = "encryption")]
+    let media = crate::encryption::open_media(file_path).await?;
+    #[cfg(feature = "encryption")]
+    let input = media.path();
+    #[cfg(not(feature = "encryption"))]
+    let input = file_path;
+
+    let output = Command::new(ffmpeg_path)
+        .args([
+            "-i",
+            input,
+            "-vf",
+            select_filter.as_str(),
+            "-vframes",
+            "1",
+            "-f",
+            "image2pipe",
+            "-vcodec",
+            "png",
+            "-",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .output()
+        .await?;
+
+    if !output.status.success() || output.stdout.is_empty() {
+        error!("FFmpeg stderr: {}", String::from_utf8_lossy(&output.stderr));
+        return Err(anyhow::anyhow!(
+            "Failed to extract frame {} from {}",
+            offset_index,
+            file_path
+        ));
+    }
+
+    Ok(output.stdout)
+}
@@ -0,0 +1,455 @@
+// Sandboxed pipes: WebAssembly modules loaded from `<data dir>/pipes`, so processing plugins can
+// be shared without running native code. A module only sees what the host API below gives it.
+//
+// Module ABI (all strings are UTF-8 JSON, pointers and lengths are i32 into the module memory):
+//   exports  memory
+//            alloc(len) -> ptr                   the host writes events and results there
+//            on_event(ptr, len)                  {"type": "ocr" | "audio", ...} for every new event
+//   imports  screenpipe.log(ptr, len)
+//            screenpipe.search(ptr, len) -> len  {"q", "content_type", "limit", "offset"}, returns
+//                                                the result length (negative on error)
+//            screenpipe.read_result(ptr)         copies the last search result to ptr
+//            screenpipe.write_tag(ptr, len) -> status
+//                                                {"content_type", "source_id", "tag"}, 0 on success
+use std::collections::HashMap;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{anyhow, Context as _};
+use async_trait::async_trait;
+use log::{debug, error, info, warn};
+use serde::Deserialize;
+use serde_json::json;
+use tokio::sync::Mutex;
+use wasmtime::{
+    Caller, Config, Engine, Linker, Memory, Module, Store, StoreLimits, StoreLimitsBuilder,
+    TypedFunc,
+};
+
+use crate::pipes::{Pipe, PipeContext, PipeFrame, PipeTranscription};
+use crate::server::into_content_item;
+use crate::{ContentType, DatabaseManager};
+
+pub const PIPES_CONFIG_FILE: &str = "pipes.json";
+
+const DEFAULT_FUEL: u64 = 100_000_000;
+const DEFAULT_MEMORY_MB: usize = 64;
+const MAX_SEARCH_LIMIT: u32 = 100;
+// Yield back to tokio regularly so a busy module doesn't hog a worker thread
+const FUEL_YIELD_INTERVAL: u64 = 1_000_000;
+
+/// `<data dir>/pipes/pipes.json`. Modules are only loaded when listed here, e.g.
+/// `{ "pipes": { "todo-extractor": { "enabled": true, "fuel": 50000000, "memory_mb": 32 } } }`
+/// loads `todo-extractor.wasm`.
+#[derive(Debug, Default, Deserialize)]
+pub struct WasmPipesConfig {
+    #[serde(default)]
+    pub pipes: HashMap<String, WasmPipeConfig>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WasmPipeConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// Instructions budget for handling a single event
+    #[serde(default = "default_fuel")]
+    pub fuel: u64,
+    #[serde(default = "default_memory_mb")]
+    pub memory_mb: usize,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn default_fuel() -> u64 {
+    DEFAULT_FUEL
+}
+
+fn default_memory_mb() -> usize {
+    DEFAULT_MEMORY_MB
+}
+
+impl WasmPipesConfig {
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        serde_json::from_str(&content).with_context(|| format!("Invalid {}", path.display()))
+    }
+}
+
+/// Loads the enabled `.wasm` modules of `pipes_dir`. Modules that fail to compile are skipped.
+pub fn load_wasm_pipes(
+    pipes_dir: &Path,
+    db: Arc<DatabaseManager>,
+) -> anyhow::Result<Vec<Arc<dyn Pipe>>> {
+    let config = WasmPipesConfig::load(&pipes_dir.join(PIPES_CONFIG_FILE))?;
+    if !pipes_dir.exists() {
+        return Ok(Vec::new());
+    }
+
+    let engine = wasm_engine()?;
+
+    let mut pipes: Vec<Arc<dyn Pipe>> = Vec::new();
+    for entry in std::fs::read_dir(pipes_dir)? {
+        let path = entry?.path();
+        if path.extension().and_then(|ext| ext.to_str()) != Some("wasm") {
+            continue;
+        }
+        let Some(name) = path.file_stem().and_then(|stem| stem.to_str()) else {
+            continue;
+        };
+
+        match config.pipes.get(name) {
+            Some(pipe_config) if pipe_config.enabled => {
+                match WasmPipe::new(&engine, name, &path, pipe_config.clone(), Arc::clone(&db)) {
+                    Ok(pipe) => {
+                        info!("Loaded WASM pipe {} from {}", name, path.display());
+                        pipes.push(Arc::new(pipe));
+                    }
+                    Err(e) => error!("Failed to load WASM pipe {}: {}", name, e),
+                }
+            }
+            Some(_) => info!("WASM pipe {} is disabled", name),
+            None => info!(
+                "WASM pipe {} is not listed in {}, not loading it",
+                name, PIPES_CONFIG_FILE
+            ),
+        }
+    }
+    Ok(pipes)
+}
+
+/// The engine WASM pipes are compiled for, with async host calls and fuel metering.
+pub fn wasm_engine() -> anyhow::Result<Engine> {
+    let mut config = Config::new();
+    config.async_support(true).consume_fuel(true);
+    Engine::new(&config)
+}
+
+struct HostState {
+    pipe_name: String,
+    db: Arc<DatabaseManager>,
+    limits: StoreLimits,
+    last_result: Vec<u8>,
+}
+
+struct WasmInstance {
+    store: Store<HostState>,
+    memory: Memory,
+    alloc: TypedFunc<i32, i32>,
+    on_event: TypedFunc<(i32, i32), ()>,
+}
+
+pub struct WasmPipe {
+    name: String,
+    path: PathBuf,
+    config: WasmPipeConfig,
+    db: Arc<DatabaseManager>,
+    engine: Engine,
+    module: Module,
+    linker: Linker<HostState>,
+    // Instantiated lazily, and again after a trap since the module state can't be trusted anymore
+    instance: Mutex<Option<WasmInstance>>,
+}
+
+impl WasmPipe {
+    pub fn new(
+        engine: &Engine,
+        name: &str,
+        path: &Path,
+        config: WasmPipeConfig,
+        db: Arc<DatabaseManager>,
+    ) -> anyhow::Result<Self> {
+        let module = Module::from_file(engine, path)?;
+        let mut linker = Linker::new(engine);
+        add_host_functions(&mut linker)?;
+
+        Ok(Self {
+            name: name.to_string(),
+            path: path.to_path_buf(),
+            config,
+            db,
+            engine: engine.clone(),
+            module,
+            linker,
+            instance: Mutex::new(None),
+        })
+    }
+
+    async fn instantiate(&self) -> anyhow::Result<WasmInstance> {
+        let limits = StoreLimitsBuilder::new()
+            .memory_size(self.config.memory_mb * 1024 * 1024)
+            .instances(1)
+            .build();
+        let mut store = Store::new(
+            &self.engine,
+            HostState {
+                pipe_name: self.name.clone(),
+                db: Arc::clone(&self.db),
+                limits,
+                last_result: Vec::new(),
+            },
+        );
+        store.limiter(|state| &mut state.limits);
+        store.set_fuel(self.config.fuel)?;
+        store.fuel_async_yield_interval(Some(FUEL_YIELD_INTERVAL))?;
+
+        let instance = self
+            .linker
+            .instantiate_async(&mut store, &self.module)
+            .await?;
+        let memory = instance
+            .get_memory(&mut store, "memory")
+            .ok_or_else(|| anyhow!("module doesn't export its memory"))?;
+        let alloc = instance.get_typed_func::<i32, i32>(&mut store, "alloc")?;
+        let on_event = instance.get_typed_func::<(i32, i32), ()>(&mut store, "on_event")?;
+
+        debug!(
+            "Instantiated WASM pipe {} from {}",
+            self.name,
+            self.path.display()
+        );
+        Ok(WasmInstance {
+            store,
+            memory,
+            alloc,
+            on_event,
+        })
+    }
+
+    /// Passes `event` to the module's `on_event`, as done for every new frame and transcription.
+    pub async fn send_event(&self, event: serde_json::Value) -> anyhow::Result<()> {
+        let mut guard = self.instance.lock().await;
+        if guard.is_none() {
+            *guard = Some(self.instantiate().await?);
+        }
+        let instance = guard.as_mut().expect("instance was just set");
+
+        let result = call_on_event(instance, self.config.fuel, event.to_string().as_bytes()).await;
+        if result.is_err() {
+            // Out of fuel, out of memory or a bug in the module, start from a fresh instance
+            *guard = None;
+        }
+        result
+    }
+}
+
+async fn call_on_event(instance: &mut WasmInstance, fuel: u64, event: &[u8]) -> anyhow::Result<()> {
+    // Every event gets the full budget
+    instance.store.set_fuel(fuel)?;
+
+    let len = i32::try_from(event.len())?;
+    let ptr = instance.alloc.call_async(&mut instance.store, len).await?;
+    let range = guest_range(ptr, len, instance.memory.data_size(&instance.store))?;
+    instance.memory.data_mut(&mut instance.store)[range].copy_from_slice(event);
+    instance
+        .on_event
+        .call_async(&mut instance.store, (ptr, len))
+        .await
+}
+
+#[async_trait]
+impl Pipe for WasmPipe {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    async fn on_frame(&self, frame: &PipeFrame, _ctx: &PipeContext) -> anyhow::Result<()> {
+        self.send_event(json!({
+            "type": "ocr",
+            "frame_id": frame.frame_id,
+            "text": frame.capture.text,
+            "timestamp": frame.timestamp,
+        }))
+        .await
+    }
+
+    async fn on_transcription(
+        &self,
+        transcription: &PipeTranscription,
+        _ctx: &PipeContext,
+    ) -> anyhow::Result<()> {
+        self.send_event(json!({
+            "type": "audio",
+            "audio_chunk_id": transcription.audio_chunk_id,
+            "text": transcription.transcription,
+            "device": transcription.device,
+            "timestamp": transcription.timestamp,
+        }))
+        .await
+    }
+}
+
+#[derive(Deserialize)]
+struct SearchArgs {
+    #[serde(default)]
+    q: String,
+    #[serde(default)]
+    content_type: ContentType,
+    #[serde(default = "default_search_limit")]
+    limit: u32,
+    #[serde(default)]
+    offset: u32,
+}
+
+fn default_search_limit() -> u32 {
+    20
+}
+
+#[derive(Deserialize)]
+struct TagArgs {
+    content_type: ContentType,
+    source_id: i64,
+    tag: String,
+}
+
+fn add_host_functions(linker: &mut Linker<HostState>) -> anyhow::Result<()> {
+    linker.func_wrap(
+        "screenpipe",
+        "log",
+        |mut caller: Caller<'_, HostState>, ptr: i32, len: i32| -> anyhow::Result<()> {
+            let message = read_guest_bytes(&mut caller, ptr, len)?;
+            info!(
+                "[{}] {}",
+                caller.data().pipe_name,
+                String::from_utf8_lossy(&message)
+            );
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap_async(
+        "screenpipe",
+        "search",
+        |mut caller: Caller<'_, HostState>, (ptr, len): (i32, i32)| {
+            Box::new(async move {
+                let args = read_guest_bytes(&mut caller, ptr, len)?;
+                let args: SearchArgs = match serde_json::from_slice(&args) {
+                    Ok(args) => args,
+                    Err(e) => {
+                        warn!(
+                            "[{}] invalid search arguments: {}",
+                            caller.data().pipe_name,
+                            e
+                        );
+                        return Ok(-1);
+                    }
+                };
+
+                let db = Arc::clone(&caller.data().db);
+                let results = match db
+                    .search(
+                        &args.q,
+                        args.content_type,
+                        args.limit.min(MAX_SEARCH_LIMIT),
+                        args.offset,
+                        None,
+                        None,
+                    )
+                    .await
+                {
+                    Ok(results) => results,
+                    Err(e) => {
+                        error!("[{}] search failed: {}", caller.data().pipe_name, e);
+                        return Ok(-1);
+                    }
+                };
+
+                let items: Vec<_> = results.into_iter().map(into_content_item).collect();
+                let result = serde_json::to_vec(&items)?;
+                let result_len = i32::try_from(result.len())?;
+                caller.data_mut().last_result = result;
+                Ok::<_, anyhow::Error>(result_len)
+            })
+        },
+    )?;
+
+    linker.func_wrap(
+        "screenpipe",
+        "read_result",
+        |mut caller: Caller<'_, HostState>, ptr: i32| -> anyhow::Result<()> {
+            let memory = guest_memory(&mut caller)?;
+            let result = std::mem::take(&mut caller.data_mut().last_result);
+            let range = guest_range(ptr, i32::try_from(result.len())?, memory.data_size(&caller))?;
+            memory.data_mut(&mut caller)[range].copy_from_slice(&result);
+            Ok(())
+        },
+    )?;
+
+    linker.func_wrap_async(
+        "screenpipe",
+        "write_tag",
+        |mut caller: Caller<'_, HostState>, (ptr, len): (i32, i32)| {
+            Box::new(async move {
+                let args = read_guest_bytes(&mut caller, ptr, len)?;
+                let args: TagArgs = match serde_json::from_slice(&args) {
+                    Ok(args) => args,
+                    Err(e) => {
+                        warn!("[{}] invalid tag: {}", caller.data().pipe_name, e);
+                        return Ok(-1);
+                    }
+                };
+
+                let state = caller.data();
+                match state
+                    .db
+                    .insert_pipe_output(
+                        &state.pipe_name,
+                        args.content_type,
+                        args.source_id,
+                        "tag",
+                        &json!({ "tag": args.tag }),
+                    )
+                    .await
+                {
+                    Ok(_) => Ok::<_, anyhow::Error>(0i32),
+                    Err(e) => {
+                        error!("[{}] failed to write tag: {}", state.pipe_name, e);
+                        Ok(-1)
+                    }
+                }
+            })
+        },
+    )?;
+
+    Ok(())
+}
+
+fn guest_memory(caller: &mut Caller<'_, HostState>) -> anyhow::Result<Memory> {
+    caller
+        .get_export("memory")
+        .and_then(|export| export.into_memory())
+        .ok_or_else(|| anyhow!("module doesn't export its memory"))
+}
+
+fn read_guest_bytes(
+    caller: &mut Caller<'_, HostState>,
+    ptr: i32,
+    len: i32,
+) -> anyhow::Result<Vec<u8>> {
+    let memory = guest_memory(caller)?;
+    let range = guest_range(ptr, len, memory.data_size(&*caller))?;
+    Ok(memory.data(&*caller)[range].to_vec())
+}
+
+/// Bytes `ptr..ptr + len` of a memory of `memory_size` bytes, checked before anything is allocated
+/// or copied for them since both come from the module.
+fn guest_range(ptr: i32, len: i32, memory_size: usize) -> anyhow::Result<Range<usize>> {
+    let (Ok(start), Ok(len)) = (usize::try_from(ptr), usize::try_from(len)) else {
+        return Err(anyhow!("negative pointer or length ({}, {})", ptr, len));
+    };
+    match start.checked_add(len) {
+        Some(end) if end <= memory_size => Ok(start..end),
+        _ => Err(anyhow!(
+            "{} bytes at {} are out of bounds of the {} bytes of memory",
+            len,
+            start,
+            memory_size
+        )),
+    }
+}
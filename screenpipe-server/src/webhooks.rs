@@ -0,0 +1,462 @@
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use axum::{
+    extract::{Json as JsonExt, Path, State},
+    http::StatusCode,
+    response::Json as JsonResponse,
+};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use log::{debug, error, info, warn};
+use rand::RngCore;
+use regex::{Regex, RegexBuilder};
+use serde::Serialize;
+use serde_json::json;
+use sha2::Sha256;
+use tokio::sync::RwLock;
+
+use crate::db::{NewWebhookRule, WebhookDeadLetter, WebhookRule};
+use crate::pipes::{Pipe, PipeContext, PipeFrame, PipeTranscription};
+use crate::server::AppState;
+use crate::{ContentType, DatabaseManager};
+
+const MAX_ATTEMPTS: u32 = 3;
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(10);
+pub const SIGNATURE_HEADER: &str = "X-Screenpipe-Signature";
+
+type HmacSha256 = Hmac<Sha256>;
+
+/// A freshly inserted OCR row or transcription, as seen by the rules engine.
+#[derive(Debug, Clone)]
+pub struct NewContent {
+    pub content_type: ContentType,
+    /// frame_id for OCR, audio_chunk_id for audio
+    pub id: i64,
+    pub text: String,
+    pub timestamp: DateTime<Utc>,
+    pub device: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct WebhookPayload<'a> {
+    rule_id: i64,
+    rule_name: &'a str,
+    content_type: ContentType,
+    content_id: i64,
+    timestamp: DateTime<Utc>,
+    text: &'a str,
+    matches: Vec<String>,
+    device: Option<&'a str>,
+}
+
+struct CompiledRule {
+    rule: WebhookRule,
+    keywords: Vec<String>,
+    pattern: Option<Regex>,
+}
+
+impl CompiledRule {
+    fn new(rule: WebhookRule) -> Result<Self, regex::Error> {
+        let pattern = rule.pattern.as_deref().map(compile_pattern).transpose()?;
+        let keywords = rule.keywords.iter().map(|k| k.to_lowercase()).collect();
+        Ok(Self {
+            rule,
+            keywords,
+            pattern,
+        })
+    }
+
+    /// Returns the matched keywords / pattern matches, or None if the rule doesn't apply.
+    fn matches(&self, content: &NewContent) -> Option<Vec<String>> {
+        if !self.rule.enabled {
+            return None;
+        }
+        if self.rule.content_type != ContentType::All
+            && self.rule.content_type != content.content_type
+        {
+            return None;
+        }
+
+        let mut matches = Vec::new();
+        if !self.keywords.is_empty() {
+            let text = content.text.to_lowercase();
+            matches.extend(
+                self.rule
+                    .keywords
+                    .iter()
+                    .zip(&self.keywords)
+                    .filter(|(_, keyword)| text.contains(keyword.as_str()))
+                    .map(|(keyword, _)| keyword.clone()),
+            );
+            if matches.is_empty() {
+                return None;
+            }
+        }
+        if let Some(pattern) = &self.pattern {
+            let pattern_matches: Vec<String> = pattern
+                .find_iter(&content.text)
+                .map(|m| m.as_str().to_string())
+                .collect();
+            if pattern_matches.is_empty() {
+                return None;
+            }
+            matches.extend(pattern_matches);
+        }
+
+        Some(matches)
+    }
+}
+
+fn compile_pattern(pattern: &str) -> Result<Regex, regex::Error> {
+    RegexBuilder::new(pattern).case_insensitive(true).build()
+}
+
+/// Evaluates new OCR rows and transcriptions against the webhook rules stored in the DB
+/// and POSTs signed payloads for every match.
+pub struct WebhookEngine {
+    db: Arc<DatabaseManager>,
+    client: reqwest::Client,
+    rules: RwLock<Vec<Arc<CompiledRule>>>,
+}
+
+impl WebhookEngine {
+    pub async fn new(db: Arc<DatabaseManager>) -> Result<Arc<Self>, sqlx::Error> {
+        let client = reqwest::Client::builder()
+            .timeout(REQUEST_TIMEOUT)
+            .build()
+            .expect("Failed to build HTTP client");
+        let engine = Arc::new(Self {
+            db,
+            client,
+            rules: RwLock::new(Vec::new()),
+        });
+        engine.reload().await?;
+        Ok(engine)
+    }
+
+    /// Reloads rules from the database, must be called after every rule change.
+    pub async fn reload(&self) -> Result<(), sqlx::Error> {
+        let rules = self.db.get_webhook_rules().await?;
+        let compiled: Vec<Arc<CompiledRule>> = rules
+            .into_iter()
+            .filter_map(|rule| match CompiledRule::new(rule) {
+                Ok(rule) => Some(Arc::new(rule)),
+                Err(e) => {
+                    error!("Skipping webhook rule with invalid pattern: {}", e);
+                    None
+                }
+            })
+            .collect();
+        info!("Loaded {} webhook rules", compiled.len());
+        *self.rules.write().await = compiled;
+        Ok(())
+    }
+
+    /// Checks `content` against every rule and delivers matches in the background.
+    pub async fn evaluate(&self, content: NewContent) {
+        let rules = self.rules.read().await;
+        if rules.is_empty() {
+            return;
+        }
+
+        let content = Arc::new(content);
+        for rule in rules.iter() {
+            if let Some(matches) = rule.matches(&content) {
+                debug!(
+                    "Webhook rule {} matched {:?} {}",
+                    rule.rule.id, content.content_type, content.id
+                );
+                let db = Arc::clone(&self.db);
+                let client = self.client.clone();
+                let rule = Arc::clone(rule);
+                let content = Arc::clone(&content);
+                tokio::spawn(async move {
+                    deliver(&db, &client, &rule.rule, &content, matches).await;
+                });
+            }
+        }
+    }
+}
+
+// Webhooks get new content through the pipe machinery like any other in-process consumer
+#[async_trait]
+impl Pipe for WebhookEngine {
+    fn name(&self) -> &str {
+        "webhooks"
+    }
+
+    async fn on_frame(&self, frame: &PipeFrame, _ctx: &PipeContext) -> anyhow::Result<()> {
+        self.evaluate(NewContent {
+            content_type: ContentType::OCR,
+            id: frame.frame_id,
+            text: frame.capture.text.clone(),
+            timestamp: frame.timestamp,
+            device: None,
+        })
+        .await;
+        Ok(())
+    }
+
+    async fn on_transcription(
+        &self,
+        transcription: &PipeTranscription,
+        _ctx: &PipeContext,
+    ) -> anyhow::Result<()> {
+        self.evaluate(NewContent {
+            content_type: ContentType::Audio,
+            id: transcription.audio_chunk_id,
+            text: transcription.transcription.clone(),
+            timestamp: transcription.timestamp,
+            device: Some(transcription.device.clone()),
+        })
+        .await;
+        Ok(())
+    }
+}
+
+async fn deliver(
+    db: &DatabaseManager,
+    client: &reqwest::Client,
+    rule: &WebhookRule,
+    content: &NewContent,
+    matches: Vec<String>,
+) {
+    let payload = WebhookPayload {
+        rule_id: rule.id,
+        rule_name: &rule.name,
+        content_type: content.content_type,
+        content_id: content.id,
+        timestamp: content.timestamp,
+        text: &content.text,
+        matches,
+        device: content.device.as_deref(),
+    };
+    let body = match serde_json::to_string(&payload) {
+        Ok(body) => body,
+        Err(e) => {
+            error!("Failed to serialize webhook payload: {}", e);
+            return;
+        }
+    };
+    let signature = format!("sha256={}", sign_payload(&rule.secret, &body));
+
+    let mut last_error = String::new();
+    for attempt in 1..=MAX_ATTEMPTS {
+        let result = client
+            .post(&rule.url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header(SIGNATURE_HEADER, &signature)
+            .body(body.clone())
+            .send()
+            .await;
+
+        match result {
+            Ok(response) if response.status().is_success() => {
+                debug!("Delivered webhook {} on attempt {}", rule.id, attempt);
+                return;
+            }
+            Ok(response) => last_error = format!("HTTP {}", response.status()),
+            Err(e) => last_error = e.to_string(),
+        }
+
+        if attempt < MAX_ATTEMPTS {
+            warn!(
+                "Webhook {} delivery failed on attempt {}/{}: {}",
+                rule.id, attempt, MAX_ATTEMPTS, last_error
+            );
+            tokio::time::sleep(Duration::from_secs(1 << (attempt - 1))).await;
+        }
+    }
+
+    error!(
+        "Webhook {} delivery failed after {} attempts: {}, moving to dead letters",
+        rule.id, MAX_ATTEMPTS, last_error
+    );
+    if let Err(e) = db
+        .insert_webhook_dead_letter(rule.id, &rule.url, &body, &last_error, MAX_ATTEMPTS)
+        .await
+    {
+        error!("Failed to insert webhook dead letter: {}", e);
+    }
+}
+
+/// Hex-encoded HMAC-SHA256 of the request body, sent as `sha256=<hex>` in [`SIGNATURE_HEADER`].
+pub fn sign_payload(secret: &str, body: &str) -> String {
+    let mut mac =
+        HmacSha256::new_from_slice(secret.as_bytes()).expect("HMAC can take key of any size");
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn generate_secret() -> String {
+    let mut bytes = [0u8; 32];
+    rand::thread_rng().fill_bytes(&mut bytes);
+    hex::encode(bytes)
+}
+
+type ApiError = (StatusCode, JsonResponse<serde_json::Value>);
+
+fn internal_error(context: &str, e: impl std::fmt::Display) -> ApiError {
+    error!("{}: {}", context, e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        JsonResponse(json!({"error": format!("{}: {}", context, e)})),
+    )
+}
+
+fn not_found(id: i64) -> ApiError {
+    (
+        StatusCode::NOT_FOUND,
+        JsonResponse(json!({"error": format!("Webhook {} not found", id)})),
+    )
+}
+
+fn validate_rule(rule: &NewWebhookRule) -> Result<(), ApiError> {
+    let bad_request = |message: String| {
+        Err((
+            StatusCode::BAD_REQUEST,
+            JsonResponse(json!({ "error": message })),
+        ))
+    };
+
+    if !rule.url.starts_with("http://") && !rule.url.starts_with("https://") {
+        return bad_request(format!("Invalid webhook url: {}", rule.url));
+    }
+    if rule.keywords.iter().all(|k| k.trim().is_empty()) && rule.pattern.is_none() {
+        return bad_request("A webhook needs at least one keyword or a pattern".to_string());
+    }
+    if let Some(pattern) = &rule.pattern {
+        if let Err(e) = compile_pattern(pattern) {
+            return bad_request(format!("Invalid pattern: {}", e));
+        }
+    }
+    Ok(())
+}
+
+async fn reload_rules(state: &AppState) -> Result<(), ApiError> {
+    state
+        .webhooks
+        .reload()
+        .await
+        .map_err(|e| internal_error("Failed to reload webhook rules", e))
+}
+
+pub(crate) async fn list_webhooks(
+    State(state): State<Arc<AppState>>,
+) -> Result<JsonResponse<Vec<WebhookRule>>, ApiError> {
+    let rules = state
+        .db
+        .get_webhook_rules()
+        .await
+        .map_err(|e| internal_error("Failed to list webhooks", e))?;
+    Ok(JsonResponse(rules))
+}
+
+pub(crate) async fn get_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<JsonResponse<WebhookRule>, ApiError> {
+    match state.db.get_webhook_rule(id).await {
+        Ok(Some(rule)) => Ok(JsonResponse(rule)),
+        Ok(None) => Err(not_found(id)),
+        Err(e) => Err(internal_error("Failed to get webhook", e)),
+    }
+}
+
+/// A rule as returned on creation, the only time its secret is shown.
+#[derive(Serialize)]
+pub(crate) struct CreatedWebhook {
+    #[serde(flatten)]
+    rule: WebhookRule,
+    secret: String,
+}
+
+pub(crate) async fn create_webhook(
+    State(state): State<Arc<AppState>>,
+    JsonExt(payload): JsonExt<NewWebhookRule>,
+) -> Result<(StatusCode, JsonResponse<CreatedWebhook>), ApiError> {
+    validate_rule(&payload)?;
+    let secret = payload.secret.clone().unwrap_or_else(generate_secret);
+    let id = state
+        .db
+        .insert_webhook_rule(&payload, &secret)
+        .await
+        .map_err(|e| internal_error("Failed to create webhook", e))?;
+    reload_rules(&state).await?;
+
+    info!("Created webhook {} ({})", id, payload.name);
+    match state.db.get_webhook_rule(id).await {
+        Ok(Some(rule)) => {
+            let secret = rule.secret.clone();
+            Ok((
+                StatusCode::CREATED,
+                JsonResponse(CreatedWebhook { rule, secret }),
+            ))
+        }
+        Ok(None) => Err(not_found(id)),
+        Err(e) => Err(internal_error("Failed to get webhook", e)),
+    }
+}
+
+pub(crate) async fn update_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    JsonExt(payload): JsonExt<NewWebhookRule>,
+) -> Result<JsonResponse<WebhookRule>, ApiError> {
+    validate_rule(&payload)?;
+    let existing = match state.db.get_webhook_rule(id).await {
+        Ok(Some(rule)) => rule,
+        Ok(None) => return Err(not_found(id)),
+        Err(e) => return Err(internal_error("Failed to get webhook", e)),
+    };
+    // Keep the existing secret unless a new one is provided
+    let secret = payload.secret.clone().unwrap_or(existing.secret);
+    let updated = state
+        .db
+        .update_webhook_rule(id, &payload, &secret)
+        .await
+        .map_err(|e| internal_error("Failed to update webhook", e))?;
+    if !updated {
+        return Err(not_found(id));
+    }
+    reload_rules(&state).await?;
+
+    info!("Updated webhook {}", id);
+    match state.db.get_webhook_rule(id).await {
+        Ok(Some(rule)) => Ok(JsonResponse(rule)),
+        Ok(None) => Err(not_found(id)),
+        Err(e) => Err(internal_error("Failed to get webhook", e)),
+    }
+}
+
+pub(crate) async fn delete_webhook(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<StatusCode, ApiError> {
+    let deleted = state
+        .db
+        .delete_webhook_rule(id)
+        .await
+        .map_err(|e| internal_error("Failed to delete webhook", e))?;
+    if !deleted {
+        return Err(not_found(id));
+    }
+    reload_rules(&state).await?;
+
+    info!("Deleted webhook {}", id);
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(crate) async fn list_webhook_dead_letters(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Result<JsonResponse<Vec<WebhookDeadLetter>>, ApiError> {
+    let dead_letters = state
+        .db
+        .get_webhook_dead_letters(id)
+        .await
+        .map_err(|e| internal_error("Failed to list webhook dead letters", e))?;
+    Ok(JsonResponse(dead_letters))
+}
@@ -0,0 +1,144 @@
+// Word boxes of OCR'd frames, stored one row per word so a client can draw search matches over
+// the frame image.
+use std::sync::Arc;
+
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Json as JsonResponse, Response},
+};
+use log::error;
+use rusty_tesseract::DataOutput;
+use screenpipe_vision::{BoundingBox, LayoutWord, OcrDocument};
+use serde::Deserialize;
+use serde_json::json;
+
+use crate::db::OcrWord;
+use crate::server::AppState;
+
+/// Words of a Tesseract output, blank ones left out.
+pub fn ocr_words(data_output: &DataOutput) -> Vec<OcrWord> {
+    data_output
+        .data
+        .iter()
+        .filter(|record| record.level == 5 && !record.text.trim().is_empty())
+        .map(|record| OcrWord {
+            text: record.text.clone(),
+            left: record.left as i64,
+            top: record.top as i64,
+            width: record.width as i64,
+            height: record.height as i64,
+            conf: record.conf as f64,
+            block_num: record.block_num as i64,
+            par_num: record.par_num as i64,
+            line_num: record.line_num as i64,
+            word_num: record.word_num as i64,
+        })
+        .collect()
+}
+
+/// Text of a frame in blocks and lines, rebuilt from its words, see `OcrDocument`.
+pub fn ocr_layout(words: Vec<OcrWord>) -> OcrDocument {
+    OcrDocument::from_words(words.into_iter().map(|word| {
+        let numbers = (
+            word.block_num as i32,
+            word.par_num as i32,
+            word.line_num as i32,
+        );
+        let word = LayoutWord {
+            text: word.text,
+            bbox: BoundingBox {
+                left: word.left as i32,
+                top: word.top as i32,
+                width: word.width as i32,
+                height: word.height as i32,
+            },
+            conf: word.conf as f32,
+        };
+        (numbers, word)
+    }))
+}
+
+/// Words containing any of the space separated terms of `query`, ignoring case.
+pub fn matching_words(words: Vec<OcrWord>, query: &str) -> Vec<OcrWord> {
+    let terms: Vec<String> = query.split_whitespace().map(str::to_lowercase).collect();
+    if terms.is_empty() {
+        return words;
+    }
+    words
+        .into_iter()
+        .filter(|word| {
+            let text = word.text.to_lowercase();
+            terms.iter().any(|term| text.contains(term.as_str()))
+        })
+        .collect()
+}
+
+#[derive(Debug, Deserialize)]
+pub(crate) struct WordsQuery {
+    /// Only return the words matching this search
+    q: Option<String>,
+}
+
+pub(crate) async fn get_frame_words(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+    Query(query): Query<WordsQuery>,
+) -> Response {
+    match state.db.get_frame(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                JsonResponse(json!({ "error": "Frame not found" })),
+            )
+                .into_response()
+        }
+        Err(e) => return words_error(e),
+    }
+
+    match state.db.get_ocr_words(id).await {
+        Ok(words) => {
+            let words = match &query.q {
+                Some(q) => matching_words(words, q),
+                None => words,
+            };
+            JsonResponse(json!({ "frame_id": id, "words": words })).into_response()
+        }
+        Err(e) => words_error(e),
+    }
+}
+
+/// Text of a frame in blocks, lines and words with their boxes, see `ocr_layout`.
+pub(crate) async fn get_frame_layout(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<i64>,
+) -> Response {
+    match state.db.get_frame(id).await {
+        Ok(Some(_)) => {}
+        Ok(None) => {
+            return (
+                StatusCode::NOT_FOUND,
+                JsonResponse(json!({ "error": "Frame not found" })),
+            )
+                .into_response()
+        }
+        Err(e) => return words_error(e),
+    }
+
+    match state.db.get_ocr_words(id).await {
+        Ok(words) => {
+            JsonResponse(json!({ "frame_id": id, "layout": ocr_layout(words) })).into_response()
+        }
+        Err(e) => words_error(e),
+    }
+}
+
+fn words_error(e: sqlx::Error) -> Response {
+    error!("Failed to get OCR words: {}", e);
+    (
+        StatusCode::INTERNAL_SERVER_ERROR,
+        JsonResponse(json!({ "error": format!("Failed to get OCR words: {}", e) })),
+    )
+        .into_response()
+}
@@ -0,0 +1,201 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::Arc;
+
+    use axum::http::Method;
+    use screenpipe_server::{
+        generate_token, required_scope, AuthPlugin, DatabaseManager, PluginRegistry, Scope, Server,
+        WebhookEngine,
+    };
+    use tokio::net::TcpListener;
+
+    struct TestServer {
+        url: String,
+        db: Arc<DatabaseManager>,
+    }
+
+    /// Serves the API behind the auth plugin, with `cors_origins` as given on the command line.
+    async fn start_server(allow_anonymous: bool, cors_origins: Vec<String>) -> TestServer {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let (audio_tx, _audio_rx) = tokio::sync::mpsc::channel(1);
+        let webhooks = WebhookEngine::new(db.clone()).await.unwrap();
+        let mut plugins = PluginRegistry::new();
+        plugins.register(AuthPlugin::new(db.clone(), allow_anonymous));
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Server::new(
+            db.clone(),
+            addr,
+            Arc::new(AtomicBool::new(true)),
+            audio_tx,
+            webhooks,
+            cors_origins,
+        )
+        .router(HashMap::new(), plugins);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        TestServer {
+            url: format!("http://{}", addr),
+            db,
+        }
+    }
+
+    async fn get(server: &TestServer, path: &str, token: Option<&str>) -> u16 {
+        let mut request = reqwest::Client::new().get(format!("{}{}", server.url, path));
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        request.send().await.unwrap().status().as_u16()
+    }
+
+    async fn create_token(server: &TestServer, scopes: &[Scope]) -> String {
+        let (token, hash) = generate_token();
+        server
+            .db
+            .insert_api_token("test", &hash, scopes)
+            .await
+            .unwrap();
+        token
+    }
+
+    #[test]
+    fn test_required_scope() {
+        for (method, path, scope) in [
+            (Method::GET, "/health", None),
+            (Method::POST, "/mcp", Some(Scope::ReadSearch)),
+            (Method::GET, "/search", Some(Scope::ReadSearch)),
+            (Method::GET, "/export", Some(Scope::ReadSearch)),
+            (Method::GET, "/timeline", Some(Scope::ReadSearch)),
+            (Method::GET, "/stats", Some(Scope::ReadSearch)),
+            (Method::GET, "/metrics", Some(Scope::ReadSearch)),
+            (Method::GET, "/audio/list", Some(Scope::ReadSearch)),
+            (Method::POST, "/audio/status", Some(Scope::ReadSearch)),
+            (Method::GET, "/vision/status", Some(Scope::ReadSearch)),
+            (Method::GET, "/audio/subtitles", Some(Scope::ReadSearch)),
+            (
+                Method::GET,
+                "/audio/chunks/3/subtitles",
+                Some(Scope::ReadSearch),
+            ),
+            (Method::POST, "/audio/start", Some(Scope::ControlRecording)),
+            (Method::POST, "/audio/stop", Some(Scope::ControlRecording)),
+            (Method::POST, "/vision/start", Some(Scope::ControlRecording)),
+            (Method::POST, "/vision/stop", Some(Scope::ControlRecording)),
+            (Method::GET, "/frames/3/words", Some(Scope::ReadMedia)),
+            (Method::GET, "/frames/3/layout", Some(Scope::ReadMedia)),
+            (Method::POST, "/video/export", Some(Scope::ReadMedia)),
+            (Method::GET, "/video/playlist.m3u8", Some(Scope::ReadMedia)),
+            (
+                Method::GET,
+                "/video/segments/3/init.mp4",
+                Some(Scope::ReadMedia),
+            ),
+            (Method::GET, "/audio/chunks/3", Some(Scope::ReadMedia)),
+            (Method::GET, "/webhooks", Some(Scope::Admin)),
+            (Method::DELETE, "/webhooks/3", Some(Scope::Admin)),
+            // Routes added later are closed until they are given a scope
+            (Method::GET, "/something-new", Some(Scope::Admin)),
+        ] {
+            assert_eq!(required_scope(&method, path), scope, "{} {}", method, path);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_tokens_and_scopes() {
+        let server = start_server(false, vec![]).await;
+        let search = create_token(&server, &[Scope::ReadSearch]).await;
+        let admin = create_token(&server, &[Scope::Admin]).await;
+
+        assert_eq!(get(&server, "/health", None).await, 200);
+        assert_eq!(get(&server, "/stats", None).await, 401);
+        assert_eq!(get(&server, "/stats", Some("sp_not_a_token")).await, 401);
+        assert_eq!(get(&server, "/stats", Some(&search)).await, 200);
+        assert_eq!(get(&server, "/webhooks", Some(&search)).await, 403);
+        assert_eq!(get(&server, "/webhooks", Some(&admin)).await, 200);
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_access_until_first_token() {
+        let server = start_server(true, vec![]).await;
+        assert_eq!(get(&server, "/webhooks", None).await, 200);
+
+        let token = create_token(&server, &[Scope::ReadSearch]).await;
+        assert_eq!(get(&server, "/webhooks", None).await, 401);
+        assert_eq!(get(&server, "/stats", Some(&token)).await, 200);
+
+        // Without anonymous access a token is always needed
+        let server = start_server(false, vec![]).await;
+        assert_eq!(get(&server, "/stats", None).await, 401);
+    }
+
+    /// The origin the API allows for a request coming from `origin`, if any.
+    async fn allowed_origin(server: &TestServer, origin: &str) -> Option<String> {
+        let response = reqwest::Client::new()
+            .get(format!("{}/health", server.url))
+            .header("Origin", origin)
+            .send()
+            .await
+            .unwrap();
+        response
+            .headers()
+            .get("access-control-allow-origin")
+            .map(|value| value.to_str().unwrap().to_string())
+    }
+
+    #[tokio::test]
+    async fn test_cors_origins() {
+        // Only localhost without an allowlist
+        let server = start_server(true, vec![]).await;
+        for origin in [
+            "http://localhost:3000",
+            "http://127.0.0.1:8080",
+            "http://[::1]:5173",
+        ] {
+            assert_eq!(
+                allowed_origin(&server, origin).await.as_deref(),
+                Some(origin)
+            );
+        }
+        for origin in ["https://example.com", "http://localhost.example.com"] {
+            assert_eq!(allowed_origin(&server, origin).await, None, "{}", origin);
+        }
+
+        let server = start_server(true, vec!["https://app.example.com".to_string()]).await;
+        assert_eq!(
+            allowed_origin(&server, "https://app.example.com")
+                .await
+                .as_deref(),
+            Some("https://app.example.com")
+        );
+        assert_eq!(allowed_origin(&server, "http://localhost:3000").await, None);
+
+        let server = start_server(true, vec!["*".to_string()]).await;
+        assert_eq!(
+            allowed_origin(&server, "https://example.com")
+                .await
+                .as_deref(),
+            Some("*")
+        );
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_needs_no_token() {
+        let server = start_server(false, vec![]).await;
+        let response = reqwest::Client::new()
+            .request(reqwest::Method::OPTIONS, format!("{}/search", server.url))
+            .header("Origin", "http://localhost:3000")
+            .header("Access-Control-Request-Method", "GET")
+            .header("Access-Control-Request-Headers", "authorization")
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status().as_u16(), 200);
+        assert_eq!(
+            response.headers()["access-control-allow-origin"],
+            "http://localhost:3000"
+        );
+    }
+}
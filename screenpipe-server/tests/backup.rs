@@ -0,0 +1,115 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+
+    use chrono::Utc;
+    use screenpipe_server::{
+        create_backup, import_backup, timestamps_path, verify_backup, ContentType, DatabaseManager,
+        SearchResult,
+    };
+
+    async fn setup_data_dir(base_dir: &Path, text: &str) -> DatabaseManager {
+        let data_dir = base_dir.join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        let db = DatabaseManager::new(&base_dir.join("db.sqlite").to_string_lossy())
+            .await
+            .unwrap();
+
+        let video_path = data_dir.join("monitor_1.mp4");
+        fs::write(&video_path, text).unwrap();
+        fs::write(timestamps_path(&video_path.to_string_lossy()), text).unwrap();
+        let video_chunk_id = db
+            .insert_video_chunk(&video_path.to_string_lossy(), 1.0)
+            .await
+            .unwrap();
+        let frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id, text, "", "", "", &[])
+            .await
+            .unwrap();
+
+        let audio_path = data_dir.join("microphone.mp4");
+        fs::write(&audio_path, text).unwrap();
+        let audio_chunk_id = db
+            .insert_audio_chunk(&audio_path.to_string_lossy())
+            .await
+            .unwrap();
+        db.insert_audio_transcription(audio_chunk_id, text, 0)
+            .await
+            .unwrap();
+        db
+    }
+
+    #[tokio::test]
+    async fn test_backup_and_import_remaps_ids() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = setup_data_dir(source_dir.path(), "from the backup").await;
+        let backup_dir = create_backup(&source, source_dir.path()).await.unwrap();
+
+        let manifest = verify_backup(&backup_dir).await.unwrap();
+        assert_eq!(manifest.files.len(), 4);
+        assert_eq!(manifest.files[0].path, "db.sqlite");
+        assert_eq!(
+            manifest.files[2].path,
+            "media/video/monitor_1.mp4.timestamps"
+        );
+
+        let target_dir = tempfile::tempdir().unwrap();
+        let target = setup_data_dir(target_dir.path(), "already here").await;
+        let stats = import_backup(&target, &backup_dir, target_dir.path())
+            .await
+            .unwrap();
+        assert_eq!(stats.video_chunks, 1);
+        assert_eq!(stats.frames, 1);
+        assert_eq!(stats.audio_chunks, 1);
+        assert_eq!(stats.audio_transcriptions, 1);
+
+        let results = target
+            .search("from the backup", ContentType::All, 10, 0, None, None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+        for result in results {
+            let (frame_or_chunk, file_path) = match result {
+                SearchResult::OCR(ocr) => (ocr.frame_id, ocr.file_path),
+                SearchResult::Audio(audio) => (audio.audio_chunk_id, audio.file_path),
+            };
+            // The existing rows keep id 1, imported ones come after
+            assert_eq!(frame_or_chunk, 2);
+            // Same name as the existing chunk, so the copy got a suffix
+            assert!(file_path.ends_with("-1.mp4"), "{}", file_path);
+            assert_eq!(fs::read_to_string(&file_path).unwrap(), "from the backup");
+        }
+        // The frame timestamps follow their chunk
+        let video_chunks = target.get_video_chunks().await.unwrap();
+        let (_, imported_video) = video_chunks.last().unwrap();
+        assert_eq!(
+            fs::read_to_string(timestamps_path(imported_video)).unwrap(),
+            "from the backup"
+        );
+
+        let results = target
+            .search("already here", ContentType::All, 10, 0, None, None)
+            .await
+            .unwrap();
+        assert_eq!(results.len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_import_rejects_tampered_backup() {
+        let source_dir = tempfile::tempdir().unwrap();
+        let source = setup_data_dir(source_dir.path(), "original").await;
+        let backup_dir = create_backup(&source, source_dir.path()).await.unwrap();
+        fs::write(backup_dir.join("media/video/monitor_1.mp4"), "tampered").unwrap();
+
+        let target_dir = tempfile::tempdir().unwrap();
+        let target = setup_data_dir(target_dir.path(), "already here").await;
+        assert!(import_backup(&target, &backup_dir, target_dir.path())
+            .await
+            .is_err());
+        assert_eq!(target.get_video_chunks().await.unwrap().len(), 1);
+    }
+}
@@ -0,0 +1,206 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{DateTime, TimeZone, Utc};
+    use screenpipe_server::{
+        clip_offset, concat_script, plan_clip, video_filter, ClipChunk, ClipOptions, ClipPiece,
+        VideoSegment,
+    };
+
+    fn start() -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 7, 26, 10, 0, 0).unwrap()
+    }
+
+    /// A chunk with frames captured `seconds` after `start()`.
+    fn chunk(id: i64, file_path: &str, seconds: &[i64], fps: Option<f64>) -> ClipChunk {
+        let frame_times: Vec<_> = seconds
+            .iter()
+            .map(|seconds| start() + chrono::Duration::seconds(*seconds))
+            .collect();
+        ClipChunk {
+            segment: VideoSegment {
+                video_chunk_id: id,
+                file_path: file_path.to_string(),
+                start_time: frame_times[0],
+                end_time: *frame_times.last().unwrap(),
+                frames: frame_times.len() as i64,
+                fps,
+            },
+            frame_times,
+        }
+    }
+
+    /// A minute long chunk at 10:00 and a 30 seconds one at 10:05, a frame every second, cut from
+    /// 10:00:30 to 10:05:10.
+    fn pieces() -> Vec<ClipPiece> {
+        let chunks = vec![
+            chunk(1, "a.mp4", &(0..60).collect::<Vec<_>>(), None),
+            chunk(2, "b.mp4", &(300..330).collect::<Vec<_>>(), Some(1.0)),
+        ];
+        plan_clip(
+            &chunks,
+            start() + chrono::Duration::seconds(30),
+            start() + chrono::Duration::seconds(310),
+        )
+    }
+
+    #[test]
+    fn test_plan_clip() {
+        assert_eq!(
+            pieces(),
+            vec![
+                ClipPiece {
+                    file_path: "a.mp4".to_string(),
+                    inpoint: 30.0,
+                    outpoint: None,
+                    start_time: start() + chrono::Duration::seconds(30),
+                    offset: 0.0,
+                    duration: 30.0,
+                },
+                ClipPiece {
+                    file_path: "b.mp4".to_string(),
+                    inpoint: 0.0,
+                    outpoint: Some(10.0),
+                    start_time: start() + chrono::Duration::minutes(5),
+                    offset: 30.0,
+                    duration: 10.0,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_plan_clip_with_irregular_frames() {
+        // 6 frames of video, 6 seconds at 1 fps, captured over 5 minutes
+        let chunks = vec![chunk(1, "a.mp4", &[0, 1, 2, 60, 61, 300], Some(1.0))];
+
+        // The frame captured at 10:00:02 is still on screen at 10:00:30
+        let pieces = plan_clip(
+            &chunks,
+            start() + chrono::Duration::seconds(30),
+            start() + chrono::Duration::seconds(200),
+        );
+        assert_eq!(
+            pieces,
+            vec![ClipPiece {
+                file_path: "a.mp4".to_string(),
+                inpoint: 2.0,
+                outpoint: Some(5.0),
+                start_time: start() + chrono::Duration::seconds(2),
+                offset: 0.0,
+                duration: 3.0,
+            }]
+        );
+
+        // Up to the end of the video, not 10 minutes past it
+        let pieces = plan_clip(
+            &chunks,
+            start() + chrono::Duration::seconds(61),
+            start() + chrono::Duration::seconds(600),
+        );
+        assert_eq!(pieces[0].inpoint, 4.0);
+        assert_eq!(pieces[0].outpoint, None);
+        assert_eq!(pieces[0].duration, 2.0);
+
+        // Nothing captured in the range
+        assert!(plan_clip(
+            &chunks,
+            start() - chrono::Duration::seconds(60),
+            start() - chrono::Duration::seconds(30),
+        )
+        .is_empty());
+    }
+
+    #[test]
+    fn test_clip_offset_skips_gaps() {
+        let pieces = pieces();
+        let at = |seconds| clip_offset(&pieces, start() + chrono::Duration::seconds(seconds));
+
+        assert_eq!(at(0), 0.0);
+        assert_eq!(at(45), 15.0);
+        assert_eq!(at(120), 30.0);
+        assert_eq!(at(305), 35.0);
+        assert_eq!(at(400), 40.0);
+    }
+
+    #[test]
+    fn test_concat_script() {
+        let paths = vec!["/data/a.mp4".to_string(), "/data/it's.mp4".to_string()];
+
+        assert_eq!(
+            concat_script(&pieces(), &paths),
+            "ffconcat version 1.0
+file '/data/a.mp4'
+inpoint 30.000
+duration 30.000
+file '/data/it'\\''s.mp4'
+inpoint 0.000
+outpoint 10.000
+duration 10.000
+"
+        );
+    }
+
+    #[test]
+    fn test_video_filter() {
+        let pieces = pieces();
+        assert_eq!(
+            video_filter(&pieces, 1280, 720, false, 1.0),
+            "[0:v]scale=1280:720:force_original_aspect_ratio=decrease,pad=1280:720:(ow-iw)/2:(oh-ih)/2,setsar=1[v]"
+        );
+
+        let filter = video_filter(&pieces, 1280, 720, true, 60.0);
+        assert_eq!(filter.matches("drawtext=").count(), 2);
+        // The second piece starts 30 seconds in the clip and was captured at 10:05
+        assert!(filter.contains(&format!(
+            "enable='between(t,30.000,40.000)':text='%{{pts\\:gmtime\\:{}.000}}'",
+            start().timestamp() + 300 - 30
+        )));
+        assert!(filter.ends_with(",setpts=PTS/60[v]"));
+    }
+
+    #[test]
+    fn test_validate_clip_options() {
+        let options = ClipOptions {
+            start_time: start(),
+            end_time: start() + chrono::Duration::hours(1),
+            monitor: None,
+            speed: 1.0,
+            timestamps: false,
+            audio: true,
+        };
+        assert!(options.validate().is_ok());
+        assert_eq!(options.file_name(), "screenpipe-20240726-100000.mp4");
+
+        assert!(ClipOptions {
+            speed: 60.0,
+            ..options.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(ClipOptions {
+            speed: 0.0,
+            audio: false,
+            ..options.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(ClipOptions {
+            monitor: Some(0),
+            ..options.clone()
+        }
+        .validate()
+        .is_ok());
+        assert!(ClipOptions {
+            monitor: Some(1),
+            ..options.clone()
+        }
+        .validate()
+        .is_err());
+        assert!(ClipOptions {
+            end_time: start(),
+            ..options
+        }
+        .validate()
+        .is_err());
+    }
+}
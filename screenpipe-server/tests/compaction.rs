@@ -0,0 +1,114 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::path::Path;
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use screenpipe_server::{
+        timestamps_path, CompactionTier, Compactor, DatabaseManager, VideoEncoderConfig, VideoScale,
+    };
+
+    #[test]
+    fn test_parse_tier() {
+        let tier: CompactionTier = "7d,scale=0.5,keep=2,crf=35".parse().unwrap();
+        assert_eq!(
+            tier,
+            CompactionTier {
+                min_age: Duration::from_secs(7 * 24 * 3600),
+                scale: Some(VideoScale::Factor(0.5)),
+                keep_every: 2,
+                crf: Some(35),
+            }
+        );
+
+        let tier: CompactionTier = "12h".parse().unwrap();
+        assert_eq!(tier.min_age, Duration::from_secs(12 * 3600));
+        assert_eq!(tier.scale, None);
+        assert_eq!(tier.keep_every, 1);
+        assert_eq!(tier.crf, None);
+    }
+
+    #[test]
+    fn test_parse_invalid_tier() {
+        assert!("".parse::<CompactionTier>().is_err());
+        assert!("7".parse::<CompactionTier>().is_err());
+        assert!("7y".parse::<CompactionTier>().is_err());
+        assert!("7d,keep=0".parse::<CompactionTier>().is_err());
+        assert!("7d,scale=2".parse::<CompactionTier>().is_err());
+        assert!("7d,fps=1".parse::<CompactionTier>().is_err());
+        assert!("7d,crf".parse::<CompactionTier>().is_err());
+        assert!("99999999999999999w".parse::<CompactionTier>().is_err());
+    }
+
+    #[tokio::test]
+    async fn test_tier_keep_must_be_a_multiple() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let tiers = |specs: &[&str]| -> Vec<CompactionTier> {
+            specs.iter().map(|spec| spec.parse().unwrap()).collect()
+        };
+
+        // Keeping 1 frame out of 3 from a chunk with every other frame left drops nothing
+        let result = Compactor::new(
+            db.clone(),
+            tiers(&["7d,keep=2", "30d,keep=3"]),
+            VideoEncoderConfig::default(),
+        );
+        assert!(result.is_err());
+        // Tiers are ordered by age, not as given
+        let result = Compactor::new(
+            db.clone(),
+            tiers(&["30d,keep=3", "7d,keep=2"]),
+            VideoEncoderConfig::default(),
+        );
+        assert!(result.is_err());
+
+        for specs in [
+            &["7d,keep=2", "30d,keep=6"][..],
+            &["7d,scale=0.5", "30d,keep=4"],
+            &["30d,keep=10", "7d,keep=2"],
+        ] {
+            let result = Compactor::new(db.clone(), tiers(specs), VideoEncoderConfig::default());
+            assert!(result.is_ok(), "{:?}", specs);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_run_finishes_recorded_compactions_only() {
+        let dir = tempfile::tempdir().unwrap();
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let path = |name: &str| dir.path().join(name).to_string_lossy().into_owned();
+
+        // Interrupted after the database update
+        let recorded = path("recorded.mp4");
+        let recorded_id = db.insert_video_chunk(&recorded, 1.0).await.unwrap();
+        db.set_video_chunk_tier(recorded_id, 1, 2).await.unwrap();
+        fs::write(&recorded, "original").unwrap();
+        fs::write(timestamps_path(&recorded), "original").unwrap();
+        fs::write(format!("{}.tier1.mp4", recorded), "compacted").unwrap();
+        fs::write(format!("{}.tier1", timestamps_path(&recorded)), "compacted").unwrap();
+        // Interrupted before
+        let unrecorded = path("unrecorded.mp4");
+        db.insert_video_chunk(&unrecorded, 1.0).await.unwrap();
+        fs::write(&unrecorded, "original").unwrap();
+        fs::write(format!("{}.tier1.mp4", unrecorded), "compacted").unwrap();
+
+        // Nothing is old enough to be compacted, ffmpeg isn't needed
+        let compactor = Compactor::new(
+            db,
+            vec!["520w,keep=2".parse().unwrap()],
+            VideoEncoderConfig::default(),
+        )
+        .unwrap();
+        assert_eq!(compactor.run_once().await.unwrap().chunks, 0);
+
+        assert_eq!(fs::read_to_string(&recorded).unwrap(), "compacted");
+        assert_eq!(
+            fs::read_to_string(timestamps_path(&recorded)).unwrap(),
+            "compacted"
+        );
+        assert!(!Path::new(&format!("{}.tier1.mp4", recorded)).exists());
+        assert_eq!(fs::read_to_string(&unrecorded).unwrap(), "original");
+        assert!(!Path::new(&format!("{}.tier1.mp4", unrecorded)).exists());
+    }
+}
@@ -1,7 +1,10 @@
 #[cfg(test)]
 mod tests {
     use chrono::Utc;
-    use screenpipe_server::{ContentType, DatabaseManager, SearchResult};
+    use screenpipe_server::{
+        generate_token, hash_token, ChunkStatus, ContentType, DatabaseManager, NewWebhookRule,
+        OcrWord, Scope, SearchResult,
+    };
 
     async fn setup_test_db() -> DatabaseManager {
         DatabaseManager::new("sqlite::memory:").await.unwrap()
@@ -10,9 +13,14 @@ mod tests {
     #[tokio::test]
     async fn test_insert_and_search_ocr() {
         let db = setup_test_db().await;
-        let _ = db.insert_video_chunk("test_video.mp4").await.unwrap();
-        let frame_id = db.insert_frame().await.unwrap();
-        db.insert_ocr_text(frame_id, "Hello, world!", "", "", "").await.unwrap();
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id, "Hello, world!", "", "", "", &[])
+            .await
+            .unwrap();
 
         let results = db
             .search("Hello", ContentType::OCR, 100, 0, None, None)
@@ -53,9 +61,12 @@ mod tests {
         let db = setup_test_db().await;
 
         // Insert OCR data
-        let _ = db.insert_video_chunk("test_video.mp4").await.unwrap();
-        let frame_id = db.insert_frame().await.unwrap();
-        db.insert_ocr_text(frame_id, "Hello from OCR", "", "", "")
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id, "Hello from OCR", "", "", "", &[])
             .await
             .unwrap();
 
@@ -91,9 +102,12 @@ mod tests {
         let start_time = Utc::now();
 
         // Insert OCR data
-        let _ = db.insert_video_chunk("test_video.mp4").await.unwrap();
-        let frame_id1 = db.insert_frame().await.unwrap();
-        db.insert_ocr_text(frame_id1, "Hello from OCR 1", "", "", "")
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let frame_id1 = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id1, "Hello from OCR 1", "", "", "", &[])
             .await
             .unwrap();
 
@@ -112,8 +126,11 @@ mod tests {
         tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
 
         // Insert remaining data
-        let frame_id2 = db.insert_frame().await.unwrap();
-        db.insert_ocr_text(frame_id2, "Hello from OCR 2", "", "", "")
+        let frame_id2 = db
+            .insert_frame_in_chunk(video_chunk_id, 1, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id2, "Hello from OCR 2", "", "", "", &[])
             .await
             .unwrap();
 
@@ -193,9 +210,12 @@ mod tests {
         let start_time = Utc::now();
 
         // Insert OCR data
-        let _ = db.insert_video_chunk("test_video.mp4").await.unwrap();
-        let frame_id1 = db.insert_frame().await.unwrap();
-        db.insert_ocr_text(frame_id1, "Hello from OCR 1", "", "", "")
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let frame_id1 = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id1, "Hello from OCR 1", "", "", "", &[])
             .await
             .unwrap();
 
@@ -212,8 +232,11 @@ mod tests {
         tokio::time::sleep(tokio::time::Duration::from_millis(10)).await;
 
         // Insert remaining data
-        let frame_id2 = db.insert_frame().await.unwrap();
-        db.insert_ocr_text(frame_id2, "Hello from OCR 2", "", "", "")
+        let frame_id2 = db
+            .insert_frame_in_chunk(video_chunk_id, 1, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id2, "Hello from OCR 2", "", "", "", &[])
             .await
             .unwrap();
 
@@ -255,4 +278,283 @@ mod tests {
             .unwrap();
         assert_eq!(count, 2);
     }
+
+    #[tokio::test]
+    async fn test_webhook_rules_crud() {
+        let db = setup_test_db().await;
+
+        let mut rule = NewWebhookRule {
+            name: "incidents".to_string(),
+            url: "http://localhost:8080/hook".to_string(),
+            secret: None,
+            keywords: vec!["outage".to_string()],
+            pattern: Some("INC-[0-9]+".to_string()),
+            content_type: ContentType::OCR,
+            enabled: true,
+        };
+        let id = db.insert_webhook_rule(&rule, "secret").await.unwrap();
+
+        let stored = db.get_webhook_rule(id).await.unwrap().unwrap();
+        assert_eq!(stored.name, "incidents");
+        assert_eq!(stored.secret, "secret");
+        // Listed rules never show the secret
+        assert!(serde_json::to_value(&stored).unwrap().get("secret").is_none());
+        assert_eq!(stored.keywords.0, vec!["outage".to_string()]);
+        assert_eq!(stored.content_type, ContentType::OCR);
+
+        rule.enabled = false;
+        assert!(db.update_webhook_rule(id, &rule, "secret").await.unwrap());
+        assert!(!db.get_webhook_rule(id).await.unwrap().unwrap().enabled);
+
+        db.insert_webhook_dead_letter(id, &rule.url, "{}", "HTTP 500", 3)
+            .await
+            .unwrap();
+        assert_eq!(db.get_webhook_dead_letters(id).await.unwrap().len(), 1);
+
+        assert!(db.delete_webhook_rule(id).await.unwrap());
+        assert!(db.get_webhook_rule(id).await.unwrap().is_none());
+        assert!(db.get_webhook_dead_letters(id).await.unwrap().is_empty());
+        assert!(!db.delete_webhook_rule(id).await.unwrap());
+    }
+
+    #[tokio::test]
+    async fn test_insert_frame_in_explicit_chunk() {
+        let db = setup_test_db().await;
+        let first = db.insert_video_chunk("first.mp4", 1.0).await.unwrap();
+        let _second = db.insert_video_chunk("second.mp4", 1.0).await.unwrap();
+
+        // A late frame of the first chunk stays there even though a newer chunk exists
+        let captured_at = Utc::now() - chrono::Duration::seconds(90);
+        let frame_id = db
+            .insert_frame_in_chunk(first, 29, captured_at, None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            db.get_frame(frame_id).await.unwrap(),
+            Some(("first.mp4".to_string(), 29))
+        );
+        let (latest_frame, _) = db.get_latest_timestamps().await.unwrap();
+        assert_eq!(latest_frame, Some(captured_at));
+    }
+
+    #[tokio::test]
+    async fn test_frame_image_hash() {
+        let db = setup_test_db().await;
+        let chunk = db.insert_video_chunk("chunk.mp4", 1.0).await.unwrap();
+        // Hashes with the top bit set don't fit in a positive i64
+        let hash = 0xF0F0_0000_0000_0001;
+        let first = db
+            .insert_frame_in_chunk(chunk, 0, Utc::now(), None, Some(hash))
+            .await
+            .unwrap();
+        db.insert_frame_in_chunk(chunk, 1, Utc::now(), None, Some(1))
+            .await
+            .unwrap();
+        db.insert_frame_in_chunk(chunk, 2, Utc::now(), None, None)
+            .await
+            .unwrap();
+        let third = db
+            .insert_frame_in_chunk(chunk, 3, Utc::now(), None, Some(hash))
+            .await
+            .unwrap();
+
+        assert_eq!(
+            db.get_frames_by_image_hash(hash).await.unwrap(),
+            vec![first, third]
+        );
+        assert!(db.get_frames_by_image_hash(2).await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_ocr_words() {
+        let db = setup_test_db().await;
+        let video_chunk_id = db.insert_video_chunk("chunk.mp4", 1.0).await.unwrap();
+        let frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        let other_frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 1, Utc::now(), None, None)
+            .await
+            .unwrap();
+        let word = |text: &str, left: i64, word_num: i64| OcrWord {
+            text: text.to_string(),
+            left,
+            top: 40,
+            width: 80,
+            height: 12,
+            conf: 91.5,
+            block_num: 1,
+            par_num: 1,
+            line_num: 1,
+            word_num,
+        };
+        let words = vec![word("let", 10, 1), word(r#""C:\\dir""#, 100, 2)];
+        db.insert_ocr_text(frame_id, "let", "", "", "", &words)
+            .await
+            .unwrap();
+        db.insert_ocr_text(other_frame_id, "other", "", "", "", &[word("other", 10, 1)])
+            .await
+            .unwrap();
+
+        assert_eq!(db.get_ocr_words(frame_id).await.unwrap(), words);
+        assert!(db
+            .get_ocr_words(other_frame_id + 1)
+            .await
+            .unwrap()
+            .is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_video_chunk_status() {
+        let db = setup_test_db().await;
+        let finished = db.insert_video_chunk("finished.mp4", 1.0).await.unwrap();
+        let crashed = db.insert_video_chunk("crashed.mp4", 1.0).await.unwrap();
+        let interrupted = db.insert_video_chunk("interrupted.mp4", 1.0).await.unwrap();
+        let status = |id| {
+            let db = &db;
+            async move { db.get_video_chunk(id).await.unwrap().unwrap().status }
+        };
+        assert_eq!(status(finished).await, ChunkStatus::Recording);
+
+        db.set_video_chunk_status(finished, ChunkStatus::Complete)
+            .await
+            .unwrap();
+        db.set_video_chunk_status(crashed, ChunkStatus::Partial)
+            .await
+            .unwrap();
+        // The next run finds the chunk the previous one was writing
+        assert_eq!(db.mark_interrupted_video_chunks().await.unwrap(), 1);
+
+        assert_eq!(status(finished).await, ChunkStatus::Complete);
+        assert_eq!(status(crashed).await, ChunkStatus::Partial);
+        assert_eq!(status(interrupted).await, ChunkStatus::Partial);
+        assert!(db.get_video_chunk(interrupted + 1).await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_compact_video_chunk() {
+        let db = setup_test_db().await;
+        let old = db.insert_video_chunk("old.mp4", 1.0).await.unwrap();
+        let recent = db.insert_video_chunk("recent.mp4", 1.0).await.unwrap();
+        let week_ago = Utc::now() - chrono::Duration::days(8);
+        let mut frame_ids = Vec::new();
+        for offset_index in 0..5 {
+            frame_ids.push(
+                db.insert_frame_in_chunk(old, offset_index, week_ago, None, None)
+                    .await
+                    .unwrap(),
+            );
+        }
+        db.insert_frame_in_chunk(recent, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        let cutoff = Utc::now() - chrono::Duration::days(7);
+
+        // Still being written
+        assert!(db
+            .get_video_chunks_to_compact(1, cutoff)
+            .await
+            .unwrap()
+            .is_empty());
+        db.set_video_chunk_status(old, ChunkStatus::Complete)
+            .await
+            .unwrap();
+        db.set_video_chunk_status(recent, ChunkStatus::Complete)
+            .await
+            .unwrap();
+        let chunks = db.get_video_chunks_to_compact(1, cutoff).await.unwrap();
+        assert_eq!(chunks.len(), 1);
+        assert_eq!((chunks[0].id, chunks[0].tier, chunks[0].frame_step), (old, 0, 1));
+
+        db.set_video_chunk_tier(old, 1, 2).await.unwrap();
+        let chunk = db.get_video_chunk(old).await.unwrap().unwrap();
+        assert_eq!((chunk.tier, chunk.frame_step), (1, 2));
+        // Dropped frames point at the kept frame before them
+        let mut offsets = Vec::new();
+        for frame_id in frame_ids {
+            offsets.push(db.get_frame(frame_id).await.unwrap().unwrap().1);
+        }
+        assert_eq!(offsets, [0, 0, 1, 1, 2]);
+
+        assert!(db
+            .get_video_chunks_to_compact(1, cutoff)
+            .await
+            .unwrap()
+            .is_empty());
+        assert_eq!(db.get_video_chunks_to_compact(2, cutoff).await.unwrap().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_get_video_segments() {
+        let db = setup_test_db().await;
+        let start = Utc::now() - chrono::Duration::hours(1);
+        let first = db.insert_video_chunk("first.mp4", 1.0).await.unwrap();
+        let second = db.insert_video_chunk("second.mp4", 0.5).await.unwrap();
+        let recording = db.insert_video_chunk("recording.mp4", 1.0).await.unwrap();
+        for (chunk, offset) in [(first, 0), (second, 10), (recording, 20)] {
+            for i in 0..3 {
+                let timestamp = start + chrono::Duration::seconds(offset + i);
+                db.insert_frame_in_chunk(chunk, i, timestamp, None, None)
+                    .await
+                    .unwrap();
+            }
+        }
+        db.set_video_chunk_status(first, ChunkStatus::Complete)
+            .await
+            .unwrap();
+        db.set_video_chunk_status(second, ChunkStatus::Partial)
+            .await
+            .unwrap();
+
+        let segments = db
+            .get_video_segments(start, start + chrono::Duration::minutes(1))
+            .await
+            .unwrap();
+        // The chunk still being written is left out
+        assert_eq!(segments.len(), 2);
+        assert_eq!(segments[0].video_chunk_id, first);
+        assert_eq!(segments[0].start_time, start);
+        assert_eq!(segments[0].end_time, start + chrono::Duration::seconds(2));
+        assert_eq!(segments[0].frames, 3);
+        assert_eq!(segments[0].fps, Some(1.0));
+        assert_eq!(segments[1].video_chunk_id, second);
+        assert_eq!(segments[1].fps, Some(0.5));
+
+        let segments = db
+            .get_video_segments(
+                start + chrono::Duration::seconds(5),
+                start + chrono::Duration::minutes(1),
+            )
+            .await
+            .unwrap();
+        assert_eq!(segments.len(), 1);
+        assert_eq!(segments[0].video_chunk_id, second);
+    }
+
+    #[tokio::test]
+    async fn test_api_tokens() {
+        let db = setup_test_db().await;
+        assert_eq!(db.count_api_tokens().await.unwrap(), 0);
+
+        let (token, hash) = generate_token();
+        assert_eq!(hash_token(&token), hash);
+        let id = db
+            .insert_api_token("dashboard", &hash, &[Scope::ReadSearch, Scope::ReadMedia])
+            .await
+            .unwrap();
+        assert_eq!(db.count_api_tokens().await.unwrap(), 1);
+
+        let stored = db.use_api_token(&hash).await.unwrap().unwrap();
+        assert_eq!(stored.id, id);
+        assert_eq!(stored.scopes.0, vec![Scope::ReadSearch, Scope::ReadMedia]);
+        assert!(stored.last_used_at.is_some());
+        // Used again right away, the last use isn't written again
+        let again = db.use_api_token(&hash).await.unwrap().unwrap();
+        assert_eq!(again.last_used_at, stored.last_used_at);
+        assert!(db.use_api_token(&hash_token("sp_wrong")).await.unwrap().is_none());
+
+        assert!(db.delete_api_token(id).await.unwrap());
+        assert!(db.use_api_token(&hash).await.unwrap().is_none());
+    }
 }
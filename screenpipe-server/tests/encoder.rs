@@ -0,0 +1,99 @@
+#[cfg(test)]
+mod tests {
+    use screenpipe_server::{VideoCodec, VideoEncoderConfig, VideoScale};
+
+    fn value_of<'a>(args: &'a [String], flag: &str) -> Option<&'a str> {
+        args.iter()
+            .position(|arg| arg == flag)
+            .map(|i| args[i + 1].as_str())
+    }
+
+    #[test]
+    fn test_default_config_reads_raw_rgba() {
+        let args = VideoEncoderConfig::default().ffmpeg_args(1920, 1080, 1.0, "out.mp4");
+
+        assert_eq!(&args[..4], ["-f", "rawvideo", "-pix_fmt", "rgba"]);
+        assert_eq!(value_of(&args, "-s"), Some("1920x1080"));
+        assert_eq!(value_of(&args, "-vcodec"), Some("libx264"));
+        assert_eq!(value_of(&args, "-preset"), Some("ultrafast"));
+        assert_eq!(value_of(&args, "-crf"), Some("25"));
+        assert_eq!(value_of(&args, "-vf"), None);
+        assert_eq!(args.last().map(String::as_str), Some("out.mp4"));
+        assert_eq!(
+            value_of(&args, "-movflags"),
+            Some("+empty_moov+default_base_moof")
+        );
+        assert_eq!(value_of(&args, "-frag_duration"), Some("1000000"));
+        assert_eq!(VideoCodec::default().extension(), "mp4");
+    }
+
+    #[test]
+    fn test_config_overrides() {
+        let config = VideoEncoderConfig {
+            codec: VideoCodec::X265,
+            preset: Some("fast".to_string()),
+            crf: Some(30),
+            pix_fmt: Some("yuv444p".to_string()),
+            scale: Some(VideoScale::Factor(0.5)),
+        };
+        let args = config.ffmpeg_args(2560, 1440, 2.0, "out.mp4");
+
+        assert_eq!(value_of(&args, "-vcodec"), Some("libx265"));
+        assert_eq!(value_of(&args, "-preset"), Some("fast"));
+        assert_eq!(value_of(&args, "-crf"), Some("30"));
+        assert_eq!(value_of(&args, "-tag:v"), Some("hvc1"));
+        assert_eq!(
+            value_of(&args, "-vf"),
+            Some("scale=trunc(iw*0.5/2)*2:trunc(ih*0.5/2)*2")
+        );
+        // The output pixel format comes after the input one
+        let pix_fmts: Vec<&String> = args
+            .iter()
+            .zip(&args[1..])
+            .filter(|(flag, _)| *flag == "-pix_fmt")
+            .map(|(_, value)| value)
+            .collect();
+        assert_eq!(pix_fmts, ["rgba", "yuv444p"]);
+    }
+
+    #[test]
+    fn test_vp9_uses_constant_quality() {
+        let config = VideoEncoderConfig {
+            codec: VideoCodec::Vp9,
+            ..Default::default()
+        };
+        let args = config.ffmpeg_args(1280, 720, 1.0, "out.webm");
+
+        assert_eq!(value_of(&args, "-vcodec"), Some("libvpx-vp9"));
+        assert_eq!(value_of(&args, "-cpu-used"), Some("8"));
+        assert_eq!(value_of(&args, "-b:v"), Some("0"));
+        assert_eq!(value_of(&args, "-preset"), None);
+        // Written to WebM, clusters take the place of MP4 fragments
+        assert_eq!(VideoCodec::Vp9.extension(), "webm");
+        assert_eq!(value_of(&args, "-cluster_time_limit"), Some("1000"));
+        assert_eq!(value_of(&args, "-movflags"), None);
+        assert_eq!(args.last().map(String::as_str), Some("out.webm"));
+    }
+
+    #[test]
+    fn test_parse_codec_and_scale() {
+        assert_eq!("hevc".parse::<VideoCodec>(), Ok(VideoCodec::X265));
+        assert_eq!("av1".parse::<VideoCodec>(), Ok(VideoCodec::Av1));
+        assert!("mjpeg".parse::<VideoCodec>().is_err());
+        assert_eq!(VideoCodec::Vp9.to_string().parse(), Ok(VideoCodec::Vp9));
+
+        assert_eq!(
+            "1280x720".parse::<VideoScale>(),
+            Ok(VideoScale::Size {
+                width: 1280,
+                height: 720
+            })
+        );
+        assert_eq!("0.5".parse::<VideoScale>(), Ok(VideoScale::Factor(0.5)));
+        assert!("2".parse::<VideoScale>().is_err());
+        assert!("1280x".parse::<VideoScale>().is_err());
+        for size in ["0x720", "1280x0", "1281x720", "1280x721"] {
+            assert!(size.parse::<VideoScale>().is_err(), "{}", size);
+        }
+    }
+}
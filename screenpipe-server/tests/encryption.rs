@@ -0,0 +1,55 @@
+#![cfg(feature = "encryption")]
+
+use std::fs;
+
+use screenpipe_server::{
+    enable_media_encryption, is_encrypted, read_frame_timestamps, reencrypt_media, timestamps_path,
+    EncryptionKey,
+};
+
+#[test]
+fn test_encrypt_decrypt_roundtrip() {
+    let key = EncryptionKey::generate();
+    let plaintext = b"fake mp4 chunk";
+
+    let encrypted = key.encrypt(plaintext).unwrap();
+    assert!(is_encrypted(&encrypted));
+    assert!(!is_encrypted(plaintext));
+    assert_ne!(&encrypted[encrypted.len() - plaintext.len()..], plaintext);
+
+    assert_eq!(key.decrypt(&encrypted).unwrap(), plaintext);
+}
+
+#[test]
+fn test_decrypt_with_wrong_key_fails() {
+    let encrypted = EncryptionKey::generate().encrypt(b"secret").unwrap();
+    assert!(EncryptionKey::generate().decrypt(&encrypted).is_err());
+    assert!(EncryptionKey::generate().decrypt(b"not encrypted").is_err());
+}
+
+#[tokio::test]
+async fn test_reencrypt_media_covers_frame_timestamps() {
+    let data_dir = tempfile::tempdir().unwrap();
+    let (old_key, new_key) = (EncryptionKey::generate(), EncryptionKey::generate());
+    let chunk = data_dir.path().join("monitor_1.mp4");
+    let chunk = chunk.to_string_lossy();
+    let timestamps = "2024-08-01T12:00:00.000Z\n2024-08-01T12:00:01.000Z\n";
+    fs::write(&*chunk, old_key.encrypt(b"fake mp4 chunk").unwrap()).unwrap();
+    fs::write(
+        timestamps_path(&chunk),
+        old_key.encrypt(timestamps.as_bytes()).unwrap(),
+    )
+    .unwrap();
+
+    assert_eq!(
+        reencrypt_media(data_dir.path(), &old_key, &new_key).unwrap(),
+        2
+    );
+
+    let sidecar = fs::read(timestamps_path(&chunk)).unwrap();
+    assert!(old_key.decrypt(&sidecar).is_err());
+    enable_media_encryption(new_key);
+    let frame_times = read_frame_timestamps(&chunk).await.unwrap();
+    assert_eq!(frame_times.len(), 2);
+    assert_eq!(frame_times[1].to_rfc3339(), "2024-08-01T12:00:01+00:00");
+}
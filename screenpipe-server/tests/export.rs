@@ -0,0 +1,97 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use futures::StreamExt;
+    use screenpipe_server::{
+        export_stream, ContentType, DatabaseManager, ExportFormat, ExportOptions,
+    };
+
+    async fn setup_test_db() -> Arc<DatabaseManager> {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let frame_id = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(frame_id, "Hello, \"world\"", "", "", "", &[])
+            .await
+            .unwrap();
+        let audio_chunk_id = db.insert_audio_chunk("test_audio.mp4").await.unwrap();
+        db.insert_audio_transcription(audio_chunk_id, "Hello from audio", 0)
+            .await
+            .unwrap();
+        Arc::new(db)
+    }
+
+    async fn export(db: Arc<DatabaseManager>, options: ExportOptions) -> String {
+        export_stream(db, options)
+            .map(|chunk| chunk.unwrap())
+            .collect::<Vec<_>>()
+            .await
+            .concat()
+    }
+
+    #[tokio::test]
+    async fn test_export_csv_is_chronological() {
+        let db = setup_test_db().await;
+        let output = export(
+            db,
+            ExportOptions {
+                format: ExportFormat::Csv,
+                ..Default::default()
+            },
+        )
+        .await;
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        assert_eq!(lines[0], "type,id,timestamp,text,file_path,offset_index");
+        assert!(lines[1].starts_with("ocr,"));
+        assert!(lines[1].contains("\"Hello, \"\"world\"\"\""));
+        assert!(lines[2].starts_with("audio,"));
+    }
+
+    #[tokio::test]
+    async fn test_export_filters() {
+        let db = setup_test_db().await;
+
+        let output = export(
+            Arc::clone(&db),
+            ExportOptions {
+                content_type: ContentType::Audio,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(output.lines().count(), 1);
+        let item: serde_json::Value = serde_json::from_str(output.trim()).unwrap();
+        assert_eq!(item["type"], "Audio");
+
+        let output = export(
+            Arc::clone(&db),
+            ExportOptions {
+                q: Some("world".to_string()),
+                format: ExportFormat::Markdown,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert!(output.starts_with("# Screenpipe export"));
+        assert!(output.contains("screen: Hello, \"world\""));
+        assert!(!output.contains("audio:"));
+
+        let output = export(
+            db,
+            ExportOptions {
+                limit: Some(1),
+                offset: 1,
+                ..Default::default()
+            },
+        )
+        .await;
+        assert_eq!(output.lines().count(), 1);
+        assert!(output.contains("Hello from audio"));
+    }
+}
@@ -0,0 +1,86 @@
+#[cfg(test)]
+mod tests {
+    use chrono::{TimeZone, Utc};
+    use screenpipe_server::{render_playlist, split_init_segment, VideoSegment};
+
+    fn mp4_box(kind: &[u8; 4], payload: &[u8]) -> Vec<u8> {
+        let mut data = ((payload.len() + 8) as u32).to_be_bytes().to_vec();
+        data.extend_from_slice(kind);
+        data.extend_from_slice(payload);
+        data
+    }
+
+    #[test]
+    fn test_render_playlist() {
+        let start = Utc.with_ymd_and_hms(2024, 7, 26, 10, 0, 0).unwrap();
+        let segments = vec![
+            VideoSegment {
+                video_chunk_id: 3,
+                file_path: "a.mp4".to_string(),
+                start_time: start,
+                end_time: start + chrono::Duration::seconds(59),
+                frames: 60,
+                fps: None,
+            },
+            VideoSegment {
+                video_chunk_id: 4,
+                file_path: "b.mp4".to_string(),
+                start_time: start + chrono::Duration::minutes(5),
+                end_time: start + chrono::Duration::minutes(5),
+                frames: 1,
+                fps: None,
+            },
+            // Captured over 10 minutes while OCR lagged, played at the frame rate it was encoded at
+            VideoSegment {
+                video_chunk_id: 5,
+                file_path: "c.mp4".to_string(),
+                start_time: start + chrono::Duration::minutes(10),
+                end_time: start + chrono::Duration::minutes(20),
+                frames: 30,
+                fps: Some(0.5),
+            },
+        ];
+
+        assert_eq!(
+            render_playlist(&segments),
+            "#EXTM3U
+#EXT-X-VERSION:7
+#EXT-X-TARGETDURATION:60
+#EXT-X-MEDIA-SEQUENCE:0
+#EXT-X-PLAYLIST-TYPE:VOD
+#EXT-X-INDEPENDENT-SEGMENTS
+#EXT-X-MAP:URI=\"segments/3/init.mp4\"
+#EXT-X-PROGRAM-DATE-TIME:2024-07-26T10:00:00.000Z
+#EXTINF:60.000,
+segments/3/media.m4s
+#EXT-X-DISCONTINUITY
+#EXT-X-MAP:URI=\"segments/4/init.mp4\"
+#EXT-X-PROGRAM-DATE-TIME:2024-07-26T10:05:00.000Z
+#EXTINF:1.000,
+segments/4/media.m4s
+#EXT-X-DISCONTINUITY
+#EXT-X-MAP:URI=\"segments/5/init.mp4\"
+#EXT-X-PROGRAM-DATE-TIME:2024-07-26T10:10:00.000Z
+#EXTINF:60.000,
+segments/5/media.m4s
+#EXT-X-ENDLIST
+"
+        );
+    }
+
+    #[test]
+    fn test_split_init_segment() {
+        let mut data = mp4_box(b"ftyp", b"isom");
+        data.extend(mp4_box(b"moov", &[0; 32]));
+        let init_len = data.len();
+        data.extend(mp4_box(b"moof", &[0; 16]));
+        data.extend(mp4_box(b"mdat", &[1; 64]));
+
+        assert_eq!(split_init_segment(&data), Some(init_len));
+        assert_eq!(split_init_segment(&data[..init_len]), None);
+        // A box claiming less than its header can't be walked past
+        let mut broken = data.clone();
+        broken[..4].copy_from_slice(&4u32.to_be_bytes());
+        assert_eq!(split_init_segment(&broken), None);
+    }
+}
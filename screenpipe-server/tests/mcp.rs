@@ -0,0 +1,249 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+
+    use chrono::Utc;
+    use reqwest::StatusCode;
+    use screenpipe_server::{
+        generate_token, AuthPlugin, DatabaseManager, PluginRegistry, Scope, Server, WebhookEngine,
+    };
+    use serde_json::{json, Value};
+    use tokio::net::TcpListener;
+
+    struct TestServer {
+        url: String,
+        db: Arc<DatabaseManager>,
+        vision_control: Arc<AtomicBool>,
+    }
+
+    async fn start_server(auth: bool) -> TestServer {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let vision_control = Arc::new(AtomicBool::new(true));
+        let (audio_tx, _audio_rx) = tokio::sync::mpsc::channel(1);
+        let webhooks = WebhookEngine::new(db.clone()).await.unwrap();
+        let mut plugins = PluginRegistry::new();
+        if auth {
+            plugins.register(AuthPlugin::new(db.clone(), false));
+        }
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Server::new(
+            db.clone(),
+            addr,
+            vision_control.clone(),
+            audio_tx,
+            webhooks,
+            vec![],
+        )
+        .router(HashMap::new(), plugins);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+
+        TestServer {
+            url: format!("http://{}/mcp", addr),
+            db,
+            vision_control,
+        }
+    }
+
+    async fn call(server: &TestServer, token: Option<&str>, body: Value) -> (StatusCode, Value) {
+        let mut request = reqwest::Client::new().post(&server.url).json(&body);
+        if let Some(token) = token {
+            request = request.bearer_auth(token);
+        }
+        let response = request.send().await.unwrap();
+        let status = response.status();
+        let body = response.json().await.unwrap_or(Value::Null);
+        (status, body)
+    }
+
+    fn tool_call(id: i64, name: &str, arguments: Value) -> Value {
+        json!({
+            "jsonrpc": "2.0",
+            "id": id,
+            "method": "tools/call",
+            "params": { "name": name, "arguments": arguments },
+        })
+    }
+
+    /// The JSON the tool returned as text content.
+    fn tool_output(body: &Value) -> Value {
+        serde_json::from_str(body["result"]["content"][0]["text"].as_str().unwrap()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_initialize_and_list_tools() {
+        let server = start_server(false).await;
+
+        let (status, body) = call(
+            &server,
+            None,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "initialize", "params": {} }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["id"], 1);
+        assert_eq!(body["result"]["serverInfo"]["name"], "screenpipe");
+        assert!(body["result"]["capabilities"]["tools"].is_object());
+
+        let (_, body) = call(
+            &server,
+            None,
+            json!({ "jsonrpc": "2.0", "id": "list", "method": "tools/list" }),
+        )
+        .await;
+        assert_eq!(body["id"], "list");
+        let names: Vec<&str> = body["result"]["tools"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|tool| tool["name"].as_str().unwrap())
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "search",
+                "get_frame",
+                "recent_activity",
+                "list_audio_devices",
+                "pause_recording"
+            ]
+        );
+        assert_eq!(
+            body["result"]["tools"][1]["inputSchema"]["required"],
+            json!(["frame_id"])
+        );
+    }
+
+    #[tokio::test]
+    async fn test_notification_gets_no_response() {
+        let server = start_server(false).await;
+
+        let (status, body) = call(
+            &server,
+            None,
+            json!({ "jsonrpc": "2.0", "method": "notifications/initialized" }),
+        )
+        .await;
+        assert_eq!(status, StatusCode::ACCEPTED);
+        assert_eq!(body, Value::Null);
+    }
+
+    #[tokio::test]
+    async fn test_protocol_errors() {
+        let server = start_server(false).await;
+
+        let (_, body) = call(
+            &server,
+            None,
+            json!({ "jsonrpc": "2.0", "id": 1, "method": "resources/list" }),
+        )
+        .await;
+        assert_eq!(body["error"]["code"], -32601);
+
+        let (_, body) = call(&server, None, tool_call(2, "delete_everything", json!({}))).await;
+        assert_eq!(body["error"]["code"], -32602);
+
+        let (_, body) = call(
+            &server,
+            None,
+            json!({ "jsonrpc": "2.0", "id": 3, "method": "tools/call" }),
+        )
+        .await;
+        assert_eq!(body["error"]["code"], -32602);
+
+        // Bad arguments are a tool error, not a protocol one
+        let (_, body) = call(&server, None, tool_call(4, "get_frame", json!({}))).await;
+        assert!(body["error"].is_null());
+        assert_eq!(body["result"]["isError"], true);
+    }
+
+    #[tokio::test]
+    async fn test_search_tool() {
+        let server = start_server(false).await;
+        let video_chunk_id = server
+            .db
+            .insert_video_chunk("test_video.mp4", 1.0)
+            .await
+            .unwrap();
+        let frame_id = server
+            .db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        server
+            .db
+            .insert_ocr_text(frame_id, "Hello from the screen", "", "", "", &[])
+            .await
+            .unwrap();
+
+        let (_, body) = call(
+            &server,
+            None,
+            tool_call(1, "search", json!({ "q": "Hello", "content_type": "ocr" })),
+        )
+        .await;
+        assert_eq!(body["result"]["isError"], false);
+        let items = tool_output(&body);
+        assert_eq!(items.as_array().unwrap().len(), 1);
+        assert_eq!(items[0]["content"]["text"], "Hello from the screen");
+        assert_eq!(items[0]["content"]["frame_id"], frame_id);
+
+        let (_, body) = call(
+            &server,
+            None,
+            tool_call(2, "get_frame", json!({ "frame_id": 999 })),
+        )
+        .await;
+        assert_eq!(body["result"]["isError"], true);
+        assert!(tool_output(&body)["error"]
+            .as_str()
+            .unwrap()
+            .contains("not found"));
+    }
+
+    #[tokio::test]
+    async fn test_pause_recording_tool() {
+        let server = start_server(false).await;
+
+        let (_, body) = call(&server, None, tool_call(1, "pause_recording", json!({}))).await;
+        assert_eq!(body["result"]["isError"], false);
+        assert_eq!(tool_output(&body), json!({ "is_running": false }));
+        assert!(!server.vision_control.load(Ordering::SeqCst));
+    }
+
+    #[tokio::test]
+    async fn test_tools_check_token_scopes() {
+        let server = start_server(true).await;
+        let (token, hash) = generate_token();
+        server
+            .db
+            .insert_api_token("assistant", &hash, &[Scope::ReadSearch])
+            .await
+            .unwrap();
+
+        let (status, _) = call(&server, None, tool_call(1, "search", json!({}))).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = call(&server, Some(&token), tool_call(2, "search", json!({}))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["result"]["isError"], false);
+
+        // The endpoint is allowed, the tool needing more than search is not
+        let (status, body) = call(
+            &server,
+            Some(&token),
+            tool_call(3, "pause_recording", json!({})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["result"]["isError"], true);
+        assert!(tool_output(&body)["error"]
+            .as_str()
+            .unwrap()
+            .contains("control-recording"));
+        assert!(server.vision_control.load(Ordering::SeqCst));
+    }
+}
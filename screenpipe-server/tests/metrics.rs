@@ -0,0 +1,81 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use async_trait::async_trait;
+    use chrono::Utc;
+    use screenpipe_server::{
+        render_metrics, DatabaseManager, Pipe, PipeContext, PipeEvent, PipeManager,
+        PipeTranscription,
+    };
+    use screenpipe_vision::metrics::FRAMES_CAPTURED;
+
+    #[test]
+    fn test_metrics_render_every_series() {
+        let before = FRAMES_CAPTURED.get();
+        FRAMES_CAPTURED.inc();
+
+        let metrics = render_metrics().unwrap();
+        for series in [
+            "screenpipe_frames_ocr_total",
+            "screenpipe_ocr_duration_seconds_bucket",
+            "screenpipe_whisper_queue_depth",
+            "screenpipe_ffmpeg_restarts_total",
+            "screenpipe_transcription_latency_seconds_count",
+            "screenpipe_ocr_insert_retries_total",
+            "screenpipe_ocr_insert_errors_total",
+            "screenpipe_process_memory_bytes",
+            "screenpipe_process_cpu_percent",
+        ] {
+            assert!(metrics.contains(series), "{} missing", series);
+        }
+        assert!(metrics.contains(&format!("screenpipe_frames_captured_total {}", before + 1)));
+    }
+
+    /// Never gets through its first event.
+    struct StuckPipe;
+
+    #[async_trait]
+    impl Pipe for StuckPipe {
+        fn name(&self) -> &str {
+            "stuck"
+        }
+
+        fn queue_capacity(&self) -> usize {
+            1
+        }
+
+        async fn on_transcription(
+            &self,
+            _transcription: &PipeTranscription,
+            _ctx: &PipeContext,
+        ) -> anyhow::Result<()> {
+            std::future::pending().await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_metrics_count_dropped_pipe_events() {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let pipes = PipeManager::new(db, vec![Arc::new(StuckPipe)]);
+        assert!(render_metrics()
+            .unwrap()
+            .contains("screenpipe_pipe_events_dropped_total{pipe=\"stuck\"} 0"));
+
+        let event = PipeEvent::Transcription(Arc::new(PipeTranscription {
+            audio_chunk_id: 1,
+            transcription: "hello".to_string(),
+            device: "mic".to_string(),
+            file_path: "audio.mp4".to_string(),
+            timestamp: Utc::now(),
+        }));
+        // The pipe task hasn't run yet, the first event fills the queue
+        for _ in 0..3 {
+            pipes.dispatch(event.clone());
+        }
+
+        assert!(render_metrics()
+            .unwrap()
+            .contains("screenpipe_pipe_events_dropped_total{pipe=\"stuck\"} 2"));
+    }
+}
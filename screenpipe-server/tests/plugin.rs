@@ -0,0 +1,199 @@
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+
+    use async_trait::async_trait;
+    use axum::{
+        body::Body,
+        extract::Request,
+        http::{HeaderValue, StatusCode},
+        response::{IntoResponse, Response},
+    };
+    use screenpipe_server::{
+        ApiPlugin, DatabaseManager, FnPlugin, PluginDecision, PluginRegistry, RequestInfo, Server,
+        WebhookEngine,
+    };
+    use tokio::net::TcpListener;
+
+    type Calls = Arc<Mutex<Vec<String>>>;
+
+    /// Records every hook it gets, and denies requests with `deny` when set.
+    struct Recorder {
+        name: &'static str,
+        calls: Calls,
+        deny: Option<StatusCode>,
+    }
+
+    impl Recorder {
+        fn new(name: &'static str, calls: &Calls) -> Self {
+            Self {
+                name,
+                calls: Arc::clone(calls),
+                deny: None,
+            }
+        }
+
+        fn record(&self, hook: &str) {
+            self.calls
+                .lock()
+                .unwrap()
+                .push(format!("{}.{}", self.name, hook));
+        }
+    }
+
+    #[async_trait]
+    impl ApiPlugin for Recorder {
+        fn name(&self) -> &str {
+            self.name
+        }
+
+        async fn on_request(&self, request: &mut Request<Body>) -> PluginDecision {
+            // Plugins after this one see what it did to the request
+            let seen = request
+                .headers()
+                .get("x-plugins")
+                .map(|value| value.to_str().unwrap().to_string());
+            let chain = match seen {
+                Some(seen) => format!("{},{}", seen, self.name),
+                None => self.name.to_string(),
+            };
+            request
+                .headers_mut()
+                .insert("x-plugins", HeaderValue::from_str(&chain).unwrap());
+            self.record(&format!("request({})", chain));
+
+            match self.deny {
+                Some(status) => PluginDecision::Deny(status.into_response()),
+                None => PluginDecision::Allow,
+            }
+        }
+
+        async fn on_response(&self, request: &RequestInfo, response: &mut Response) {
+            self.record(&format!(
+                "response({} {})",
+                request.uri.path(),
+                response.status()
+            ));
+        }
+
+        async fn on_error(&self, _request: &RequestInfo, _response: &mut Response) {
+            self.record("error");
+        }
+    }
+
+    /// Serves the API with `plugins` in front and returns its base url.
+    async fn start_server(plugins: PluginRegistry) -> String {
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let (audio_tx, _audio_rx) = tokio::sync::mpsc::channel(1);
+        let webhooks = WebhookEngine::new(db.clone()).await.unwrap();
+
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Server::new(
+            db,
+            addr,
+            Arc::new(AtomicBool::new(true)),
+            audio_tx,
+            webhooks,
+            vec![],
+        )
+        .router(HashMap::new(), plugins);
+        tokio::spawn(async move { axum::serve(listener, router).await });
+        format!("http://{}", addr)
+    }
+
+    fn calls(calls: &Calls) -> Vec<String> {
+        calls.lock().unwrap().clone()
+    }
+
+    #[tokio::test]
+    async fn test_plugins_run_in_registration_order() {
+        let recorded = Calls::default();
+        let mut plugins = PluginRegistry::new();
+        plugins
+            .register(Recorder::new("first", &recorded))
+            .register(Recorder::new("second", &recorded));
+        let url = start_server(plugins).await;
+
+        let response = reqwest::get(format!("{}/health", url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK.as_u16());
+        assert_eq!(
+            calls(&recorded),
+            vec![
+                "first.request(first)",
+                "second.request(first,second)",
+                "first.response(/health 200 OK)",
+                "second.response(/health 200 OK)",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_denying_plugin_skips_the_rest() {
+        let recorded = Calls::default();
+        let mut plugins = PluginRegistry::new();
+        let mut gate = Recorder::new("gate", &recorded);
+        gate.deny = Some(StatusCode::IM_A_TEAPOT);
+        plugins
+            .register(gate)
+            .register(Recorder::new("after", &recorded));
+        let url = start_server(plugins).await;
+
+        let response = reqwest::get(format!("{}/health", url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::IM_A_TEAPOT.as_u16());
+        // Every plugin still sees the response
+        assert_eq!(
+            calls(&recorded),
+            vec![
+                "gate.request(gate)",
+                "gate.response(/health 418 I'm a teapot)",
+                "after.response(/health 418 I'm a teapot)",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_server_errors_reach_on_error() {
+        let recorded = Calls::default();
+        let mut plugins = PluginRegistry::new();
+        let mut failing = Recorder::new("failing", &recorded);
+        failing.deny = Some(StatusCode::INTERNAL_SERVER_ERROR);
+        plugins.register(failing);
+        let url = start_server(plugins).await;
+
+        let response = reqwest::get(format!("{}/stats", url)).await.unwrap();
+        assert_eq!(
+            response.status(),
+            StatusCode::INTERNAL_SERVER_ERROR.as_u16()
+        );
+        assert_eq!(
+            calls(&recorded),
+            vec![
+                "failing.request(failing)",
+                "failing.response(/stats 500 Internal Server Error)",
+                "failing.error",
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_fn_plugin_observes_requests() {
+        let seen = Calls::default();
+        let mut plugins = PluginRegistry::new();
+        let observed = Arc::clone(&seen);
+        plugins.register(FnPlugin::new("observer", move |request: &Request<Body>| {
+            observed
+                .lock()
+                .unwrap()
+                .push(format!("{} {}", request.method(), request.uri().path()));
+        }));
+        assert!(!plugins.is_empty());
+        let url = start_server(plugins).await;
+
+        let response = reqwest::get(format!("{}/health", url)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK.as_u16());
+        assert_eq!(calls(&seen), vec!["GET /health"]);
+    }
+}
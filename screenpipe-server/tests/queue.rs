@@ -0,0 +1,50 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use screenpipe_server::{BoundedQueue, DropPolicy};
+
+    #[test]
+    fn test_drop_oldest_keeps_latest_items() {
+        let queue = BoundedQueue::new("test", 2, DropPolicy::DropOldest);
+        assert!(queue.push(1));
+        assert!(queue.push(2));
+        assert!(!queue.push(3));
+
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.fill_ratio(), 1.0);
+        assert_eq!(queue.try_pop(), Some(2));
+        assert_eq!(queue.try_pop(), Some(3));
+        assert_eq!(queue.try_pop(), None);
+    }
+
+    #[test]
+    fn test_drop_newest_keeps_queued_items() {
+        let queue = BoundedQueue::new("test", 2, DropPolicy::DropNewest);
+        queue.push(1);
+        queue.push(2);
+        assert!(!queue.push(3));
+
+        assert_eq!(queue.dropped(), 1);
+        assert_eq!(queue.try_pop(), Some(1));
+        assert_eq!(queue.try_pop(), Some(2));
+        assert!(queue.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_pop_timeout_wakes_up_on_push() {
+        let queue = Arc::new(BoundedQueue::new("test", 4, DropPolicy::DropOldest));
+        assert_eq!(queue.pop_timeout(Duration::from_millis(10)).await, None);
+
+        let producer = Arc::clone(&queue);
+        tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            producer.push("frame");
+        });
+        assert_eq!(
+            queue.pop_timeout(Duration::from_secs(5)).await,
+            Some("frame")
+        );
+    }
+}
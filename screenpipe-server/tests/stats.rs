@@ -0,0 +1,119 @@
+#[cfg(test)]
+mod tests {
+    use std::fs;
+    use std::time::Duration;
+
+    use chrono::Utc;
+    use screenpipe_server::{collect_stats, format_stats, DatabaseManager};
+
+    #[tokio::test]
+    async fn test_stats_count_days_and_classify_disk_usage() {
+        let base_dir = tempfile::tempdir().unwrap();
+        let data_dir = base_dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        let db = DatabaseManager::new(&base_dir.path().join("db.sqlite").to_string_lossy())
+            .await
+            .unwrap();
+
+        let video_path = data_dir.join("monitor_1.mp4");
+        fs::write(&video_path, vec![0u8; 1000]).unwrap();
+        let video_chunk_id = db
+            .insert_video_chunk(&video_path.to_string_lossy(), 1.0)
+            .await
+            .unwrap();
+        for (offset_index, latency) in [(0, 100), (1, 300)] {
+            db.insert_frame_in_chunk(
+                video_chunk_id,
+                offset_index,
+                Utc::now(),
+                Some(Duration::from_millis(latency)),
+                None,
+            )
+            .await
+            .unwrap();
+        }
+
+        let audio_path = data_dir.join("microphone.mp4");
+        fs::write(&audio_path, vec![0u8; 200]).unwrap();
+        let audio_chunk_id = db
+            .insert_device_audio_chunk(
+                &audio_path.to_string_lossy(),
+                "microphone (input)",
+                Utc::now(),
+                Duration::from_secs(2),
+            )
+            .await
+            .unwrap();
+        db.insert_audio_transcription(audio_chunk_id, "hello", 0)
+            .await
+            .unwrap();
+        fs::write(data_dir.join("leftover.tmp"), vec![0u8; 30]).unwrap();
+
+        let stats = collect_stats(&db).await.unwrap();
+
+        assert_eq!(stats.days.len(), 1);
+        let today = &stats.days[0];
+        assert_eq!(today.day, Utc::now().format("%Y-%m-%d").to_string());
+        assert_eq!(
+            (
+                today.frames,
+                today.video_chunks,
+                today.audio_chunks,
+                today.transcriptions
+            ),
+            (2, 1, 1, 1)
+        );
+
+        let usage = &stats.disk_usage;
+        assert_eq!(usage.video_bytes, 1000);
+        assert_eq!(usage.audio_bytes, 200);
+        assert_eq!(usage.other_bytes, 30);
+        assert!(usage.database_bytes > 0);
+        assert_eq!(usage.total_bytes, 1000 + 200 + 30 + usage.database_bytes);
+
+        assert_eq!(stats.ocr_latency_ms, Some(200.0));
+        assert_eq!(stats.transcription_latency_ms, Some(2000.0));
+        // Everything was recorded today
+        assert_eq!(
+            stats.growth_bytes_per_day,
+            Some(1200.0 + usage.database_bytes as f64)
+        );
+
+        assert!(format_stats(&stats).contains("Disk usage:"));
+    }
+
+    #[tokio::test]
+    async fn test_growth_counts_chunks_by_recording_time() {
+        let base_dir = tempfile::tempdir().unwrap();
+        let data_dir = base_dir.path().join("data");
+        fs::create_dir_all(&data_dir).unwrap();
+        let db = DatabaseManager::new(&base_dir.path().join("db.sqlite").to_string_lossy())
+            .await
+            .unwrap();
+
+        // Written today like a compacted chunk, but recorded a month ago
+        let recorded_at = [
+            ("old.mp4", 5000, Utc::now() - chrono::Duration::days(30)),
+            ("new.mp4", 700, Utc::now()),
+        ];
+        for (name, size, timestamp) in recorded_at {
+            let path = data_dir.join(name);
+            fs::write(&path, vec![0u8; size]).unwrap();
+            let video_chunk_id = db
+                .insert_video_chunk(&path.to_string_lossy(), 1.0)
+                .await
+                .unwrap();
+            db.insert_frame_in_chunk(video_chunk_id, 0, timestamp, None, None)
+                .await
+                .unwrap();
+        }
+
+        let stats = collect_stats(&db).await.unwrap();
+
+        let database_bytes = stats.disk_usage.database_bytes as f64;
+        assert_eq!(
+            stats.growth_bytes_per_day,
+            Some(700.0 / 7.0 + database_bytes / 31.0)
+        );
+    }
+}
@@ -0,0 +1,122 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration as StdDuration;
+
+    use chrono::{Duration, TimeZone, Utc};
+    use screenpipe_audio::TranscriptionSegment;
+    use screenpipe_server::{
+        chunk_cues, render_subtitles, stitch_cues, Cue, DatabaseManager, SubtitleFormat,
+    };
+
+    fn segment(start: f64, end: f64, text: &str) -> TranscriptionSegment {
+        TranscriptionSegment {
+            start,
+            end,
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_srt_and_vtt() {
+        let cues = vec![
+            Cue {
+                start: 0.0,
+                end: 2.5,
+                text: "Hello".to_string(),
+            },
+            Cue {
+                start: 3661.25,
+                end: 3662.0,
+                text: "a <b> & c".to_string(),
+            },
+        ];
+
+        assert_eq!(
+            render_subtitles(SubtitleFormat::Srt, &cues),
+            "1\n00:00:00,000 --> 00:00:02,500\nHello\n\n\
+             2\n01:01:01,250 --> 01:01:02,000\na <b> & c\n\n"
+        );
+        assert_eq!(
+            render_subtitles(SubtitleFormat::Vtt, &cues),
+            "WEBVTT\n\n\
+             1\n00:00:00.000 --> 00:00:02.500\nHello\n\n\
+             2\n01:01:01.250 --> 01:01:02.000\na &lt;b&gt; &amp; c\n\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_chunk_cues_keep_segment_timing() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let id = db
+            .insert_device_audio_chunk("mic.mp4", "mic (input)", Utc::now(), StdDuration::ZERO)
+            .await
+            .unwrap();
+        db.insert_audio_segments(
+            id,
+            &[segment(0.0, 4.0, " first "), segment(4.0, 6.0, "\n\n")],
+        )
+        .await
+        .unwrap();
+
+        let cues = chunk_cues(&db.get_audio_segments(id).await.unwrap());
+        assert_eq!(
+            cues,
+            vec![Cue {
+                start: 0.0,
+                end: 4.0,
+                text: "first".to_string(),
+            }]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_stitch_cues_across_chunks_of_one_device() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let start = Utc.with_ymd_and_hms(2024, 7, 26, 10, 0, 0).unwrap();
+
+        let first = db
+            .insert_device_audio_chunk("mic_1.mp4", "mic (input)", start, StdDuration::ZERO)
+            .await
+            .unwrap();
+        db.insert_audio_segments(
+            first,
+            &[segment(1.0, 3.0, "one"), segment(28.0, 30.0, "two")],
+        )
+        .await
+        .unwrap();
+        let second = db
+            .insert_device_audio_chunk(
+                "mic_2.mp4",
+                "mic (input)",
+                start + Duration::seconds(30),
+                StdDuration::ZERO,
+            )
+            .await
+            .unwrap();
+        db.insert_audio_segments(second, &[segment(0.0, 2.0, "three")])
+            .await
+            .unwrap();
+        let other = db
+            .insert_device_audio_chunk("speaker.mp4", "speaker (output)", start, StdDuration::ZERO)
+            .await
+            .unwrap();
+        db.insert_audio_segments(other, &[segment(0.0, 2.0, "other device")])
+            .await
+            .unwrap();
+
+        let range_start = start + Duration::seconds(2);
+        let range_end = start + Duration::seconds(31);
+        let segments = db
+            .get_device_audio_segments("mic (input)", start - Duration::minutes(10), range_end)
+            .await
+            .unwrap();
+        let cues = stitch_cues(&segments, range_start, range_end);
+
+        let texts: Vec<&str> = cues.iter().map(|cue| cue.text.as_str()).collect();
+        assert_eq!(texts, vec!["one", "two", "three"]);
+        // Cut to the range and shifted to its start
+        assert_eq!((cues[0].start, cues[0].end), (0.0, 1.0));
+        assert_eq!((cues[1].start, cues[1].end), (26.0, 28.0));
+        assert_eq!((cues[2].start, cues[2].end), (28.0, 29.0));
+    }
+}
@@ -0,0 +1,67 @@
+#[cfg(test)]
+mod tests {
+    use std::time::Duration as StdDuration;
+
+    use chrono::{Duration, Utc};
+    use screenpipe_audio::TranscriptionSegment;
+    use screenpipe_server::DatabaseManager;
+
+    #[tokio::test]
+    async fn test_timeline_buckets() {
+        let db = DatabaseManager::new("sqlite::memory:").await.unwrap();
+        let start = Utc::now() - Duration::minutes(1);
+
+        let video_chunk_id = db.insert_video_chunk("test_video.mp4", 1.0).await.unwrap();
+        let short = db
+            .insert_frame_in_chunk(video_chunk_id, 0, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(short, "short", "", "", "", &[])
+            .await
+            .unwrap();
+        let long = db
+            .insert_frame_in_chunk(video_chunk_id, 1, Utc::now(), None, None)
+            .await
+            .unwrap();
+        db.insert_ocr_text(long, "a lot more text on this frame", "", "", "", &[])
+            .await
+            .unwrap();
+
+        let audio_chunk_id = db
+            .insert_device_audio_chunk(
+                "test_audio.mp4",
+                "mic (input)",
+                Utc::now(),
+                StdDuration::ZERO,
+            )
+            .await
+            .unwrap();
+        db.insert_audio_segments(
+            audio_chunk_id,
+            &[TranscriptionSegment {
+                start: 0.0,
+                end: 2.0,
+                text: " hello there\ngeneral kenobi ".to_string(),
+            }],
+        )
+        .await
+        .unwrap();
+
+        let end = Utc::now() + Duration::minutes(1);
+        // A bucket large enough to hold everything
+        let buckets = db
+            .get_timeline(start, end, 365 * 24 * 60 * 60)
+            .await
+            .unwrap();
+        assert_eq!(buckets.len(), 1);
+        assert_eq!(buckets[0].frame_count, 2);
+        assert_eq!(buckets[0].frame_id, Some(long));
+        assert_eq!(buckets[0].word_count, 4);
+
+        let buckets = db
+            .get_timeline(end, end + Duration::hours(1), 60)
+            .await
+            .unwrap();
+        assert!(buckets.is_empty());
+    }
+}
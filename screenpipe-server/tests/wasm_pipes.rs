@@ -0,0 +1,98 @@
+#![cfg(feature = "wasm-pipes")]
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use screenpipe_server::{wasm_engine, DatabaseManager, WasmPipe, WasmPipeConfig};
+    use serde_json::json;
+    use tempfile::TempDir;
+
+    const TAG: &str = r#"{"content_type":"ocr","source_id":7,"tag":"todo"}"#;
+
+    /// A module whose `on_event` runs `body`, with `TAG` at address 0 and 1 page of memory.
+    fn module(body: &str) -> String {
+        format!(
+            r#"(module
+                (import "screenpipe" "log" (func $log (param i32 i32)))
+                (import "screenpipe" "write_tag" (func $write_tag (param i32 i32) (result i32)))
+                (memory (export "memory") 1)
+                (data (i32.const 0) "{}")
+                (func (export "alloc") (param $len i32) (result i32)
+                    (i32.const 1024))
+                (func (export "on_event") (param $ptr i32) (param $len i32)
+                    {}))"#,
+            TAG.replace('"', "\\\""),
+            body
+        )
+    }
+
+    async fn load_pipe(dir: &TempDir, wat: &str, memory_mb: usize) -> WasmPipe {
+        // Modules may be given as text too
+        let path = dir.path().join("test.wasm");
+        std::fs::write(&path, wat).unwrap();
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+        let config = WasmPipeConfig {
+            enabled: true,
+            fuel: 1_000_000,
+            memory_mb,
+        };
+        WasmPipe::new(&wasm_engine().unwrap(), "test", &path, config, db).unwrap()
+    }
+
+    fn event() -> serde_json::Value {
+        json!({ "type": "ocr", "frame_id": 7, "text": "buy milk" })
+    }
+
+    #[tokio::test]
+    async fn test_pipe_reads_events_and_writes_tags() {
+        let dir = TempDir::new().unwrap();
+        let body = format!(
+            "(call $log (local.get $ptr) (local.get $len))
+             (if (i32.ne (call $write_tag (i32.const 0) (i32.const {})) (i32.const 0))
+                 (then unreachable))",
+            TAG.len()
+        );
+        let pipe = load_pipe(&dir, &module(&body), 1).await;
+
+        pipe.send_event(event()).await.unwrap();
+        pipe.send_event(event()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_out_of_bounds_reads_fail() {
+        let dir = TempDir::new().unwrap();
+        for (ptr, len, error) in [
+            (-1, 16, "negative"),
+            (0, -1, "negative"),
+            // Would allocate 2 GiB if the length was trusted
+            (0, i32::MAX, "out of bounds"),
+            (65530, 16, "out of bounds"),
+        ] {
+            let body = format!("(call $log (i32.const {}) (i32.const {}))", ptr, len);
+            let pipe = load_pipe(&dir, &module(&body), 1).await;
+
+            let result = pipe.send_event(event()).await;
+            let message = format!("{:?}", result.unwrap_err());
+            assert!(message.contains(error), "({}, {}): {}", ptr, len, message);
+        }
+
+        // The last byte of memory is still readable
+        let body = "(call $log (i32.const 65535) (i32.const 1))";
+        let pipe = load_pipe(&dir, &module(body), 1).await;
+        pipe.send_event(event()).await.unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_memory_limit() {
+        let dir = TempDir::new().unwrap();
+        // 2 MiB more on every event
+        let body = "(if (i32.eq (memory.grow (i32.const 32)) (i32.const -1)) (then unreachable))";
+        let pipe = load_pipe(&dir, &module(body), 3).await;
+
+        pipe.send_event(event()).await.unwrap();
+        assert!(pipe.send_event(event()).await.is_err());
+        // The instance that hit the limit is dropped, the next event starts from a fresh one
+        pipe.send_event(event()).await.unwrap();
+    }
+}
@@ -0,0 +1,155 @@
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+    use std::time::Duration;
+
+    use axum::{
+        body::Bytes,
+        extract::{Path, State},
+        http::HeaderMap,
+        routing::post,
+        Router,
+    };
+    use chrono::Utc;
+    use screenpipe_server::{
+        sign_payload, ContentType, DatabaseManager, NewContent, NewWebhookRule, WebhookEngine,
+        SIGNATURE_HEADER,
+    };
+    use serde_json::Value;
+    use tokio::net::TcpListener;
+    use tokio::sync::mpsc::{unbounded_channel, UnboundedReceiver, UnboundedSender};
+    use tokio::time::timeout;
+
+    struct Delivery {
+        path: String,
+        signature: String,
+        body: String,
+    }
+
+    async fn receive(
+        State(tx): State<UnboundedSender<Delivery>>,
+        Path(path): Path<String>,
+        headers: HeaderMap,
+        body: Bytes,
+    ) {
+        let _ = tx.send(Delivery {
+            path,
+            signature: headers[SIGNATURE_HEADER].to_str().unwrap().to_string(),
+            body: String::from_utf8(body.to_vec()).unwrap(),
+        });
+    }
+
+    /// Serves `/:path` and passes on every request it gets.
+    async fn start_receiver() -> (String, UnboundedReceiver<Delivery>) {
+        let (tx, rx) = unbounded_channel();
+        let app = Router::new().route("/:path", post(receive)).with_state(tx);
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await });
+        (url, rx)
+    }
+
+    fn rule(name: &str, url: String, content_type: ContentType) -> NewWebhookRule {
+        NewWebhookRule {
+            name: name.to_string(),
+            url,
+            secret: None,
+            keywords: Vec::new(),
+            pattern: None,
+            content_type,
+            enabled: true,
+        }
+    }
+
+    fn content(content_type: ContentType, text: &str) -> NewContent {
+        NewContent {
+            content_type,
+            id: 7,
+            text: text.to_string(),
+            timestamp: Utc::now(),
+            device: None,
+        }
+    }
+
+    async fn next_delivery(rx: &mut UnboundedReceiver<Delivery>) -> Delivery {
+        timeout(Duration::from_secs(5), rx.recv())
+            .await
+            .expect("webhook was not delivered")
+            .unwrap()
+    }
+
+    async fn assert_no_delivery(rx: &mut UnboundedReceiver<Delivery>) {
+        if let Ok(Some(delivery)) = timeout(Duration::from_millis(300), rx.recv()).await {
+            panic!("unexpected delivery to /{}", delivery.path);
+        }
+    }
+
+    #[test]
+    fn test_sign_payload() {
+        // RFC 4231 test case 2
+        assert_eq!(
+            sign_payload("Jefe", "what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rules_match_and_deliver_signed_payloads() {
+        let (url, mut rx) = start_receiver().await;
+        let db = Arc::new(DatabaseManager::new("sqlite::memory:").await.unwrap());
+
+        let mut invoices = rule("invoices", format!("{}/invoices", url), ContentType::OCR);
+        invoices.keywords = vec!["Invoice".to_string(), "receipt".to_string()];
+        let invoices_id = db.insert_webhook_rule(&invoices, "s3cret").await.unwrap();
+
+        let mut incidents = rule(
+            "incidents",
+            format!("{}/incidents", url),
+            ContentType::Audio,
+        );
+        incidents.pattern = Some("INC-[0-9]+".to_string());
+        db.insert_webhook_rule(&incidents, "other").await.unwrap();
+
+        let mut disabled = rule("disabled", format!("{}/disabled", url), ContentType::All);
+        disabled.keywords = vec!["invoice".to_string()];
+        disabled.enabled = false;
+        db.insert_webhook_rule(&disabled, "other").await.unwrap();
+
+        let webhooks = WebhookEngine::new(db.clone()).await.unwrap();
+
+        // Keywords match case-insensitively, the audio rule doesn't see OCR
+        webhooks
+            .evaluate(content(ContentType::OCR, "Paid the INVOICE for INC-42"))
+            .await;
+        let delivery = next_delivery(&mut rx).await;
+        assert_eq!(delivery.path, "invoices");
+        assert_eq!(
+            delivery.signature,
+            format!("sha256={}", sign_payload("s3cret", &delivery.body))
+        );
+        let payload: Value = serde_json::from_str(&delivery.body).unwrap();
+        assert_eq!(payload["rule_id"], invoices_id);
+        assert_eq!(payload["content_id"], 7);
+        assert_eq!(payload["matches"], serde_json::json!(["Invoice"]));
+        assert_no_delivery(&mut rx).await;
+
+        webhooks
+            .evaluate(content(ContentType::Audio, "incident inc-7 was opened"))
+            .await;
+        let delivery = next_delivery(&mut rx).await;
+        assert_eq!(delivery.path, "incidents");
+        assert_eq!(
+            delivery.signature,
+            format!("sha256={}", sign_payload("other", &delivery.body))
+        );
+        let payload: Value = serde_json::from_str(&delivery.body).unwrap();
+        assert_eq!(payload["matches"], serde_json::json!(["inc-7"]));
+        assert_no_delivery(&mut rx).await;
+
+        // Neither keywords nor pattern
+        webhooks
+            .evaluate(content(ContentType::OCR, "nothing to see here"))
+            .await;
+        assert_no_delivery(&mut rx).await;
+    }
+}
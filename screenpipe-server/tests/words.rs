@@ -0,0 +1,83 @@
+#[cfg(test)]
+mod tests {
+    use rusty_tesseract::{Data, DataOutput};
+    use screenpipe_server::core::DataOutputWrapper;
+    use screenpipe_server::{matching_words, ocr_layout, ocr_words};
+    use screenpipe_vision::OcrDocument;
+
+    fn record(level: i32, word_num: i32, left: i32, text: &str) -> Data {
+        Data {
+            level,
+            page_num: 1,
+            block_num: 1,
+            par_num: 1,
+            line_num: 1,
+            word_num,
+            left,
+            top: 20,
+            width: 60,
+            height: 14,
+            conf: 88.0,
+            text: text.to_string(),
+        }
+    }
+
+    fn data_output() -> DataOutput {
+        DataOutput {
+            output: "open \"C:\\tmp\"\n".to_string(),
+            data: vec![
+                record(4, 0, 10, ""),
+                record(5, 1, 10, "open"),
+                record(5, 2, 80, " "),
+                record(5, 3, 150, "\"C:\\tmp\""),
+                record(5, 4, 250, "Screenpipe"),
+            ],
+        }
+    }
+
+    #[test]
+    fn test_ocr_words_keep_only_words() {
+        let words = ocr_words(&data_output());
+
+        let texts: Vec<&str> = words.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(texts, ["open", "\"C:\\tmp\"", "Screenpipe"]);
+        assert_eq!((words[1].left, words[1].word_num), (150, 3));
+    }
+
+    #[test]
+    fn test_matching_words_ignore_case() {
+        let words = ocr_words(&data_output());
+
+        let matches = matching_words(words.clone(), "SCREEN tmp");
+        let texts: Vec<&str> = matches.iter().map(|word| word.text.as_str()).collect();
+        assert_eq!(texts, ["\"C:\\tmp\"", "Screenpipe"]);
+        assert_eq!(matching_words(words.clone(), " ").len(), words.len());
+    }
+
+    #[test]
+    fn test_ocr_layout_matches_the_ocr_output() {
+        let mut data_output = data_output();
+        data_output.data.push(Data {
+            block_num: 2,
+            top: 60,
+            ..record(5, 1, 10, "below")
+        });
+
+        let layout = ocr_layout(ocr_words(&data_output));
+        assert_eq!(layout, OcrDocument::from_data_output(&data_output));
+        assert_eq!(layout.to_text(), "open \"C:\\tmp\" Screenpipe\n\nbelow");
+    }
+
+    #[test]
+    fn test_raw_data_output_is_valid_json() {
+        let json = DataOutputWrapper {
+            data_output: data_output(),
+        }
+        .to_json();
+
+        let value: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(value["output"], "open \"C:\\tmp\"\n");
+        assert_eq!(value["data"][3]["text"], "\"C:\\tmp\"");
+        assert_eq!(value["data"][4]["left"], 250);
+    }
+}
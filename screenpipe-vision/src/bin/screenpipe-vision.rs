@@ -9,6 +9,15 @@ struct Cli {
     /// Save text files
     #[arg(long, default_value_t = false)]
     save_text_files: bool,
+
+    /// Skip frames whose perceptual hash is at most this many bits (of 64) away from the last
+    /// frame picked for OCR, 0 to keep every frame
+    #[arg(long, default_value_t = 0)]
+    dedup_threshold: u32,
+
+    /// Number of frames OCR'd in parallel, 0 for one per CPU
+    #[arg(long, default_value_t = 1)]
+    ocr_workers: usize,
 }
 
 #[tokio::main]
@@ -19,9 +28,19 @@ async fn main() {
     let (result_tx, mut result_rx) = channel(512);
 
     let save_text_files = cli.save_text_files;
+    let dedup_threshold = cli.dedup_threshold;
+    let ocr_workers = cli.ocr_workers;
 
     let capture_thread = tokio::spawn(async move {
-        continuous_capture(&mut control_rx, result_tx, Duration::from_secs(1), save_text_files).await
+        continuous_capture(
+            &mut control_rx,
+            result_tx,
+            Duration::from_secs(1),
+            save_text_files,
+            dedup_threshold,
+            ocr_workers,
+        )
+        .await
     });
 
     // Example: Process results for 10 seconds, then pause for 5 seconds, then stop
@@ -1,21 +1,19 @@
 use image::DynamicImage;
-use log::{debug, error};
-use serde_json;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicU64, Ordering};
 use std::{
-    collections::{HashMap, HashSet},
+    collections::HashMap,
     sync::Arc,
     time::{Duration, Instant},
 };
-use strsim::levenshtein;
-use tokio::sync::{
-    mpsc::{Receiver, Sender},
-    Mutex,
-}; // Corrected import for Mutex
+use tokio::sync::mpsc::{Receiver, Sender};
 use xcap::Monitor;
 
-use crate::utils::{capture_screenshot, compare_with_previous_image, perform_ocr, save_text_files};
-use rusty_tesseract::{DataOutput, Data}; // Add this import
+use crate::metrics::{FRAMES_CAPTURED, FRAMES_DROPPED};
+use crate::pool::OcrPool;
+use crate::utils::{
+    capture_screenshot, clone_data_output, compare_with_previous_image, is_duplicate_frame,
+};
+use rusty_tesseract::DataOutput; // Add this import
 
 pub enum ControlMessage {
     Pause,
@@ -29,17 +27,28 @@ pub struct DataOutputWrapper {
 
 impl DataOutputWrapper {
     pub fn to_json(&self) -> String {
-        let data_json: Vec<String> = self.data_output.data.iter().map(|d| {
-            format!(
-                r#"{{"level": {}, "page_num": {}, "block_num": {}, "par_num": {}, "line_num": {}, "word_num": {}, "left": {}, "top": {}, "width": {}, "height": {}, "conf": {}, "text": "{}"}}"#,
-                d.level, d.page_num, d.block_num, d.par_num, d.line_num, d.word_num, d.left, d.top, d.width, d.height, d.conf, d.text
-            )
-        }).collect();
-        format!(
-            r#"{{"output": "{}", "data": [{}]}}"#,
-            self.data_output.output,
-            data_json.join(", ")
-        )
+        let data: Vec<serde_json::Value> = self
+            .data_output
+            .data
+            .iter()
+            .map(|d| {
+                serde_json::json!({
+                    "level": d.level,
+                    "page_num": d.page_num,
+                    "block_num": d.block_num,
+                    "par_num": d.par_num,
+                    "line_num": d.line_num,
+                    "word_num": d.word_num,
+                    "left": d.left,
+                    "top": d.top,
+                    "width": d.width,
+                    "height": d.height,
+                    "conf": d.conf,
+                    "text": d.text,
+                })
+            })
+            .collect();
+        serde_json::json!({ "output": self.data_output.output, "data": data }).to_string()
     }
 }
 
@@ -51,6 +60,10 @@ pub struct CaptureResult {
     pub frame_number: u64,
     pub timestamp: Instant,
     pub data_output: DataOutput,
+    /// Perceptual hash of the frame, see `perceptual_hash`
+    pub image_hash: u64,
+    /// Time the OCR of the frame took, not counting the wait for a worker
+    pub ocr_duration: Duration,
 }
 
 impl Clone for CaptureResult {
@@ -62,110 +75,115 @@ impl Clone for CaptureResult {
             new_text_json: self.new_text_json.clone(),
             frame_number: self.frame_number,
             timestamp: self.timestamp,
-            data_output: DataOutput {
-                output: self.data_output.output.clone(),
-                data: self.data_output.data.iter().map(|d| Data {
-                    level: d.level,
-                    page_num: d.page_num,
-                    block_num: d.block_num,
-                    par_num: d.par_num,
-                    line_num: d.line_num,
-                    word_num: d.word_num,
-                    left: d.left,
-                    top: d.top,
-                    width: d.width,
-                    height: d.height,
-                    conf: d.conf,
-                    text: d.text.clone(),
-                }).collect(),
-            },
+            image_hash: self.image_hash,
+            ocr_duration: self.ocr_duration,
+            data_output: clone_data_output(&self.data_output),
         }
     }
 }
 
+/// Time between screenshots, shared with the consumers of the captures so they can slow capture
+/// down while they are behind.
+#[derive(Clone, Debug)]
+pub struct CaptureInterval(Arc<AtomicU64>);
+
+impl CaptureInterval {
+    pub fn new(interval: Duration) -> Self {
+        Self(Arc::new(AtomicU64::new(interval.as_nanos() as u64)))
+    }
+
+    pub fn get(&self) -> Duration {
+        Duration::from_nanos(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn set(&self, interval: Duration) {
+        self.0.store(interval.as_nanos() as u64, Ordering::Relaxed);
+    }
+}
+
+impl From<Duration> for CaptureInterval {
+    fn from(interval: Duration) -> Self {
+        Self::new(interval)
+    }
+}
+
 pub struct OcrTaskData {
     pub image: Arc<DynamicImage>,
+    pub image_hash: u64,
     pub frame_number: u64,
     pub timestamp: Instant,
-    pub result_tx: Sender<CaptureResult>,
 }
 
+/// Captures the screen every `interval` and sends the OCR results of the frames that changed
+/// the most. Frames whose perceptual hash is at most `dedup_threshold` bits away from the last
+/// frame picked for OCR are skipped without further comparison, 0 turns this off. Up to
+/// `ocr_workers` frames are OCR'd at once, one per CPU when 0.
 pub async fn continuous_capture(
     _control_rx: &mut Receiver<ControlMessage>,
     result_tx: Sender<CaptureResult>,
-    interval: Duration,
+    interval: impl Into<CaptureInterval>,
     save_text_files_flag: bool,
+    dedup_threshold: u32,
+    ocr_workers: usize,
 ) {
+    let interval = interval.into();
     let monitor = Monitor::all().unwrap().first().unwrap().clone(); // Simplified monitor retrieval
-    let previous_text_json = Arc::new(Mutex::new(None));
-    let ocr_task_running = Arc::new(AtomicBool::new(false));
+    let mut ocr_pool = OcrPool::new(ocr_workers, result_tx.clone(), save_text_files_flag);
     let mut frame_counter: u64 = 0;
     // let start_time = Instant::now();
     let mut previous_image: Option<Arc<DynamicImage>> = None;
     let mut max_average: Option<MaxAverageFrame> = None;
     let mut max_avg_value = 0.0;
+    let mut last_selected_hash: Option<u64> = None;
 
     loop {
         let (image, image_hash, _capture_duration) = capture_screenshot(&monitor).await;
-        let current_average = compare_with_previous_image(
-            &previous_image,
-            &image,
-            &mut max_average,
-            frame_counter,
-            &mut max_avg_value,
-        )
-        .await;
-        if current_average > max_avg_value {
-            max_average = Some(MaxAverageFrame {
-                image: Arc::new(image.clone()),
-                image_hash,
-                frame_number: frame_counter,
-                timestamp: Instant::now(),
-                result_tx: result_tx.clone(),
-                average: current_average,
-            });
-            max_avg_value = current_average;
-        }
-
-        previous_image = Some(Arc::new(image.clone()));
-        // debug!("ocr_task_running {} BEFORE if if !ocr_task_running.load(Ordering::SeqCst)", ocr_task_running.load(Ordering::SeqCst));
+        FRAMES_CAPTURED.inc();
+        let duplicate = last_selected_hash
+            .is_some_and(|selected| is_duplicate_frame(selected, image_hash, dedup_threshold));
+        if duplicate {
+            // Neither OCR'd nor stored, and spares the histogram and SSIM comparisons
+            FRAMES_DROPPED.with_label_values(&["duplicate"]).inc();
+        } else {
+            let current_average = compare_with_previous_image(
+                &previous_image,
+                &image,
+                &mut max_average,
+                frame_counter,
+                &mut max_avg_value,
+            )
+            .await;
+            // Only the most changed frame since the last OCR gets OCR'd, the others are dropped
+            if current_average > max_avg_value {
+                if max_average.is_some() {
+                    FRAMES_DROPPED.with_label_values(&["not_selected"]).inc();
+                }
+                max_average = Some(MaxAverageFrame {
+                    image: Arc::new(image.clone()),
+                    image_hash,
+                    frame_number: frame_counter,
+                    timestamp: Instant::now(),
+                    result_tx: result_tx.clone(),
+                    average: current_average,
+                });
+                max_avg_value = current_average;
+                last_selected_hash = Some(image_hash);
+            } else {
+                FRAMES_DROPPED.with_label_values(&["not_selected"]).inc();
+            }
 
-        if !ocr_task_running.load(Ordering::SeqCst) {
-            // debug!("max_avg_frame {} before if let Some(", max_avg_value);
+            previous_image = Some(Arc::new(image));
+        }
+        if !ocr_pool.is_full() {
             if let Some(max_avg_frame) = max_average.take() {
-                // Use take() to move out the value
-                let ocr_task_data = OcrTaskData {
-                    image: max_avg_frame.image.clone(),
+                ocr_pool.submit(OcrTaskData {
+                    image: max_avg_frame.image,
+                    image_hash: max_avg_frame.image_hash,
                     frame_number: max_avg_frame.frame_number,
                     timestamp: max_avg_frame.timestamp,
-                    result_tx: result_tx.clone(),
-                };
-
-                let previous_text_json_clone = previous_text_json.clone();
-                let ocr_task_running_clone = ocr_task_running.clone();
-
-                ocr_task_running.store(true, Ordering::SeqCst);
-                // debug!("ocr_task_running {}", ocr_task_running.load(Ordering::SeqCst));
-                tokio::spawn(async move {
-                    if let Err(e) = process_ocr_task(
-                        ocr_task_data.image,
-                        ocr_task_data.frame_number,
-                        ocr_task_data.timestamp,
-                        ocr_task_data.result_tx,
-                        &previous_text_json_clone,
-                        save_text_files_flag, // Pass the flag here
-                    )
-                    .await
-                    {
-                        error!("Error processing OCR task: {}", e);
-                    }
-                    ocr_task_running_clone.store(false, Ordering::SeqCst);
-                    // debug!("ocr_task_running_clone {}", ocr_task_running_clone.load(Ordering::SeqCst));
                 });
-
-                // Reset max_average and max_avg_value after spawning the OCR task
+                // The next frame picked for OCR is the most changed one from now on
                 max_avg_value = 0.0;
-                // debug!("max_avg_value {}", max_avg_value);
             }
         }
 
@@ -178,7 +196,7 @@ pub async fn continuous_capture(
         //     total_duration,
         //     frame_counter as f64 / total_duration.as_secs_f64()
         // );
-        tokio::time::sleep(interval).await;
+        tokio::time::sleep(interval.get()).await;
         // debug!("paseed tokio::time::sleep");
     }
 }
@@ -191,88 +209,3 @@ pub struct MaxAverageFrame {
     pub result_tx: Sender<CaptureResult>,
     pub average: f64,
 }
-
-async fn process_ocr_task(
-    image_arc: Arc<DynamicImage>,
-    frame_number: u64,
-    timestamp: Instant,
-    result_tx: Sender<CaptureResult>,
-    previous_text_json: &Arc<Mutex<Option<Vec<HashMap<String, String>>>>>,
-    save_text_files_flag: bool, // Add this parameter
-) -> Result<(), std::io::Error> {
-    let start_time = Instant::now();
-
-    // not to confuse with frame id which is wholly different thing 
-    debug!("Performing OCR for frame number since beginning of program {}", frame_number);
-    let (text, data_output, json_output) = perform_ocr(&image_arc);
-
-    let current_text_json: Vec<HashMap<String, String>> = serde_json::from_str(&json_output)
-        .unwrap_or_else(|e| {
-            error!("Failed to parse JSON output: {}", e);
-            Vec::new()
-        });
-
-    let mut previous_text_json = previous_text_json.lock().await;
-    let mut new_text_json = Vec::new();
-    if let Some(prev_json) = &*previous_text_json {
-        for current_record in &current_text_json {
-            let confidence: f64 = current_record["confidence"].parse().unwrap_or(0.0);
-            if confidence > 60.0 {
-                let is_new = prev_json.iter().all(|prev_record| {
-                    let distance = levenshtein(&current_record["text"], &prev_record["text"]);
-                    let threshold = (prev_record["text"].len() as f64 * 0.1).ceil() as usize;
-                    distance > threshold
-                });
-                if is_new {
-                    new_text_json.push(current_record.clone());
-                }
-            }
-        }
-    } else {
-        new_text_json = current_text_json
-            .iter()
-            .filter(|record| record["confidence"].parse::<f64>().unwrap_or(0.0) > 60.0)
-            .cloned()
-            .collect();
-    }
-
-    let mut seen_texts = HashSet::new();
-    new_text_json.retain(|record| seen_texts.insert(record["text"].clone()));
-
-    if save_text_files_flag {
-        save_text_files(
-            frame_number,
-            &new_text_json,
-            &current_text_json,
-            &previous_text_json,
-        )
-        .await;
-    }
-
-    *previous_text_json = Some(current_text_json.clone());
-
-    if let Err(e) = result_tx
-        .send(CaptureResult {
-            image: image_arc.into(),
-            text: text.clone(),
-            text_json: current_text_json,
-            new_text_json, 
-            frame_number,
-            timestamp,
-            data_output,
-        })
-        .await
-    {
-        error!("Failed to send OCR result: {}", e);
-        return Err(std::io::Error::new(
-            std::io::ErrorKind::Other,
-            "Failed to send OCR result",
-        ));
-    }
-    let _duration = start_time.elapsed();
-    debug!(
-        "OCR task processed frame {} in {:?}",
-        frame_number, _duration
-    );
-    Ok(())
-}
@@ -0,0 +1,241 @@
+// Layout-aware text: the words Tesseract read are grouped back into paragraphs and lines by the
+// numbers it gave them, paragraphs are put in reading order by where they sit on the frame, and
+// lines keep their indentation, so columns, code and chat messages don't run into each other.
+use std::collections::BTreeMap;
+
+use rusty_tesseract::DataOutput;
+use serde::{Deserialize, Serialize};
+
+/// A rectangle of the frame, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BoundingBox {
+    pub left: i32,
+    pub top: i32,
+    pub width: i32,
+    pub height: i32,
+}
+
+impl BoundingBox {
+    pub fn right(&self) -> i32 {
+        self.left + self.width
+    }
+
+    pub fn bottom(&self) -> i32 {
+        self.top + self.height
+    }
+
+    fn union(&self, other: &BoundingBox) -> BoundingBox {
+        let (left, top) = (self.left.min(other.left), self.top.min(other.top));
+        BoundingBox {
+            left,
+            top,
+            width: self.right().max(other.right()) - left,
+            height: self.bottom().max(other.bottom()) - top,
+        }
+    }
+
+    fn enclosing<'a>(boxes: impl IntoIterator<Item = &'a BoundingBox>) -> BoundingBox {
+        boxes
+            .into_iter()
+            .copied()
+            .reduce(|all, bbox| all.union(&bbox))
+            .unwrap_or(BoundingBox {
+                left: 0,
+                top: 0,
+                width: 0,
+                height: 0,
+            })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayoutWord {
+    pub text: String,
+    pub bbox: BoundingBox,
+    pub conf: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayoutLine {
+    pub bbox: BoundingBox,
+    pub words: Vec<LayoutWord>,
+}
+
+/// A paragraph as Tesseract found it.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LayoutBlock {
+    pub bbox: BoundingBox,
+    pub lines: Vec<LayoutLine>,
+}
+
+impl LayoutBlock {
+    /// Lines indented by how far they start from the left of the block, in characters.
+    fn to_text(&self) -> String {
+        let char_width = self.char_width();
+        self.lines
+            .iter()
+            .map(|line| {
+                let indent = char_width.map_or(0, |char_width| {
+                    ((line.bbox.left - self.bbox.left) as f32 / char_width).round() as usize
+                });
+                let words: Vec<&str> = line.words.iter().map(|word| word.text.as_str()).collect();
+                format!("{}{}", " ".repeat(indent), words.join(" "))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    /// Median width of a character in the block, in pixels.
+    fn char_width(&self) -> Option<f32> {
+        let mut widths: Vec<f32> = self
+            .lines
+            .iter()
+            .flat_map(|line| &line.words)
+            .filter_map(|word| {
+                let chars = word.text.chars().count();
+                (chars > 0 && word.bbox.width > 0).then(|| word.bbox.width as f32 / chars as f32)
+            })
+            .collect();
+        if widths.is_empty() {
+            return None;
+        }
+        widths.sort_by(f32::total_cmp);
+        Some(widths[widths.len() / 2])
+    }
+}
+
+/// The text of a frame as laid out on screen, blocks in reading order.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct OcrDocument {
+    pub blocks: Vec<LayoutBlock>,
+}
+
+impl OcrDocument {
+    pub fn from_data_output(data_output: &DataOutput) -> Self {
+        // Words are grouped by their numbers, incremental OCR may have dropped the block and line
+        // records around them
+        Self::from_words(
+            data_output
+                .data
+                .iter()
+                .filter(|record| record.level == 5)
+                .map(|record| {
+                    let numbers = (record.block_num, record.par_num, record.line_num);
+                    let word = LayoutWord {
+                        text: record.text.clone(),
+                        bbox: BoundingBox {
+                            left: record.left,
+                            top: record.top,
+                            width: record.width,
+                            height: record.height,
+                        },
+                        conf: record.conf,
+                    };
+                    (numbers, word)
+                }),
+        )
+    }
+
+    /// Words grouped into lines and paragraphs by their (block, paragraph, line) numbers, in the
+    /// order they are read within a line. Blank words are left out.
+    pub fn from_words(words: impl IntoIterator<Item = ((i32, i32, i32), LayoutWord)>) -> Self {
+        let mut lines: BTreeMap<(i32, i32, i32), Vec<LayoutWord>> = BTreeMap::new();
+        for (numbers, word) in words {
+            let text = word.text.trim();
+            if text.is_empty() {
+                continue;
+            }
+            let text = text.to_string();
+            lines
+                .entry(numbers)
+                .or_default()
+                .push(LayoutWord { text, ..word });
+        }
+
+        let mut blocks: BTreeMap<(i32, i32), Vec<LayoutLine>> = BTreeMap::new();
+        for ((block_num, par_num, _), words) in lines {
+            let bbox = BoundingBox::enclosing(words.iter().map(|word| &word.bbox));
+            blocks
+                .entry((block_num, par_num))
+                .or_default()
+                .push(LayoutLine { bbox, words });
+        }
+        let blocks = blocks
+            .into_values()
+            .map(|lines| LayoutBlock {
+                bbox: BoundingBox::enclosing(lines.iter().map(|line| &line.bbox)),
+                lines,
+            })
+            .collect();
+
+        OcrDocument {
+            blocks: reading_order(blocks),
+        }
+    }
+
+    /// Blocks separated by a blank line, lines by a line break.
+    pub fn to_text(&self) -> String {
+        self.blocks
+            .iter()
+            .map(LayoutBlock::to_text)
+            .collect::<Vec<_>>()
+            .join("\n\n")
+    }
+}
+
+/// Puts blocks in reading order: split into columns wherever a vertical gap runs through all of
+/// them and blocks on both sides sit next to each other, read left to right, else the top row is
+/// cut off at the first horizontal gap running through all of them and read before the rest, each
+/// part ordered the same way. Blocks that can't be split are read top to bottom.
+fn reading_order(blocks: Vec<LayoutBlock>) -> Vec<LayoutBlock> {
+    if blocks.len() < 2 {
+        return blocks;
+    }
+    let columns = split_at_gaps(blocks, |bbox| (bbox.left, bbox.right()));
+    // Chat bubbles alternating left and right are rows, not columns
+    if columns.len() > 1 && side_by_side(&columns) {
+        return columns.into_iter().flat_map(reading_order).collect();
+    }
+    let mut rows = split_at_gaps(columns.concat(), |bbox| (bbox.top, bbox.bottom()));
+    if rows.len() > 1 {
+        // What's below a heading may be in columns
+        let rest = rows.split_off(1).concat();
+        let mut blocks = reading_order(rows.concat());
+        blocks.extend(reading_order(rest));
+        return blocks;
+    }
+
+    let mut blocks = rows.pop().unwrap_or_default();
+    blocks.sort_by_key(|block| (block.bbox.top, block.bbox.left));
+    blocks
+}
+
+/// Whether blocks of different columns share some height of the frame.
+fn side_by_side(columns: &[Vec<LayoutBlock>]) -> bool {
+    columns.iter().enumerate().any(|(i, column)| {
+        columns[i + 1..].iter().flatten().any(|other| {
+            column.iter().any(|block| {
+                block.bbox.top < other.bbox.bottom() && other.bbox.top < block.bbox.bottom()
+            })
+        })
+    })
+}
+
+/// Groups of blocks whose spans overlap, in order along the axis.
+fn split_at_gaps(
+    mut blocks: Vec<LayoutBlock>,
+    span: fn(&BoundingBox) -> (i32, i32),
+) -> Vec<Vec<LayoutBlock>> {
+    blocks.sort_by_key(|block| span(&block.bbox));
+    let mut parts: Vec<Vec<LayoutBlock>> = Vec::new();
+    let mut end = i32::MIN;
+    for block in blocks {
+        let (start, block_end) = span(&block.bbox);
+        match parts.last_mut() {
+            Some(part) if start < end => part.push(block),
+            _ => parts.push(vec![block]),
+        }
+        end = end.max(block_end);
+    }
+    parts
+}
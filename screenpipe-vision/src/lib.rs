@@ -1,4 +1,13 @@
 pub mod core;
+mod layout;
+pub mod metrics;
+mod pool;
+mod tiles;
 mod utils;
-pub use core::{continuous_capture, CaptureResult, ControlMessage};
-pub use utils::perform_ocr;
+pub use core::{continuous_capture, CaptureInterval, CaptureResult, ControlMessage};
+pub use layout::{BoundingBox, LayoutBlock, LayoutLine, LayoutWord, OcrDocument};
+pub use pool::{OcrPool, ReorderBuffer};
+pub use tiles::{
+    changed_regions, changed_tiles, incremental_ocr, merge_ocr, OcrCache, Region, TILE_SIZE,
+};
+pub use utils::{hamming_distance, is_duplicate_frame, perceptual_hash, perform_ocr};
@@ -0,0 +1,35 @@
+// Prometheus series of the capture and OCR pipeline, registered in the default registry and
+// exposed by the server at `/metrics`.
+use lazy_static::lazy_static;
+use prometheus::{
+    register_histogram, register_int_counter, register_int_counter_vec, Histogram, IntCounter,
+    IntCounterVec,
+};
+
+lazy_static! {
+    pub static ref FRAMES_CAPTURED: IntCounter =
+        register_int_counter!("screenpipe_frames_captured_total", "Screenshots taken").unwrap();
+    pub static ref FRAMES_OCRED: IntCounter = register_int_counter!(
+        "screenpipe_frames_ocr_total",
+        "Frames that went through OCR"
+    )
+    .unwrap();
+    pub static ref FRAMES_DROPPED: IntCounterVec = register_int_counter_vec!(
+        "screenpipe_frames_dropped_total",
+        "Frames that never made it to the database, by reason",
+        &["reason"]
+    )
+    .unwrap();
+    pub static ref OCR_TILES: IntCounterVec = register_int_counter_vec!(
+        "screenpipe_ocr_tiles_total",
+        "Tiles of OCR'd frames, changed ones are read again, cached ones reuse the last text",
+        &["state"]
+    )
+    .unwrap();
+    pub static ref OCR_DURATION: Histogram = register_histogram!(
+        "screenpipe_ocr_duration_seconds",
+        "Time spent on OCR per frame",
+        vec![0.1, 0.25, 0.5, 1.0, 2.0, 4.0, 8.0, 16.0]
+    )
+    .unwrap();
+}
@@ -0,0 +1,245 @@
+// OCR worker pool: frames are OCR'd in parallel on dedicated threads, and their results are sent
+// on in the order the frames were captured, each compared with the one before for new text.
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex as StdMutex};
+use std::time::{Duration, Instant};
+
+use log::{debug, error};
+use rusty_tesseract::DataOutput;
+use strsim::levenshtein;
+use threadpool::ThreadPool;
+use tokio::sync::mpsc::{unbounded_channel, Sender, UnboundedReceiver, UnboundedSender};
+
+use crate::core::{CaptureResult, OcrTaskData};
+use crate::metrics::{FRAMES_OCRED, OCR_DURATION};
+use crate::tiles::{incremental_ocr, OcrCache};
+use crate::utils::{clone_data_output, save_text_files};
+
+/// Lines below this confidence never count as new text.
+const MIN_NEW_TEXT_CONFIDENCE: f64 = 60.0;
+
+/// Puts results of numbered tasks back in order, whatever order they complete in.
+pub struct ReorderBuffer<T> {
+    next: u64,
+    pending: BTreeMap<u64, Option<T>>,
+}
+
+impl<T> Default for ReorderBuffer<T> {
+    fn default() -> Self {
+        Self {
+            next: 0,
+            pending: BTreeMap::new(),
+        }
+    }
+}
+
+impl<T> ReorderBuffer<T> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Takes the result of task `seq`, `None` if it failed, and returns the results that are now
+    /// next in line. Tasks are numbered from 0.
+    pub fn push(&mut self, seq: u64, result: Option<T>) -> Vec<T> {
+        self.pending.insert(seq, result);
+        let mut ready = Vec::new();
+        while let Some(result) = self.pending.remove(&self.next) {
+            self.next += 1;
+            ready.extend(result);
+        }
+        ready
+    }
+
+    /// Results waiting for an earlier one.
+    pub fn pending(&self) -> usize {
+        self.pending.len()
+    }
+}
+
+struct OcrOutput {
+    task: OcrTaskData,
+    text: String,
+    text_json: Vec<HashMap<String, String>>,
+    data_output: DataOutput,
+    duration: Duration,
+}
+
+pub struct OcrPool {
+    pool: ThreadPool,
+    workers: usize,
+    in_flight: Arc<AtomicUsize>,
+    next_seq: u64,
+    /// Last frame OCR'd by any worker, for incremental OCR
+    cache: Arc<StdMutex<Option<OcrCache>>>,
+    output_tx: UnboundedSender<(u64, Option<OcrOutput>)>,
+}
+
+impl OcrPool {
+    /// Starts `workers` OCR threads, one per CPU when 0. Results are sent to `result_tx` in the
+    /// order frames are submitted.
+    pub fn new(workers: usize, result_tx: Sender<CaptureResult>, save_text_files: bool) -> Self {
+        let workers = if workers == 0 {
+            num_cpus::get()
+        } else {
+            workers
+        };
+        let (output_tx, output_rx) = unbounded_channel();
+        tokio::spawn(send_in_order(output_rx, result_tx, save_text_files));
+        Self {
+            pool: ThreadPool::with_name("ocr".to_string(), workers),
+            workers,
+            in_flight: Arc::new(AtomicUsize::new(0)),
+            next_seq: 0,
+            cache: Arc::new(StdMutex::new(None)),
+            output_tx,
+        }
+    }
+
+    /// Whether every worker is busy.
+    pub fn is_full(&self) -> bool {
+        self.in_flight.load(Ordering::SeqCst) >= self.workers
+    }
+
+    pub fn submit(&mut self, task: OcrTaskData) {
+        let seq = self.next_seq;
+        self.next_seq += 1;
+        self.in_flight.fetch_add(1, Ordering::SeqCst);
+
+        let in_flight = Arc::clone(&self.in_flight);
+        let cache = Arc::clone(&self.cache);
+        let output_tx = self.output_tx.clone();
+        self.pool.execute(move || {
+            let frame_number = task.frame_number;
+            // Tesseract failures panic, the frame is skipped rather than holding up the ones after
+            let output = catch_unwind(AssertUnwindSafe(|| ocr_frame(task, &cache))).ok();
+            if output.is_none() {
+                error!("OCR of frame {} failed", frame_number);
+            }
+            in_flight.fetch_sub(1, Ordering::SeqCst);
+            let _ = output_tx.send((seq, output));
+        });
+    }
+}
+
+fn ocr_frame(task: OcrTaskData, cache: &StdMutex<Option<OcrCache>>) -> OcrOutput {
+    let start_time = Instant::now();
+    // not to confuse with frame id which is wholly different thing
+    debug!(
+        "Performing OCR for frame number since beginning of program {}",
+        task.frame_number
+    );
+
+    // Copied out so workers don't wait on each other, any cached frame is a valid base
+    let previous = cache.lock().unwrap().as_ref().map(|cached| OcrCache {
+        frame_number: cached.frame_number,
+        image: Arc::clone(&cached.image),
+        data_output: clone_data_output(&cached.data_output),
+    });
+    let (document, data_output, json_output) = incremental_ocr(&task.image, previous.as_ref());
+    {
+        let mut cache = cache.lock().unwrap();
+        if cache
+            .as_ref()
+            .map_or(true, |cached| cached.frame_number < task.frame_number)
+        {
+            *cache = Some(OcrCache {
+                frame_number: task.frame_number,
+                image: Arc::clone(&task.image),
+                data_output: clone_data_output(&data_output),
+            });
+        }
+    }
+
+    let text_json: Vec<HashMap<String, String>> = serde_json::from_str(&json_output)
+        .unwrap_or_else(|e| {
+            error!("Failed to parse JSON output: {}", e);
+            Vec::new()
+        });
+
+    let text = document.to_text();
+
+    let duration = start_time.elapsed();
+    FRAMES_OCRED.inc();
+    OCR_DURATION.observe(duration.as_secs_f64());
+    debug!(
+        "OCR task processed frame {} in {:?}",
+        task.frame_number, duration
+    );
+    OcrOutput {
+        task,
+        text,
+        text_json,
+        data_output,
+        duration,
+    }
+}
+
+/// Lines of `current` that weren't in `previous`, give or take a few OCR errors.
+fn new_text_lines(
+    current: &[HashMap<String, String>],
+    previous: Option<&[HashMap<String, String>]>,
+) -> Vec<HashMap<String, String>> {
+    let mut new_text_json: Vec<HashMap<String, String>> = current
+        .iter()
+        .filter(|record| {
+            record["confidence"].parse::<f64>().unwrap_or(0.0) > MIN_NEW_TEXT_CONFIDENCE
+        })
+        .filter(|record| {
+            previous.map_or(true, |previous| {
+                previous.iter().all(|prev_record| {
+                    let distance = levenshtein(&record["text"], &prev_record["text"]);
+                    let threshold = (prev_record["text"].len() as f64 * 0.1).ceil() as usize;
+                    distance > threshold
+                })
+            })
+        })
+        .cloned()
+        .collect();
+
+    let mut seen_texts = HashSet::new();
+    new_text_json.retain(|record| seen_texts.insert(record["text"].clone()));
+    new_text_json
+}
+
+async fn send_in_order(
+    mut output_rx: UnboundedReceiver<(u64, Option<OcrOutput>)>,
+    result_tx: Sender<CaptureResult>,
+    save_text_files_flag: bool,
+) {
+    let mut buffer = ReorderBuffer::new();
+    let mut previous_text_json: Option<Vec<HashMap<String, String>>> = None;
+    while let Some((seq, output)) = output_rx.recv().await {
+        for output in buffer.push(seq, output) {
+            let frame_number = output.task.frame_number;
+            let new_text_json = new_text_lines(&output.text_json, previous_text_json.as_deref());
+            if save_text_files_flag {
+                save_text_files(
+                    frame_number,
+                    &new_text_json,
+                    &output.text_json,
+                    &previous_text_json,
+                )
+                .await;
+            }
+            previous_text_json = Some(output.text_json.clone());
+
+            let result = CaptureResult {
+                image: output.task.image,
+                text: output.text,
+                text_json: output.text_json,
+                new_text_json,
+                frame_number,
+                timestamp: output.task.timestamp,
+                data_output: output.data_output,
+                image_hash: output.task.image_hash,
+                ocr_duration: output.duration,
+            };
+            if result_tx.send(result).await.is_err() {
+                error!("Failed to send OCR result of frame {}", frame_number);
+                return;
+            }
+        }
+    }
+}
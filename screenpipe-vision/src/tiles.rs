@@ -0,0 +1,395 @@
+// Incremental OCR: frames are split in tiles, and only the tiles that changed since the last OCR'd
+// frame are read again. Their words replace the cached words of that area, the rest of the text
+// is carried over as is.
+use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use image::{DynamicImage, GenericImageView, RgbaImage};
+use rusty_tesseract::{Data, DataOutput};
+
+use crate::layout::OcrDocument;
+use crate::metrics::OCR_TILES;
+use crate::utils::{clone_data, clone_data_output, data_output_to_lines, read_data};
+
+/// Side of a tile in pixels.
+pub const TILE_SIZE: u32 = 128;
+/// Past this share of the frame changed, one OCR of the whole frame beats several of its parts.
+const FULL_OCR_RATIO: f64 = 0.5;
+
+/// The last OCR'd frame and its words, in frame coordinates.
+pub struct OcrCache {
+    pub frame_number: u64,
+    pub image: Arc<DynamicImage>,
+    pub data_output: DataOutput,
+}
+
+/// A rectangle of a frame, in pixels.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Region {
+    pub left: u32,
+    pub top: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Region {
+    fn right(&self) -> u32 {
+        self.left + self.width
+    }
+
+    fn bottom(&self) -> u32 {
+        self.top + self.height
+    }
+
+    fn overlaps(&self, other: &Region) -> bool {
+        self.left < other.right()
+            && other.left < self.right()
+            && self.top < other.bottom()
+            && other.top < self.bottom()
+    }
+
+    fn union(&self, other: &Region) -> Region {
+        let (left, top) = (self.left.min(other.left), self.top.min(other.top));
+        Region {
+            left,
+            top,
+            width: self.right().max(other.right()) - left,
+            height: self.bottom().max(other.bottom()) - top,
+        }
+    }
+
+    fn intersects_box(&self, record: &Data) -> bool {
+        let (left, top) = (record.left as i64, record.top as i64);
+        left < self.right() as i64
+            && (self.left as i64) < left + record.width as i64
+            && top < self.bottom() as i64
+            && (self.top as i64) < top + record.height as i64
+    }
+}
+
+fn rgba(image: &DynamicImage) -> Cow<'_, RgbaImage> {
+    match image.as_rgba8() {
+        Some(rgba) => Cow::Borrowed(rgba),
+        None => Cow::Owned(image.to_rgba8()),
+    }
+}
+
+/// Tiles of `tile_size` pixels that differ between two frames, as `(column, row)`. `None` when
+/// the frames differ in size.
+pub fn changed_tiles(
+    previous: &DynamicImage,
+    current: &DynamicImage,
+    tile_size: u32,
+) -> Option<Vec<(u32, u32)>> {
+    if previous.dimensions() != current.dimensions() {
+        return None;
+    }
+    let (previous, current) = (rgba(previous), rgba(current));
+    let (width, height) = current.dimensions();
+    let (previous, current) = (previous.as_raw(), current.as_raw());
+    let row_bytes = width as usize * 4;
+
+    let mut changed = Vec::new();
+    for row in 0..height.div_ceil(tile_size) {
+        let rows = row * tile_size..((row + 1) * tile_size).min(height);
+        for column in 0..width.div_ceil(tile_size) {
+            let start = (column * tile_size) as usize * 4;
+            let end = ((column + 1) * tile_size).min(width) as usize * 4;
+            // Captures are lossless, unchanged pixels are equal byte for byte
+            let differs = rows.clone().any(|y| {
+                let offset = y as usize * row_bytes;
+                previous[offset + start..offset + end] != current[offset + start..offset + end]
+            });
+            if differs {
+                changed.push((column, row));
+            }
+        }
+    }
+    Some(changed)
+}
+
+/// Rectangles covering groups of touching changed tiles, with one tile of margin so text at the
+/// edge of a change is read whole. Overlapping rectangles are merged, no word is read twice.
+pub fn changed_regions(
+    tiles: &[(u32, u32)],
+    tile_size: u32,
+    width: u32,
+    height: u32,
+) -> Vec<Region> {
+    let (columns, rows) = (width.div_ceil(tile_size), height.div_ceil(tile_size));
+    let index = |column: u32, row: u32| (row * columns + column) as usize;
+    let mut changed = vec![false; (columns * rows) as usize];
+    for &(column, row) in tiles {
+        if column < columns && row < rows {
+            changed[index(column, row)] = true;
+        }
+    }
+
+    let mut regions: Vec<Region> = Vec::new();
+    let mut seen = vec![false; changed.len()];
+    for start in 0..changed.len() {
+        if !changed[start] || seen[start] {
+            continue;
+        }
+        seen[start] = true;
+        let (column, row) = (start as u32 % columns, start as u32 / columns);
+        let (mut first_column, mut first_row, mut last_column, mut last_row) =
+            (column, row, column, row);
+        let mut stack = vec![(column, row)];
+        while let Some((column, row)) = stack.pop() {
+            first_column = first_column.min(column);
+            first_row = first_row.min(row);
+            last_column = last_column.max(column);
+            last_row = last_row.max(row);
+            for neighbour_row in row.saturating_sub(1)..=(row + 1).min(rows - 1) {
+                for neighbour_column in column.saturating_sub(1)..=(column + 1).min(columns - 1) {
+                    let i = index(neighbour_column, neighbour_row);
+                    if changed[i] && !seen[i] {
+                        seen[i] = true;
+                        stack.push((neighbour_column, neighbour_row));
+                    }
+                }
+            }
+        }
+
+        let left = first_column.saturating_sub(1) * tile_size;
+        let top = first_row.saturating_sub(1) * tile_size;
+        let mut region = Region {
+            left,
+            top,
+            width: ((last_column + 2) * tile_size).min(width) - left,
+            height: ((last_row + 2) * tile_size).min(height) - top,
+        };
+        // A merged rectangle may reach others it didn't overlap before
+        while let Some(i) = regions.iter().position(|other| other.overlaps(&region)) {
+            region = region.union(&regions.swap_remove(i));
+        }
+        regions.push(region);
+    }
+    regions
+}
+
+/// Replaces the cached words that touch a region by the words read in it, given relative to the
+/// region. A line read again takes the place of the cached line level with it, lines with nothing
+/// cached beside them make blocks of their own, placed before the first cached block below them.
+pub fn merge_ocr(previous: &DataOutput, regions: &[(Region, DataOutput)]) -> DataOutput {
+    // Words anywhere near a change are read again
+    let (kept, replaced): (Vec<&Data>, Vec<&Data>) = previous
+        .data
+        .iter()
+        .filter(|record| record.level == 5)
+        .partition(|record| {
+            !regions
+                .iter()
+                .any(|(region, _)| region.intersects_box(record))
+        });
+    let mut words: Vec<Data> = kept.into_iter().map(clone_data).collect();
+    let page = previous
+        .data
+        .iter()
+        .find(|record| record.level == 1)
+        .map(clone_data);
+
+    let cached_blocks = previous
+        .data
+        .iter()
+        .map(|record| record.block_num)
+        .max()
+        .unwrap_or(0);
+    let mut next_block = cached_blocks;
+    for (region, output) in regions {
+        let mut lines: BTreeMap<(i32, i32, i32), Vec<Data>> = BTreeMap::new();
+        for record in output.data.iter().filter(|record| record.level == 5) {
+            let mut record = clone_data(record);
+            record.left += region.left as i32;
+            record.top += region.top as i32;
+            lines
+                .entry((record.block_num, record.par_num, record.line_num))
+                .or_default()
+                .push(record);
+        }
+
+        let mut new_blocks = HashMap::new();
+        for ((block_num, par_num, line_num), mut line) in lines {
+            let top = line.iter().map(|word| word.top).min().unwrap_or(0);
+            let bottom = line
+                .iter()
+                .map(|word| word.top + word.height)
+                .max()
+                .unwrap_or(0);
+            let numbers = replaced
+                .iter()
+                .filter(|word| region.intersects_box(word))
+                .find(|word| (top..bottom).contains(&(word.top + word.height / 2)))
+                .map(|word| (word.block_num, word.par_num, word.line_num))
+                .unwrap_or_else(|| {
+                    let block = *new_blocks.entry(block_num).or_insert_with(|| {
+                        next_block += 1;
+                        next_block
+                    });
+                    (block, par_num, line_num)
+                });
+            for word in &mut line {
+                (word.block_num, word.par_num, word.line_num) = numbers;
+            }
+            words.extend(line);
+        }
+    }
+
+    DataOutput {
+        // There is no Tesseract output for the merged words
+        output: String::new(),
+        data: layout_records(page, words, cached_blocks),
+    }
+}
+
+/// Lays words out as Tesseract does: the page, then a record for each block, paragraph and line
+/// followed by what it holds, numbered in that order and words left to right. Blocks up to
+/// `cached_blocks` keep their order, the others go before the first block below them.
+fn layout_records(page: Option<Data>, words: Vec<Data>, cached_blocks: i32) -> Vec<Data> {
+    let mut blocks: BTreeMap<i32, BTreeMap<(i32, i32), Vec<Data>>> = BTreeMap::new();
+    for word in words {
+        blocks
+            .entry(word.block_num)
+            .or_default()
+            .entry((word.par_num, word.line_num))
+            .or_default()
+            .push(word);
+    }
+    let top = |lines: &BTreeMap<(i32, i32), Vec<Data>>| {
+        lines.values().flatten().map(|word| word.top).min()
+    };
+    let mut order: Vec<BTreeMap<(i32, i32), Vec<Data>>> = Vec::new();
+    for (block_num, lines) in blocks {
+        let index = if block_num <= cached_blocks {
+            order.len()
+        } else {
+            order
+                .iter()
+                .position(|block| top(block) > top(&lines))
+                .unwrap_or(order.len())
+        };
+        order.insert(index, lines);
+    }
+
+    let all: Vec<&Data> = order
+        .iter()
+        .flat_map(|lines| lines.values().flatten())
+        .collect();
+    let mut records = vec![page.unwrap_or_else(|| layout_record(1, (0, 0, 0, 0), &all))];
+    for (block_index, lines) in order.iter().enumerate() {
+        let block_num = block_index as i32 + 1;
+        let block: Vec<&Data> = lines.values().flatten().collect();
+        records.push(layout_record(2, (block_num, 0, 0, 0), &block));
+        let mut par_num = 0;
+        let mut line_num = 0;
+        let mut last_par = None;
+        for ((par, _), line) in lines {
+            if last_par != Some(*par) {
+                last_par = Some(*par);
+                par_num += 1;
+                line_num = 0;
+                let paragraph: Vec<&Data> = lines
+                    .iter()
+                    .filter(|((other, _), _)| other == par)
+                    .flat_map(|(_, line)| line)
+                    .collect();
+                records.push(layout_record(3, (block_num, par_num, 0, 0), &paragraph));
+            }
+            line_num += 1;
+            let mut line: Vec<&Data> = line.iter().collect();
+            line.sort_by_key(|word| word.left);
+            records.push(layout_record(4, (block_num, par_num, line_num, 0), &line));
+            for (word_index, word) in line.into_iter().enumerate() {
+                let mut word = clone_data(word);
+                (word.block_num, word.par_num, word.line_num, word.word_num) =
+                    (block_num, par_num, line_num, word_index as i32 + 1);
+                records.push(word);
+            }
+        }
+    }
+    records
+}
+
+/// A page, block, paragraph or line record enclosing `words`.
+fn layout_record(level: i32, numbers: (i32, i32, i32, i32), words: &[&Data]) -> Data {
+    let left = words.iter().map(|word| word.left).min().unwrap_or(0);
+    let top = words.iter().map(|word| word.top).min().unwrap_or(0);
+    let right = words
+        .iter()
+        .map(|word| word.left + word.width)
+        .max()
+        .unwrap_or(0);
+    let bottom = words
+        .iter()
+        .map(|word| word.top + word.height)
+        .max()
+        .unwrap_or(0);
+    let (block_num, par_num, line_num, word_num) = numbers;
+    Data {
+        level,
+        page_num: 1,
+        block_num,
+        par_num,
+        line_num,
+        word_num,
+        left,
+        top,
+        width: right - left,
+        height: bottom - top,
+        conf: -1.0,
+        text: String::new(),
+    }
+}
+
+/// Same as `perform_ocr`, but only reads the parts of `image` that changed since `previous`, and
+/// returns the layout of the text rather than its plain text rendering.
+pub fn incremental_ocr(
+    image: &DynamicImage,
+    previous: Option<&OcrCache>,
+) -> (OcrDocument, DataOutput, String) {
+    let data_output = incremental_data(image, previous);
+    let document = OcrDocument::from_data_output(&data_output);
+    let json_output = serde_json::to_string_pretty(&data_output_to_lines(&data_output)).unwrap();
+    (document, data_output, json_output)
+}
+
+fn incremental_data(image: &DynamicImage, previous: Option<&OcrCache>) -> DataOutput {
+    let Some(previous) = previous else {
+        return read_data(image);
+    };
+    let Some(tiles) = changed_tiles(&previous.image, image, TILE_SIZE) else {
+        return read_data(image);
+    };
+    let (width, height) = image.dimensions();
+    let total_tiles = width.div_ceil(TILE_SIZE) * height.div_ceil(TILE_SIZE);
+    OCR_TILES
+        .with_label_values(&["changed"])
+        .inc_by(tiles.len() as u64);
+    OCR_TILES
+        .with_label_values(&["cached"])
+        .inc_by((total_tiles as usize - tiles.len()) as u64);
+
+    let regions = changed_regions(&tiles, TILE_SIZE, width, height);
+    let changed_area: u64 = regions
+        .iter()
+        .map(|region| region.width as u64 * region.height as u64)
+        .sum();
+    if changed_area as f64 > width as f64 * height as f64 * FULL_OCR_RATIO {
+        return read_data(image);
+    }
+
+    let read: Vec<(Region, DataOutput)> = regions
+        .into_iter()
+        .map(|region| {
+            let crop = image.crop_imm(region.left, region.top, region.width, region.height);
+            (region, read_data(&crop))
+        })
+        .collect();
+    if read.is_empty() {
+        clone_data_output(&previous.data_output)
+    } else {
+        merge_ocr(&previous.data_output, &read)
+    }
+}
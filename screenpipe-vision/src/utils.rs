@@ -1,21 +1,44 @@
 use crate::core::MaxAverageFrame; // Assuming core.rs is in the same crate under the `core` module
+use crate::layout::OcrDocument;
 use image::DynamicImage;
 use image_compare::{Algorithm, Metric, Similarity}; // Added import for Similarity
 use log::{debug, error};
-use rusty_tesseract::{Args, DataOutput, Image}; // Added import for Args, Image, DataOutput
+use rusty_tesseract::{Args, Data, DataOutput, Image}; // Added import for Args, Image, DataOutput
 use serde_json;
 use std::collections::HashMap;
 use std::fs::{self, File};
-use std::hash::{DefaultHasher, Hash, Hasher};
 use std::io::Write;
 use std::sync::Arc;
 use std::time::{Duration, Instant};
 use xcap::Monitor;
 
-pub fn calculate_hash(image: &DynamicImage) -> u64 {
-    let mut hasher = DefaultHasher::new();
-    image.as_bytes().hash(&mut hasher);
-    hasher.finish()
+/// Side of the grayscale grid a frame is shrunk to for its perceptual hash.
+const HASH_SIZE: u32 = 8;
+
+/// Difference hash (dHash) of a frame: shrunk to a 9x8 grayscale grid, each bit tells whether a
+/// cell is brighter than its right neighbour. A few changed pixels, like a clock ticking, leave it
+/// unchanged, unlike a hash of the raw bytes.
+pub fn perceptual_hash(image: &DynamicImage) -> u64 {
+    let grid = image.thumbnail_exact(HASH_SIZE + 1, HASH_SIZE).to_luma8();
+    let mut hash = 0u64;
+    for y in 0..HASH_SIZE {
+        for x in 0..HASH_SIZE {
+            let brighter = grid.get_pixel(x, y)[0] > grid.get_pixel(x + 1, y)[0];
+            hash = (hash << 1) | brighter as u64;
+        }
+    }
+    hash
+}
+
+/// Number of bits two perceptual hashes differ in, out of 64.
+pub fn hamming_distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Whether a frame hashed `current_hash` is at most `threshold` bits away from the one hashed
+/// `previous_hash`, a threshold of 0 turns dedup off.
+pub fn is_duplicate_frame(previous_hash: u64, current_hash: u64, threshold: u32) -> bool {
+    threshold > 0 && hamming_distance(previous_hash, current_hash) <= threshold
 }
 
 pub fn compare_images_histogram(image1: &DynamicImage, image2: &DynamicImage) -> f64 {
@@ -38,6 +61,21 @@ pub fn compare_images_ssim(image1: &DynamicImage, image2: &DynamicImage) -> f64
 
 pub fn perform_ocr(image: &DynamicImage) -> (String, DataOutput, String) {
     // debug!("inside perform_ocr");
+    let data_output = read_data(image);
+    // let tsv_output = data_output_to_tsv(&data_output);
+
+    // Extract text from data output
+    let text = OcrDocument::from_data_output(&data_output).to_text();
+
+    // Extract JSON output with confidence scores
+    let lines = data_output_to_lines(&data_output);
+    let json_output = serde_json::to_string_pretty(&lines).unwrap();
+
+    (text, data_output, json_output)
+}
+
+/// The words Tesseract read in `image`, and the blocks, paragraphs and lines around them.
+pub(crate) fn read_data(image: &DynamicImage) -> DataOutput {
     let args = Args {
         lang: "eng".to_string(),
         config_variables: HashMap::from([("tessedit_create_tsv".into(), "1".into())]),
@@ -49,13 +87,11 @@ pub fn perform_ocr(image: &DynamicImage) -> (String, DataOutput, String) {
     let ocr_image = Image::from_dynamic_image(image).unwrap();
 
     // Extract data output
-    let data_output = rusty_tesseract::image_to_data(&ocr_image, &args).unwrap();
-    // let tsv_output = data_output_to_tsv(&data_output);
-
-    // Extract text from data output
-    let text = data_output_to_text(&data_output);
+    rusty_tesseract::image_to_data(&ocr_image, &args).unwrap()
+}
 
-    // Extract JSON output with confidence scores
+/// Lines of words with their average confidence, in the order Tesseract reported them.
+pub(crate) fn data_output_to_lines(data_output: &DataOutput) -> Vec<HashMap<String, String>> {
     let mut lines: Vec<HashMap<String, String>> = Vec::new();
     let mut current_line = String::new();
     let mut current_conf = 0.0;
@@ -107,29 +143,39 @@ pub fn perform_ocr(image: &DynamicImage) -> (String, DataOutput, String) {
 
     // Sort lines by confidence in descending order
     // lines.sort_by(|a, b| b["confidence"].partial_cmp(&a["confidence"]).unwrap());
-    let json_output = serde_json::to_string_pretty(&lines).unwrap();
+    lines
+}
 
-    (text, data_output, json_output)
+/// Copies an OCR result, `DataOutput` doesn't implement `Clone`.
+pub(crate) fn clone_data_output(data_output: &DataOutput) -> DataOutput {
+    DataOutput {
+        output: data_output.output.clone(),
+        data: data_output.data.iter().map(clone_data).collect(),
+    }
 }
 
-fn data_output_to_text(data_output: &DataOutput) -> String {
-    let mut text = String::new();
-    for record in &data_output.data {
-        if !record.text.is_empty() {
-            if !text.is_empty() {
-                text.push(' ');
-            }
-            text.push_str(&record.text);
-        }
+pub(crate) fn clone_data(d: &Data) -> Data {
+    Data {
+        level: d.level,
+        page_num: d.page_num,
+        block_num: d.block_num,
+        par_num: d.par_num,
+        line_num: d.line_num,
+        word_num: d.word_num,
+        left: d.left,
+        top: d.top,
+        width: d.width,
+        height: d.height,
+        conf: d.conf,
+        text: d.text.clone(),
     }
-    text
 }
 
 pub async fn capture_screenshot(monitor: &Monitor) -> (DynamicImage, u64, Duration) {
     let capture_start = Instant::now();
     let buffer = monitor.capture_image().unwrap();
     let image = DynamicImage::ImageRgba8(buffer);
-    let image_hash = calculate_hash(&image);
+    let image_hash = perceptual_hash(&image);
     let capture_duration = capture_start.elapsed();
     (image, image_hash, capture_duration)
 }
@@ -0,0 +1,40 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use screenpipe_vision::{hamming_distance, is_duplicate_frame, perceptual_hash};
+
+/// Brightness going up from left to right, or down when `reversed`.
+fn gradient(reversed: bool) -> RgbaImage {
+    RgbaImage::from_fn(1920, 1080, |x, _| {
+        let value = (x * 255 / 1919) as u8;
+        let value = if reversed { 255 - value } else { value };
+        Rgba([value, value, value, 255])
+    })
+}
+
+#[test]
+fn test_clock_tick_is_a_duplicate() {
+    let frame = DynamicImage::ImageRgba8(gradient(false));
+    // The clock in the corner of the screen ticking over
+    let mut ticked = gradient(false);
+    ticked.put_pixel(1900, 10, Rgba([0, 0, 0, 255]));
+    let ticked = DynamicImage::ImageRgba8(ticked);
+
+    let hash = perceptual_hash(&frame);
+    let ticked_hash = perceptual_hash(&ticked);
+    assert_eq!(hamming_distance(hash, ticked_hash), 0);
+
+    assert!(is_duplicate_frame(hash, ticked_hash, 1));
+    // Dedup is off at 0
+    assert!(!is_duplicate_frame(hash, ticked_hash, 0));
+}
+
+#[test]
+fn test_different_frames_get_distant_hashes() {
+    let hash = perceptual_hash(&DynamicImage::ImageRgba8(gradient(false)));
+    let reversed = perceptual_hash(&DynamicImage::ImageRgba8(gradient(true)));
+
+    assert_eq!(hash, 0);
+    assert_eq!(reversed, u64::MAX);
+    assert_eq!(hamming_distance(hash, reversed), 64);
+    assert!(!is_duplicate_frame(hash, reversed, 63));
+    assert!(is_duplicate_frame(hash, reversed, 64));
+}
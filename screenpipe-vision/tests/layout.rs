@@ -0,0 +1,87 @@
+use rusty_tesseract::{Data, DataOutput};
+use screenpipe_vision::{BoundingBox, OcrDocument};
+
+/// A word 12 pixels high, `(block_num, par_num, line_num)` in `position`.
+fn word(position: (i32, i32, i32), left: i32, top: i32, width: i32, text: &str) -> Data {
+    Data {
+        level: 5,
+        page_num: 1,
+        block_num: position.0,
+        par_num: position.1,
+        line_num: position.2,
+        word_num: 1,
+        left,
+        top,
+        width,
+        height: 12,
+        conf: 90.0,
+        text: text.to_string(),
+    }
+}
+
+fn document(data: Vec<Data>) -> OcrDocument {
+    OcrDocument::from_data_output(&DataOutput {
+        output: String::new(),
+        data,
+    })
+}
+
+#[test]
+fn test_columns_are_read_one_after_the_other() {
+    // Blocks numbered out of reading order, as after an incremental OCR
+    let document = document(vec![
+        word((3, 1, 1), 100, 10, 250, "Release"),
+        word((3, 1, 1), 360, 10, 240, "notes"),
+        word((1, 1, 1), 100, 50, 30, "The"),
+        word((2, 1, 1), 400, 50, 40, "lazy"),
+        word((1, 1, 1), 140, 50, 50, "quick"),
+        word((2, 1, 1), 450, 50, 40, "dogs"),
+        word((1, 1, 2), 100, 70, 50, "brown"),
+        word((2, 1, 2), 400, 70, 50, "sleep"),
+        word((1, 1, 2), 160, 70, 30, "fox"),
+        word((1, 2, 1), 100, 110, 50, "jumps"),
+        word((1, 2, 1), 160, 110, 40, "over"),
+    ]);
+
+    assert_eq!(
+        document.to_text(),
+        "Release notes\n\nThe quick\nbrown fox\n\njumps over\n\nlazy dogs\nsleep"
+    );
+    assert_eq!(document.blocks.len(), 4);
+    assert_eq!(
+        document.blocks[1].bbox,
+        BoundingBox {
+            left: 100,
+            top: 50,
+            width: 90,
+            height: 32,
+        }
+    );
+}
+
+#[test]
+fn test_chat_bubbles_are_read_top_to_bottom() {
+    let document = document(vec![
+        word((1, 1, 1), 20, 10, 20, "hi"),
+        word((1, 1, 1), 50, 10, 50, "there"),
+        word((2, 1, 1), 20, 70, 30, "how"),
+        word((2, 1, 1), 60, 70, 30, "are"),
+        word((2, 1, 1), 100, 70, 30, "you"),
+        word((3, 1, 1), 400, 40, 50, "hello"),
+    ]);
+
+    assert_eq!(document.to_text(), "hi there\n\nhello\n\nhow are you");
+}
+
+#[test]
+fn test_indentation_is_kept() {
+    let document = document(vec![
+        word((1, 1, 1), 0, 0, 20, "fn"),
+        word((1, 1, 1), 30, 0, 60, "main()"),
+        word((1, 1, 1), 100, 0, 10, "{"),
+        word((1, 1, 2), 40, 20, 150, "println!(\"hi\");"),
+        word((1, 1, 3), 0, 40, 10, "}"),
+    ]);
+
+    assert_eq!(document.to_text(), "fn main() {\n    println!(\"hi\");\n}");
+}
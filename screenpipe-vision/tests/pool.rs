@@ -0,0 +1,23 @@
+use screenpipe_vision::ReorderBuffer;
+
+#[test]
+fn test_results_come_out_in_submission_order() {
+    let mut buffer = ReorderBuffer::new();
+
+    assert_eq!(buffer.push(1, Some("second")), Vec::<&str>::new());
+    assert_eq!(buffer.push(2, Some("third")), Vec::<&str>::new());
+    assert_eq!(buffer.pending(), 2);
+    assert_eq!(buffer.push(0, Some("first")), ["first", "second", "third"]);
+    assert_eq!(buffer.push(3, Some("fourth")), ["fourth"]);
+    assert_eq!(buffer.pending(), 0);
+}
+
+#[test]
+fn test_failed_tasks_dont_hold_up_the_next_ones() {
+    let mut buffer = ReorderBuffer::new();
+
+    assert_eq!(buffer.push(1, Some(1)), Vec::<i32>::new());
+    assert_eq!(buffer.push(0, None), [1]);
+    assert_eq!(buffer.push(2, None), Vec::<i32>::new());
+    assert_eq!(buffer.push(3, Some(3)), [3]);
+}
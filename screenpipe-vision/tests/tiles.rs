@@ -0,0 +1,202 @@
+use image::{DynamicImage, Rgba, RgbaImage};
+use rusty_tesseract::{Data, DataOutput};
+use screenpipe_vision::{changed_regions, changed_tiles, merge_ocr, OcrDocument, Region};
+
+fn record(
+    level: i32,
+    block_num: i32,
+    word_num: i32,
+    bbox: (i32, i32, i32, i32),
+    text: &str,
+) -> Data {
+    Data {
+        level,
+        page_num: 1,
+        block_num,
+        par_num: 1,
+        line_num: 1,
+        word_num,
+        left: bbox.0,
+        top: bbox.1,
+        width: bbox.2,
+        height: bbox.3,
+        conf: 90.0,
+        text: text.to_string(),
+    }
+}
+
+fn words(data_output: &DataOutput) -> Vec<&str> {
+    data_output
+        .data
+        .iter()
+        .filter(|record| record.level == 5)
+        .map(|record| record.text.as_str())
+        .collect()
+}
+
+#[test]
+fn test_changed_tiles() {
+    let frame = RgbaImage::from_pixel(1000, 600, Rgba([0, 0, 0, 255]));
+    let mut next = frame.clone();
+    // A cursor in the first tile and a message across two tiles of the last row
+    next.put_pixel(5, 5, Rgba([255, 255, 255, 255]));
+    for x in 250..270 {
+        next.put_pixel(x, 590, Rgba([255, 255, 255, 255]));
+    }
+    let (frame, next) = (
+        DynamicImage::ImageRgba8(frame),
+        DynamicImage::ImageRgba8(next),
+    );
+
+    assert_eq!(
+        changed_tiles(&frame, &next, 128),
+        Some(vec![(0, 0), (1, 4), (2, 4)])
+    );
+    assert_eq!(changed_tiles(&frame, &frame, 128), Some(vec![]));
+    let smaller = DynamicImage::ImageRgba8(RgbaImage::new(800, 600));
+    assert_eq!(changed_tiles(&frame, &smaller, 128), None);
+}
+
+#[test]
+fn test_changed_regions_are_padded_and_merged() {
+    // 10x5 tiles of 100 pixels, the last column and row are 50 pixels wide
+    let regions = changed_regions(&[(0, 0), (5, 2), (6, 3), (8, 4)], 100, 950, 450);
+
+    assert_eq!(
+        regions,
+        vec![
+            Region {
+                left: 0,
+                top: 0,
+                width: 200,
+                height: 200,
+            },
+            // The margin of the second group reaches the one of the lone tile below
+            Region {
+                left: 400,
+                top: 100,
+                width: 550,
+                height: 350,
+            },
+        ]
+    );
+}
+
+#[test]
+fn test_merge_ocr_replaces_words_of_changed_regions() {
+    let previous = DataOutput {
+        output: String::new(),
+        data: vec![
+            record(1, 0, 0, (0, 0, 1000, 600), ""),
+            record(2, 1, 0, (10, 10, 300, 20), ""),
+            record(5, 1, 1, (10, 10, 100, 20), "kept"),
+            record(5, 1, 2, (200, 10, 110, 20), "stale"),
+        ],
+    };
+    let region = Region {
+        left: 150,
+        top: 0,
+        width: 300,
+        height: 100,
+    };
+    let read = DataOutput {
+        output: String::new(),
+        data: vec![
+            record(1, 0, 0, (0, 0, 300, 100), ""),
+            record(2, 1, 0, (50, 10, 120, 20), ""),
+            record(5, 1, 1, (50, 10, 120, 20), "fresh"),
+        ],
+    };
+
+    let merged = merge_ocr(&previous, &[(region, read)]);
+
+    // The word read again stays on the line of the one it replaced
+    assert_eq!(words(&merged), ["kept", "fresh"]);
+    let fresh = merged.data.last().unwrap();
+    assert_eq!((fresh.left, fresh.top), (200, 10));
+    assert_eq!(
+        (
+            fresh.block_num,
+            fresh.par_num,
+            fresh.line_num,
+            fresh.word_num
+        ),
+        (1, 1, 1, 2)
+    );
+    // The page, the block, its paragraph and its line
+    assert_eq!(
+        merged
+            .data
+            .iter()
+            .map(|record| record.level)
+            .collect::<Vec<_>>(),
+        [1, 2, 3, 4, 5, 5]
+    );
+}
+
+#[test]
+fn test_merge_ocr_keeps_reading_order() {
+    let line = |line_num, top, text| {
+        let mut word = record(5, 1, 1, (10, top, 200, 20), text);
+        word.line_num = line_num;
+        word
+    };
+    // A paragraph of three lines and a block below it
+    let previous = DataOutput {
+        output: String::new(),
+        data: vec![
+            record(1, 0, 0, (0, 0, 1000, 600), ""),
+            line(1, 10, "first"),
+            line(2, 40, "second"),
+            line(3, 70, "third"),
+            record(5, 2, 1, (10, 400, 200, 20), "footer"),
+        ],
+    };
+    // The middle line changed, and a message showed up between the paragraph and the footer
+    let edited = (
+        Region {
+            left: 0,
+            top: 35,
+            width: 300,
+            height: 30,
+        },
+        DataOutput {
+            output: String::new(),
+            data: vec![record(5, 1, 1, (10, 5, 200, 20), "edited")],
+        },
+    );
+    let message = (
+        Region {
+            left: 0,
+            top: 150,
+            width: 300,
+            height: 100,
+        },
+        DataOutput {
+            output: String::new(),
+            data: vec![record(5, 1, 1, (10, 50, 200, 20), "message")],
+        },
+    );
+
+    let merged = merge_ocr(&previous, &[edited, message]);
+
+    assert_eq!(
+        words(&merged),
+        ["first", "edited", "third", "message", "footer"]
+    );
+    assert_eq!(
+        OcrDocument::from_data_output(&merged).to_text(),
+        "first\nedited\nthird\n\nmessage\n\nfooter"
+    );
+    // Lines are numbered again after every line record, as text_json reads them
+    let numbers: Vec<_> = merged
+        .data
+        .iter()
+        .filter(|record| record.level == 5)
+        .map(|record| (record.block_num, record.line_num, record.word_num))
+        .collect();
+    assert_eq!(
+        numbers,
+        [(1, 1, 1), (1, 2, 1), (1, 3, 1), (2, 1, 1), (3, 1, 1)]
+    );
+}